@@ -1,34 +1,140 @@
-use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
-use json_sync::JsonSync;
+//! Each group runs the same workload against every backend, so the criterion
+//! report lines them up side by side. DashMap joins in with `--features dashmap`.
+
+use criterion::{criterion_group, criterion_main, Bencher, BenchmarkId, Criterion};
+use json_sync::backend::MapBackend;
+use json_sync::{JsonSync, JsonSyncHandle};
+use parking_lot::RwLock;
 use shardmap::ShardMap;
+use std::collections::HashMap;
 use std::hint::black_box;
 use std::path::PathBuf;
 use std::time::Duration;
 
-fn bench_path(name: &str, size: usize) -> PathBuf {
-    std::env::temp_dir().join(format!("json_sync_bench_{}_{}.json", name, size))
+type Store<M> = JsonSyncHandle<String, i32, M>;
+
+fn bench_path(name: &str, backend: &str, size: usize) -> PathBuf {
+    std::env::temp_dir().join(format!("json_sync_bench_{name}_{backend}_{size}.json"))
+}
+
+/// Open a fresh store for one benchmark, run `f` on it, then clean up.
+fn with_store<M, F>(name: &str, backend: &str, size: usize, f: F)
+where
+    M: MapBackend<String, i32> + Default + 'static,
+    F: FnOnce(&Store<M>),
+{
+    let path = bench_path(name, backend, size);
+    let _ = std::fs::remove_file(&path);
+    let db = JsonSync::<String, i32, M>::open(&path).unwrap();
+    f(&db);
+    drop(db);
+    let _ = std::fs::remove_file(&path);
+}
+
+fn fill<M>(db: &Store<M>, size: usize)
+where
+    M: MapBackend<String, i32> + 'static,
+{
+    db.extend((0..size).map(|i| (format!("k{i}"), i as i32)))
+        .unwrap();
+}
+
+/// Register `$run::<M>` once per backend in `$group` for the given size.
+macro_rules! for_each_backend {
+    ($group:expr, $size:expr, $run:ident) => {{
+        $group.bench_with_input(BenchmarkId::new("shardmap", $size), &$size, |b, &size| {
+            $run::<ShardMap<String, i32>>(b, "shardmap", size)
+        });
+        $group.bench_with_input(
+            BenchmarkId::new("rwlock_hashmap", $size),
+            &$size,
+            |b, &size| $run::<RwLock<HashMap<String, i32>>>(b, "rwlock_hashmap", size),
+        );
+        #[cfg(feature = "dashmap")]
+        $group.bench_with_input(BenchmarkId::new("dashmap", $size), &$size, |b, &size| {
+            $run::<dashmap::DashMap<String, i32>>(b, "dashmap", size)
+        });
+    }};
+}
+
+// ---- workloads ---------------------------------------------------------------
+
+fn run_insert_get_remove<M>(b: &mut Bencher, backend: &str, size: usize)
+where
+    M: MapBackend<String, i32> + Default + 'static,
+{
+    with_store::<M, _>("igr", backend, size, |db| {
+        b.iter(|| {
+            for i in 0..size {
+                let _ = db.insert(format!("k{i}"), i as i32).unwrap();
+            }
+            for i in 0..size {
+                black_box(db.get(&format!("k{i}")));
+            }
+            for i in 0..size {
+                let _ = db.remove(&format!("k{i}")).unwrap();
+            }
+        });
+    });
+}
+
+fn run_flush<M>(b: &mut Bencher, backend: &str, size: usize)
+where
+    M: MapBackend<String, i32> + Default + 'static,
+{
+    with_store::<M, _>("flush", backend, size, |db| {
+        fill(db, size);
+        b.iter(|| db.flush().unwrap());
+    });
+}
+
+fn run_extend<M>(b: &mut Bencher, backend: &str, size: usize)
+where
+    M: MapBackend<String, i32> + Default + 'static,
+{
+    with_store::<M, _>("extend", backend, size, |db| {
+        let batch: Vec<(String, i32)> = (0..size).map(|i| (format!("k{i}"), i as i32)).collect();
+        b.iter(|| {
+            db.extend(batch.clone()).unwrap();
+            db.clear().unwrap();
+        });
+    });
+}
+
+fn run_update<M>(b: &mut Bencher, backend: &str, size: usize)
+where
+    M: MapBackend<String, i32> + Default + 'static,
+{
+    with_store::<M, _>("update", backend, size, |db| {
+        fill(db, size);
+        b.iter(|| {
+            for i in 0..size {
+                db.update(&format!("k{i}"), |v| *v += 1).unwrap();
+            }
+        });
+    });
+}
+
+fn run_clear<M>(b: &mut Bencher, backend: &str, size: usize)
+where
+    M: MapBackend<String, i32> + Default + 'static,
+{
+    with_store::<M, _>("clear", backend, size, |db| {
+        b.iter(|| {
+            for i in 0..size {
+                db.insert(format!("k{i}"), i as i32).unwrap();
+            }
+            db.clear().unwrap();
+        });
+    });
 }
 
+// ---- groups ------------------------------------------------------------------
+
 fn bench_insert_get_remove(c: &mut Criterion) {
     let mut group = c.benchmark_group("insert_get_remove");
     for size in [10, 100, 1000] {
-        group.bench_with_input(BenchmarkId::new("shardmap", size), &size, |b, &size| {
-            let path = bench_path("igr", size);
-            let _ = std::fs::remove_file(&path);
-            let db = JsonSync::<String, i32, ShardMap<String, i32>>::open(&path).unwrap();
-            b.iter(|| {
-                for i in 0..size {
-                    let _ = db.insert(format!("k{i}"), i as i32).unwrap();
-                }
-                for i in 0..size {
-                    black_box(db.get(&format!("k{i}")));
-                }
-                for i in 0..size {
-                    let _ = db.remove(&format!("k{i}")).unwrap();
-                }
-            });
-            let _ = std::fs::remove_file(&path);
-        });
+        for_each_backend!(group, size, run_insert_get_remove);
     }
 }
 
@@ -37,54 +143,21 @@ fn bench_flush(c: &mut Criterion) {
     group.sample_size(50);
     group.measurement_time(Duration::from_secs(8));
     for size in [100, 1000, 10_000] {
-        group.bench_with_input(BenchmarkId::new("shardmap", size), &size, |b, &size| {
-            let path = bench_path("flush", size);
-            let _ = std::fs::remove_file(&path);
-            let db = JsonSync::<String, i32, ShardMap<String, i32>>::open(&path).unwrap();
-            for i in 0..size {
-                db.insert(format!("k{i}"), i as i32).unwrap();
-            }
-            b.iter(|| db.flush().unwrap());
-            let _ = std::fs::remove_file(&path);
-        });
+        for_each_backend!(group, size, run_flush);
     }
 }
 
 fn bench_extend(c: &mut Criterion) {
     let mut group = c.benchmark_group("extend");
     for size in [100, 1000] {
-        group.bench_with_input(BenchmarkId::new("shardmap", size), &size, |b, &size| {
-            let path = bench_path("extend", size);
-            let _ = std::fs::remove_file(&path);
-            let db = JsonSync::<String, i32, ShardMap<String, i32>>::open(&path).unwrap();
-            let batch: Vec<(String, i32)> =
-                (0..size).map(|i| (format!("k{i}"), i as i32)).collect();
-            b.iter(|| {
-                db.extend(batch.clone()).unwrap();
-                db.clear().unwrap();
-            });
-            let _ = std::fs::remove_file(&path);
-        });
+        for_each_backend!(group, size, run_extend);
     }
 }
 
 fn bench_update(c: &mut Criterion) {
     let mut group = c.benchmark_group("update");
     for size in [100, 1000] {
-        group.bench_with_input(BenchmarkId::new("shardmap", size), &size, |b, &size| {
-            let path = bench_path("update", size);
-            let _ = std::fs::remove_file(&path);
-            let db = JsonSync::<String, i32, ShardMap<String, i32>>::open(&path).unwrap();
-            for i in 0..size {
-                db.insert(format!("k{i}"), i as i32).unwrap();
-            }
-            b.iter(|| {
-                for i in 0..size {
-                    db.update(&format!("k{i}"), |v| *v += 1).unwrap();
-                }
-            });
-            let _ = std::fs::remove_file(&path);
-        });
+        for_each_backend!(group, size, run_update);
     }
 }
 
@@ -92,18 +165,7 @@ fn bench_clear(c: &mut Criterion) {
     let mut group = c.benchmark_group("clear");
     group.sample_size(50);
     for size in [100, 1000] {
-        group.bench_with_input(BenchmarkId::new("shardmap", size), &size, |b, &size| {
-            let path = bench_path("clear", size);
-            let _ = std::fs::remove_file(&path);
-            let db = JsonSync::<String, i32, ShardMap<String, i32>>::open(&path).unwrap();
-            b.iter(|| {
-                for i in 0..size {
-                    db.insert(format!("k{i}"), i as i32).unwrap();
-                }
-                db.clear().unwrap();
-            });
-            let _ = std::fs::remove_file(&path);
-        });
+        for_each_backend!(group, size, run_clear);
     }
 }
 
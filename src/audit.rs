@@ -0,0 +1,74 @@
+//! Append-only audit trail of mutations, kept separate from the data file.
+//!
+//! One JSON object per line: `{"ts":<unix ms>,"op":"insert","key":...}`. Values
+//! are left out unless asked for, since they may be sensitive. The log is never
+//! read back by the store.
+
+use crate::error::Result;
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Serialize)]
+struct Line<'a, K, V> {
+    ts: u64,
+    op: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    key: Option<&'a K>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    value: Option<&'a V>,
+}
+
+pub(crate) struct AuditLog {
+    file: Mutex<File>,
+    values: bool,
+    fsync: bool,
+}
+
+impl AuditLog {
+    /// Open `path` for appending, creating it if needed.
+    pub(crate) fn open(path: &Path, values: bool, fsync: bool) -> Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file: Mutex::new(file),
+            values,
+            fsync,
+        })
+    }
+
+    /// Whether lines include values at all.
+    pub(crate) fn logs_values(&self) -> bool {
+        self.values
+    }
+
+    /// Append one line. The whole line goes out in a single write under the
+    /// lock, so concurrent mutations never interleave within a line.
+    pub(crate) fn record<K, V>(&self, op: &str, key: Option<&K>, value: Option<&V>) -> Result<()>
+    where
+        K: Serialize,
+        V: Serialize,
+    {
+        let ts = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        let line = Line {
+            ts,
+            op,
+            key,
+            value: value.filter(|_| self.values),
+        };
+        let mut bytes = serde_json::to_vec(&line)?;
+        bytes.push(b'\n');
+
+        let mut file = self.file.lock();
+        file.write_all(&bytes)?;
+        if self.fsync {
+            file.sync_data()?;
+        }
+        Ok(())
+    }
+}
@@ -5,6 +5,8 @@
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use std::hash::Hash;
+use std::ops::{Bound, RangeBounds};
+use std::sync::Arc;
 
 /// Trait that a concurrent map must satisfy to back a [`JsonSync`](crate::JsonSync) store.
 ///
@@ -30,6 +32,98 @@ where
     /// locks that would block concurrent writers.
     fn iter_snapshot(&self) -> Box<dyn Iterator<Item = (K, V)> + Send + '_>;
 
+    /// Apply `f` to the value at `key` without handing out an owned copy.
+    /// The default clones via [`get`](Self::get); backends that can lend a
+    /// reference should override it.
+    fn get_if<R, F>(&self, key: &K, f: F) -> Option<R>
+    where
+        F: FnOnce(&V) -> R,
+    {
+        self.get(key).map(|v| f(&v))
+    }
+
+    /// Read-modify-write: compute the new value for `key` from the current one
+    /// (`None` if absent), store it, and return it.
+    ///
+    /// The default is a plain get-then-insert, so a concurrent writer can slip
+    /// in between. Backends with an entry API or a single lock override this to
+    /// make it atomic (`RwLock<HashMap>` and DashMap do; ShardMap doesn't).
+    fn upsert<F>(&self, key: K, f: F) -> V
+    where
+        F: FnOnce(Option<&V>) -> V,
+    {
+        let new = f(self.get(&key).as_ref());
+        self.insert(key, new.clone());
+        new
+    }
+
+    /// Mutate the value at `key` in place and return the result, or `None`
+    /// (without calling `f`) if the key is absent.
+    ///
+    /// Same atomicity story as [`upsert`](Self::upsert): the default is
+    /// get-then-insert, so a concurrent write can land in between and be
+    /// overwritten, or a concurrent remove can be undone. Backends that can
+    /// hand out a mutable reference under their lock override it.
+    fn modify<F>(&self, key: &K, f: F) -> Option<V>
+    where
+        F: FnOnce(&mut V),
+    {
+        let mut v = self.get(key)?;
+        f(&mut v);
+        self.insert(key.clone(), v.clone());
+        Some(v)
+    }
+
+    /// Entry with the smallest key. The default scans a full snapshot (O(n));
+    /// ordered backends override it.
+    fn first(&self) -> Option<(K, V)>
+    where
+        K: Ord,
+    {
+        self.iter_snapshot().min_by(|a, b| a.0.cmp(&b.0))
+    }
+
+    /// Entry with the largest key. Same cost caveat as [`first`](Self::first).
+    fn last(&self) -> Option<(K, V)>
+    where
+        K: Ord,
+    {
+        self.iter_snapshot().max_by(|a, b| a.0.cmp(&b.0))
+    }
+
+    /// Entries whose keys fall within `bounds`, in ascending key order. The
+    /// default filters and sorts a full snapshot (O(n log n)); ordered
+    /// backends override it with a direct range scan.
+    fn range<R>(&self, bounds: R) -> Vec<(K, V)>
+    where
+        K: Ord,
+        R: RangeBounds<K>,
+    {
+        let mut out: Vec<(K, V)> = self
+            .iter_snapshot()
+            .filter(|(k, _)| bounds.contains(k))
+            .collect();
+        out.sort_by(|a, b| a.0.cmp(&b.0));
+        out
+    }
+
+    /// Up to `limit` entries with keys strictly greater than `after` (or from
+    /// the start if `None`), sorted by key. Pass the last key of one page as
+    /// `after` to get the next. The default sorts a full snapshot per call;
+    /// ordered backends should override it.
+    fn scan(&self, after: Option<&K>, limit: usize) -> Vec<(K, V)>
+    where
+        K: Ord,
+    {
+        let mut out: Vec<(K, V)> = self
+            .iter_snapshot()
+            .filter(|(k, _)| after.is_none_or(|a| k > a))
+            .collect();
+        out.sort_by(|a, b| a.0.cmp(&b.0));
+        out.truncate(limit);
+        out
+    }
+
     /// Number of entries. Override this — the default returns 0.
     fn map_len(&self) -> usize {
         0
@@ -41,6 +135,19 @@ where
         self.get(key).is_some()
     }
 
+    /// Call `f` on every entry. The default walks
+    /// [`iter_snapshot`](Self::iter_snapshot); override to lend out
+    /// references instead of cloning each entry. Overrides may hold a lock
+    /// while `f` runs, so `f` mustn't write to the map.
+    fn for_each<F>(&self, mut f: F)
+    where
+        F: FnMut(&K, &V),
+    {
+        for (k, v) in self.iter_snapshot() {
+            f(&k, &v);
+        }
+    }
+
     /// Drop all entries. The default does iter + remove which is slow; override
     /// with the backend's native clear when available.
     fn clear(&self) {
@@ -49,6 +156,68 @@ where
             self.remove(k);
         }
     }
+
+    /// Remove every entry `f` returns `true` for and return them. The default
+    /// snapshots, then removes the matches one by one, so writes landing in
+    /// between can be missed or drained after they changed; backends with a
+    /// single lock override it to do the whole pass under that lock.
+    fn drain_filter<F>(&self, mut f: F) -> Vec<(K, V)>
+    where
+        F: FnMut(&K, &V) -> bool,
+    {
+        let keys: Vec<K> = self
+            .iter_snapshot()
+            .filter(|(k, v)| f(k, v))
+            .map(|(k, _)| k)
+            .collect();
+        keys.into_iter()
+            .filter_map(|k| {
+                let v = self.remove(&k)?;
+                Some((k, v))
+            })
+            .collect()
+    }
+
+    /// Release spare capacity left behind by removals. Default is a no-op for
+    /// backends that don't expose their capacity.
+    fn shrink_to_fit(&self) {}
+
+    /// `true` if a thread panicked while holding the backend's lock, so an
+    /// update may have been left half done. Only `std::sync::RwLock`
+    /// poisons; everything else says `false`.
+    fn is_poisoned(&self) -> bool {
+        false
+    }
+
+    /// Forget an earlier poisoning, once you've checked the data is sound.
+    /// No-op by default.
+    fn clear_poison(&self) {}
+
+    /// Drop all entries and insert `entries` in their place. The default is
+    /// `clear` followed by inserts, so readers can see the store in between;
+    /// override when the backend can do the swap under a single lock.
+    fn reset<I>(&self, entries: I)
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        self.clear();
+        for (k, v) in entries {
+            self.insert(k, v);
+        }
+    }
+}
+
+/// Backends that already keep each value behind an [`Arc`] and can hand that
+/// out instead of deep-cloning the value.
+pub trait ArcBackendExt<K, V>: MapBackend<K, V>
+where
+    K: Hash + Eq + Send + Sync + Clone + Serialize + DeserializeOwned,
+    V: Send + Sync + Clone + Serialize + DeserializeOwned,
+{
+    /// Shared handle to the value at `key`. Later writes to `key` replace the
+    /// stored `Arc` rather than mutating it, so the returned value never
+    /// changes underneath you.
+    fn get_arc(&self, key: &K) -> Option<Arc<V>>;
 }
 
 // ---- ShardMap ----------------------------------------------------------------
@@ -74,6 +243,13 @@ where
         Box::new(self.iter_snapshot().map(|(k, arc_v)| (k, (*arc_v).clone())))
     }
 
+    fn get_if<R, F>(&self, key: &K, f: F) -> Option<R>
+    where
+        F: FnOnce(&V) -> R,
+    {
+        shardmap::ShardMap::get(self, key).map(|arc| f(&arc))
+    }
+
     fn map_len(&self) -> usize {
         self.len()
     }
@@ -82,6 +258,25 @@ where
     fn contains_key(&self, key: &K) -> bool {
         shardmap::ShardMap::get(self, key).is_some()
     }
+
+    fn for_each<F>(&self, mut f: F)
+    where
+        F: FnMut(&K, &V),
+    {
+        for (k, v) in shardmap::ShardMap::iter_snapshot(self) {
+            f(&k, &v);
+        }
+    }
+}
+
+impl<K, V> ArcBackendExt<K, V> for shardmap::ShardMap<K, V>
+where
+    K: Hash + Eq + Send + Sync + Clone + Serialize + DeserializeOwned,
+    V: Send + Sync + Clone + Serialize + DeserializeOwned,
+{
+    fn get_arc(&self, key: &K) -> Option<Arc<V>> {
+        shardmap::ShardMap::get(self, key)
+    }
 }
 
 // ---- RwLock<HashMap> ---------------------------------------------------------
@@ -112,6 +307,175 @@ where
         Box::new(snap.into_iter())
     }
 
+    fn get_if<R, F>(&self, key: &K, f: F) -> Option<R>
+    where
+        F: FnOnce(&V) -> R,
+    {
+        self.read().get(key).map(f)
+    }
+
+    fn upsert<F>(&self, key: K, f: F) -> V
+    where
+        F: FnOnce(Option<&V>) -> V,
+    {
+        let mut map = self.write();
+        let new = f(map.get(&key));
+        map.insert(key, new.clone());
+        new
+    }
+
+    fn modify<F>(&self, key: &K, f: F) -> Option<V>
+    where
+        F: FnOnce(&mut V),
+    {
+        let mut map = self.write();
+        let v = map.get_mut(key)?;
+        f(v);
+        Some(v.clone())
+    }
+
+    fn map_len(&self) -> usize {
+        self.read().len()
+    }
+
+    fn contains_key(&self, key: &K) -> bool {
+        self.read().contains_key(key)
+    }
+
+    fn for_each<F>(&self, mut f: F)
+    where
+        F: FnMut(&K, &V),
+    {
+        for (k, v) in self.read().iter() {
+            f(k, v);
+        }
+    }
+
+    fn clear(&self) {
+        self.write().clear()
+    }
+
+    fn shrink_to_fit(&self) {
+        self.write().shrink_to_fit()
+    }
+
+    fn drain_filter<F>(&self, mut f: F) -> Vec<(K, V)>
+    where
+        F: FnMut(&K, &V) -> bool,
+    {
+        let mut map = self.write();
+        let keys: Vec<K> = map
+            .iter()
+            .filter(|(k, v)| f(k, v))
+            .map(|(k, _)| k.clone())
+            .collect();
+        keys.iter().filter_map(|k| map.remove_entry(k)).collect()
+    }
+
+    // One write lock across clear + inserts, so readers never see the gap.
+    fn reset<I>(&self, entries: I)
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let mut map = self.write();
+        map.clear();
+        map.extend(entries);
+    }
+}
+
+// ---- RwLock<BTreeMap> --------------------------------------------------------
+
+// Ordered backend: keys come back sorted and `first`/`last` are O(log n).
+impl<K, V> MapBackend<K, V> for parking_lot::RwLock<std::collections::BTreeMap<K, V>>
+where
+    K: Hash + Eq + Ord + Send + Sync + Clone + Serialize + DeserializeOwned,
+    V: Send + Sync + Clone + Serialize + DeserializeOwned,
+{
+    fn insert(&self, key: K, value: V) -> Option<V> {
+        self.write().insert(key, value)
+    }
+
+    fn get(&self, key: &K) -> Option<V> {
+        self.read().get(key).cloned()
+    }
+
+    fn remove(&self, key: &K) -> Option<V> {
+        self.write().remove(key)
+    }
+
+    fn iter_snapshot(&self) -> Box<dyn Iterator<Item = (K, V)> + Send + '_> {
+        let snap: Vec<_> = self
+            .read()
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        Box::new(snap.into_iter())
+    }
+
+    fn get_if<R, F>(&self, key: &K, f: F) -> Option<R>
+    where
+        F: FnOnce(&V) -> R,
+    {
+        self.read().get(key).map(f)
+    }
+
+    fn upsert<F>(&self, key: K, f: F) -> V
+    where
+        F: FnOnce(Option<&V>) -> V,
+    {
+        let mut map = self.write();
+        let new = f(map.get(&key));
+        map.insert(key, new.clone());
+        new
+    }
+
+    fn modify<F>(&self, key: &K, f: F) -> Option<V>
+    where
+        F: FnOnce(&mut V),
+    {
+        let mut map = self.write();
+        let v = map.get_mut(key)?;
+        f(v);
+        Some(v.clone())
+    }
+
+    fn first(&self) -> Option<(K, V)> {
+        self.read()
+            .first_key_value()
+            .map(|(k, v)| (k.clone(), v.clone()))
+    }
+
+    fn last(&self) -> Option<(K, V)> {
+        self.read()
+            .last_key_value()
+            .map(|(k, v)| (k.clone(), v.clone()))
+    }
+
+    fn range<R>(&self, bounds: R) -> Vec<(K, V)>
+    where
+        R: RangeBounds<K>,
+    {
+        if is_empty_range(&bounds) {
+            return Vec::new();
+        }
+        self.read()
+            .range(bounds)
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect()
+    }
+
+    fn scan(&self, after: Option<&K>, limit: usize) -> Vec<(K, V)> {
+        let lower = match after {
+            Some(key) => Bound::Excluded(key),
+            None => Bound::Unbounded,
+        };
+        self.read()
+            .range((lower, Bound::Unbounded))
+            .take(limit)
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect()
+    }
+
     fn map_len(&self) -> usize {
         self.read().len()
     }
@@ -120,9 +484,179 @@ where
         self.read().contains_key(key)
     }
 
+    fn for_each<F>(&self, mut f: F)
+    where
+        F: FnMut(&K, &V),
+    {
+        for (k, v) in self.read().iter() {
+            f(k, v);
+        }
+    }
+
     fn clear(&self) {
         self.write().clear()
     }
+
+    fn drain_filter<F>(&self, mut f: F) -> Vec<(K, V)>
+    where
+        F: FnMut(&K, &V) -> bool,
+    {
+        let mut map = self.write();
+        let keys: Vec<K> = map
+            .iter()
+            .filter(|(k, v)| f(k, v))
+            .map(|(k, _)| k.clone())
+            .collect();
+        keys.iter().filter_map(|k| map.remove_entry(k)).collect()
+    }
+
+    fn reset<I>(&self, entries: I)
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let mut map = self.write();
+        map.clear();
+        map.extend(entries);
+    }
+}
+
+/// Bounds that can't hold a key: start past end, or start equal to end with
+/// both excluded. `BTreeMap::range` panics on these; the other backends just
+/// find nothing.
+fn is_empty_range<K: Ord, R: RangeBounds<K>>(bounds: &R) -> bool {
+    match (bounds.start_bound(), bounds.end_bound()) {
+        (Bound::Excluded(start), Bound::Excluded(end)) => start >= end,
+        (
+            Bound::Included(start) | Bound::Excluded(start),
+            Bound::Included(end) | Bound::Excluded(end),
+        ) => start > end,
+        _ => false,
+    }
+}
+
+// ---- std::sync::RwLock<HashMap> ----------------------------------------------
+
+// Same as the parking_lot version, except std's lock poisons when a holder
+// panics. Every operation goes ahead anyway on the data as the panicking
+// thread left it, and the poison flag stays set for `is_poisoned` to report
+// until someone calls `clear_poison`.
+
+fn std_read<T>(lock: &std::sync::RwLock<T>) -> std::sync::RwLockReadGuard<'_, T> {
+    lock.read()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+}
+
+fn std_write<T>(lock: &std::sync::RwLock<T>) -> std::sync::RwLockWriteGuard<'_, T> {
+    lock.write()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+}
+
+impl<K, V> MapBackend<K, V> for std::sync::RwLock<std::collections::HashMap<K, V>>
+where
+    K: Hash + Eq + Send + Sync + Clone + Serialize + DeserializeOwned,
+    V: Send + Sync + Clone + Serialize + DeserializeOwned,
+{
+    fn insert(&self, key: K, value: V) -> Option<V> {
+        std_write(self).insert(key, value)
+    }
+
+    fn get(&self, key: &K) -> Option<V> {
+        std_read(self).get(key).cloned()
+    }
+
+    fn remove(&self, key: &K) -> Option<V> {
+        std_write(self).remove(key)
+    }
+
+    fn iter_snapshot(&self) -> Box<dyn Iterator<Item = (K, V)> + Send + '_> {
+        let snap: Vec<_> = std_read(self)
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        Box::new(snap.into_iter())
+    }
+
+    fn get_if<R, F>(&self, key: &K, f: F) -> Option<R>
+    where
+        F: FnOnce(&V) -> R,
+    {
+        std_read(self).get(key).map(f)
+    }
+
+    fn upsert<F>(&self, key: K, f: F) -> V
+    where
+        F: FnOnce(Option<&V>) -> V,
+    {
+        let mut map = std_write(self);
+        let new = f(map.get(&key));
+        map.insert(key, new.clone());
+        new
+    }
+
+    fn modify<F>(&self, key: &K, f: F) -> Option<V>
+    where
+        F: FnOnce(&mut V),
+    {
+        let mut map = std_write(self);
+        let v = map.get_mut(key)?;
+        f(v);
+        Some(v.clone())
+    }
+
+    fn map_len(&self) -> usize {
+        std_read(self).len()
+    }
+
+    fn contains_key(&self, key: &K) -> bool {
+        std_read(self).contains_key(key)
+    }
+
+    fn for_each<F>(&self, mut f: F)
+    where
+        F: FnMut(&K, &V),
+    {
+        for (k, v) in std_read(self).iter() {
+            f(k, v);
+        }
+    }
+
+    fn clear(&self) {
+        std_write(self).clear()
+    }
+
+    fn shrink_to_fit(&self) {
+        std_write(self).shrink_to_fit()
+    }
+
+    fn is_poisoned(&self) -> bool {
+        std::sync::RwLock::is_poisoned(self)
+    }
+
+    fn clear_poison(&self) {
+        std::sync::RwLock::clear_poison(self)
+    }
+
+    fn drain_filter<F>(&self, mut f: F) -> Vec<(K, V)>
+    where
+        F: FnMut(&K, &V) -> bool,
+    {
+        let mut map = std_write(self);
+        let keys: Vec<K> = map
+            .iter()
+            .filter(|(k, v)| f(k, v))
+            .map(|(k, _)| k.clone())
+            .collect();
+        keys.iter().filter_map(|k| map.remove_entry(k)).collect()
+    }
+
+    fn reset<I>(&self, entries: I)
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let mut map = std_write(self);
+        map.clear();
+        map.extend(entries);
+    }
 }
 
 // ---- DashMap (feature-gated) -------------------------------------------------
@@ -153,6 +687,40 @@ where
         Box::new(snap.into_iter())
     }
 
+    fn get_if<R, F>(&self, key: &K, f: F) -> Option<R>
+    where
+        F: FnOnce(&V) -> R,
+    {
+        dashmap::DashMap::get(self, key).map(|r| f(r.value()))
+    }
+
+    fn upsert<F>(&self, key: K, f: F) -> V
+    where
+        F: FnOnce(Option<&V>) -> V,
+    {
+        match self.entry(key) {
+            dashmap::mapref::entry::Entry::Occupied(mut e) => {
+                let new = f(Some(e.get()));
+                e.insert(new.clone());
+                new
+            }
+            dashmap::mapref::entry::Entry::Vacant(e) => {
+                let new = f(None);
+                e.insert(new.clone());
+                new
+            }
+        }
+    }
+
+    fn modify<F>(&self, key: &K, f: F) -> Option<V>
+    where
+        F: FnOnce(&mut V),
+    {
+        let mut v = dashmap::DashMap::get_mut(self, key)?;
+        f(&mut v);
+        Some(v.clone())
+    }
+
     fn map_len(&self) -> usize {
         self.len()
     }
@@ -161,7 +729,20 @@ where
         dashmap::DashMap::contains_key(self, key)
     }
 
+    fn for_each<F>(&self, mut f: F)
+    where
+        F: FnMut(&K, &V),
+    {
+        for r in self.iter() {
+            f(r.key(), r.value());
+        }
+    }
+
     fn clear(&self) {
         dashmap::DashMap::clear(self)
     }
+
+    fn shrink_to_fit(&self) {
+        dashmap::DashMap::shrink_to_fit(self)
+    }
 }
@@ -0,0 +1,116 @@
+//! Where a store reads the time from, so time-dependent behaviour can be
+//! tested without sleeping.
+//!
+//! The clock decides when [`insert_with_ttl`](crate::JsonSync::insert_with_ttl)
+//! entries expire (on insert, on open, and in
+//! [`purge_expired`](crate::JsonSync::purge_expired)), when tombstones were
+//! left and when [`purge_tombstones`](crate::JsonSync::purge_tombstones)
+//! drops them, and how checkpoints are named. Expiries and tombstones are
+//! written to disk, so a clock gives wall-clock time ([`SystemTime`]) rather
+//! than an [`Instant`](std::time::Instant).
+//!
+//! The background flush worker keeps time by it too: the interval of the
+//! [`Async`](crate::FlushPolicy::Async) policies, the
+//! [`park_worker_after`](crate::JsonSyncBuilder::park_worker_after) grace
+//! period, and the times a
+//! [`Scheduled`](crate::FlushPolicy::Scheduled) store flushes at. Advancing
+//! a [`ManualClock`] wakes the worker, so a test can make it flush without
+//! sleeping. Only the durations measured for
+//! [`FlushReport`](crate::FlushReport) run on real time.
+
+use parking_lot::Mutex;
+use std::time::{Duration, SystemTime};
+
+/// Source of the current time for a store. Set one with
+/// [`JsonSyncBuilder::clock`](crate::JsonSyncBuilder::clock).
+pub trait Clock: Send + Sync {
+    /// The current wall-clock time.
+    fn now(&self) -> SystemTime;
+
+    /// Call `wake` whenever this clock is moved other than by time passing,
+    /// for as long as it returns `true`. A store's flush worker registers
+    /// here so it hears about a jump straight away rather than at its next
+    /// real-time wake-up. Clocks that just follow real time can leave this
+    /// alone; the default does nothing.
+    fn watch(&self, wake: Box<dyn Fn() -> bool + Send + Sync>) {
+        drop(wake);
+    }
+}
+
+/// The real clock, [`SystemTime::now`]. What every store uses by default.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A clock that only moves when told to. Hand it to a store in tests and
+/// [`advance`](Self::advance) it to expire entries, or run the flush
+/// worker's timer out, on the spot.
+///
+/// ```rust
+/// use json_sync::clock::{Clock, ManualClock};
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// let clock = ManualClock::new(UNIX_EPOCH);
+/// clock.advance(Duration::from_secs(60));
+/// assert_eq!(clock.now(), UNIX_EPOCH + Duration::from_secs(60));
+/// ```
+pub struct ManualClock {
+    now: Mutex<SystemTime>,
+    watchers: Mutex<Vec<Box<dyn Fn() -> bool + Send + Sync>>>,
+}
+
+impl ManualClock {
+    /// A clock stopped at `start`.
+    pub fn new(start: SystemTime) -> Self {
+        Self {
+            now: Mutex::new(start),
+            watchers: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Move the clock forward by `by`.
+    pub fn advance(&self, by: Duration) {
+        *self.now.lock() += by;
+        self.moved();
+    }
+
+    /// Set the clock to `to`, which may be earlier than it reads now.
+    pub fn set(&self, to: SystemTime) {
+        *self.now.lock() = to;
+        self.moved();
+    }
+
+    fn moved(&self) {
+        self.watchers.lock().retain(|wake| wake());
+    }
+}
+
+impl std::fmt::Debug for ManualClock {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ManualClock")
+            .field("now", &*self.now.lock())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for ManualClock {
+    /// Stopped at the real time it was created.
+    fn default() -> Self {
+        Self::new(SystemTime::now())
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> SystemTime {
+        *self.now.lock()
+    }
+
+    fn watch(&self, wake: Box<dyn Fn() -> bool + Send + Sync>) {
+        self.watchers.lock().push(wake);
+    }
+}
@@ -0,0 +1,353 @@
+//! Focused wrappers over [`JsonSync`] for common shapes of data.
+
+use crate::backend::MapBackend;
+use crate::error::{Error, Result};
+use crate::flush::FlushPolicy;
+use crate::serializer::Serializer;
+use crate::store::{JsonSync, JsonSyncHandle};
+use parking_lot::RwLock;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::io::Write;
+use std::path::Path;
+
+// ---- JsonCounters ------------------------------------------------------------
+
+/// Persistent map of named `u64` counters.
+///
+/// Increments go through [`MapBackend::upsert`], so they're atomic on backends
+/// that override it (`RwLock<HashMap>`, DashMap). ShardMap has no entry API and
+/// falls back to get-then-insert, which can lose increments under contention —
+/// pick one of the others if several threads bump the same counter.
+///
+/// Follows whatever flush policy the wrapped store was opened with.
+///
+/// ```rust,no_run
+/// use json_sync::collections::JsonCounters;
+/// use parking_lot::RwLock;
+/// use std::collections::HashMap;
+///
+/// let hits = JsonCounters::<RwLock<HashMap<String, u64>>>::open("hits.json").unwrap();
+/// hits.incr("/index", 1).unwrap();
+/// assert_eq!(hits.get("/index"), 1);
+/// ```
+pub struct JsonCounters<M> {
+    store: JsonSyncHandle<String, u64, M>,
+}
+
+impl<M> JsonCounters<M>
+where
+    M: MapBackend<String, u64> + 'static,
+{
+    /// Open (or create) a counter file with manual flush.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self>
+    where
+        M: Default,
+    {
+        JsonSync::open(path).map(Self::new)
+    }
+
+    /// Open with a specific flush policy.
+    pub fn open_with_policy(path: impl AsRef<Path>, policy: FlushPolicy) -> Result<Self>
+    where
+        M: Default,
+    {
+        JsonSync::open_with_policy(path, policy).map(Self::new)
+    }
+
+    /// Wrap a store you've already configured through the builder.
+    pub fn new(store: JsonSyncHandle<String, u64, M>) -> Self {
+        Self { store }
+    }
+
+    /// Add `by` to the counter (starting from 0) and return the new value.
+    /// Saturates at `u64::MAX`.
+    pub fn incr(&self, key: &str, by: u64) -> Result<u64> {
+        self.apply(key, |n| n.saturating_add(by))
+    }
+
+    /// Subtract `by` from the counter and return the new value. Saturates at 0.
+    pub fn decr(&self, key: &str, by: u64) -> Result<u64> {
+        self.apply(key, |n| n.saturating_sub(by))
+    }
+
+    /// Current value of the counter; 0 if it was never touched.
+    #[must_use]
+    pub fn get(&self, key: &str) -> u64 {
+        self.store.get(&key.to_string()).unwrap_or(0)
+    }
+
+    /// Remove the counter, returning its last value if it existed.
+    pub fn reset(&self, key: &str) -> Result<Option<u64>> {
+        self.store.remove(&key.to_string())
+    }
+
+    /// Write all counters to disk now.
+    pub fn flush(&self) -> Result<()> {
+        self.store.flush()
+    }
+
+    /// Unwrap into the underlying store handle.
+    pub fn into_inner(self) -> JsonSyncHandle<String, u64, M> {
+        self.store
+    }
+
+    fn apply(&self, key: &str, f: impl FnOnce(u64) -> u64) -> Result<u64> {
+        let key = key.to_string();
+        let _serial = self.store.persister.serial();
+        let added = self.store.size_hint(&key, &0);
+        self.store.claim_pending(&key);
+        let new = self
+            .store
+            .map
+            .upsert(key.clone(), |cur| f(cur.copied().unwrap_or(0)));
+        self.store.reindex(Some(&key));
+        // the new value only exists after the upsert, so this line is written
+        // after the fact rather than ahead of it like the store's own mutations
+        self.store.audit("update", Some(&key), Some(&new))?;
+        self.store.notify_mutation(added)?;
+        Ok(new)
+    }
+}
+
+impl<M> std::fmt::Debug for JsonCounters<M> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JsonCounters")
+            .field("store", &self.store)
+            .finish()
+    }
+}
+
+// ---- JsonSet -----------------------------------------------------------------
+
+/// Persistent set of values, for "have I seen this ID" bookkeeping.
+///
+/// Backed by a `JsonSync<T, (), M>`, so the file is an object whose values are
+/// all `null` — `{"a":null,"b":null}`. That keeps it readable by anything that
+/// reads the plain store; if `T` isn't a string-like key, open the store with
+/// [`as_pairs`](crate::JsonSyncBuilder::as_pairs) and hand it to
+/// [`new`](Self::new).
+///
+/// Follows whatever flush policy the wrapped store was opened with.
+///
+/// ```rust,no_run
+/// use json_sync::collections::JsonSet;
+/// use shardmap::ShardMap;
+///
+/// let seen = JsonSet::<String, ShardMap<String, ()>>::open("seen.json").unwrap();
+/// assert!(seen.insert("msg-1".into()).unwrap());
+/// assert!(!seen.insert("msg-1".into()).unwrap());
+/// ```
+pub struct JsonSet<T, M> {
+    store: JsonSyncHandle<T, (), M>,
+}
+
+impl<T, M> JsonSet<T, M>
+where
+    T: Hash + Eq + Send + Sync + Clone + Serialize + DeserializeOwned + 'static,
+    M: MapBackend<T, ()> + 'static,
+{
+    /// Open (or create) a set file with manual flush.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self>
+    where
+        M: Default,
+    {
+        JsonSync::open(path).map(Self::new)
+    }
+
+    /// Open with a specific flush policy.
+    pub fn open_with_policy(path: impl AsRef<Path>, policy: FlushPolicy) -> Result<Self>
+    where
+        M: Default,
+    {
+        JsonSync::open_with_policy(path, policy).map(Self::new)
+    }
+
+    /// Wrap a store you've already configured through the builder.
+    pub fn new(store: JsonSyncHandle<T, (), M>) -> Self {
+        Self { store }
+    }
+
+    /// Add `value`. Returns `true` if it wasn't already in the set.
+    pub fn insert(&self, value: T) -> Result<bool> {
+        self.store.insert(value, ()).map(|prev| prev.is_none())
+    }
+
+    /// `true` if `value` is in the set.
+    #[must_use]
+    pub fn contains(&self, value: &T) -> bool {
+        self.store.contains_key(value)
+    }
+
+    /// Remove `value`. Returns `true` if it was in the set.
+    pub fn remove(&self, value: &T) -> Result<bool> {
+        self.store.remove(value).map(|prev| prev.is_some())
+    }
+
+    /// Number of values.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.store.len()
+    }
+
+    /// `true` when the set has no values.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.store.is_empty()
+    }
+
+    /// Snapshot of all values, in no particular order.
+    #[must_use]
+    pub fn iter(&self) -> Vec<T> {
+        self.store.keys()
+    }
+
+    /// Write the set to disk now.
+    pub fn flush(&self) -> Result<()> {
+        self.store.flush()
+    }
+
+    /// Unwrap into the underlying store handle.
+    pub fn into_inner(self) -> JsonSyncHandle<T, (), M> {
+        self.store
+    }
+}
+
+impl<T, M> std::fmt::Debug for JsonSet<T, M> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JsonSet")
+            .field("store", &self.store)
+            .finish()
+    }
+}
+
+// ---- JsonCell ----------------------------------------------------------------
+
+/// A single persisted value — a config blob, a cursor position.
+///
+/// The file holds just the value's JSON, not a map, and is empty when the
+/// cell is. A value that serializes to `null` (`Some(None)` in a
+/// `JsonCell<Option<T>>`) is still a value. Under the hood it's a one-entry
+/// store, so it follows the flush policy like the other wrappers.
+///
+/// ```rust,no_run
+/// use json_sync::collections::JsonCell;
+///
+/// let cursor = JsonCell::<u64>::open("cursor.json").unwrap();
+/// cursor.set(cursor.get().unwrap_or(0) + 100).unwrap();
+/// cursor.flush().unwrap();
+/// ```
+pub struct JsonCell<V> {
+    store: JsonSyncHandle<(), V, RwLock<HashMap<(), V>>, CellSerializer>,
+}
+
+impl<V> JsonCell<V>
+where
+    V: Send + Sync + Clone + Serialize + DeserializeOwned + 'static,
+{
+    /// Open (or create) a cell file with manual flush.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::open_with_policy(path, FlushPolicy::Manual)
+    }
+
+    /// Open with a specific flush policy.
+    pub fn open_with_policy(path: impl AsRef<Path>, policy: FlushPolicy) -> Result<Self> {
+        let store = JsonSync::builder(path)
+            .policy(policy)
+            .serializer(CellSerializer)
+            .build()?;
+        Ok(Self { store })
+    }
+
+    /// Current value, or `None` if the cell is empty.
+    #[must_use]
+    pub fn get(&self) -> Option<V> {
+        self.store.get(&())
+    }
+
+    /// Store `value`, returning the previous one.
+    pub fn set(&self, value: V) -> Result<Option<V>> {
+        self.store.insert((), value)
+    }
+
+    /// Mutate the value in place. Returns `false` (and skips `f`) if the cell
+    /// is empty.
+    pub fn update<F>(&self, f: F) -> Result<bool>
+    where
+        F: FnOnce(&mut V),
+    {
+        self.store.update(&(), f)
+    }
+
+    /// Empty the cell, returning what was in it.
+    pub fn take(&self) -> Result<Option<V>> {
+        self.store.remove(&())
+    }
+
+    /// Write the value to disk now.
+    pub fn flush(&self) -> Result<()> {
+        self.store.flush()
+    }
+}
+
+impl<V> std::fmt::Debug for JsonCell<V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JsonCell")
+            .field("store", &self.store)
+            .finish()
+    }
+}
+
+/// Writes the only value of a one-entry map as bare JSON, and reads it back
+/// under the unit key. No value is no bytes at all, so presence never hangs
+/// on what the value looks like.
+#[derive(Clone, Copy, Default)]
+struct CellSerializer;
+
+impl Serializer for CellSerializer {
+    fn serialize<K, V>(&self, data: &HashMap<K, V>) -> Result<Vec<u8>>
+    where
+        K: Serialize,
+        V: Serialize,
+    {
+        let mut buf = Vec::new();
+        self.serialize_to(data, &mut buf)?;
+        Ok(buf)
+    }
+
+    fn serialize_to<K, V, W>(&self, data: &HashMap<K, V>, writer: W) -> Result<()>
+    where
+        K: Serialize,
+        V: Serialize,
+        W: Write,
+    {
+        match data.values().next() {
+            Some(value) => serde_json::to_writer(writer, value).map_err(Error::from),
+            None => Ok(()),
+        }
+    }
+
+    fn reads_json(&self) -> bool {
+        true
+    }
+
+    fn deserialize<K, V>(&self, bytes: &[u8]) -> Result<HashMap<K, V>>
+    where
+        K: for<'de> Deserialize<'de> + Eq + std::hash::Hash,
+        V: for<'de> Deserialize<'de>,
+    {
+        let mut map = HashMap::new();
+        if bytes.iter().all(u8::is_ascii_whitespace) {
+            return Ok(map);
+        }
+        let value: V =
+            serde_json::from_slice(bytes).map_err(|e| Error::Deserialize(e.to_string()))?;
+        // the only key a cell uses is `()`, which reads from `null`
+        let key = K::deserialize(serde_json::Value::Null)
+            .map_err(|e| Error::Deserialize(e.to_string()))?;
+        map.insert(key, value);
+        Ok(map)
+    }
+}
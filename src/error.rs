@@ -12,6 +12,9 @@ pub enum Error {
     Deserialize(String),
     /// Bad configuration (invalid path, policy, etc.).
     Config(String),
+    /// The map backend contradicted itself (see
+    /// [`JsonSync::check_invariants`](crate::JsonSync::check_invariants)).
+    Backend(String),
 }
 
 impl std::fmt::Display for Error {
@@ -21,6 +24,7 @@ impl std::fmt::Display for Error {
             Error::Serialize(msg) => write!(f, "serialization error: {msg}"),
             Error::Deserialize(msg) => write!(f, "deserialization error: {msg}"),
             Error::Config(msg) => write!(f, "config error: {msg}"),
+            Error::Backend(msg) => write!(f, "backend error: {msg}"),
         }
     }
 }
@@ -1,10 +1,14 @@
 //! Flush policies and the background flush worker.
 
+use crate::clock::{Clock, SystemClock};
+use crate::error::{Error, Result};
+#[cfg(feature = "schedule")]
+use crate::schedule::Schedule;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc;
 use std::sync::Arc;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 /// Controls when the map gets written to disk.
 #[non_exhaustive]
@@ -13,9 +17,97 @@ pub enum FlushPolicy {
     /// Write after every insert/remove. Safest, but most I/O.
     Immediate,
     /// Background thread writes on a timer and whenever the map changes.
+    /// The thread isn't spawned until the first change, so a store that's
+    /// only read never has one; see also
+    /// [`park_worker_after`](crate::JsonSyncBuilder::park_worker_after).
+    /// Shorthand for [`AsyncBounded`](Self::AsyncBounded) with a one-slot
+    /// channel and [`OnFull::Drop`].
     Async(Duration),
+    /// [`Async`](Self::Async) with control over the channel that carries
+    /// "something changed" nudges to the worker.
+    AsyncBounded {
+        /// Timer between flushes when nothing nudges the worker.
+        interval: Duration,
+        /// Nudges that can queue up while the worker is busy. The worker
+        /// takes everything queued at once and flushes a single time for the
+        /// lot. 0 makes every nudge a rendezvous with the worker.
+        channel_capacity: usize,
+        /// What a mutation does when the channel is full.
+        on_full: OnFull,
+    },
     /// Only write when you call `flush()` yourself.
     Manual,
+    /// Write once the data added since the last flush crosses this many bytes.
+    ///
+    /// The size is an estimate: each inserted key and value is measured as
+    /// compact JSON, so overwrites count in full and removals count as zero.
+    /// Pretty-printing and object punctuation aren't included.
+    OnGrowth(usize),
+    /// Write at fixed wall-clock times, like the top of every hour, whatever
+    /// happens in between. A background thread sleeps until the next time in
+    /// the [`Schedule`], flushes, and works out the one after. Mutations
+    /// don't wake it; [`sync`](crate::JsonSyncHandle::sync) still flushes
+    /// right away.
+    ///
+    /// The times come from the store's [`clock`](crate::JsonSyncBuilder::clock),
+    /// re-read at least once a second, so the thread notices the clock being
+    /// set forward or back. Several times skipped in one jump get a single
+    /// flush; times the clock is set back over aren't repeated.
+    #[cfg(feature = "schedule")]
+    Scheduled(Schedule),
+}
+
+impl FlushPolicy {
+    /// `(interval, channel_capacity, on_full)` for the async policies.
+    pub(crate) fn async_params(&self) -> Option<(Duration, usize, OnFull)> {
+        match *self {
+            FlushPolicy::Async(interval) => Some((interval, 1, OnFull::Drop)),
+            FlushPolicy::AsyncBounded {
+                interval,
+                channel_capacity,
+                on_full,
+            } => Some((interval, channel_capacity, on_full)),
+            _ => None,
+        }
+    }
+}
+
+/// What a mutation does when the async flush channel is full.
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnFull {
+    /// Skip the nudge. A full channel means a flush is already due and it
+    /// snapshots the map after this mutation, so nothing is lost as long as
+    /// the channel holds at least one slot.
+    Drop,
+    /// Wait for room. No nudge is ever skipped, at the cost of stalling
+    /// writers while the worker is behind. Don't mutate the store from an
+    /// `on_flush` hook under this — the worker would be waiting on itself.
+    /// Under [`serialized_writes`](crate::JsonSyncBuilder::serialized_writes)
+    /// writers hold the store-wide lock the worker needs to flush, so they
+    /// can't wait on it and this acts like [`Drop`](Self::Drop).
+    Block,
+}
+
+/// Name given to the background flush thread unless the builder overrides it.
+pub const DEFAULT_THREAD_NAME: &str = "json-sync-flush";
+
+/// How a store's background flush thread is set up: its
+/// [`thread_name`](crate::JsonSyncBuilder::thread_name) and whether it runs
+/// at [`thread_low_priority`](crate::JsonSyncBuilder::thread_low_priority).
+#[derive(Clone, Debug)]
+pub(crate) struct WorkerThread {
+    pub(crate) name: String,
+    pub(crate) low_priority: bool,
+}
+
+impl Default for WorkerThread {
+    fn default() -> Self {
+        Self {
+            name: DEFAULT_THREAD_NAME.into(),
+            low_priority: false,
+        }
+    }
 }
 
 /// Background thread that calls a flush closure on a timer or when poked.
@@ -31,27 +123,104 @@ impl AsyncFlushWorker {
     /// sender side and drops it when the store is done — that signals the worker
     /// to exit.
     pub fn start_with_receiver<F>(interval: Duration, flush_fn: F, rx: mpsc::Receiver<()>) -> Self
+    where
+        F: Fn() + Send + 'static,
+    {
+        Self::start_named(DEFAULT_THREAD_NAME.into(), interval, flush_fn, rx)
+            .expect("failed to spawn flush thread")
+    }
+
+    /// Like [`start_with_receiver`](Self::start_with_receiver), but names the
+    /// thread so it's recognizable in `top`, debuggers, and profilers.
+    pub fn start_named<F>(
+        name: String,
+        interval: Duration,
+        flush_fn: F,
+        rx: mpsc::Receiver<()>,
+    ) -> Result<Self>
+    where
+        F: Fn() + Send + 'static,
+    {
+        let wakeups = Wakeups { rx, nudged: None };
+        Self::spawn(
+            name,
+            interval,
+            None,
+            Arc::new(SystemClock),
+            flush_fn,
+            wakeups,
+        )
+    }
+
+    /// A store's worker: like [`start_named`](Self::start_named), but the
+    /// thread sleeps on the channel until the first nudge, and again whenever
+    /// `park_after` passes without one, instead of waking every `interval`
+    /// for nothing. Both are measured on `clock`.
+    pub(crate) fn start_parked<F>(
+        name: String,
+        interval: Duration,
+        park_after: Duration,
+        clock: Arc<dyn Clock>,
+        flush_fn: F,
+        wakeups: Wakeups,
+    ) -> Result<Self>
+    where
+        F: Fn() + Send + 'static,
+    {
+        Self::spawn(name, interval, Some(park_after), clock, flush_fn, wakeups)
+    }
+
+    fn spawn<F>(
+        name: String,
+        interval: Duration,
+        park_after: Option<Duration>,
+        clock: Arc<dyn Clock>,
+        flush_fn: F,
+        wakeups: Wakeups,
+    ) -> Result<Self>
     where
         F: Fn() + Send + 'static,
     {
         let stop = Arc::new(AtomicBool::new(false));
-        let stop_flag = Arc::clone(&stop);
+        let join_handle = spawn_loop(
+            name,
+            Arc::clone(&stop),
+            interval,
+            park_after,
+            clock,
+            flush_fn,
+            wakeups,
+        )?;
 
-        let join_handle = thread::spawn(move || loop {
-            if stop_flag.load(Ordering::Relaxed) {
-                break;
-            }
-            match rx.recv_timeout(interval) {
-                Ok(()) | Err(mpsc::RecvTimeoutError::Timeout) => flush_fn(),
-                Err(mpsc::RecvTimeoutError::Disconnected) => break,
-            }
-        });
+        Ok(Self {
+            stop,
+            tx: None,
+            join_handle: Some(join_handle),
+        })
+    }
 
-        Self {
+    /// A [`FlushPolicy::Scheduled`] store's worker: flushes at each time in
+    /// `schedule` by `clock`, and whenever nudged.
+    #[cfg(feature = "schedule")]
+    pub(crate) fn start_scheduled<F>(
+        name: String,
+        schedule: Schedule,
+        clock: Arc<dyn Clock>,
+        flush_fn: F,
+        wakeups: Wakeups,
+    ) -> Result<Self>
+    where
+        F: Fn() + Send + 'static,
+    {
+        let stop = Arc::new(AtomicBool::new(false));
+        let join_handle =
+            spawn_scheduled(name, Arc::clone(&stop), schedule, clock, flush_fn, wakeups)?;
+
+        Ok(Self {
             stop,
             tx: None,
             join_handle: Some(join_handle),
-        }
+        })
     }
 
     /// Spawn a worker that owns both ends of the channel.
@@ -60,18 +229,17 @@ impl AsyncFlushWorker {
         F: Fn() + Send + 'static,
     {
         let stop = Arc::new(AtomicBool::new(false));
-        let stop_flag = Arc::clone(&stop);
-        let (tx, rx) = mpsc::sync_channel::<()>(0);
-
-        let join_handle = thread::spawn(move || loop {
-            if stop_flag.load(Ordering::Relaxed) {
-                break;
-            }
-            match rx.recv_timeout(interval) {
-                Ok(()) | Err(mpsc::RecvTimeoutError::Timeout) => flush_fn(),
-                Err(mpsc::RecvTimeoutError::Disconnected) => break,
-            }
-        });
+        let (tx, rx) = mpsc::sync_channel::<()>(1);
+        let join_handle = spawn_loop(
+            DEFAULT_THREAD_NAME.into(),
+            Arc::clone(&stop),
+            interval,
+            None,
+            Arc::new(SystemClock),
+            flush_fn,
+            Wakeups { rx, nudged: None },
+        )
+        .expect("failed to spawn flush thread");
 
         Self {
             stop,
@@ -80,15 +248,260 @@ impl AsyncFlushWorker {
         }
     }
 
-    /// Non-blocking nudge to flush now. If the worker is busy the nudge is
-    /// silently dropped (the next timer tick will catch up).
+    /// Non-blocking nudge to flush now. If a nudge is already pending this one
+    /// is dropped — the pending one will cover it.
     pub fn trigger(&self) {
         if let Some(ref t) = self.tx {
             let _ = t.try_send(());
         }
     }
+
+    /// Ask the worker to exit. It stops at the next wake-up without flushing;
+    /// if you gave it an external channel, nudge it through your sender so it
+    /// doesn't sleep out the rest of the interval.
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.trigger();
+    }
+
+    /// Wait up to `timeout` for the worker thread to exit after
+    /// [`stop`](Self::stop). On timeout the thread is detached and left to
+    /// finish on its own.
+    pub fn join_timeout(mut self, timeout: Duration) -> Result<()> {
+        let Some(handle) = self.join_handle.take() else {
+            return Ok(());
+        };
+        let deadline = Instant::now() + timeout;
+        while !handle.is_finished() {
+            if Instant::now() >= deadline {
+                return Err(Error::Io("worker did not stop in time".into()));
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        let _ = handle.join();
+        Ok(())
+    }
+}
+
+/// A store's end of its worker's channel. A message only wakes the worker;
+/// whether there's a change to flush travels separately in `nudged`, so a
+/// wake-up from the clock can't take the place of a mutation's nudge.
+pub(crate) struct Nudges {
+    tx: mpsc::SyncSender<()>,
+    nudged: Arc<AtomicBool>,
+}
+
+impl Nudges {
+    /// Both ends of a channel with room for `capacity` queued messages.
+    pub(crate) fn channel(capacity: usize) -> (Self, Wakeups) {
+        let (tx, rx) = mpsc::sync_channel(capacity);
+        let nudged = Arc::new(AtomicBool::new(false));
+        let wakeups = Wakeups {
+            rx,
+            nudged: Some(Arc::clone(&nudged)),
+        };
+        (Self { tx, nudged }, wakeups)
+    }
+
+    /// Ask for a flush without waiting. If the channel is full the worker
+    /// is about to wake anyway, and sees the nudge then.
+    pub(crate) fn nudge(&self) {
+        self.nudged.store(true, Ordering::Release);
+        let _ = self.tx.try_send(());
+    }
+
+    /// Ask for a flush, waiting for room in the channel. `false` if the
+    /// worker has gone.
+    pub(crate) fn nudge_blocking(&self) -> bool {
+        self.nudged.store(true, Ordering::Release);
+        self.tx.send(()).is_ok()
+    }
+
+    /// Wake the worker to look at the clock and its stop flag, without
+    /// asking for a flush.
+    pub(crate) fn wake(&self) {
+        let _ = self.tx.try_send(());
+    }
+}
+
+/// The worker's end of the channel. Without `nudged`, every message is a
+/// nudge.
+pub(crate) struct Wakeups {
+    rx: mpsc::Receiver<()>,
+    nudged: Option<Arc<AtomicBool>>,
+}
+
+impl Wakeups {
+    /// Wait up to `timeout` (or for good) for a message and take everything
+    /// queued behind it. `Ok(true)` if there's a nudge to answer, straight
+    /// away if one came in since the last call.
+    fn wait(&self, timeout: Option<Duration>) -> std::result::Result<bool, mpsc::RecvTimeoutError> {
+        if let Some(nudged) = &self.nudged {
+            if nudged.swap(false, Ordering::AcqRel) {
+                while self.rx.try_recv().is_ok() {}
+                return Ok(true);
+            }
+        }
+        match timeout {
+            Some(timeout) => self.rx.recv_timeout(timeout)?,
+            None => self
+                .rx
+                .recv()
+                .map_err(|_| mpsc::RecvTimeoutError::Disconnected)?,
+        }
+        // one flush covers every nudge queued so far
+        while self.rx.try_recv().is_ok() {}
+        Ok(self
+            .nudged
+            .as_ref()
+            .is_none_or(|nudged| nudged.swap(false, Ordering::AcqRel)))
+    }
 }
 
+/// The timer counts `interval` on `clock` from the last flush; a clock set
+/// back holds it up by at most one interval. With `park_after`, the loop
+/// starts out parked — blocked on the channel with no timer — and parks
+/// again once that long passes without a nudge.
+fn spawn_loop<F>(
+    name: String,
+    stop: Arc<AtomicBool>,
+    interval: Duration,
+    park_after: Option<Duration>,
+    clock: Arc<dyn Clock>,
+    flush_fn: F,
+    wakeups: Wakeups,
+) -> Result<thread::JoinHandle<()>>
+where
+    F: Fn() + Send + 'static,
+{
+    let builder = thread::Builder::new().name(name);
+    let handle = builder.spawn(move || {
+        let mut parked = park_after.is_some();
+        let mut last_nudge = clock.now();
+        let mut due = last_nudge.checked_add(interval);
+        loop {
+            if stop.load(Ordering::Relaxed) {
+                break;
+            }
+            let timeout = if parked {
+                None
+            } else {
+                let now = clock.now();
+                if let Some(reset) = now.checked_add(interval) {
+                    due = Some(due.map_or(reset, |due| due.min(reset)));
+                }
+                due.map(|due| due.duration_since(now).unwrap_or_default())
+            };
+            let nudged = match wakeups.wait(timeout) {
+                Ok(nudged) => nudged,
+                Err(mpsc::RecvTimeoutError::Timeout) => false,
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            };
+            // a stop request wakes us through the channel; don't flush on it
+            if stop.load(Ordering::Relaxed) {
+                break;
+            }
+            let now = clock.now();
+            if nudged {
+                parked = false;
+                last_nudge = now;
+            } else if parked || due.is_none_or(|due| due > now) {
+                // woken by the clock, or by real time, before the timer is
+                // due by the clock
+                continue;
+            } else if park_after
+                .is_some_and(|grace| now.duration_since(last_nudge).unwrap_or_default() >= grace)
+            {
+                // every nudge so far has had its flush; nothing new to write
+                parked = true;
+                continue;
+            }
+            flush_fn();
+            due = clock.now().checked_add(interval);
+        }
+    })?;
+    Ok(handle)
+}
+
+/// Longest the scheduled loop sleeps before reading the clock again, so a
+/// system clock that jumps is noticed soon after.
+#[cfg(feature = "schedule")]
+const CLOCK_RECHECK: Duration = Duration::from_secs(1);
+
+#[cfg(feature = "schedule")]
+fn spawn_scheduled<F>(
+    name: String,
+    stop: Arc<AtomicBool>,
+    schedule: Schedule,
+    clock: Arc<dyn Clock>,
+    flush_fn: F,
+    wakeups: Wakeups,
+) -> Result<thread::JoinHandle<()>>
+where
+    F: Fn() + Send + 'static,
+{
+    let builder = thread::Builder::new().name(name);
+    let handle = builder.spawn(move || {
+        let mut last = clock.now();
+        loop {
+            if stop.load(Ordering::Relaxed) {
+                break;
+            }
+            let now = clock.now();
+            // one flush however many times were passed since the last look;
+            // a clock set back passes none
+            let due = schedule.next_after(last).is_some_and(|at| at <= now);
+            last = now;
+            if due {
+                flush_fn();
+                continue;
+            }
+            let wait = schedule
+                .next_after(now)
+                .and_then(|at| at.duration_since(now).ok())
+                .map_or(CLOCK_RECHECK, |left| left.min(CLOCK_RECHECK));
+            match wakeups.wait(Some(wait)) {
+                Ok(nudged) => {
+                    if stop.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    if nudged {
+                        flush_fn();
+                    }
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) => {}
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            }
+        }
+    })?;
+    Ok(handle)
+}
+
+/// Nice value the flush thread asks for under
+/// [`thread_low_priority`](crate::JsonSyncBuilder::thread_low_priority).
+#[cfg(target_os = "linux")]
+const LOW_PRIORITY_NICE: libc::c_int = 10;
+
+/// Lower the calling thread's scheduling priority. Best effort: Linux is the
+/// only platform with a per-thread nice value, and a refusal (say, a process
+/// already running nicer than this) leaves the thread as it was.
+#[cfg(target_os = "linux")]
+pub(crate) fn lower_thread_priority() {
+    // SAFETY: gettid takes no arguments and can't fail.
+    let tid = unsafe { libc::syscall(libc::SYS_gettid) };
+    let Ok(tid) = libc::id_t::try_from(tid) else {
+        return;
+    };
+    // SAFETY: setpriority only reads its integer arguments; on Linux a
+    // thread id as `who` affects just that thread.
+    unsafe {
+        libc::setpriority(libc::PRIO_PROCESS, tid, LOW_PRIORITY_NICE);
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn lower_thread_priority() {}
+
 impl Drop for AsyncFlushWorker {
     fn drop(&mut self) {
         self.stop.store(true, Ordering::Relaxed);
@@ -19,16 +19,33 @@
 #![deny(missing_docs)]
 #![warn(clippy::all)]
 
+mod audit;
 pub mod backend;
+pub mod clock;
+pub mod collections;
 pub mod error;
 pub mod flush;
+pub mod migrate;
 pub mod persist;
+#[cfg(feature = "mmap")]
+pub mod readonly;
+#[cfg(feature = "single-instance")]
+mod registry;
+#[cfg(feature = "schedule")]
+pub mod schedule;
 pub mod serializer;
+#[cfg(all(unix, feature = "signal"))]
+pub mod signal;
 pub mod store;
 
+pub use clock::Clock;
 pub use error::{Error, Result};
-pub use flush::FlushPolicy;
-pub use store::{JsonSync, JsonSyncBuilder, JsonSyncHandle};
+pub use flush::{FlushPolicy, OnFull};
+pub use serializer::DuplicatePolicy;
+pub use store::{
+    ChangeCheck, Changes, FlushEvent, FlushReport, FlushSuspendGuard, JsonSync, JsonSyncBuilder,
+    JsonSyncHandle, Op, ValueErrorPolicy,
+};
 
 /// Default backend: ShardMap.
 pub type DefaultBackend<K, V> = shardmap::ShardMap<K, V>;
@@ -0,0 +1,165 @@
+//! Schema migrations over a store's values, and a dry run to preview them.
+//!
+//! A file's version is the one in its `{"json_sync_version": n, "data": ...}`
+//! envelope (see
+//! [`JsonSerializer::version`](crate::serializer::JsonSerializer::version)),
+//! or 0 if it has none. Each [`Migration`] rewrites values from one version
+//! to the next, as JSON, so the old value type doesn't have to exist any
+//! more. A chain of them takes a file from whatever version it's at up to
+//! the newest one any migration reaches.
+//!
+//! ```rust,no_run
+//! use json_sync::migrate::{self, Migration};
+//!
+//! let migrations = [
+//!     // v0 stored a bare name; v1 wraps it in an object
+//!     Migration::new(0, |v| serde_json::json!({ "name": v })),
+//! ];
+//! let report = migrate::dry_run("users.json", &migrations).unwrap();
+//! println!("{} of {} entries would change", report.changed, report.entries);
+//! ```
+
+use crate::error::{Error, Result};
+use crate::persist::{decompressed, read_or_empty};
+use crate::serializer::envelope_version;
+use serde_json::Value;
+use std::path::Path;
+
+/// One step of a migration chain: rewrites a value stored at version `from`
+/// into its shape at `from + 1`.
+pub struct Migration {
+    from: u32,
+    f: Box<dyn Fn(Value) -> Value + Send + Sync>,
+}
+
+impl Migration {
+    /// A step taking values from version `from` to `from + 1`. `f` sees each
+    /// value as it's stored on disk and returns its replacement.
+    pub fn new<F>(from: u32, f: F) -> Self
+    where
+        F: Fn(Value) -> Value + Send + Sync + 'static,
+    {
+        Migration {
+            from,
+            f: Box::new(f),
+        }
+    }
+
+    /// Version this step upgrades from.
+    #[must_use]
+    pub fn from_version(&self) -> u32 {
+        self.from
+    }
+}
+
+impl std::fmt::Debug for Migration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Migration")
+            .field("from", &self.from)
+            .finish_non_exhaustive()
+    }
+}
+
+/// What a migration chain would do to a file, from [`dry_run`].
+#[non_exhaustive]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationReport {
+    /// Version the file is at now (0 without an envelope).
+    pub from_version: u32,
+    /// Version it would be at after the chain. Equal to `from_version` when
+    /// no migration starts there.
+    pub to_version: u32,
+    /// Entries in the file.
+    pub entries: usize,
+    /// Entries whose value the chain would change.
+    pub changed: usize,
+}
+
+/// Load the file at `path`, run `migrations` over its values in memory, and
+/// report what would change. Nothing is written.
+///
+/// Starting at the file's version, each step is the migration whose
+/// [`from_version`](Migration::from_version) matches, until none does. The
+/// order of `migrations` doesn't matter, but two starting at the same
+/// version are `Error::Config`. A missing or empty file reports zero
+/// entries, the same as opening it would. Maps written as
+/// [`as_pairs`](crate::serializer::JsonSerializer::as_pairs) are read too,
+/// and gzip and zstd files with those features.
+pub fn dry_run(path: impl AsRef<Path>, migrations: &[Migration]) -> Result<MigrationReport> {
+    check_chain(migrations)?;
+    let bytes = read_or_empty(path.as_ref())?;
+    let bytes = decompressed(&bytes)?;
+    let (from_version, data) = if bytes.iter().all(u8::is_ascii_whitespace) {
+        (0, Value::Object(serde_json::Map::new()))
+    } else {
+        let doc: Value = serde_json::from_slice(&bytes)?;
+        match envelope_version(&bytes) {
+            Some(version) => (version, envelope_data(doc)?),
+            None => (0, doc),
+        }
+    };
+    let values: Vec<&Value> = match &data {
+        Value::Object(map) => map.values().collect(),
+        Value::Array(pairs) => pairs
+            .iter()
+            .map(|pair| match pair.as_array().map(Vec::as_slice) {
+                Some([_, v]) => Ok(v),
+                _ => Err(Error::Deserialize(
+                    "expected a [key, value] pair in a pairs file".into(),
+                )),
+            })
+            .collect::<Result<_>>()?,
+        _ => {
+            return Err(Error::Deserialize(
+                "expected a JSON object or an array of [key, value] pairs".into(),
+            ))
+        }
+    };
+
+    let mut to_version = from_version;
+    let mut steps = Vec::new();
+    while let Some(step) = migrations.iter().find(|m| m.from == to_version) {
+        let Some(next) = to_version.checked_add(1) else {
+            break;
+        };
+        steps.push(step);
+        to_version = next;
+    }
+    let changed = values
+        .iter()
+        .filter(|&&v| {
+            let migrated = steps.iter().fold(v.clone(), |v, step| (step.f)(v));
+            migrated != *v
+        })
+        .count();
+    Ok(MigrationReport {
+        from_version,
+        to_version,
+        entries: values.len(),
+        changed,
+    })
+}
+
+/// Error if two migrations start at the same version, since the chain
+/// wouldn't know which to take.
+fn check_chain(migrations: &[Migration]) -> Result<()> {
+    for (i, m) in migrations.iter().enumerate() {
+        if migrations[..i].iter().any(|earlier| earlier.from == m.from) {
+            return Err(Error::Config(format!(
+                "two migrations start at version {}",
+                m.from
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// The `data` field of a version envelope.
+fn envelope_data(doc: Value) -> Result<Value> {
+    match doc {
+        Value::Object(mut map) => map
+            .remove("data")
+            .ok_or_else(|| Error::Deserialize("missing field `data`".into())),
+        _ => Err(Error::Deserialize("expected a version envelope".into())),
+    }
+}
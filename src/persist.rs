@@ -7,34 +7,534 @@
 use crate::error::{Error, Result};
 use crate::serializer::Serializer;
 use serde::Deserialize;
+use std::borrow::Cow;
 use std::collections::HashMap;
-use std::path::Path;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Write buffer used by a flush unless the builder says otherwise.
+pub const DEFAULT_WRITE_BUFFER_SIZE: usize = 64 * 1024;
 
 /// Reads and deserializes the file at `path`. Returns an empty map if the file
 /// is missing or empty (not an error).
+///
+/// With the `gzip` feature, a gzipped file is decompressed before it reaches
+/// `serializer`, whatever the serializer is — so switching a store back from
+/// [`Compressed`](crate::serializer::Compressed) to plain JSON still reads the
+/// old files. The same goes for zstd files with the `zstd` feature.
 pub fn load<K, V, S>(path: &Path, serializer: &S) -> Result<HashMap<K, V>>
 where
     K: for<'de> Deserialize<'de> + Eq + std::hash::Hash,
     V: for<'de> Deserialize<'de>,
     S: Serializer,
 {
-    let bytes = match std::fs::read(path) {
-        Ok(b) => b,
-        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(HashMap::new()),
-        Err(e) => return Err(Error::Io(e.to_string())),
-    };
+    decode(&read_or_empty(path)?, serializer)
+}
+
+/// The file's bytes, or none at all if it doesn't exist.
+pub(crate) fn read_or_empty(path: &Path) -> Result<Vec<u8>> {
+    match std::fs::read(path) {
+        Ok(b) => Ok(b),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(Error::Io(e.to_string())),
+    }
+}
+
+/// Whether the file at `path` is missing or holds nothing but whitespace.
+pub(crate) fn is_blank(path: &Path) -> Result<bool> {
+    Ok(read_or_empty(path)?.iter().all(u8::is_ascii_whitespace))
+}
+
+/// Parse a whole file's bytes the way [`load`] does: empty means an empty
+/// map, and gzip and zstd are recognized whatever `serializer` is.
+pub(crate) fn decode<K, V, S>(bytes: &[u8], serializer: &S) -> Result<HashMap<K, V>>
+where
+    K: for<'de> Deserialize<'de> + Eq + std::hash::Hash,
+    V: for<'de> Deserialize<'de>,
+    S: Serializer,
+{
     if bytes.is_empty() {
         return Ok(HashMap::new());
     }
-    serializer.deserialize(&bytes)
+    serializer.deserialize(&decompressed(bytes)?)
+}
+
+/// `bytes` unpacked if they're gzip or zstd (with those features), or as they
+/// are otherwise.
+pub(crate) fn decompressed(bytes: &[u8]) -> Result<Cow<'_, [u8]>> {
+    #[cfg(feature = "gzip")]
+    if crate::serializer::is_gzip(bytes) {
+        return crate::serializer::gunzip(bytes).map(Cow::Owned);
+    }
+    #[cfg(feature = "zstd")]
+    if crate::serializer::is_zstd(bytes) {
+        return crate::serializer::unzstd(bytes).map(Cow::Owned);
+    }
+    Ok(Cow::Borrowed(bytes))
+}
+
+/// Error unless no array or object in the JSON text `bytes` sits more than
+/// `max` levels deep, counting the top-level value as one. A plain scan over
+/// the bytes, so it can't overflow the stack however deep the input goes.
+pub(crate) fn check_depth(bytes: &[u8], max: usize) -> Result<()> {
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+    for &b in bytes {
+        if in_string {
+            match b {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match b {
+            b'"' => in_string = true,
+            b'[' | b'{' => {
+                depth += 1;
+                if depth > max {
+                    return Err(Error::Deserialize("max nesting depth exceeded".into()));
+                }
+            }
+            b']' | b'}' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// [`load`], plus cleanup after a flush that died between writing the temp
+/// file and renaming it over `path`.
+///
+/// If a leftover temp file exists and `path` loads fine, the temp file is
+/// stale and gets deleted. If `path` is missing or unreadable and `promote` is
+/// set, a temp file that loads cleanly is renamed over `path` — finishing the
+/// interrupted write — and its contents returned. Otherwise the temp file is
+/// left alone and the result of loading `path` is returned as usual.
+pub fn load_recovering<K, V, S>(path: &Path, serializer: &S, promote: bool) -> Result<HashMap<K, V>>
+where
+    K: for<'de> Deserialize<'de> + Eq + std::hash::Hash,
+    V: for<'de> Deserialize<'de>,
+    S: Serializer,
+{
+    load_recovering_with(path, promote, |p| load(p, serializer))
+}
+
+/// [`load_recovering`] with a custom way of reading one file.
+pub(crate) fn load_recovering_with<K, V, F>(
+    path: &Path,
+    promote: bool,
+    load: F,
+) -> Result<HashMap<K, V>>
+where
+    F: Fn(&Path) -> Result<HashMap<K, V>>,
+{
+    let tmp = temp_path(path);
+    if !tmp.exists() {
+        return load(path);
+    }
+    match load(path) {
+        Ok(data) if path.exists() => {
+            let _ = std::fs::remove_file(&tmp);
+            Ok(data)
+        }
+        main => {
+            if promote {
+                if let Ok(data) = load(&tmp) {
+                    std::fs::rename(&tmp, path).map_err(|e| Error::Io(e.to_string()))?;
+                    return Ok(data);
+                }
+            }
+            main
+        }
+    }
 }
 
 /// Write `bytes` to `<path>.tmp` and then rename over `path`. This avoids
 /// leaving a half-written file if the process crashes mid-write.
+///
+/// If `path` is a symlink, the rename replaces the link itself with a regular
+/// file. Pass the result of [`resolve_symlinks`] instead to write through it.
 pub fn atomic_write(path: &Path, bytes: &[u8]) -> Result<()> {
-    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("json");
-    let tmp = path.with_extension(format!("{ext}.tmp"));
-    std::fs::write(&tmp, bytes).map_err(|e| Error::Io(e.to_string()))?;
+    let tmp = temp_path(path);
+    let written = File::create(&tmp).and_then(|mut file| file.write_all(bytes));
+    if let Err(e) = written {
+        let _ = std::fs::remove_file(&tmp);
+        return Err(e.into());
+    }
     std::fs::rename(&tmp, path).map_err(|e| Error::Io(e.to_string()))?;
     Ok(())
 }
+
+/// Where a store's flushes go. By default that's its file, replaced with
+/// [`atomic_write`] ([`FileTarget`]); hand
+/// [`JsonSyncBuilder::target`](crate::JsonSyncBuilder::target) anything else
+/// — an in-memory buffer in tests, a socket, a stream you compress yourself.
+pub trait PersistTarget: Send + Sync {
+    /// Store `bytes`, one complete serialized snapshot, in place of whatever
+    /// the last call wrote.
+    fn write(&self, bytes: &[u8]) -> Result<()>;
+}
+
+/// The file at a path, replaced with [`atomic_write`] on every write.
+#[derive(Debug, Clone)]
+pub struct FileTarget {
+    path: PathBuf,
+}
+
+impl FileTarget {
+    /// Target the file at `path`.
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+}
+
+impl PersistTarget for FileTarget {
+    fn write(&self, bytes: &[u8]) -> Result<()> {
+        atomic_write(&self.path, bytes)
+    }
+}
+
+/// Like [`atomic_write`], but `write` streams the contents into the temp file
+/// through a buffer of `capacity` bytes, so the full payload never has to sit
+/// in memory. Returns the number of bytes written. If `write` fails the temp
+/// file is removed and `path` is left untouched.
+pub fn atomic_write_with<F>(path: &Path, capacity: usize, write: F) -> Result<u64>
+where
+    F: FnOnce(&mut dyn Write) -> Result<()>,
+{
+    atomic_write_preallocated(path, capacity, 0, write)
+}
+
+/// [`atomic_write_with`], reserving `reserve` bytes of disk for the temp file
+/// up front so the filesystem doesn't have to extend it mid-write. Only does
+/// anything on Linux (`fallocate` with `FALLOC_FL_KEEP_SIZE`, so the file's
+/// length still ends up exactly what was written).
+pub(crate) fn atomic_write_preallocated<F>(
+    path: &Path,
+    capacity: usize,
+    reserve: u64,
+    write: F,
+) -> Result<u64>
+where
+    F: FnOnce(&mut dyn Write) -> Result<()>,
+{
+    let tmp = temp_path(path);
+    let written = (|| {
+        let file = File::create(&tmp)?;
+        if reserve > 0 {
+            reserve_space(&file, reserve);
+        }
+        let mut out = CountingWriter {
+            inner: BufWriter::with_capacity(capacity, file),
+            count: 0,
+        };
+        write(&mut out)?;
+        out.inner.flush()?;
+        Ok(out.count)
+    })();
+    match written {
+        Ok(count) => {
+            std::fs::rename(&tmp, path).map_err(|e| Error::Io(e.to_string()))?;
+            Ok(count)
+        }
+        Err(e) => {
+            let _ = std::fs::remove_file(&tmp);
+            Err(e)
+        }
+    }
+}
+
+/// Best effort: a filesystem without `fallocate` just grows the file as usual.
+#[cfg(target_os = "linux")]
+fn reserve_space(file: &File, len: u64) {
+    use std::os::unix::io::AsRawFd;
+
+    let len = libc::off_t::try_from(len).unwrap_or(libc::off_t::MAX);
+    // SAFETY: the descriptor belongs to `file`, which outlives the call, and
+    // fallocate doesn't touch any memory of ours.
+    unsafe {
+        libc::fallocate(file.as_raw_fd(), libc::FALLOC_FL_KEEP_SIZE, 0, len);
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn reserve_space(_file: &File, _len: u64) {}
+
+/// Alignment (and length granularity) `O_DIRECT` writes are padded to.
+#[cfg(target_os = "linux")]
+const DIRECT_IO_ALIGN: usize = 4096;
+
+/// Like [`atomic_write`], but on Linux the temp file is opened with
+/// `O_DIRECT` so the write bypasses the page cache. The payload is copied into
+/// a block-aligned buffer padded to a whole number of blocks, and the file is
+/// truncated back to its real length afterwards.
+///
+/// Filesystems that refuse `O_DIRECT` (tmpfs, some network mounts) get a plain
+/// [`atomic_write`] instead. On other platforms this is always a plain
+/// [`atomic_write`].
+pub fn atomic_write_direct(path: &Path, bytes: &[u8]) -> Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+
+        let tmp = temp_path(path);
+        let file = match std::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .custom_flags(libc::O_DIRECT)
+            .open(&tmp)
+        {
+            Ok(file) => file,
+            Err(e) if e.raw_os_error() == Some(libc::EINVAL) => return atomic_write(path, bytes),
+            Err(e) => return Err(e.into()),
+        };
+
+        let padded = bytes.len().div_ceil(DIRECT_IO_ALIGN) * DIRECT_IO_ALIGN;
+        let mut block = vec![0u8; padded + DIRECT_IO_ALIGN];
+        let start = block.as_ptr().align_offset(DIRECT_IO_ALIGN);
+        let aligned = &mut block[start..start + padded];
+        aligned[..bytes.len()].copy_from_slice(bytes);
+
+        let written = (&file)
+            .write_all(aligned)
+            .and_then(|()| file.set_len(bytes.len() as u64));
+        drop(file);
+        if let Err(e) = written {
+            let _ = std::fs::remove_file(&tmp);
+            return Err(e.into());
+        }
+        std::fs::rename(&tmp, path).map_err(|e| Error::Io(e.to_string()))
+    }
+    #[cfg(not(target_os = "linux"))]
+    atomic_write(path, bytes)
+}
+
+/// `path` made absolute: its directory canonicalized, created first if it
+/// doesn't exist, with the file name joined back on. The file itself is left
+/// unresolved, so a symlink stays a symlink and needn't point anywhere yet.
+pub(crate) fn canonical_path(path: &Path) -> Result<PathBuf> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| Error::Config(format!("{} doesn't name a file", path.display())))?;
+    let dir = match path.parent() {
+        Some(d) if !d.as_os_str().is_empty() => d,
+        _ => Path::new("."),
+    };
+    std::fs::create_dir_all(dir)?;
+    Ok(dir.canonicalize()?.join(name))
+}
+
+/// Fail fast with `Error::Config` if `path` can't be a store's file: it's a
+/// directory, or the nearest of its parents that exists is a file, so
+/// nothing can be created under it.
+pub(crate) fn check_file_path(path: &Path) -> Result<()> {
+    if path.is_dir() {
+        return Err(Error::Config(format!(
+            "path is a directory, expected a file: {}",
+            path.display()
+        )));
+    }
+    let existing = path
+        .ancestors()
+        .skip(1)
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .find(|dir| dir.exists());
+    match existing {
+        Some(dir) if !dir.is_dir() => Err(Error::Config(format!(
+            "parent is a file, not a directory: {} (for {})",
+            dir.display(),
+            path.display()
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Follow `path` through any chain of symlinks to the file it finally names,
+/// which doesn't have to exist yet. Relative link targets are taken relative
+/// to the link's directory. A path that isn't a symlink comes back as is.
+pub fn resolve_symlinks(path: &Path) -> Result<PathBuf> {
+    // same limit Linux puts on path resolution
+    const MAX_HOPS: usize = 40;
+    let mut current = path.to_path_buf();
+    for _ in 0..MAX_HOPS {
+        match std::fs::symlink_metadata(&current) {
+            Ok(meta) if meta.file_type().is_symlink() => {
+                let target = std::fs::read_link(&current)?;
+                current = match current.parent() {
+                    Some(dir) => dir.join(target),
+                    None => target,
+                };
+            }
+            Ok(_) => return Ok(current),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(current),
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Err(Error::Io(format!(
+        "too many levels of symlinks resolving {}",
+        path.display()
+    )))
+}
+
+/// Check that `pointer` is a JSON Pointer (RFC 6901): empty, or starting
+/// with `/`.
+pub(crate) fn validate_pointer(pointer: &str) -> Result<()> {
+    if pointer.is_empty() || pointer.starts_with('/') {
+        Ok(())
+    } else {
+        Err(Error::Config(format!(
+            "json pointer {pointer:?} must be empty or start with '/'"
+        )))
+    }
+}
+
+/// The JSON found at `pointer` inside the document `doc`, re-encoded on its
+/// own. Nothing there (or no document at all) reads as no bytes, i.e. an
+/// empty map.
+pub(crate) fn extract_pointer(doc: &[u8], pointer: &str) -> Result<Vec<u8>> {
+    if doc.is_empty() {
+        return Ok(Vec::new());
+    }
+    let doc: serde_json::Value = serde_json::from_slice(doc)?;
+    match doc.pointer(pointer) {
+        Some(sub) => Ok(serde_json::to_vec(sub)?),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// `doc` with the value at `pointer` replaced by the JSON in `sub`, creating
+/// objects along the way as needed. Everything else in `doc` is kept. The
+/// result is indented if `sub` was.
+pub(crate) fn splice_pointer(doc: &[u8], pointer: &str, sub: &[u8]) -> Result<Vec<u8>> {
+    let sub_value: serde_json::Value = serde_json::from_slice(sub).map_err(|e| {
+        Error::Config(format!(
+            "json pointer needs a serializer that writes JSON: {e}"
+        ))
+    })?;
+    let mut root = if doc.is_empty() {
+        serde_json::Value::Object(Default::default())
+    } else {
+        serde_json::from_slice(doc)?
+    };
+
+    let mut slot = &mut root;
+    for token in pointer.split('/').skip(1) {
+        let token = token.replace("~1", "/").replace("~0", "~");
+        slot = match slot {
+            serde_json::Value::Object(map) => map
+                .entry(token)
+                .or_insert_with(|| serde_json::Value::Object(Default::default())),
+            serde_json::Value::Array(items) => {
+                match token.parse::<usize>().ok().and_then(|i| items.get_mut(i)) {
+                    Some(item) => item,
+                    None => {
+                        return Err(Error::Config(format!(
+                            "json pointer {pointer:?}: no array element {token:?}"
+                        )))
+                    }
+                }
+            }
+            _ => {
+                return Err(Error::Config(format!(
+                    "json pointer {pointer:?} runs through a value that isn't an object"
+                )))
+            }
+        };
+    }
+    *slot = sub_value;
+
+    let out = if sub.contains(&b'\n') {
+        serde_json::to_vec_pretty(&root)?
+    } else {
+        serde_json::to_vec(&root)?
+    };
+    Ok(out)
+}
+
+/// `<path>.<ext>.tmp`, the scratch file a flush writes before renaming.
+fn temp_path(path: &Path) -> PathBuf {
+    sidecar_path(path, "tmp")
+}
+
+/// Delete the temp files flushes left behind in `dir` — `<name>.<ext>.tmp`
+/// files, as written by [`atomic_write`] and friends — that were last
+/// modified at least `older_than` ago, and return how many went. For
+/// sweeping a data directory after crashes; no store needs to be open.
+///
+/// Only regular files directly in `dir` are considered, and only those
+/// sitting next to the `<name>.<ext>` they'd replace, so other programs'
+/// `.tmp` files in the same directory are left alone. Keep `older_than`
+/// well above your longest flush, or this can delete a temp file out from
+/// under a write that's still going.
+pub fn cleanup_temp_files(dir: &Path, older_than: Duration) -> Result<usize> {
+    let now = SystemTime::now();
+    let mut removed = 0;
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if !is_temp_file(&path) {
+            continue;
+        }
+        let meta = match entry.metadata() {
+            Ok(meta) => meta,
+            // renamed into place since the listing
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+        if !meta.is_file() {
+            continue;
+        }
+        // a modification time in the future isn't stale
+        let age = now.duration_since(meta.modified()?).unwrap_or_default();
+        if age < older_than {
+            continue;
+        }
+        match std::fs::remove_file(&path) {
+            Ok(()) => removed += 1,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(removed)
+}
+
+/// Whether `path` is named like a [`temp_path`] — `.tmp` on top of another
+/// extension — and the file it would be renamed over exists.
+fn is_temp_file(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e == "tmp")
+        && Path::new(path.file_stem().unwrap_or_default())
+            .extension()
+            .is_some()
+        && path.with_extension("").is_file()
+}
+
+/// `path` with `.<suffix>` tacked onto its extension, e.g. `db.json.lock`.
+pub(crate) fn sidecar_path(path: &Path, suffix: &str) -> PathBuf {
+    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("json");
+    path.with_extension(format!("{ext}.{suffix}"))
+}
+
+struct CountingWriter<W> {
+    inner: W,
+    count: u64,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.count += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,171 @@
+//! Read-only view of a store file, loaded through a memory map (feature
+//! `mmap`).
+//!
+//! Meant for fan-out setups where many processes read a file that one writer
+//! rarely changes: each reader maps the file instead of copying it into its
+//! own buffer, so the bytes come straight from the shared page cache, and
+//! parses it once. There's no way to write through a [`ReadOnlyStore`].
+
+use crate::error::Result;
+use crate::persist::decode;
+use crate::serializer::JsonSerializer;
+use parking_lot::{Mutex, RwLock};
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// Read-only snapshot of a store file, from
+/// [`JsonSync::open_mmap_readonly`](crate::JsonSync::open_mmap_readonly).
+///
+/// The file is parsed once into a shared map. Call [`refresh`](Self::refresh)
+/// to pick up a newer version after the writer flushes; until then reads see
+/// the old one.
+pub struct ReadOnlyStore<K, V> {
+    path: PathBuf,
+    data: RwLock<Arc<HashMap<K, V>>>,
+    stamp: Mutex<Option<Stamp>>,
+}
+
+/// Enough of a file's metadata to notice it was replaced.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Stamp {
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+impl<K, V> ReadOnlyStore<K, V>
+where
+    K: Hash + Eq + DeserializeOwned,
+    V: DeserializeOwned,
+{
+    pub(crate) fn open(path: &Path) -> Result<Self> {
+        let (data, stamp) = map_and_parse(path)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            data: RwLock::new(Arc::new(data)),
+            stamp: Mutex::new(stamp),
+        })
+    }
+
+    /// Re-read the file if it changed since the last load (by modification
+    /// time or length) and return whether it did. Handles that already took a
+    /// [`snapshot`](Self::snapshot) keep the old data.
+    pub fn refresh(&self) -> Result<bool> {
+        let mut stamp = self.stamp.lock();
+        if *stamp == stamp_of(&self.path)? {
+            return Ok(false);
+        }
+        let (data, fresh) = map_and_parse(&self.path)?;
+        *self.data.write() = Arc::new(data);
+        *stamp = fresh;
+        Ok(true)
+    }
+
+    /// Shared handle to the whole parsed map.
+    #[must_use]
+    pub fn snapshot(&self) -> Arc<HashMap<K, V>> {
+        Arc::clone(&self.data.read())
+    }
+
+    /// `true` if the key exists.
+    #[must_use]
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.data.read().contains_key(key)
+    }
+
+    /// Number of entries.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.data.read().len()
+    }
+
+    /// `true` when the file holds no entries.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Path to the mapped file.
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl<K, V> ReadOnlyStore<K, V>
+where
+    K: Hash + Eq + Clone + DeserializeOwned,
+    V: Clone + DeserializeOwned,
+{
+    /// Get the value for `key`, or `None` if absent.
+    #[must_use]
+    pub fn get(&self, key: &K) -> Option<V> {
+        self.data.read().get(key).cloned()
+    }
+
+    /// Snapshot of all key-value pairs.
+    #[must_use]
+    pub fn iter(&self) -> Vec<(K, V)> {
+        let data = self.data.read();
+        data.iter().map(|(k, v)| (k.clone(), v.clone())).collect()
+    }
+
+    /// Snapshot of all keys.
+    #[must_use]
+    pub fn keys(&self) -> Vec<K> {
+        self.data.read().keys().cloned().collect()
+    }
+}
+
+impl<K, V> std::fmt::Debug for ReadOnlyStore<K, V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ReadOnlyStore")
+            .field("path", &self.path)
+            .field("len", &self.data.read().len())
+            .finish()
+    }
+}
+
+/// `None` for a missing file.
+fn stamp_of(path: &Path) -> Result<Option<Stamp>> {
+    match std::fs::metadata(path) {
+        Ok(meta) => Ok(Some(Stamp {
+            modified: meta.modified().ok(),
+            len: meta.len(),
+        })),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Parse the file at `path` through a read-only mapping. A missing or empty
+/// file is an empty map, as with [`load`](crate::persist::load).
+fn map_and_parse<K, V>(path: &Path) -> Result<(HashMap<K, V>, Option<Stamp>)>
+where
+    K: Hash + Eq + DeserializeOwned,
+    V: DeserializeOwned,
+{
+    let file = match std::fs::File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok((HashMap::new(), None)),
+        Err(e) => return Err(e.into()),
+    };
+    let meta = file.metadata()?;
+    let stamp = Some(Stamp {
+        modified: meta.modified().ok(),
+        len: meta.len(),
+    });
+    if meta.len() == 0 {
+        return Ok((HashMap::new(), stamp));
+    }
+    // SAFETY: the mapping is only read, and only until parsing finishes. The
+    // store's writers replace the file by renaming a new one over it, which
+    // leaves this mapping's inode intact; something truncating the file in
+    // place mid-parse would fault, as with any mmap.
+    let mapped = unsafe { memmap2::Mmap::map(&file)? };
+    let data = decode(&mapped, &JsonSerializer::new())?;
+    Ok((data, stamp))
+}
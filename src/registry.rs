@@ -0,0 +1,71 @@
+//! Process-wide table of open stores, keyed by path (feature
+//! `single-instance`). Lets a second `build()` of the same file hand back the
+//! store the first one made, instead of a second map that overwrites the
+//! first one's flushes.
+
+use crate::error::{Error, Result};
+use parking_lot::{Mutex, MutexGuard};
+use std::any::Any;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock, Weak};
+
+pub(crate) struct Registry {
+    open: HashMap<PathBuf, Weak<dyn Any + Send + Sync>>,
+}
+
+static OPEN: LazyLock<Mutex<Registry>> = LazyLock::new(|| {
+    Mutex::new(Registry {
+        open: HashMap::new(),
+    })
+});
+
+/// Lock the table. Held while a store is built and while a handle works out
+/// whether it's the last one, so an open can't slip in between.
+pub(crate) fn lock() -> MutexGuard<'static, Registry> {
+    OPEN.lock()
+}
+
+/// What `path` is filed under: its canonical form, or for a file that doesn't
+/// exist yet its canonical directory plus the file name.
+pub(crate) fn key(path: &Path) -> PathBuf {
+    if let Ok(p) = path.canonicalize() {
+        return p;
+    }
+    let dir = match path.parent() {
+        Some(d) if !d.as_os_str().is_empty() => d,
+        _ => Path::new("."),
+    };
+    match (dir.canonicalize(), path.file_name()) {
+        (Ok(dir), Some(name)) => dir.join(name),
+        _ => path.to_path_buf(),
+    }
+}
+
+impl Registry {
+    /// The live store filed under `key`, if there is one. Errors if it was
+    /// opened with different types.
+    pub(crate) fn get<T: Any + Send + Sync>(&mut self, key: &Path) -> Result<Option<Arc<T>>> {
+        let Some(store) = self.open.get(key).and_then(Weak::upgrade) else {
+            self.open.remove(key);
+            return Ok(None);
+        };
+        store.downcast::<T>().map(Some).map_err(|_| {
+            Error::Config(format!(
+                "{} is already open in this process with different key, value, backend, or serializer types",
+                key.display()
+            ))
+        })
+    }
+
+    pub(crate) fn insert<T: Any + Send + Sync>(&mut self, key: PathBuf, store: &Arc<T>) {
+        let store: Arc<dyn Any + Send + Sync> = store.clone();
+        self.open.insert(key, Arc::downgrade(&store));
+    }
+
+    /// Drop `store`'s entry, so later opens build a fresh one.
+    pub(crate) fn remove<T>(&mut self, store: &Arc<T>) {
+        let ptr = Arc::as_ptr(store).cast::<()>();
+        self.open.retain(|_, w| w.as_ptr().cast::<()>() != ptr);
+    }
+}
@@ -0,0 +1,94 @@
+//! Wall-clock schedules for [`FlushPolicy::Scheduled`](crate::FlushPolicy::Scheduled).
+//!
+//! A schedule is a set of instants fixed to the calendar rather than to when
+//! the store was opened: "the top of every hour" fires at 10:00, 11:00, ...
+//! whether the process started at 9:59 or 9:01. All times are UTC.
+//!
+//! ```rust
+//! use chrono::NaiveTime;
+//! use json_sync::schedule::Schedule;
+//! use std::time::{Duration, UNIX_EPOCH};
+//!
+//! let hourly = Schedule::hourly();
+//! let at = UNIX_EPOCH + Duration::from_secs(90 * 60);
+//! assert_eq!(hourly.next_after(at), Some(UNIX_EPOCH + Duration::from_secs(2 * 3600)));
+//!
+//! let twice_a_day = Schedule::daily_at([
+//!     NaiveTime::from_hms_opt(6, 0, 0).unwrap(),
+//!     NaiveTime::from_hms_opt(18, 0, 0).unwrap(),
+//! ]);
+//! assert_eq!(twice_a_day.next_after(at), Some(UNIX_EPOCH + Duration::from_secs(6 * 3600)));
+//! ```
+
+use chrono::{DateTime, NaiveTime, Utc};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// When a [`FlushPolicy::Scheduled`](crate::FlushPolicy::Scheduled) store
+/// writes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schedule {
+    kind: Kind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Kind {
+    Every(Duration),
+    Daily(Vec<NaiveTime>),
+}
+
+impl Schedule {
+    /// At the top of every hour.
+    pub fn hourly() -> Self {
+        Self::every(Duration::from_secs(3600))
+    }
+
+    /// Every `period`, counted from the Unix epoch rather than from when the
+    /// store opened, so `every(15 minutes)` fires at :00, :15, :30 and :45.
+    /// Periods that divide a day evenly line up with midnight. A zero period
+    /// never fires.
+    pub fn every(period: Duration) -> Self {
+        Schedule {
+            kind: Kind::Every(period),
+        }
+    }
+
+    /// Once a day at each of `times`. An empty list never fires.
+    pub fn daily_at(times: impl IntoIterator<Item = NaiveTime>) -> Self {
+        let mut times: Vec<_> = times.into_iter().collect();
+        times.sort_unstable();
+        times.dedup();
+        Schedule {
+            kind: Kind::Daily(times),
+        }
+    }
+
+    /// The first firing strictly after `t`, or `None` if the schedule never
+    /// fires.
+    #[must_use]
+    pub fn next_after(&self, t: SystemTime) -> Option<SystemTime> {
+        match &self.kind {
+            Kind::Every(period) => {
+                let period = period.as_nanos();
+                if period == 0 {
+                    return None;
+                }
+                // clocks before 1970 aren't worth lining up exactly
+                let since = t.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+                let next = (since / period + 1).checked_mul(period)?;
+                let secs = u64::try_from(next / 1_000_000_000).ok()?;
+                let nanos = (next % 1_000_000_000) as u32;
+                UNIX_EPOCH.checked_add(Duration::new(secs, nanos))
+            }
+            Kind::Daily(times) => {
+                let now = DateTime::<Utc>::from(t);
+                let today = now.date_naive();
+                [Some(today), today.succ_opt()]
+                    .into_iter()
+                    .flatten()
+                    .flat_map(|day| times.iter().map(move |&time| day.and_time(time).and_utc()))
+                    .find(|&at| at > now)
+                    .map(SystemTime::from)
+            }
+        }
+    }
+}
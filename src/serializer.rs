@@ -1,10 +1,21 @@
 //! Serialization layer. Defaults to JSON via serde_json.
 //!
 //! Implement [`Serializer`] if you need a different format (RON, MessagePack, etc.).
+//! Serializers work on their own too: [`Serializer::serialize_to`] and
+//! [`Serializer::deserialize_from`] take any writer or reader, no store needed.
+//! [`Transformed`] rewrites the JSON document on its way to and from disk.
+//! [`SwappableSerializer`] lets you change formats while the store runs.
+//! With the `gzip` feature, wrap any serializer in [`Compressed`] to gzip the
+//! file; with `zstd`, in [`Zstd`].
 
 use crate::error::{Error, Result};
+use serde::de::{self, DeserializeSeed, MapAccess, SeqAccess, Visitor};
+use serde::ser;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::marker::PhantomData;
 
 /// Converts map snapshots to/from bytes for persistence.
 pub trait Serializer: Send + Sync {
@@ -14,17 +25,117 @@ pub trait Serializer: Send + Sync {
         K: Serialize,
         V: Serialize;
 
+    /// Encode a map straight into `writer`. The default goes through
+    /// [`serialize`](Self::serialize); override it to stream without holding
+    /// the whole encoding in memory.
+    fn serialize_to<K, V, W>(&self, data: &HashMap<K, V>, mut writer: W) -> Result<()>
+    where
+        K: Serialize,
+        V: Serialize,
+        W: Write,
+    {
+        writer.write_all(&self.serialize(data)?)?;
+        Ok(())
+    }
+
+    /// Decode a map from `reader`. The default reads everything and hands it
+    /// to [`deserialize`](Self::deserialize).
+    fn deserialize_from<K, V, R>(&self, mut reader: R) -> Result<HashMap<K, V>>
+    where
+        K: for<'de> Deserialize<'de> + Eq + std::hash::Hash,
+        V: for<'de> Deserialize<'de>,
+        R: Read,
+    {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        self.deserialize(&bytes)
+    }
+
     /// Decode bytes back into a map.
     fn deserialize<K, V>(&self, bytes: &[u8]) -> Result<HashMap<K, V>>
     where
         K: for<'de> Deserialize<'de> + Eq + std::hash::Hash,
         V: for<'de> Deserialize<'de>;
+
+    /// Cheap guess at whether `bytes` are in this format, from a glance at the
+    /// first few bytes. Used by [`AutoSerializer`] to pick a reader; `true`
+    /// means "worth trying", not "will parse". The default says yes to
+    /// anything.
+    fn sniff(&self, bytes: &[u8]) -> bool {
+        let _ = bytes;
+        true
+    }
+
+    /// Whether this format is JSON text, once any compression is taken off.
+    /// Things that only make sense on JSON —
+    /// [`max_depth`](crate::JsonSyncBuilder::max_depth)'s scan and
+    /// [`on_value_error`](crate::JsonSyncBuilder::on_value_error)'s
+    /// value-at-a-time parsing — are skipped or refused otherwise. The
+    /// default says no; [`JsonSerializer`] and the wrappers in this module
+    /// say yes when what they wrap does.
+    fn reads_json(&self) -> bool {
+        false
+    }
+
+    /// Read one value of a map in this format from `deserializer`, for the
+    /// places that hold a value's JSON on its own —
+    /// [`lazy_values`](crate::JsonSyncBuilder::lazy_values) and
+    /// [`on_value_error`](crate::JsonSyncBuilder::on_value_error). Override
+    /// it if reading a map does more to its values than plain serde does;
+    /// the default is `V::deserialize`.
+    fn deserialize_value<'de, D, V>(&self, deserializer: D) -> std::result::Result<V, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+        V: Deserialize<'de>,
+    {
+        V::deserialize(deserializer)
+    }
+
+    /// Fail if this format can't write maps keyed by `K`. Called by
+    /// [`build`](crate::JsonSyncBuilder::build), so a bad key type is an
+    /// [`Error::Config`] when the store opens instead of an error at the
+    /// first flush. The default accepts anything.
+    fn check_keys<K>(&self) -> Result<()>
+    where
+        K: for<'de> Deserialize<'de>,
+    {
+        Ok(())
+    }
+}
+
+/// What reading does with a key that appears more than once in a file. Easy
+/// to end up with in the array-of-pairs layout (see
+/// [`JsonSerializer::as_pairs`]), where nothing stops a hand edit or another
+/// program from repeating a key; a JSON object can repeat one too.
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DuplicatePolicy {
+    /// Keep the value from the last occurrence, as serde_json does.
+    #[default]
+    LastWins,
+    /// Keep the value from the first occurrence and ignore the rest.
+    FirstWins,
+    /// Fail with [`Error::Deserialize`].
+    Error,
 }
 
 /// JSON serializer with optional pretty-printing.
+///
+/// With a [`version`](Self::version) set, the map is wrapped in an envelope —
+/// `{"json_sync_version": 2, "data": {...}}` — so readers can tell which schema
+/// wrote the file. Files without the envelope read as version 0.
+///
+/// Reading accepts the map either as an object or as an array of `[key, value]`
+/// pairs (see [`as_pairs`](Self::as_pairs)), whichever the file holds.
 #[derive(Clone, Default)]
 pub struct JsonSerializer {
-    pretty: bool,
+    pub(crate) pretty: bool,
+    duplicates: DuplicatePolicy,
+    pairs: bool,
+    skip_nulls: bool,
+    large_ints: bool,
+    version: Option<u32>,
+    max_version: Option<u32>,
 }
 
 impl JsonSerializer {
@@ -35,7 +146,251 @@ impl JsonSerializer {
 
     /// Pretty-printed JSON with indentation — easier to read by hand.
     pub fn pretty() -> Self {
-        Self { pretty: true }
+        Self {
+            pretty: true,
+            ..Self::default()
+        }
+    }
+
+    /// Fail with [`Error::Deserialize`] when the file repeats a top-level key.
+    /// serde_json normally keeps the last occurrence and says nothing.
+    /// Shorthand for [`on_duplicate_key`](Self::on_duplicate_key) with
+    /// [`DuplicatePolicy::Error`] (or, for `false`, the default
+    /// [`LastWins`](DuplicatePolicy::LastWins)).
+    pub fn reject_duplicate_keys(mut self, yes: bool) -> Self {
+        self.duplicates = if yes {
+            DuplicatePolicy::Error
+        } else {
+            DuplicatePolicy::LastWins
+        };
+        self
+    }
+
+    /// Choose which value wins when the file repeats a top-level key, or
+    /// refuse the file (default: [`DuplicatePolicy::LastWins`]). Applies to
+    /// both layouts, object and pairs.
+    pub fn on_duplicate_key(mut self, policy: DuplicatePolicy) -> Self {
+        self.duplicates = policy;
+        self
+    }
+
+    /// Write the map as `[[k, v], [k, v], ...]` instead of an object.
+    ///
+    /// JSON object keys must be strings, so maps keyed by structs, tuples or
+    /// other non-string types can only be persisted this way. Reading doesn't
+    /// depend on this flag; both layouts are always accepted.
+    pub fn as_pairs(mut self, yes: bool) -> Self {
+        self.pairs = yes;
+        self
+    }
+
+    /// Leave `null` fields out of objects inside values when writing, so a
+    /// struct full of `None`s doesn't fill the file with them.
+    ///
+    /// Reading needs nothing special: serde already fills a missing `Option`
+    /// field with `None`. Other fields that can be `null` need
+    /// `#[serde(default)]` to read back once pruned. Only object fields are
+    /// dropped — nulls in arrays, and values that are `null` themselves, stay,
+    /// since removing them would lose a position or a key. Each value goes
+    /// through a `serde_json::Value` on the way out, so writes get slower and
+    /// object fields come out sorted by name.
+    /// Empty collections can't be told from meaningful ones here; skip those
+    /// per field with `#[serde(default, skip_serializing_if = "Vec::is_empty")]`.
+    pub fn skip_nulls(mut self, yes: bool) -> Self {
+        self.skip_nulls = yes;
+        self
+    }
+
+    /// Write integers beyond ±(2^53 − 1) as quoted strings
+    /// (`"18446744073709551615"`), so JavaScript readers, whose numbers are
+    /// doubles, get them exactly. `u64`, `i64`, `u128`, and `i128` are
+    /// quoted past that range; smaller integer types never reach it. In the
+    /// pairs layout, keys are quoted too.
+    ///
+    /// Reading accepts an integer field written either way, so turning this
+    /// on for an existing file is fine. Only fields whose type asks for an
+    /// integer are unquoted: a `String` holding digits stays a string, and
+    /// so does a quoted integer read into an untyped `serde_json::Value`, or
+    /// through `#[serde(flatten)]` or an untagged enum, which don't say what
+    /// they expect.
+    pub fn large_ints_as_strings(mut self, yes: bool) -> Self {
+        self.large_ints = yes;
+        self
+    }
+
+    /// Write files inside a `{"json_sync_version": n, "data": ...}` envelope
+    /// and recognize the envelope when reading. A file is taken for an
+    /// envelope only if `json_sync_version` is its first key, as written
+    /// here, so an ordinary map keyed `version` and `data` reads as a map.
+    pub fn version(mut self, n: u32) -> Self {
+        self.version = Some(n);
+        self
+    }
+
+    /// Refuse to read a file stamped with a version above `n`, returning
+    /// [`Error::Config`] before any of its data is parsed. Guards against an
+    /// older binary loading (and later overwriting) a newer schema.
+    pub fn max_version(mut self, n: u32) -> Self {
+        self.max_version = Some(n);
+        self
+    }
+
+    /// Check that `K` can be a key of a JSON object: strings, integers,
+    /// floats, bools, chars, unit-variant enums, and newtypes of those. Structs,
+    /// tuples, sequences, and options return [`Error::Config`] pointing at
+    /// [`as_pairs`](Self::as_pairs).
+    ///
+    /// No `K` value is needed — the check asks `K`'s `Deserialize` impl what
+    /// shape it expects. Types that accept anything (`deserialize_any`) pass.
+    pub fn check_key_type<K>() -> Result<()>
+    where
+        K: for<'de> Deserialize<'de>,
+    {
+        match K::deserialize(KeyShape) {
+            Err(KeyProbe::Unfit(shape)) => Err(Error::Config(format!(
+                "{} is {shape}, which can't be a JSON object key; \
+                 use as_pairs mode for non-string keys",
+                std::any::type_name::<K>()
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    fn versioned(&self) -> bool {
+        self.version.is_some() || self.max_version.is_some()
+    }
+
+    fn encode_to<T, W>(&self, data: &T, writer: W) -> Result<()>
+    where
+        T: Serialize,
+        W: Write,
+    {
+        if self.large_ints {
+            return self.write_json(&QuotedInts(data), writer);
+        }
+        self.write_json(data, writer)
+    }
+
+    fn write_json<T, W>(&self, data: &T, writer: W) -> Result<()>
+    where
+        T: Serialize,
+        W: Write,
+    {
+        let written = match self.version {
+            Some(version) => {
+                let env = EnvelopeOut { version, data };
+                if self.pretty {
+                    serde_json::to_writer_pretty(writer, &env)
+                } else {
+                    serde_json::to_writer(writer, &env)
+                }
+            }
+            None if self.pretty => serde_json::to_writer_pretty(writer, data),
+            None => serde_json::to_writer(writer, data),
+        };
+        written.map_err(Error::from)
+    }
+
+    fn seed<K, V>(&self) -> MapSeed<K, V> {
+        MapSeed {
+            duplicates: self.duplicates,
+            large_ints: self.large_ints,
+            _marker: PhantomData,
+        }
+    }
+
+    /// `T` from `value`, with integers quoted by
+    /// [`large_ints_as_strings`](Self::large_ints_as_strings) unquoted.
+    fn read_value<T>(&self, value: Value) -> Result<T>
+    where
+        T: for<'de> Deserialize<'de>,
+    {
+        self.deserialize_value(value)
+            .map_err(|e| Error::Deserialize(e.to_string()))
+    }
+
+    fn parse<'de, R, T>(&self, mut de: serde_json::Deserializer<R>, seed: T) -> Result<T::Value>
+    where
+        R: serde_json::de::Read<'de>,
+        T: DeserializeSeed<'de>,
+    {
+        seed.deserialize(&mut de)
+            .and_then(|value| de.end().map(|()| value))
+            .map_err(|e| {
+                if e.is_io() {
+                    Error::Io(e.to_string())
+                } else {
+                    Error::Deserialize(e.to_string())
+                }
+            })
+    }
+}
+
+#[derive(Serialize)]
+struct EnvelopeOut<'a, T> {
+    #[serde(rename = "json_sync_version")]
+    version: u32,
+    data: &'a T,
+}
+
+/// Serializes a map as a sequence of `(key, value)` tuples.
+struct Pairs<'a, K, V>(&'a HashMap<K, V>);
+
+impl<K: Serialize, V: Serialize> Serialize for Pairs<'_, K, V> {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_seq(self.0.iter())
+    }
+}
+
+/// A value with `null` object fields pruned (see
+/// [`JsonSerializer::skip_nulls`]).
+struct NullsSkipped<'a, V>(&'a V);
+
+impl<V: Serialize> Serialize for NullsSkipped<'_, V> {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut value = serde_json::to_value(self.0).map_err(serde::ser::Error::custom)?;
+        if let Value::Object(_) | Value::Array(_) = value {
+            prune_nulls(&mut value);
+        }
+        value.serialize(serializer)
+    }
+}
+
+fn prune_nulls(value: &mut Value) {
+    match value {
+        Value::Object(fields) => {
+            fields.retain(|_, v| !v.is_null());
+            fields.values_mut().for_each(prune_nulls);
+        }
+        Value::Array(items) => items.iter_mut().for_each(prune_nulls),
+        _ => {}
+    }
+}
+
+/// The map with every value wrapped in [`NullsSkipped`], laid out as an
+/// object or as pairs.
+struct SkipNullsMap<'a, K, V> {
+    data: &'a HashMap<K, V>,
+    pairs: bool,
+}
+
+impl<K: Serialize, V: Serialize> Serialize for SkipNullsMap<'_, K, V> {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let entries = self.data.iter().map(|(k, v)| (k, NullsSkipped(v)));
+        if self.pairs {
+            serializer.collect_seq(entries)
+        } else {
+            serializer.collect_map(entries)
+        }
     }
 }
 
@@ -45,12 +400,64 @@ impl Serializer for JsonSerializer {
         K: Serialize,
         V: Serialize,
     {
-        let bytes = if self.pretty {
-            serde_json::to_vec_pretty(data)
+        let mut buf = Vec::new();
+        self.serialize_to(data, &mut buf)?;
+        Ok(buf)
+    }
+
+    /// An object or array, after any leading whitespace.
+    fn sniff(&self, bytes: &[u8]) -> bool {
+        matches!(
+            bytes.iter().find(|b| !b.is_ascii_whitespace()),
+            Some(b'{' | b'[')
+        )
+    }
+
+    fn reads_json(&self) -> bool {
+        true
+    }
+
+    /// Unquotes integers under
+    /// [`large_ints_as_strings`](Self::large_ints_as_strings).
+    fn deserialize_value<'de, D, V>(&self, deserializer: D) -> std::result::Result<V, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+        V: Deserialize<'de>,
+    {
+        if self.large_ints {
+            V::deserialize(Unquote(deserializer))
+        } else {
+            V::deserialize(deserializer)
+        }
+    }
+
+    fn check_keys<K>(&self) -> Result<()>
+    where
+        K: for<'de> Deserialize<'de>,
+    {
+        if self.pairs {
+            return Ok(());
+        }
+        Self::check_key_type::<K>()
+    }
+
+    fn serialize_to<K, V, W>(&self, data: &HashMap<K, V>, writer: W) -> Result<()>
+    where
+        K: Serialize,
+        V: Serialize,
+        W: Write,
+    {
+        if self.skip_nulls {
+            let map = SkipNullsMap {
+                data,
+                pairs: self.pairs,
+            };
+            self.encode_to(&map, writer)
+        } else if self.pairs {
+            self.encode_to(&Pairs(data), writer)
         } else {
-            serde_json::to_vec(data)
-        };
-        bytes.map_err(Error::from)
+            self.encode_to(data, writer)
+        }
     }
 
     fn deserialize<K, V>(&self, bytes: &[u8]) -> Result<HashMap<K, V>>
@@ -58,6 +465,1633 @@ impl Serializer for JsonSerializer {
         K: for<'de> Deserialize<'de> + Eq + std::hash::Hash,
         V: for<'de> Deserialize<'de>,
     {
-        serde_json::from_slice(bytes).map_err(Error::from)
+        let file_version = if self.versioned() {
+            envelope_version(bytes)
+        } else {
+            None
+        };
+        if let (Some(found), Some(max)) = (file_version, self.max_version) {
+            if found > max {
+                return Err(Error::Config(format!(
+                    "file version {found} is newer than supported {max}"
+                )));
+            }
+        }
+
+        let de = serde_json::Deserializer::from_slice(bytes);
+        if file_version.is_some() {
+            self.parse(de, EnvelopeSeed(self.seed()))
+        } else {
+            self.parse(de, self.seed())
+        }
+    }
+
+    fn deserialize_from<K, V, R>(&self, mut reader: R) -> Result<HashMap<K, V>>
+    where
+        K: for<'de> Deserialize<'de> + Eq + std::hash::Hash,
+        V: for<'de> Deserialize<'de>,
+        R: Read,
+    {
+        // The version has to be known before the data is parsed, so an
+        // envelope-aware read buffers; a plain one streams.
+        if self.versioned() {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes)?;
+            return self.deserialize(&bytes);
+        }
+        let de = serde_json::Deserializer::from_reader(reader);
+        self.parse(de, self.seed())
+    }
+}
+
+/// Reads a map laid out as a JSON object or as an array of `[key, value]`
+/// pairs, handling keys that show up twice as `duplicates` says. With
+/// `large_ints`, entries are read through [`Unquote`].
+struct MapSeed<K, V> {
+    duplicates: DuplicatePolicy,
+    large_ints: bool,
+    _marker: PhantomData<(K, V)>,
+}
+
+impl<K, V> MapSeed<K, V>
+where
+    K: Eq + std::hash::Hash,
+{
+    fn insert<E: de::Error>(
+        &self,
+        map: &mut HashMap<K, V>,
+        key: K,
+        value: V,
+    ) -> std::result::Result<(), E> {
+        match self.duplicates {
+            DuplicatePolicy::LastWins => {
+                map.insert(key, value);
+            }
+            DuplicatePolicy::FirstWins => {
+                map.entry(key).or_insert(value);
+            }
+            DuplicatePolicy::Error => {
+                if map.insert(key, value).is_some() {
+                    return Err(E::custom("duplicate key"));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'de, K, V> DeserializeSeed<'de> for MapSeed<K, V>
+where
+    K: Deserialize<'de> + Eq + std::hash::Hash,
+    V: Deserialize<'de>,
+{
+    type Value = HashMap<K, V>;
+
+    fn deserialize<D>(self, deserializer: D) -> std::result::Result<Self::Value, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de, K, V> Visitor<'de> for MapSeed<K, V>
+where
+    K: Deserialize<'de> + Eq + std::hash::Hash,
+    V: Deserialize<'de>,
+{
+    type Value = HashMap<K, V>;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("a map or an array of [key, value] pairs")
+    }
+
+    fn visit_map<A>(self, mut access: A) -> std::result::Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut map = HashMap::with_capacity(access.size_hint().unwrap_or(0));
+        loop {
+            let entry = if self.large_ints {
+                access.next_entry_seed(Unquoted(PhantomData), Unquoted(PhantomData))?
+            } else {
+                access.next_entry()?
+            };
+            let Some((k, v)) = entry else {
+                return Ok(map);
+            };
+            self.insert(&mut map, k, v)?;
+        }
+    }
+
+    fn visit_seq<A>(self, mut access: A) -> std::result::Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut map = HashMap::with_capacity(access.size_hint().unwrap_or(0));
+        loop {
+            let entry = if self.large_ints {
+                access.next_element_seed(Unquoted(PhantomData))?
+            } else {
+                access.next_element()?
+            };
+            let Some((k, v)) = entry else {
+                return Ok(map);
+            };
+            self.insert(&mut map, k, v)?;
+        }
+    }
+}
+
+/// Key a version envelope's version sits under. Written first, so
+/// [`envelope_version`] can spot an envelope from the opening bytes.
+pub(crate) const VERSION_KEY: &str = "json_sync_version";
+
+/// The version stamped in `bytes` if they open with a version envelope's
+/// `{"json_sync_version": n`, or `None` for anything else. Looks no further,
+/// so the document is parsed once, by whatever reads it next.
+pub(crate) fn envelope_version(bytes: &[u8]) -> Option<u32> {
+    fn skip_ws(bytes: &[u8]) -> &[u8] {
+        let start = bytes.iter().position(|b| !b.is_ascii_whitespace());
+        &bytes[start.unwrap_or(bytes.len())..]
+    }
+
+    let rest = skip_ws(bytes).strip_prefix(b"{")?;
+    let rest = skip_ws(rest)
+        .strip_prefix(b"\"")?
+        .strip_prefix(VERSION_KEY.as_bytes())?
+        .strip_prefix(b"\"")?;
+    let rest = skip_ws(skip_ws(rest).strip_prefix(b":")?);
+    let digits = rest.iter().take_while(|b| b.is_ascii_digit()).count();
+    std::str::from_utf8(&rest[..digits]).ok()?.parse().ok()
+}
+
+/// Pulls the `data` field out of a version envelope through a [`MapSeed`].
+struct EnvelopeSeed<K, V>(MapSeed<K, V>);
+
+impl<'de, K, V> DeserializeSeed<'de> for EnvelopeSeed<K, V>
+where
+    K: Deserialize<'de> + Eq + std::hash::Hash,
+    V: Deserialize<'de>,
+{
+    type Value = HashMap<K, V>;
+
+    fn deserialize<D>(self, deserializer: D) -> std::result::Result<Self::Value, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de, K, V> Visitor<'de> for EnvelopeSeed<K, V>
+where
+    K: Deserialize<'de> + Eq + std::hash::Hash,
+    V: Deserialize<'de>,
+{
+    type Value = HashMap<K, V>;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("a version envelope")
+    }
+
+    fn visit_map<A>(self, mut access: A) -> std::result::Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut seed = Some(self.0);
+        let mut data = None;
+        while let Some(key) = access.next_key::<String>()? {
+            if key == "data" {
+                let seed = seed
+                    .take()
+                    .ok_or_else(|| de::Error::duplicate_field("data"))?;
+                data = Some(access.next_value_seed(seed)?);
+            } else {
+                access.next_value::<de::IgnoredAny>()?;
+            }
+        }
+        data.ok_or_else(|| de::Error::missing_field("data"))
+    }
+}
+
+// ---- large integers ----------------------------------------------------------
+
+/// Largest integer a JavaScript number holds exactly
+/// (`Number.MAX_SAFE_INTEGER`).
+const MAX_SAFE_INTEGER: u64 = (1 << 53) - 1;
+
+/// A value written with its integers past [`MAX_SAFE_INTEGER`] quoted (see
+/// [`JsonSerializer::large_ints_as_strings`]).
+struct QuotedInts<'a, T: ?Sized>(&'a T);
+
+impl<T: Serialize + ?Sized> Serialize for QuotedInts<'_, T> {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        self.0.serialize(Quote(serializer))
+    }
+}
+
+/// Passes everything through to the serializer it wraps, except `u64`,
+/// `i64`, `u128`, and `i128` values past [`MAX_SAFE_INTEGER`], which go out
+/// as strings. What it hands back for compound values wraps their fields in
+/// turn, so nested integers are quoted too.
+struct Quote<S>(S);
+
+macro_rules! quote_forward {
+    ($($method:ident($($arg:ident: $ty:ty),*),)*) => {
+        $(
+            fn $method(self, $($arg: $ty),*) -> std::result::Result<S::Ok, S::Error> {
+                self.0.$method($($arg),*)
+            }
+        )*
+    };
+}
+
+impl<S: serde::Serializer> serde::Serializer for Quote<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+    type SerializeSeq = Quote<S::SerializeSeq>;
+    type SerializeTuple = Quote<S::SerializeTuple>;
+    type SerializeTupleStruct = Quote<S::SerializeTupleStruct>;
+    type SerializeTupleVariant = Quote<S::SerializeTupleVariant>;
+    type SerializeMap = Quote<S::SerializeMap>;
+    type SerializeStruct = Quote<S::SerializeStruct>;
+    type SerializeStructVariant = Quote<S::SerializeStructVariant>;
+
+    quote_forward! {
+        serialize_bool(v: bool),
+        serialize_i8(v: i8),
+        serialize_i16(v: i16),
+        serialize_i32(v: i32),
+        serialize_u8(v: u8),
+        serialize_u16(v: u16),
+        serialize_u32(v: u32),
+        serialize_f32(v: f32),
+        serialize_f64(v: f64),
+        serialize_char(v: char),
+        serialize_str(v: &str),
+        serialize_bytes(v: &[u8]),
+        serialize_none(),
+        serialize_unit(),
+        serialize_unit_struct(name: &'static str),
+        serialize_unit_variant(name: &'static str, index: u32, variant: &'static str),
+    }
+
+    fn serialize_i64(self, v: i64) -> std::result::Result<S::Ok, S::Error> {
+        if v.unsigned_abs() > MAX_SAFE_INTEGER {
+            return self.0.serialize_str(&v.to_string());
+        }
+        self.0.serialize_i64(v)
+    }
+
+    fn serialize_u64(self, v: u64) -> std::result::Result<S::Ok, S::Error> {
+        if v > MAX_SAFE_INTEGER {
+            return self.0.serialize_str(&v.to_string());
+        }
+        self.0.serialize_u64(v)
+    }
+
+    fn serialize_i128(self, v: i128) -> std::result::Result<S::Ok, S::Error> {
+        if v.unsigned_abs() > u128::from(MAX_SAFE_INTEGER) {
+            return self.0.serialize_str(&v.to_string());
+        }
+        self.0.serialize_i128(v)
+    }
+
+    fn serialize_u128(self, v: u128) -> std::result::Result<S::Ok, S::Error> {
+        if v > u128::from(MAX_SAFE_INTEGER) {
+            return self.0.serialize_str(&v.to_string());
+        }
+        self.0.serialize_u128(v)
+    }
+
+    fn serialize_some<T>(self, value: &T) -> std::result::Result<S::Ok, S::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.0.serialize_some(&QuotedInts(value))
     }
+
+    fn serialize_newtype_struct<T>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> std::result::Result<S::Ok, S::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.0.serialize_newtype_struct(name, &QuotedInts(value))
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> std::result::Result<S::Ok, S::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.0
+            .serialize_newtype_variant(name, index, variant, &QuotedInts(value))
+    }
+
+    fn serialize_seq(
+        self,
+        len: Option<usize>,
+    ) -> std::result::Result<Self::SerializeSeq, S::Error> {
+        self.0.serialize_seq(len).map(Quote)
+    }
+
+    fn serialize_tuple(self, len: usize) -> std::result::Result<Self::SerializeTuple, S::Error> {
+        self.0.serialize_tuple(len).map(Quote)
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        name: &'static str,
+        len: usize,
+    ) -> std::result::Result<Self::SerializeTupleStruct, S::Error> {
+        self.0.serialize_tuple_struct(name, len).map(Quote)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> std::result::Result<Self::SerializeTupleVariant, S::Error> {
+        self.0
+            .serialize_tuple_variant(name, index, variant, len)
+            .map(Quote)
+    }
+
+    fn serialize_map(
+        self,
+        len: Option<usize>,
+    ) -> std::result::Result<Self::SerializeMap, S::Error> {
+        self.0.serialize_map(len).map(Quote)
+    }
+
+    fn serialize_struct(
+        self,
+        name: &'static str,
+        len: usize,
+    ) -> std::result::Result<Self::SerializeStruct, S::Error> {
+        self.0.serialize_struct(name, len).map(Quote)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> std::result::Result<Self::SerializeStructVariant, S::Error> {
+        self.0
+            .serialize_struct_variant(name, index, variant, len)
+            .map(Quote)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.0.is_human_readable()
+    }
+}
+
+impl<S: ser::SerializeSeq> ser::SerializeSeq for Quote<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> std::result::Result<(), S::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.0.serialize_element(&QuotedInts(value))
+    }
+
+    fn end(self) -> std::result::Result<S::Ok, S::Error> {
+        self.0.end()
+    }
+}
+
+impl<S: ser::SerializeTuple> ser::SerializeTuple for Quote<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> std::result::Result<(), S::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.0.serialize_element(&QuotedInts(value))
+    }
+
+    fn end(self) -> std::result::Result<S::Ok, S::Error> {
+        self.0.end()
+    }
+}
+
+impl<S: ser::SerializeTupleStruct> ser::SerializeTupleStruct for Quote<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> std::result::Result<(), S::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.0.serialize_field(&QuotedInts(value))
+    }
+
+    fn end(self) -> std::result::Result<S::Ok, S::Error> {
+        self.0.end()
+    }
+}
+
+impl<S: ser::SerializeTupleVariant> ser::SerializeTupleVariant for Quote<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> std::result::Result<(), S::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.0.serialize_field(&QuotedInts(value))
+    }
+
+    fn end(self) -> std::result::Result<S::Ok, S::Error> {
+        self.0.end()
+    }
+}
+
+impl<S: ser::SerializeMap> ser::SerializeMap for Quote<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_key<T>(&mut self, key: &T) -> std::result::Result<(), S::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.0.serialize_key(&QuotedInts(key))
+    }
+
+    fn serialize_value<T>(&mut self, value: &T) -> std::result::Result<(), S::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.0.serialize_value(&QuotedInts(value))
+    }
+
+    fn end(self) -> std::result::Result<S::Ok, S::Error> {
+        self.0.end()
+    }
+}
+
+impl<S: ser::SerializeStruct> ser::SerializeStruct for Quote<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_field<T>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> std::result::Result<(), S::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.0.serialize_field(key, &QuotedInts(value))
+    }
+
+    fn skip_field(&mut self, key: &'static str) -> std::result::Result<(), S::Error> {
+        self.0.skip_field(key)
+    }
+
+    fn end(self) -> std::result::Result<S::Ok, S::Error> {
+        self.0.end()
+    }
+}
+
+impl<S: ser::SerializeStructVariant> ser::SerializeStructVariant for Quote<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_field<T>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> std::result::Result<(), S::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.0.serialize_field(key, &QuotedInts(value))
+    }
+
+    fn skip_field(&mut self, key: &'static str) -> std::result::Result<(), S::Error> {
+        self.0.skip_field(key)
+    }
+
+    fn end(self) -> std::result::Result<S::Ok, S::Error> {
+        self.0.end()
+    }
+}
+
+/// Reads what the seed it wraps reads through [`Unquote`].
+struct Unquoted<S>(S);
+
+impl<'de, S: DeserializeSeed<'de>> DeserializeSeed<'de> for Unquoted<S> {
+    type Value = S::Value;
+
+    fn deserialize<D>(self, deserializer: D) -> std::result::Result<S::Value, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        self.0.deserialize(Unquote(deserializer))
+    }
+}
+
+/// Undoes [`Quote`] on the way in: wherever the type being read asks for a
+/// `u64`, `i64`, `u128`, or `i128`, a string of digits is accepted as well
+/// as a number. Every other request, including strings, passes through
+/// untouched. Wraps the seq, map, and enum accesses it hands out too, so
+/// nested fields are covered.
+struct Unquote<T>(T);
+
+macro_rules! unquote_forward {
+    ($($method:ident,)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> std::result::Result<V::Value, D::Error> {
+                self.0.$method(Unquoting(visitor))
+            }
+        )*
+    };
+}
+
+impl<'de, D: serde::Deserializer<'de>> serde::Deserializer<'de> for Unquote<D> {
+    type Error = D::Error;
+
+    unquote_forward! {
+        deserialize_any,
+        deserialize_bool,
+        deserialize_i8,
+        deserialize_i16,
+        deserialize_i32,
+        deserialize_u8,
+        deserialize_u16,
+        deserialize_u32,
+        deserialize_f32,
+        deserialize_f64,
+        deserialize_char,
+        deserialize_str,
+        deserialize_string,
+        deserialize_bytes,
+        deserialize_byte_buf,
+        deserialize_option,
+        deserialize_unit,
+        deserialize_seq,
+        deserialize_map,
+        deserialize_identifier,
+        deserialize_ignored_any,
+    }
+
+    // a number that fits in 64 bits is read exactly by deserialize_any, and
+    // a string comes back as one for IntVisitor to parse
+    fn deserialize_i64<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> std::result::Result<V::Value, D::Error> {
+        self.0.deserialize_any(IntVisitor(visitor))
+    }
+
+    fn deserialize_u64<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> std::result::Result<V::Value, D::Error> {
+        self.0.deserialize_any(IntVisitor(visitor))
+    }
+
+    // deserialize_any would read a number past 64 bits as a float, so these
+    // take the raw token instead
+    fn deserialize_i128<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> std::result::Result<V::Value, D::Error> {
+        let raw = Box::<serde_json::value::RawValue>::deserialize(self.0)?;
+        match unquote_digits(raw.get()).parse() {
+            Ok(n) => visitor.visit_i128(n),
+            Err(_) => Err(de::Error::invalid_value(
+                de::Unexpected::Other(raw.get()),
+                &visitor,
+            )),
+        }
+    }
+
+    fn deserialize_u128<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> std::result::Result<V::Value, D::Error> {
+        let raw = Box::<serde_json::value::RawValue>::deserialize(self.0)?;
+        match unquote_digits(raw.get()).parse() {
+            Ok(n) => visitor.visit_u128(n),
+            Err(_) => Err(de::Error::invalid_value(
+                de::Unexpected::Other(raw.get()),
+                &visitor,
+            )),
+        }
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> std::result::Result<V::Value, D::Error> {
+        self.0.deserialize_unit_struct(name, Unquoting(visitor))
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> std::result::Result<V::Value, D::Error> {
+        self.0.deserialize_newtype_struct(name, Unquoting(visitor))
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        len: usize,
+        visitor: V,
+    ) -> std::result::Result<V::Value, D::Error> {
+        self.0.deserialize_tuple(len, Unquoting(visitor))
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> std::result::Result<V::Value, D::Error> {
+        self.0
+            .deserialize_tuple_struct(name, len, Unquoting(visitor))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> std::result::Result<V::Value, D::Error> {
+        self.0.deserialize_struct(name, fields, Unquoting(visitor))
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> std::result::Result<V::Value, D::Error> {
+        self.0.deserialize_enum(name, variants, Unquoting(visitor))
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.0.is_human_readable()
+    }
+}
+
+/// A raw JSON token with the quotes taken off if it's a string.
+fn unquote_digits(raw: &str) -> &str {
+    raw.strip_prefix('"')
+        .and_then(|digits| digits.strip_suffix('"'))
+        .unwrap_or(raw)
+}
+
+impl<'de, A: SeqAccess<'de>> SeqAccess<'de> for Unquote<A> {
+    type Error = A::Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> std::result::Result<Option<T::Value>, A::Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        self.0.next_element_seed(Unquoted(seed))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.0.size_hint()
+    }
+}
+
+impl<'de, A: MapAccess<'de>> MapAccess<'de> for Unquote<A> {
+    type Error = A::Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> std::result::Result<Option<K::Value>, A::Error>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        self.0.next_key_seed(Unquoted(seed))
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> std::result::Result<V::Value, A::Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        self.0.next_value_seed(Unquoted(seed))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.0.size_hint()
+    }
+}
+
+impl<'de, A: de::EnumAccess<'de>> de::EnumAccess<'de> for Unquote<A> {
+    type Error = A::Error;
+    type Variant = Unquote<A::Variant>;
+
+    fn variant_seed<V>(self, seed: V) -> std::result::Result<(V::Value, Self::Variant), A::Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        self.0
+            .variant_seed(Unquoted(seed))
+            .map(|(value, variant)| (value, Unquote(variant)))
+    }
+}
+
+impl<'de, A: de::VariantAccess<'de>> de::VariantAccess<'de> for Unquote<A> {
+    type Error = A::Error;
+
+    fn unit_variant(self) -> std::result::Result<(), A::Error> {
+        self.0.unit_variant()
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> std::result::Result<T::Value, A::Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        self.0.newtype_variant_seed(Unquoted(seed))
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(
+        self,
+        len: usize,
+        visitor: V,
+    ) -> std::result::Result<V::Value, A::Error> {
+        self.0.tuple_variant(len, Unquoting(visitor))
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> std::result::Result<V::Value, A::Error> {
+        self.0.struct_variant(fields, Unquoting(visitor))
+    }
+}
+
+/// Hands everything on to the visitor it wraps, putting [`Unquote`] around
+/// whatever can hold nested values.
+struct Unquoting<V>(V);
+
+macro_rules! unquoting_forward {
+    ($($method:ident($ty:ty),)*) => {
+        $(
+            fn $method<E: de::Error>(self, v: $ty) -> std::result::Result<V::Value, E> {
+                self.0.$method(v)
+            }
+        )*
+    };
+}
+
+impl<'de, V: Visitor<'de>> Visitor<'de> for Unquoting<V> {
+    type Value = V::Value;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.expecting(f)
+    }
+
+    unquoting_forward! {
+        visit_bool(bool),
+        visit_i8(i8),
+        visit_i16(i16),
+        visit_i32(i32),
+        visit_i64(i64),
+        visit_i128(i128),
+        visit_u8(u8),
+        visit_u16(u16),
+        visit_u32(u32),
+        visit_u64(u64),
+        visit_u128(u128),
+        visit_f32(f32),
+        visit_f64(f64),
+        visit_char(char),
+        visit_str(&str),
+        visit_borrowed_str(&'de str),
+        visit_string(String),
+        visit_bytes(&[u8]),
+        visit_borrowed_bytes(&'de [u8]),
+        visit_byte_buf(Vec<u8>),
+    }
+
+    fn visit_none<E: de::Error>(self) -> std::result::Result<V::Value, E> {
+        self.0.visit_none()
+    }
+
+    fn visit_unit<E: de::Error>(self) -> std::result::Result<V::Value, E> {
+        self.0.visit_unit()
+    }
+
+    fn visit_some<D>(self, deserializer: D) -> std::result::Result<V::Value, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        self.0.visit_some(Unquote(deserializer))
+    }
+
+    fn visit_newtype_struct<D>(self, deserializer: D) -> std::result::Result<V::Value, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        self.0.visit_newtype_struct(Unquote(deserializer))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, access: A) -> std::result::Result<V::Value, A::Error> {
+        self.0.visit_seq(Unquote(access))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, access: A) -> std::result::Result<V::Value, A::Error> {
+        self.0.visit_map(Unquote(access))
+    }
+
+    fn visit_enum<A>(self, access: A) -> std::result::Result<V::Value, A::Error>
+    where
+        A: de::EnumAccess<'de>,
+    {
+        self.0.visit_enum(Unquote(access))
+    }
+}
+
+/// Wraps the visitor of a 64-bit integer request: numbers go straight
+/// through, and a string is parsed as the integer it spells.
+struct IntVisitor<V>(V);
+
+impl<'de, V: Visitor<'de>> Visitor<'de> for IntVisitor<V> {
+    type Value = V::Value;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.expecting(f)
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> std::result::Result<V::Value, E> {
+        self.0.visit_i64(v)
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> std::result::Result<V::Value, E> {
+        self.0.visit_u64(v)
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> std::result::Result<V::Value, E> {
+        self.0.visit_f64(v)
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> std::result::Result<V::Value, E> {
+        if let Ok(n) = v.parse::<u64>() {
+            self.0.visit_u64(n)
+        } else if let Ok(n) = v.parse::<i64>() {
+            self.0.visit_i64(n)
+        } else {
+            self.0.visit_str(v)
+        }
+    }
+}
+
+// ---- key type check ----------------------------------------------------------
+
+/// What [`KeyShape`] found out about a key type. Returned as the error, since
+/// the probe never produces a value.
+#[derive(Debug)]
+enum KeyProbe {
+    /// A shape serde_json writes as an object key, or one the probe can't
+    /// judge.
+    Fits,
+    /// A shape serde_json refuses as an object key.
+    Unfit(&'static str),
+}
+
+impl std::fmt::Display for KeyProbe {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Fits => f.write_str("usable as an object key"),
+            Self::Unfit(shape) => write!(f, "{shape} can't be an object key"),
+        }
+    }
+}
+
+impl std::error::Error for KeyProbe {}
+
+impl de::Error for KeyProbe {
+    fn custom<T: std::fmt::Display>(_msg: T) -> Self {
+        Self::Fits
+    }
+}
+
+/// A deserializer that answers every request with a verdict on whether that
+/// shape can be a JSON object key, for [`JsonSerializer::check_key_type`].
+struct KeyShape;
+
+macro_rules! key_shape {
+    ($($method:ident => $verdict:expr,)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, _visitor: V) -> std::result::Result<V::Value, KeyProbe> {
+                Err($verdict)
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for KeyShape {
+    type Error = KeyProbe;
+
+    key_shape! {
+        deserialize_any => KeyProbe::Fits,
+        deserialize_bool => KeyProbe::Fits,
+        deserialize_i8 => KeyProbe::Fits,
+        deserialize_i16 => KeyProbe::Fits,
+        deserialize_i32 => KeyProbe::Fits,
+        deserialize_i64 => KeyProbe::Fits,
+        deserialize_i128 => KeyProbe::Fits,
+        deserialize_u8 => KeyProbe::Fits,
+        deserialize_u16 => KeyProbe::Fits,
+        deserialize_u32 => KeyProbe::Fits,
+        deserialize_u64 => KeyProbe::Fits,
+        deserialize_u128 => KeyProbe::Fits,
+        deserialize_f32 => KeyProbe::Fits,
+        deserialize_f64 => KeyProbe::Fits,
+        deserialize_char => KeyProbe::Fits,
+        deserialize_str => KeyProbe::Fits,
+        deserialize_string => KeyProbe::Fits,
+        deserialize_identifier => KeyProbe::Fits,
+        deserialize_ignored_any => KeyProbe::Fits,
+        deserialize_bytes => KeyProbe::Unfit("bytes"),
+        deserialize_byte_buf => KeyProbe::Unfit("bytes"),
+        deserialize_option => KeyProbe::Unfit("an Option"),
+        deserialize_unit => KeyProbe::Unfit("()"),
+        deserialize_seq => KeyProbe::Unfit("a sequence"),
+        deserialize_map => KeyProbe::Unfit("a map"),
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _visitor: V,
+    ) -> std::result::Result<V::Value, KeyProbe> {
+        Err(KeyProbe::Unfit("a unit struct"))
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> std::result::Result<V::Value, KeyProbe> {
+        // a newtype key is written as whatever it wraps
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        _visitor: V,
+    ) -> std::result::Result<V::Value, KeyProbe> {
+        Err(KeyProbe::Unfit("a tuple"))
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        _visitor: V,
+    ) -> std::result::Result<V::Value, KeyProbe> {
+        Err(KeyProbe::Unfit("a tuple struct"))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        _visitor: V,
+    ) -> std::result::Result<V::Value, KeyProbe> {
+        Err(KeyProbe::Unfit("a struct"))
+    }
+
+    // unit variants are written as their names; other variants only fail
+    // when a key actually holds one
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        _visitor: V,
+    ) -> std::result::Result<V::Value, KeyProbe> {
+        Err(KeyProbe::Fits)
+    }
+}
+
+// ---- value transforms --------------------------------------------------------
+
+/// A rewrite applied to the whole document as a [`serde_json::Value`].
+pub type ValueTransform = std::sync::Arc<dyn Fn(Value) -> Value + Send + Sync>;
+
+/// Runs the document through a [`serde_json::Value`] rewrite on the way to
+/// and from disk, around an inner [`JsonSerializer`].
+///
+/// Use it when the file format is set by someone else — renaming fields to
+/// camelCase, moving things around, dropping legacy keys — and you'd rather
+/// not bend your Rust types to match. `to_disk` sees the map as a JSON object
+/// just before it's written; `from_disk` sees what was read, before it's
+/// turned back into `K`/`V`. The two should be inverses.
+///
+/// Everything passes through an intermediate `Value`, so this is slower and
+/// hungrier than plain [`JsonSerializer`]. Keys must serialize as strings.
+#[derive(Clone)]
+pub struct Transformed {
+    inner: JsonSerializer,
+    to_disk: ValueTransform,
+    from_disk: ValueTransform,
+}
+
+impl Transformed {
+    /// Wrap `inner`, rewriting with `to_disk` when writing and `from_disk`
+    /// when reading.
+    pub fn new<W, R>(inner: JsonSerializer, to_disk: W, from_disk: R) -> Self
+    where
+        W: Fn(Value) -> Value + Send + Sync + 'static,
+        R: Fn(Value) -> Value + Send + Sync + 'static,
+    {
+        Self {
+            inner,
+            to_disk: std::sync::Arc::new(to_disk),
+            from_disk: std::sync::Arc::new(from_disk),
+        }
+    }
+}
+
+impl Serializer for Transformed {
+    fn serialize<K, V>(&self, data: &HashMap<K, V>) -> Result<Vec<u8>>
+    where
+        K: Serialize,
+        V: Serialize,
+    {
+        let mut buf = Vec::new();
+        self.serialize_to(data, &mut buf)?;
+        Ok(buf)
+    }
+
+    fn serialize_to<K, V, W>(&self, data: &HashMap<K, V>, writer: W) -> Result<()>
+    where
+        K: Serialize,
+        V: Serialize,
+        W: Write,
+    {
+        let value = (self.to_disk)(serde_json::to_value(data)?);
+        self.inner.encode_to(&value, writer)
+    }
+
+    fn deserialize<K, V>(&self, bytes: &[u8]) -> Result<HashMap<K, V>>
+    where
+        K: for<'de> Deserialize<'de> + Eq + std::hash::Hash,
+        V: for<'de> Deserialize<'de>,
+    {
+        let raw: HashMap<String, Value> = self.inner.deserialize(bytes)?;
+        let value = (self.from_disk)(Value::Object(raw.into_iter().collect()));
+        self.inner.read_value(value)
+    }
+
+    fn sniff(&self, bytes: &[u8]) -> bool {
+        self.inner.sniff(bytes)
+    }
+
+    fn reads_json(&self) -> bool {
+        true
+    }
+
+    fn deserialize_value<'de, D, V>(&self, deserializer: D) -> std::result::Result<V, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+        V: Deserialize<'de>,
+    {
+        self.inner.deserialize_value(deserializer)
+    }
+
+    fn check_keys<K>(&self) -> Result<()>
+    where
+        K: for<'de> Deserialize<'de>,
+    {
+        JsonSerializer::check_key_type::<K>()
+    }
+}
+
+// ---- format detection --------------------------------------------------------
+
+/// Writes with `primary`, reads whichever of `primary` or `fallback` the file
+/// looks like.
+///
+/// Meant for migrations where old files in one format sit next to new ones in
+/// another: every file opens, and each flush moves it to `primary`'s format.
+/// Reading asks each serializer's [`sniff`](Serializer::sniff) and tries the
+/// ones that claim the bytes, `primary` first. Nest to accept more formats:
+/// `AutoSerializer::new(a, AutoSerializer::new(b, c))`.
+#[derive(Clone, Default)]
+pub struct AutoSerializer<P, F> {
+    primary: P,
+    fallback: F,
+}
+
+impl<P: Serializer, F: Serializer> AutoSerializer<P, F> {
+    /// Write with `primary`; also read files `fallback` recognizes.
+    pub fn new(primary: P, fallback: F) -> Self {
+        Self { primary, fallback }
+    }
+}
+
+impl<P: Serializer, F: Serializer> Serializer for AutoSerializer<P, F> {
+    fn serialize<K, V>(&self, data: &HashMap<K, V>) -> Result<Vec<u8>>
+    where
+        K: Serialize,
+        V: Serialize,
+    {
+        self.primary.serialize(data)
+    }
+
+    fn serialize_to<K, V, W>(&self, data: &HashMap<K, V>, writer: W) -> Result<()>
+    where
+        K: Serialize,
+        V: Serialize,
+        W: Write,
+    {
+        self.primary.serialize_to(data, writer)
+    }
+
+    fn deserialize<K, V>(&self, bytes: &[u8]) -> Result<HashMap<K, V>>
+    where
+        K: for<'de> Deserialize<'de> + Eq + std::hash::Hash,
+        V: for<'de> Deserialize<'de>,
+    {
+        let first = if self.primary.sniff(bytes) {
+            match self.primary.deserialize(bytes) {
+                Ok(map) => return Ok(map),
+                Err(e) => Some(e),
+            }
+        } else {
+            None
+        };
+        if self.fallback.sniff(bytes) {
+            match self.fallback.deserialize(bytes) {
+                Ok(map) => return Ok(map),
+                // the primary's complaint is usually the more useful one
+                Err(e) => return Err(first.unwrap_or(e)),
+            }
+        }
+        match first {
+            Some(e) => Err(e),
+            None => self.primary.deserialize(bytes),
+        }
+    }
+
+    fn sniff(&self, bytes: &[u8]) -> bool {
+        self.primary.sniff(bytes) || self.fallback.sniff(bytes)
+    }
+
+    fn reads_json(&self) -> bool {
+        self.primary.reads_json() && self.fallback.reads_json()
+    }
+
+    fn deserialize_value<'de, D, V>(&self, deserializer: D) -> std::result::Result<V, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+        V: Deserialize<'de>,
+    {
+        self.primary.deserialize_value(deserializer)
+    }
+
+    fn check_keys<K>(&self) -> Result<()>
+    where
+        K: for<'de> Deserialize<'de>,
+    {
+        self.primary.check_keys::<K>()
+    }
+}
+
+// ---- runtime swapping --------------------------------------------------------
+
+/// Object-safe face of [`Serializer`], so serializers can sit behind
+/// `Arc<dyn DynSerializer>` and be picked at runtime.
+///
+/// `Serializer`'s methods are generic over the key and value types, which
+/// rules out `dyn Serializer`. This trait erases them instead: the map goes
+/// through `serde_json::Value`, the same way [`Transformed`] does, so keys
+/// must serialize as strings. Every `Serializer` implements it for free.
+pub trait DynSerializer: Send + Sync {
+    /// Encode a map whose values were already turned into JSON values.
+    fn serialize_erased(&self, data: &HashMap<String, Value>) -> Result<Vec<u8>>;
+
+    /// Decode bytes into a map of JSON values.
+    fn deserialize_erased(&self, bytes: &[u8]) -> Result<HashMap<String, Value>>;
+
+    /// [`Serializer::sniff`].
+    fn sniff_erased(&self, bytes: &[u8]) -> bool;
+
+    /// [`Serializer::reads_json`].
+    fn reads_json_erased(&self) -> bool;
+}
+
+impl<S: Serializer> DynSerializer for S {
+    fn serialize_erased(&self, data: &HashMap<String, Value>) -> Result<Vec<u8>> {
+        self.serialize(data)
+    }
+
+    fn deserialize_erased(&self, bytes: &[u8]) -> Result<HashMap<String, Value>> {
+        self.deserialize(bytes)
+    }
+
+    fn sniff_erased(&self, bytes: &[u8]) -> bool {
+        self.sniff(bytes)
+    }
+
+    fn reads_json_erased(&self) -> bool {
+        self.reads_json()
+    }
+}
+
+/// A serializer that can be replaced while the store is running — for
+/// moving a live store to a new format without a rebuild.
+///
+/// Writes use the current serializer; swap it with
+/// [`set`](Self::set) (or [`JsonSync::set_serializer`](crate::JsonSync::set_serializer))
+/// and the next flush uses the new one. Reads try the current serializer
+/// and then every one it replaced or that was added with
+/// [`read_also`](Self::read_also), asking each one's `sniff` first like
+/// [`AutoSerializer`], so files in any of the formats keep opening.
+///
+/// Data goes through `serde_json::Value` (see [`DynSerializer`]), so keys must
+/// serialize as strings and it costs more than a concrete serializer.
+pub struct SwappableSerializer {
+    current: parking_lot::RwLock<std::sync::Arc<dyn DynSerializer>>,
+    readers: parking_lot::RwLock<Vec<std::sync::Arc<dyn DynSerializer>>>,
+}
+
+impl SwappableSerializer {
+    /// Start out writing with `initial`.
+    pub fn new(initial: std::sync::Arc<dyn DynSerializer>) -> Self {
+        Self {
+            current: parking_lot::RwLock::new(initial),
+            readers: parking_lot::RwLock::new(Vec::new()),
+        }
+    }
+
+    /// Also accept files written by `reader` when loading.
+    pub fn read_also(self, reader: std::sync::Arc<dyn DynSerializer>) -> Self {
+        self.readers.write().push(reader);
+        self
+    }
+
+    /// Write with `next` from now on. The one it replaces stays available for
+    /// reading.
+    ///
+    /// Serializers are told apart by `Arc` identity: switching back and forth
+    /// between the same two `Arc`s keeps each listed once, but a fresh `Arc`
+    /// every call adds a reader every call.
+    pub fn set(&self, next: std::sync::Arc<dyn DynSerializer>) {
+        use std::sync::Arc;
+
+        let mut readers = self.readers.write();
+        let prev = std::mem::replace(&mut *self.current.write(), Arc::clone(&next));
+        readers.retain(|r| !Arc::ptr_eq(r, &prev) && !Arc::ptr_eq(r, &next));
+        if !Arc::ptr_eq(&prev, &next) {
+            readers.insert(0, prev);
+        }
+    }
+
+    /// Every serializer reads try, current first.
+    fn candidates(&self) -> Vec<std::sync::Arc<dyn DynSerializer>> {
+        let mut all = vec![std::sync::Arc::clone(&self.current.read())];
+        all.extend(self.readers.read().iter().cloned());
+        all
+    }
+}
+
+impl Default for SwappableSerializer {
+    fn default() -> Self {
+        Self::new(std::sync::Arc::new(JsonSerializer::new()))
+    }
+}
+
+impl Serializer for SwappableSerializer {
+    fn serialize<K, V>(&self, data: &HashMap<K, V>) -> Result<Vec<u8>>
+    where
+        K: Serialize,
+        V: Serialize,
+    {
+        let erased = match serde_json::to_value(data)? {
+            Value::Object(map) => map.into_iter().collect(),
+            _ => return Err(Error::Serialize("keys must serialize as strings".into())),
+        };
+        let current = std::sync::Arc::clone(&self.current.read());
+        current.serialize_erased(&erased)
+    }
+
+    fn deserialize<K, V>(&self, bytes: &[u8]) -> Result<HashMap<K, V>>
+    where
+        K: for<'de> Deserialize<'de> + Eq + std::hash::Hash,
+        V: for<'de> Deserialize<'de>,
+    {
+        let mut first = None;
+        for s in self.candidates() {
+            if !s.sniff_erased(bytes) {
+                continue;
+            }
+            match s.deserialize_erased(bytes) {
+                Ok(raw) => {
+                    let value = Value::Object(raw.into_iter().collect());
+                    return serde_json::from_value(value)
+                        .map_err(|e| Error::Deserialize(e.to_string()));
+                }
+                // the current serializer's complaint is usually the useful one
+                Err(e) => {
+                    first.get_or_insert(e);
+                }
+            }
+        }
+        Err(first.unwrap_or_else(|| {
+            Error::Deserialize("no registered serializer recognizes the file".into())
+        }))
+    }
+
+    fn sniff(&self, bytes: &[u8]) -> bool {
+        self.candidates().iter().any(|s| s.sniff_erased(bytes))
+    }
+
+    fn reads_json(&self) -> bool {
+        self.candidates().iter().all(|s| s.reads_json_erased())
+    }
+
+    fn check_keys<K>(&self) -> Result<()>
+    where
+        K: for<'de> Deserialize<'de>,
+    {
+        JsonSerializer::check_key_type::<K>()
+    }
+}
+
+// ---- gzip (feature-gated) ----------------------------------------------------
+
+/// The two bytes every gzip stream starts with.
+pub const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// `true` if `bytes` looks like a gzip stream.
+pub fn is_gzip(bytes: &[u8]) -> bool {
+    bytes.starts_with(&GZIP_MAGIC)
+}
+
+/// `reader` with its first `len` bytes read ahead into memory, so a magic
+/// number can be sniffed without losing them. Short reads are retried until
+/// there are `len` bytes or the stream ends; a single `read` or `fill_buf`
+/// may stop partway through the magic.
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+fn read_ahead<R: Read>(
+    mut reader: R,
+    len: usize,
+) -> std::io::Result<std::io::Chain<std::io::Cursor<Vec<u8>>, R>> {
+    let mut head = Vec::with_capacity(len);
+    (&mut reader).take(len as u64).read_to_end(&mut head)?;
+    Ok(std::io::Cursor::new(head).chain(reader))
+}
+
+/// Wraps another serializer and gzips its output.
+///
+/// Reading sniffs the gzip magic bytes and passes anything else straight to the
+/// inner serializer, so a store can load both old plain files and new
+/// compressed ones while you migrate. Writes are always compressed.
+#[cfg(feature = "gzip")]
+#[derive(Clone, Default)]
+pub struct Compressed<S> {
+    inner: S,
+    level: Option<u32>,
+}
+
+#[cfg(feature = "gzip")]
+impl<S: Serializer> Compressed<S> {
+    /// Compress `inner`'s output at the default level.
+    pub fn new(inner: S) -> Self {
+        Self { inner, level: None }
+    }
+
+    /// Compression level from 0 (none) to 9 (smallest, slowest).
+    pub fn level(mut self, level: u32) -> Self {
+        self.level = Some(level.min(9));
+        self
+    }
+}
+
+#[cfg(feature = "gzip")]
+impl<S: Serializer> Serializer for Compressed<S> {
+    fn serialize<K, V>(&self, data: &HashMap<K, V>) -> Result<Vec<u8>>
+    where
+        K: Serialize,
+        V: Serialize,
+    {
+        let mut buf = Vec::new();
+        self.serialize_to(data, &mut buf)?;
+        Ok(buf)
+    }
+
+    fn sniff(&self, bytes: &[u8]) -> bool {
+        is_gzip(bytes) || self.inner.sniff(bytes)
+    }
+
+    fn reads_json(&self) -> bool {
+        self.inner.reads_json()
+    }
+
+    fn deserialize_value<'de, D, V>(&self, deserializer: D) -> std::result::Result<V, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+        V: Deserialize<'de>,
+    {
+        self.inner.deserialize_value(deserializer)
+    }
+
+    fn check_keys<K>(&self) -> Result<()>
+    where
+        K: for<'de> Deserialize<'de>,
+    {
+        self.inner.check_keys::<K>()
+    }
+
+    fn serialize_to<K, V, W>(&self, data: &HashMap<K, V>, writer: W) -> Result<()>
+    where
+        K: Serialize,
+        V: Serialize,
+        W: Write,
+    {
+        let level = self.level.map(flate2::Compression::new).unwrap_or_default();
+        let mut enc = flate2::write::GzEncoder::new(writer, level);
+        self.inner.serialize_to(data, &mut enc)?;
+        enc.finish()?;
+        Ok(())
+    }
+
+    fn deserialize<K, V>(&self, bytes: &[u8]) -> Result<HashMap<K, V>>
+    where
+        K: for<'de> Deserialize<'de> + Eq + std::hash::Hash,
+        V: for<'de> Deserialize<'de>,
+    {
+        if is_gzip(bytes) {
+            self.inner.deserialize(&gunzip(bytes)?)
+        } else {
+            self.inner.deserialize(bytes)
+        }
+    }
+
+    fn deserialize_from<K, V, R>(&self, reader: R) -> Result<HashMap<K, V>>
+    where
+        K: for<'de> Deserialize<'de> + Eq + std::hash::Hash,
+        V: for<'de> Deserialize<'de>,
+        R: Read,
+    {
+        let reader = read_ahead(reader, GZIP_MAGIC.len())?;
+        if is_gzip(reader.get_ref().0.get_ref()) {
+            self.inner
+                .deserialize_from(flate2::read::GzDecoder::new(reader))
+        } else {
+            self.inner.deserialize_from(reader)
+        }
+    }
+}
+
+/// Decompress a whole gzip stream.
+#[cfg(feature = "gzip")]
+pub(crate) fn gunzip(bytes: &[u8]) -> Result<Vec<u8>> {
+    use std::io::Read;
+
+    let mut out = Vec::new();
+    flate2::read::GzDecoder::new(bytes)
+        .read_to_end(&mut out)
+        .map_err(|e| Error::Deserialize(format!("bad gzip stream: {e}")))?;
+    Ok(out)
+}
+
+// ---- zstd (feature-gated) ----------------------------------------------------
+
+/// The four bytes every zstd frame starts with.
+pub const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// `true` if `bytes` looks like a zstd frame.
+pub fn is_zstd(bytes: &[u8]) -> bool {
+    bytes.starts_with(&ZSTD_MAGIC)
+}
+
+/// Wraps another serializer and compresses its output with zstd — usually
+/// smaller and faster than gzip on large JSON.
+///
+/// Like the gzip `Compressed`, reading sniffs the magic bytes and hands
+/// anything else straight to the inner serializer, so plain files still load.
+#[cfg(feature = "zstd")]
+#[derive(Clone, Default)]
+pub struct Zstd<S> {
+    inner: S,
+    level: Option<i32>,
+}
+
+#[cfg(feature = "zstd")]
+impl<S: Serializer> Zstd<S> {
+    /// Compress `inner`'s output at zstd's default level (3).
+    pub fn new(inner: S) -> Self {
+        Self { inner, level: None }
+    }
+
+    /// Compression level from 1 (fastest) to 19 (smallest); values outside
+    /// that range are clamped.
+    pub fn level(mut self, level: i32) -> Self {
+        self.level = Some(level.clamp(1, 19));
+        self
+    }
+}
+
+#[cfg(feature = "zstd")]
+impl<S: Serializer> Serializer for Zstd<S> {
+    fn serialize<K, V>(&self, data: &HashMap<K, V>) -> Result<Vec<u8>>
+    where
+        K: Serialize,
+        V: Serialize,
+    {
+        let mut buf = Vec::new();
+        self.serialize_to(data, &mut buf)?;
+        Ok(buf)
+    }
+
+    fn sniff(&self, bytes: &[u8]) -> bool {
+        is_zstd(bytes) || self.inner.sniff(bytes)
+    }
+
+    fn reads_json(&self) -> bool {
+        self.inner.reads_json()
+    }
+
+    fn deserialize_value<'de, D, V>(&self, deserializer: D) -> std::result::Result<V, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+        V: Deserialize<'de>,
+    {
+        self.inner.deserialize_value(deserializer)
+    }
+
+    fn check_keys<K>(&self) -> Result<()>
+    where
+        K: for<'de> Deserialize<'de>,
+    {
+        self.inner.check_keys::<K>()
+    }
+
+    fn serialize_to<K, V, W>(&self, data: &HashMap<K, V>, writer: W) -> Result<()>
+    where
+        K: Serialize,
+        V: Serialize,
+        W: Write,
+    {
+        let level = self.level.unwrap_or(zstd::DEFAULT_COMPRESSION_LEVEL);
+        let mut enc = zstd::Encoder::new(writer, level)?;
+        self.inner.serialize_to(data, &mut enc)?;
+        enc.finish()?;
+        Ok(())
+    }
+
+    fn deserialize<K, V>(&self, bytes: &[u8]) -> Result<HashMap<K, V>>
+    where
+        K: for<'de> Deserialize<'de> + Eq + std::hash::Hash,
+        V: for<'de> Deserialize<'de>,
+    {
+        if is_zstd(bytes) {
+            self.inner.deserialize(&unzstd(bytes)?)
+        } else {
+            self.inner.deserialize(bytes)
+        }
+    }
+
+    fn deserialize_from<K, V, R>(&self, reader: R) -> Result<HashMap<K, V>>
+    where
+        K: for<'de> Deserialize<'de> + Eq + std::hash::Hash,
+        V: for<'de> Deserialize<'de>,
+        R: Read,
+    {
+        let reader = read_ahead(reader, ZSTD_MAGIC.len())?;
+        if is_zstd(reader.get_ref().0.get_ref()) {
+            self.inner.deserialize_from(zstd::Decoder::new(reader)?)
+        } else {
+            self.inner.deserialize_from(reader)
+        }
+    }
+}
+
+/// Decompress a whole zstd stream.
+#[cfg(feature = "zstd")]
+pub(crate) fn unzstd(bytes: &[u8]) -> Result<Vec<u8>> {
+    zstd::decode_all(bytes).map_err(|e| Error::Deserialize(format!("bad zstd stream: {e}")))
 }
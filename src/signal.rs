@@ -0,0 +1,118 @@
+//! Flush stores when the process gets a termination signal (feature `signal`,
+//! Unix only). See
+//! [`JsonSyncBuilder::flush_on_signal`](crate::JsonSyncBuilder::flush_on_signal).
+//!
+//! Signal handling is process-wide, so this is too: one background thread
+//! (started by the first registration and never stopped) waits for every
+//! signal any store asked for. When one arrives it flushes every store that
+//! registered that signal, then lets the signal's default action run —
+//! usually terminating the process. Once a signal has been registered it stays
+//! routed through that thread for the rest of the process, even after the
+//! stores are gone; it just has nothing left to flush.
+
+use crate::error::{Error, Result};
+use signal_hook::iterator::{Handle, Signals};
+use std::sync::{Arc, Mutex};
+
+pub use signal_hook::consts::signal::{SIGHUP, SIGINT, SIGQUIT, SIGTERM};
+
+/// A signal number, like [`SIGTERM`].
+pub type Signal = std::os::raw::c_int;
+
+type Flusher = Arc<dyn Fn() + Send + Sync>;
+
+struct Entry {
+    id: u64,
+    signals: Vec<Signal>,
+    flush: Flusher,
+}
+
+struct Watcher {
+    handle: Handle,
+    entries: Arc<Mutex<Vec<Entry>>>,
+    next_id: u64,
+}
+
+static WATCHER: Mutex<Option<Watcher>> = Mutex::new(None);
+
+/// Keeps a store's flush registered until dropped.
+pub(crate) struct Registration {
+    id: u64,
+    signals: Vec<Signal>,
+}
+
+impl Registration {
+    /// The signals this registration listens for.
+    pub(crate) fn signals(&self) -> &[Signal] {
+        &self.signals
+    }
+}
+
+/// Run `flush` when any of `signals` arrives, before the signal's default
+/// action.
+pub(crate) fn register(signals: &[Signal], flush: Flusher) -> Result<Registration> {
+    let mut watcher = WATCHER.lock().unwrap_or_else(|e| e.into_inner());
+    let watcher = match &mut *watcher {
+        Some(w) => w,
+        empty => empty.insert(start()?),
+    };
+    for &sig in signals {
+        watcher
+            .handle
+            .add_signal(sig)
+            .map_err(|e| Error::Config(format!("can't handle signal {sig}: {e}")))?;
+    }
+    let id = watcher.next_id;
+    watcher.next_id += 1;
+    lock(&watcher.entries).push(Entry {
+        id,
+        signals: signals.to_vec(),
+        flush,
+    });
+    Ok(Registration {
+        id,
+        signals: signals.to_vec(),
+    })
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        let watcher = WATCHER.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(w) = &*watcher {
+            lock(&w.entries).retain(|e| e.id != self.id);
+        }
+    }
+}
+
+fn start() -> Result<Watcher> {
+    let mut signals = Signals::new(std::iter::empty::<Signal>())?;
+    let handle = signals.handle();
+    let entries: Arc<Mutex<Vec<Entry>>> = Arc::default();
+    let shared = Arc::clone(&entries);
+    std::thread::Builder::new()
+        .name("json-sync-signals".into())
+        .spawn(move || {
+            for sig in signals.forever() {
+                // clone the hooks out so a flush can't deadlock against a
+                // store registering or dropping on another thread
+                let due: Vec<Flusher> = lock(&shared)
+                    .iter()
+                    .filter(|e| e.signals.contains(&sig))
+                    .map(|e| Arc::clone(&e.flush))
+                    .collect();
+                for flush in due {
+                    flush();
+                }
+                let _ = signal_hook::low_level::emulate_default_handler(sig);
+            }
+        })?;
+    Ok(Watcher {
+        handle,
+        entries,
+        next_id: 0,
+    })
+}
+
+fn lock<T>(m: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    m.lock().unwrap_or_else(|e| e.into_inner())
+}
@@ -1,32 +1,84 @@
 //! Core store type, handle, and builder.
 
-use crate::backend::MapBackend;
-use crate::error::Result;
-use crate::flush::{AsyncFlushWorker, FlushPolicy};
-use crate::persist::{atomic_write, load};
-use crate::serializer::{JsonSerializer, Serializer};
+use crate::audit::AuditLog;
+use crate::backend::{ArcBackendExt, MapBackend};
+use crate::clock::{Clock, SystemClock};
+use crate::error::{Error, Result};
+use crate::flush::{
+    lower_thread_priority, AsyncFlushWorker, FlushPolicy, Nudges, OnFull, WorkerThread,
+};
+use crate::persist::{
+    atomic_write, atomic_write_direct, atomic_write_preallocated, atomic_write_with,
+    canonical_path, check_depth, check_file_path, decode, decompressed, extract_pointer, is_blank,
+    load, load_recovering, load_recovering_with, read_or_empty, resolve_symlinks, sidecar_path,
+    splice_pointer, validate_pointer, PersistTarget, DEFAULT_WRITE_BUFFER_SIZE,
+};
+use crate::serializer::{
+    DuplicatePolicy, DynSerializer, JsonSerializer, Serializer, SwappableSerializer, Transformed,
+};
 use serde::de::DeserializeOwned;
-use serde::Serialize;
-use std::collections::HashMap;
-use std::hash::Hash;
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::io::{Read, Write};
 use std::marker::PhantomData;
+use std::ops::RangeBounds;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 /// Persistent JSON-backed key-value store.
 ///
-/// Generic over key `K`, value `V`, and map backend `M`. Use [`open`](Self::open)
-/// for a quick start or [`builder`](Self::builder) for full control over flush
-/// policy, pretty-printing, etc.
+/// Generic over key `K`, value `V`, map backend `M`, and on-disk format `S`
+/// (compact or pretty JSON unless you pick another [`Serializer`]). Use
+/// [`open`](Self::open) for a quick start or [`builder`](Self::builder) for
+/// full control over flush policy, pretty-printing, etc.
 ///
 /// All operations are thread-safe — the concurrency guarantees come from
 /// whichever backend you pick.
-pub struct JsonSync<K, V, M> {
+///
+/// `V` can be `Box<serde_json::value::RawValue>` (with serde_json's
+/// `raw_value` feature) to hold pre-serialized JSON that's written back
+/// verbatim instead of being parsed and re-encoded.
+pub struct JsonSync<K, V, M, S = JsonSerializer> {
     pub(crate) map: Arc<M>,
-    pub(crate) path: PathBuf,
-    pub(crate) serializer: JsonSerializer,
+    pub(crate) persister: Arc<Persister<S>>,
     pub(crate) policy: FlushPolicy,
-    pub(crate) trigger: Option<Arc<std::sync::mpsc::SyncSender<()>>>,
+    pub(crate) worker_thread: WorkerThread,
+    pub(crate) park_worker_after: Duration,
+    pub(crate) grown: AtomicUsize,
+    pub(crate) version: AtomicU64,
+    pub(crate) audit: Option<AuditLog>,
+    pub(crate) fingerprint: parking_lot::Mutex<Option<Fingerprint>>,
+    pub(crate) loader: Option<Loader<K, V>>,
+    pub(crate) index: Option<Index<K, V>>,
+    pub(crate) redactor: Option<Redactor<K>>,
+    pub(crate) max_value_bytes: Option<usize>,
+    pub(crate) max_snapshot_entries: Option<usize>,
+    pub(crate) max_memory_bytes: Option<usize>,
+    /// Running estimate of [`memory_usage`](Self::memory_usage) under
+    /// `max_memory_bytes`; 0 otherwise.
+    pub(crate) held: AtomicUsize,
+    pub(crate) sidecars: Sidecars<K>,
+    pub(crate) clock: Arc<dyn Clock>,
+    pub(crate) suspended: AtomicUsize,
+    pub(crate) sentinel: Option<PathBuf>,
+    pub(crate) unclean: bool,
+    pub(crate) created: bool,
+    pub(crate) deferred: parking_lot::Mutex<Option<usize>>,
+    /// Serializes [`update`](Self::update)s of the same key, so backends
+    /// without an atomic [`MapBackend::modify`] don't drop concurrent edits.
+    pub(crate) update_locks: [parking_lot::Mutex<()>; UPDATE_LOCK_STRIPES],
+    pub(crate) trigger: Option<Trigger>,
+    pub(crate) syncs: Arc<Syncs>,
+    /// After `trigger`, so a worker still here when the store drops sees its
+    /// channel close and exits.
+    pub(crate) worker: LazyWorker,
+    #[cfg(all(unix, feature = "signal", feature = "single-instance"))]
+    pub(crate) parked: parking_lot::Mutex<Parked>,
     pub(crate) _marker: PhantomData<(K, V)>,
 }
 
@@ -56,6 +108,84 @@ where
         Self::builder(path).policy(policy).build()
     }
 
+    /// Open a file written with an older value type, converting every value
+    /// with `f` on the way in. The file keeps its old shape until the first
+    /// flush rewrites it with the new values.
+    ///
+    /// ```rust,no_run
+    /// # use json_sync::JsonSync;
+    /// # use shardmap::ShardMap;
+    /// // values used to be bare strings, now they carry a count too
+    /// let db = JsonSync::<String, (String, u32), ShardMap<String, (String, u32)>>::open_migrating(
+    ///     "db.json",
+    ///     |old: String| (old, 0),
+    /// )
+    /// .unwrap();
+    /// db.flush().unwrap();
+    /// ```
+    pub fn open_migrating<OldV, F>(path: impl AsRef<Path>, f: F) -> Result<JsonSyncHandle<K, V, M>>
+    where
+        M: Default,
+        OldV: DeserializeOwned,
+        F: Fn(OldV) -> V,
+    {
+        let builder = Self::builder(path);
+        builder.serializer.check_keys::<K>()?;
+        let created = !builder.path.exists();
+        let old = load::<K, OldV, _>(&builder.path, &builder.serializer)?;
+        builder.build_from(
+            old.into_iter().map(|(k, v)| (k, f(v))),
+            created,
+            HashMap::new(),
+            HashMap::new(),
+            HashMap::new(),
+        )
+    }
+
+    /// Open a store that never touches the filesystem: its contents are read
+    /// once from `reader` and every flush goes to `target`. Meant for tests
+    /// that want to seed a store from bytes and assert on what it writes.
+    ///
+    /// Defaults apply as with [`open`](Self::open) (manual flushes, compact
+    /// JSON), an empty reader means an empty store, and [`path`](Self::path)
+    /// is empty. Gzip and zstd input is recognized with those features, as
+    /// it is from a file.
+    pub fn open_with_io(
+        mut reader: impl Read,
+        target: Arc<dyn PersistTarget>,
+    ) -> Result<JsonSyncHandle<K, V, M>>
+    where
+        M: Default,
+    {
+        let builder = Self::builder("").target(target);
+        builder.serializer.check_keys::<K>()?;
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        let data: HashMap<K, V> = decode(&bytes, &builder.serializer)?;
+        builder.build_from(
+            data,
+            bytes.is_empty(),
+            HashMap::new(),
+            HashMap::new(),
+            HashMap::new(),
+        )
+    }
+
+    /// Open `path` for reading only, through a memory map (feature `mmap`).
+    /// The file is parsed once into a map shared by every read; nothing can
+    /// be written through the result. Many processes can do this over the
+    /// same file and read it from the shared page cache. A missing file reads
+    /// as empty.
+    ///
+    /// Call [`ReadOnlyStore::refresh`](crate::readonly::ReadOnlyStore::refresh)
+    /// to pick up the writer's later flushes.
+    #[cfg(feature = "mmap")]
+    pub fn open_mmap_readonly(
+        path: impl AsRef<Path>,
+    ) -> Result<crate::readonly::ReadOnlyStore<K, V>> {
+        crate::readonly::ReadOnlyStore::open(path.as_ref())
+    }
+
     /// Start configuring a new store. Call [`.build()`](JsonSyncBuilder::build)
     /// when ready.
     pub fn builder(path: impl AsRef<Path>) -> JsonSyncBuilder<K, V, M>
@@ -64,26 +194,103 @@ where
     {
         JsonSyncBuilder::new(path)
     }
+}
 
+impl<K, V, M, S> JsonSync<K, V, M, S>
+where
+    K: Hash + Eq + Send + Sync + Clone + Serialize + DeserializeOwned + 'static,
+    V: Send + Sync + Clone + Serialize + DeserializeOwned + 'static,
+    M: MapBackend<K, V> + 'static,
+    S: Serializer + 'static,
+{
     // ---- reads ----
 
     /// Get the value for `key`, or `None` if absent.
     #[must_use]
     pub fn get(&self, key: &K) -> Option<V> {
+        self.parse_pending(key);
         self.map.get(key)
     }
 
+    /// Get a shared handle to the value for `key` without cloning the value
+    /// itself. Only available on backends that store values behind an `Arc`
+    /// (ShardMap) — see [`ArcBackendExt`].
+    #[must_use]
+    pub fn get_arc(&self, key: &K) -> Option<Arc<V>>
+    where
+        M: ArcBackendExt<K, V>,
+    {
+        self.parse_pending(key);
+        self.map.get_arc(key)
+    }
+
+    /// Run `f` on the value for `key` and return its result, or `None` if the
+    /// key is absent. Lets you pull one field out of a large value without
+    /// cloning the whole thing (on backends that support it). `f` runs while
+    /// the backend holds its read lock, so keep it short.
+    pub fn get_if<R, F>(&self, key: &K, f: F) -> Option<R>
+    where
+        F: FnOnce(&V) -> R,
+    {
+        self.parse_pending(key);
+        self.map.get_if(key, f)
+    }
+
     /// `true` if the key exists. Avoids cloning the value when the backend
     /// supports it.
     #[must_use]
     pub fn contains_key(&self, key: &K) -> bool {
+        self.parse_pending(key);
         self.map.contains_key(key)
     }
 
+    /// Every entry whose [`index`](JsonSyncBuilder::index)ed field equals
+    /// `ik`, in no particular order. Empty if the store has no index or `IK`
+    /// isn't the type its extractor returns.
+    ///
+    /// ```rust,no_run
+    /// # use json_sync::JsonSync;
+    /// # use shardmap::ShardMap;
+    /// # #[derive(Clone, serde::Serialize, serde::Deserialize)]
+    /// # struct User { email: String }
+    /// let users = JsonSync::<u64, User, ShardMap<u64, User>>::builder("users.json")
+    ///     .index(|u: &User| u.email.clone())
+    ///     .build()
+    ///     .unwrap();
+    /// let found = users.get_by_index(&"ada@example.com".to_string());
+    /// ```
+    #[must_use]
+    pub fn get_by_index<IK>(&self, ik: &IK) -> Vec<(K, V)>
+    where
+        IK: Hash + Eq + 'static,
+    {
+        let Some(index) = &self.index else {
+            return Vec::new();
+        };
+        let Some(extract) = index
+            .extract
+            .downcast_ref::<Arc<dyn Fn(&V) -> IK + Send + Sync>>()
+        else {
+            return Vec::new();
+        };
+        let keys: Vec<K> = index
+            .tables
+            .lock()
+            .by_hash
+            .get(&hash_of(ik))
+            .map_or_else(Vec::new, |keys| keys.iter().cloned().collect());
+        keys.into_iter()
+            .filter_map(|k| {
+                let v = self.map.get(&k)?;
+                (extract(&v) == *ik).then_some((k, v))
+            })
+            .collect()
+    }
+
     /// Number of entries.
     #[must_use]
     pub fn len(&self) -> usize {
-        self.map.map_len()
+        self.map.map_len() + self.pending_len()
     }
 
     /// `true` when the store has no entries.
@@ -92,78 +299,573 @@ where
         self.len() == 0
     }
 
+    /// Rough size of the store's contents: every key and value's length as
+    /// compact JSON, added up. Not the true heap footprint — it leaves out the
+    /// backend's own overhead and counts a number as its digits — but it
+    /// grows and shrinks with the data, which is what a cap needs. Costs a
+    /// pass over the store; see
+    /// [`max_memory_bytes`](JsonSyncBuilder::max_memory_bytes).
+    #[must_use]
+    pub fn memory_usage(&self) -> usize {
+        let _serial = self.persister.serial();
+        self.parse_all_pending();
+        total_size(self.map.as_ref())
+    }
+
     /// Snapshot of all key-value pairs.
     #[must_use]
     pub fn iter(&self) -> Vec<(K, V)> {
+        let _serial = self.persister.serial();
+        self.parse_all_pending();
         self.map.iter_snapshot().collect()
     }
 
     /// Snapshot of all keys.
     #[must_use]
     pub fn keys(&self) -> Vec<K> {
+        let _serial = self.persister.serial();
+        self.parse_all_pending();
         self.map.iter_snapshot().map(|(k, _)| k).collect()
     }
 
     /// Snapshot of all values.
     #[must_use]
     pub fn values(&self) -> Vec<V> {
+        let _serial = self.persister.serial();
+        self.parse_all_pending();
         self.map.iter_snapshot().map(|(_, v)| v).collect()
     }
 
+    /// [`iter`](Self::iter), unless the store holds more entries than the
+    /// builder's [`max_snapshot_entries`](JsonSyncBuilder::max_snapshot_entries)
+    /// allows, in which case it returns `Error::Config` pointing at
+    /// [`entries_iter`](Self::entries_iter). The count is checked before
+    /// anything is copied.
+    pub fn try_iter(&self) -> Result<Vec<(K, V)>> {
+        self.check_snapshot_size("entries_iter")?;
+        Ok(self.iter())
+    }
+
+    /// [`keys`](Self::keys) under the same cap as [`try_iter`](Self::try_iter).
+    pub fn try_keys(&self) -> Result<Vec<K>> {
+        self.check_snapshot_size("keys_iter")?;
+        Ok(self.keys())
+    }
+
+    /// [`values`](Self::values) under the same cap as
+    /// [`try_iter`](Self::try_iter).
+    pub fn try_values(&self) -> Result<Vec<V>> {
+        self.check_snapshot_size("values_iter")?;
+        Ok(self.values())
+    }
+
+    /// [`iter`](Self::iter) without collecting into a `Vec`: entries come out
+    /// as you pull them, so `.take(n)`, `.find()`, or `.any()` stop early.
+    ///
+    /// How much work an early stop saves depends on the backend. ShardMap
+    /// snapshots cheap `Arc`s up front and clones each value only when it's
+    /// reached; the `RwLock` backends and DashMap copy everything under their
+    /// lock first, so there the saving is just the caller's `Vec`. Under
+    /// [`serialized_writes`](JsonSyncBuilder::serialized_writes) every backend
+    /// copies up front, while it holds the store-wide lock.
+    pub fn entries_iter(&self) -> impl Iterator<Item = (K, V)> + '_ {
+        self.snapshot_iter()
+    }
+
+    /// [`keys`](Self::keys) as a lazy iterator; see
+    /// [`entries_iter`](Self::entries_iter).
+    pub fn keys_iter(&self) -> impl Iterator<Item = K> + '_ {
+        self.snapshot_iter().map(|(k, _)| k)
+    }
+
+    /// [`values`](Self::values) as a lazy iterator; see
+    /// [`entries_iter`](Self::entries_iter).
+    pub fn values_iter(&self) -> impl Iterator<Item = V> + '_ {
+        self.snapshot_iter().map(|(_, v)| v)
+    }
+
+    /// The backend's snapshot for the lazy iterators. The serial lock is
+    /// gone by the time the caller pulls from it, so when there is one the
+    /// entries are collected while it's held.
+    fn snapshot_iter(&self) -> Box<dyn Iterator<Item = (K, V)> + Send + '_> {
+        let serial = self.persister.serial();
+        self.parse_all_pending();
+        let entries = self.map.iter_snapshot();
+        match serial {
+            Some(_) => Box::new(entries.collect::<Vec<_>>().into_iter()),
+            None => entries,
+        }
+    }
+
+    /// Fold over every entry without cloning them into a `Vec` — or, on
+    /// backends that lend out references ([`MapBackend::for_each`]), without
+    /// cloning them at all. The order is unspecified.
+    ///
+    /// `f` may run under a backend read lock, so it mustn't write to the
+    /// store.
+    pub fn fold<B, F>(&self, init: B, mut f: F) -> B
+    where
+        F: FnMut(B, &K, &V) -> B,
+    {
+        let _serial = self.persister.serial();
+        self.parse_all_pending();
+        let mut acc = Some(init);
+        self.map.for_each(|k, v| {
+            let prev = acc.take().expect("accumulator is put back every step");
+            acc = Some(f(prev, k, v));
+        });
+        acc.expect("accumulator is put back every step")
+    }
+
+    /// Sum of all values; `V`'s zero for an empty store.
+    #[must_use]
+    pub fn sum_values(&self) -> V
+    where
+        V: std::iter::Sum,
+    {
+        self.values_iter().sum()
+    }
+
+    /// Largest value, or `None` if the store is empty. Only clones a value
+    /// when it beats the running maximum.
+    #[must_use]
+    pub fn max_value(&self) -> Option<V>
+    where
+        V: Ord,
+    {
+        self.fold(None, |best: Option<V>, _, v| match best {
+            Some(best) if best >= *v => Some(best),
+            _ => Some(v.clone()),
+        })
+    }
+
+    /// Entry with the smallest key. O(log n) on `RwLock<BTreeMap>`; hash
+    /// backends scan everything.
+    #[must_use]
+    pub fn first(&self) -> Option<(K, V)>
+    where
+        K: Ord,
+    {
+        self.parse_all_pending();
+        self.map.first()
+    }
+
+    /// Entry with the largest key. Same cost notes as [`first`](Self::first).
+    #[must_use]
+    pub fn last(&self) -> Option<(K, V)>
+    where
+        K: Ord,
+    {
+        self.parse_all_pending();
+        self.map.last()
+    }
+
+    /// Entries whose keys fall within `bounds`, sorted by key. Efficient on
+    /// `RwLock<BTreeMap>`; hash backends filter a full snapshot.
+    ///
+    /// ```rust,no_run
+    /// # use json_sync::JsonSync;
+    /// # use parking_lot::RwLock;
+    /// # use std::collections::BTreeMap;
+    /// let db = JsonSync::<u64, f64, RwLock<BTreeMap<u64, f64>>>::open("ts.json").unwrap();
+    /// let last_hour = db.range(1_700_000_000..1_700_003_600);
+    /// ```
+    #[must_use]
+    pub fn range<R>(&self, bounds: R) -> Vec<(K, V)>
+    where
+        K: Ord,
+        R: RangeBounds<K>,
+    {
+        let _serial = self.persister.serial();
+        self.parse_all_pending();
+        self.map.range(bounds)
+    }
+
+    /// The current contents in pages of at most `page_size` entries (in no
+    /// particular order). The snapshot is taken up front, so later mutations
+    /// don't show up in pages not yet yielded.
+    pub fn iter_paged(&self, page_size: usize) -> impl Iterator<Item = Vec<(K, V)>> {
+        let page_size = page_size.max(1);
+        let mut rest = self.iter().into_iter();
+        std::iter::from_fn(move || {
+            let page: Vec<_> = rest.by_ref().take(page_size).collect();
+            (!page.is_empty()).then_some(page)
+        })
+    }
+
+    /// Up to `limit` entries with keys after `after`, sorted by key — a
+    /// resumable cursor for paginating an ordered store. Pass the last key of
+    /// the previous page to continue; an empty result means you're done.
+    /// Cheap on `RwLock<BTreeMap>`; hash backends sort a full snapshot each
+    /// call.
+    #[must_use]
+    pub fn scan(&self, after: Option<&K>, limit: usize) -> Vec<(K, V)>
+    where
+        K: Ord,
+    {
+        let _serial = self.persister.serial();
+        self.parse_all_pending();
+        self.map.scan(after, limit)
+    }
+
+    /// Compare the current contents against `base` (say, what a remote last
+    /// saw) and sort the differences into added, removed, and changed keys.
+    /// Read-only; nothing is flushed or modified.
+    #[must_use]
+    pub fn changes_since(&self, base: &HashMap<K, V>) -> Changes<K, V>
+    where
+        V: PartialEq,
+    {
+        let _serial = self.persister.serial();
+        let mut changes = Changes {
+            added: Vec::new(),
+            removed: Vec::new(),
+            changed: Vec::new(),
+        };
+        let mut present = std::collections::HashSet::with_capacity(base.len());
+        self.parse_all_pending();
+        for (k, v) in self.map.iter_snapshot() {
+            match base.get(&k) {
+                None => changes.added.push((k, v)),
+                Some(old) => {
+                    present.insert(k.clone());
+                    if *old != v {
+                        changes.changed.push((k, v));
+                    }
+                }
+            }
+        }
+        changes.removed = base
+            .keys()
+            .filter(|k| !present.contains(*k))
+            .cloned()
+            .collect();
+        changes
+    }
+
+    /// Counter bumped by every mutating call — once per `insert`, `remove`,
+    /// `extend`, `apply`, etc., whatever the flush policy. Starts at 0 on
+    /// open and isn't persisted.
+    ///
+    /// Snapshot it before building a cached view and compare later: the same
+    /// number means nothing was written since. A change doesn't guarantee the
+    /// data differs (removing a missing key still counts).
+    #[must_use]
+    pub fn version(&self) -> u64 {
+        self.version.load(Ordering::Acquire)
+    }
+
+    /// `true` if [`detect_unclean_shutdown`](JsonSyncBuilder::detect_unclean_shutdown)
+    /// is on and the previous run's sentinel was still there at open — that
+    /// run crashed or leaked its handle, so unflushed writes may be missing.
+    /// Always `false` with the option off.
+    #[must_use]
+    pub fn was_unclean_shutdown(&self) -> bool {
+        self.unclean
+    }
+
+    /// `true` if there was no data file at open, so the store started empty
+    /// because this is its first run rather than because it was cleared. A
+    /// temp file promoted by [`recover_temp`](JsonSyncBuilder::recover_temp)
+    /// counts as an existing file. Use it to run one-time seeding.
+    ///
+    /// ```rust,no_run
+    /// # use json_sync::JsonSync;
+    /// # use shardmap::ShardMap;
+    /// let db = JsonSync::<String, i32, ShardMap<String, i32>>::open("db.json").unwrap();
+    /// if db.was_created() {
+    ///     db.insert("schema_version".into(), 1).unwrap();
+    ///     db.flush().unwrap();
+    /// }
+    /// ```
+    #[must_use]
+    pub fn was_created(&self) -> bool {
+        self.created
+    }
+
     /// Path to the backing JSON file.
     #[must_use]
     pub fn path(&self) -> &Path {
-        &self.path
+        &self.persister.path
+    }
+
+    /// The backend itself, for operations [`MapBackend`] doesn't cover
+    /// (DashMap's `alter_all`, say).
+    ///
+    /// Changes made through it go around the store: no flush policy fires,
+    /// nothing is audited, [`version`](Self::version) doesn't move, and TTLs,
+    /// tombstones, and the [`index`](JsonSyncBuilder::index) aren't updated.
+    /// Call [`flush`](Self::flush) yourself afterwards to get them on disk.
+    #[must_use]
+    pub fn backend(&self) -> &M {
+        &self.map
     }
 
     // ---- writes ----
 
     /// Insert a key-value pair, returning the previous value if the key existed.
     pub fn insert(&self, key: K, value: V) -> Result<Option<V>> {
+        let _serial = self.persister.serial();
+        self.check_value_size(&value)?;
+        self.audit("insert", Some(&key), Some(&value))?;
+        let added = self.size_hint(&key, &value);
+        self.forget_expiry(&key);
+        self.claim_pending(&key);
+        let touched = self.index_key(&key);
+        let prev = self.map.insert(key, value);
+        self.reindex(touched.as_ref());
+        self.notify_mutation(added)?;
+        Ok(prev)
+    }
+
+    /// [`insert`](Self::insert) an entry that expires after `ttl`. Needs
+    /// [`value_ttl`](JsonSyncBuilder::value_ttl) on the builder; without it
+    /// this returns `Error::Config`.
+    ///
+    /// The expiry is written to disk with the value, and expired entries are
+    /// dropped the next time the file is opened. Until then they stay
+    /// readable; call [`purge_expired`](Self::purge_expired) to drop them from
+    /// a long-running store. Re-inserting the key with plain `insert` clears
+    /// its expiry. A `ttl` too long to add to the current time never expires.
+    pub fn insert_with_ttl(&self, key: K, value: V, ttl: Duration) -> Result<Option<V>> {
+        let _serial = self.persister.serial();
+        let Some(expiries) = &self.sidecars.ttl else {
+            return Err(Error::Config(
+                "insert_with_ttl needs JsonSyncBuilder::value_ttl(true)".into(),
+            ));
+        };
+        self.check_value_size(&value)?;
+        self.audit("insert", Some(&key), Some(&value))?;
+        let added = self.size_hint(&key, &value);
+        let exp = self.clock.now().checked_add(ttl).map_or(u64::MAX, unix_ms);
+        expiries.lock().insert(key.clone(), exp);
+        let touched = self.index_key(&key);
         let prev = self.map.insert(key, value);
-        self.notify_mutation()?;
+        self.reindex(touched.as_ref());
+        self.notify_mutation(added)?;
         Ok(prev)
     }
 
-    /// Remove a key, returning its value if it was present.
+    /// When `key` expires, if it was inserted with
+    /// [`insert_with_ttl`](Self::insert_with_ttl). `None` for entries without
+    /// a TTL, missing keys, and stores without
+    /// [`value_ttl`](JsonSyncBuilder::value_ttl).
+    #[must_use]
+    pub fn expires_at(&self, key: &K) -> Option<SystemTime> {
+        let exp = *self.sidecars.ttl.as_ref()?.lock().get(key)?;
+        Some(UNIX_EPOCH + Duration::from_millis(exp))
+    }
+
+    /// Remove every entry whose TTL has run out and return how many went.
+    /// Triggers the flush policy only if something was removed.
+    pub fn purge_expired(&self) -> Result<usize> {
+        let _serial = self.persister.serial();
+        let Some(expiries) = &self.sidecars.ttl else {
+            return Ok(0);
+        };
+        let now = unix_ms(self.clock.now());
+        let expired: Vec<K> = {
+            let mut expiries = expiries.lock();
+            let expired = expiries
+                .iter()
+                .filter(|(_, &exp)| exp <= now)
+                .map(|(k, _)| k.clone())
+                .collect::<Vec<_>>();
+            for k in &expired {
+                expiries.remove(k);
+            }
+            expired
+        };
+        let mut removed = 0;
+        for k in &expired {
+            self.audit("remove", Some(k), None)?;
+            if self.map.remove(k).is_some() {
+                self.reindex(Some(k));
+                removed += 1;
+            }
+        }
+        if removed > 0 {
+            self.notify_mutation(0)?;
+        }
+        Ok(removed)
+    }
+
+    /// Drop the tombstones of keys deleted at least `older_than` ago, once
+    /// every peer has had its chance to see them, and return how many went.
+    /// Triggers the flush policy only if something was dropped. Does nothing
+    /// without [`tombstones`](JsonSyncBuilder::tombstones).
+    pub fn purge_tombstones(&self, older_than: Duration) -> Result<usize> {
+        let _serial = self.persister.serial();
+        let Some(tombstones) = &self.sidecars.tombstones else {
+            return Ok(0);
+        };
+        let cutoff = self.clock.now().checked_sub(older_than).map_or(0, unix_ms);
+        let purged = {
+            let mut tombstones = tombstones.lock();
+            let before = tombstones.len();
+            // keys inserted again since aren't written anyway; drop those too
+            tombstones.retain(|k, ts| *ts > cutoff && !self.map.contains_key(k));
+            before - tombstones.len()
+        };
+        if purged > 0 {
+            self.notify_mutation(0)?;
+        }
+        Ok(purged)
+    }
+
+    /// Remove a key, returning its value if it was present. Under
+    /// [`tombstones`](JsonSyncBuilder::tombstones) the removal is also
+    /// recorded in the file.
     pub fn remove(&self, key: &K) -> Result<Option<V>> {
+        let _serial = self.persister.serial();
+        self.audit("remove", Some(key), None)?;
+        self.forget_expiry(key);
+        self.claim_pending(key);
         let prev = self.map.remove(key);
-        self.notify_mutation()?;
+        if prev.is_some() {
+            self.reindex(Some(key));
+            self.bury(key);
+        }
+        self.notify_mutation(0)?;
         Ok(prev)
     }
 
-    /// Drop all entries from the store.
+    /// Drop all entries from the store. A flush running alongside sees the
+    /// map from before or after, never partly cleared.
     pub fn clear(&self) -> Result<()> {
-        self.map.clear();
-        self.notify_mutation()
+        let _serial = self.persister.serial();
+        self.audit("clear", None, None)?;
+        {
+            let _flushing = self.persister.lock.lock();
+            self.forget_all_expiries();
+            self.forget_all_pending();
+            self.map.clear();
+        }
+        self.rebuild_index();
+        self.notify_mutation(0)
+    }
+
+    /// Remove every entry `f` returns `true` for and hand them back, flushing
+    /// once at the end (and only if something was removed).
+    ///
+    /// On `RwLock<HashMap>` and `RwLock<BTreeMap>` the whole pass runs under
+    /// the write lock, so it's atomic. Sharded backends (ShardMap, DashMap)
+    /// snapshot and then remove the matches; a write racing the drain may be
+    /// missed, or drained with a newer value than `f` saw (see
+    /// [`MapBackend::drain_filter`]).
+    ///
+    /// ```rust,no_run
+    /// # use json_sync::JsonSync;
+    /// # use shardmap::ShardMap;
+    /// let jobs = JsonSync::<String, String, ShardMap<String, String>>::open("jobs.json").unwrap();
+    /// let done = jobs.drain_filter(|_, status| status == "done").unwrap();
+    /// ```
+    pub fn drain_filter<F>(&self, f: F) -> Result<Vec<(K, V)>>
+    where
+        F: FnMut(&K, &V) -> bool,
+    {
+        let _serial = self.persister.serial();
+        self.parse_all_pending();
+        let drained = self.map.drain_filter(f);
+        if drained.is_empty() {
+            return Ok(drained);
+        }
+        // like update, the removed keys are only known once they're gone, so
+        // these lines trail the mutation
+        for (k, _) in &drained {
+            self.forget_expiry(k);
+            self.reindex(Some(k));
+            self.bury(k);
+        }
+        for (k, _) in &drained {
+            self.audit("remove", Some(k), None)?;
+        }
+        self.notify_mutation(0)?;
+        Ok(drained)
     }
 
     /// Bulk-insert from an iterator. Only triggers one flush at the end, not
-    /// one per entry.
+    /// one per entry. If any value is over
+    /// [`max_value_bytes`](JsonSyncBuilder::max_value_bytes), nothing is
+    /// inserted.
     pub fn extend<I>(&self, iter: I) -> Result<()>
     where
         I: IntoIterator<Item = (K, V)>,
     {
-        for (k, v) in iter {
+        let _serial = self.persister.serial();
+        let added = self.insert_all(iter)?;
+        self.notify_mutation(added)
+    }
+
+    /// Copy every entry of `other`, another store in this process, into this
+    /// one and flush once — for handing state over to a fresh store without
+    /// a round trip through either file. Keys already here are overwritten
+    /// and the rest kept, as with [`extend`](Self::extend). `other` is left
+    /// as it was; its TTLs and tombstones don't come along, so the copies
+    /// never expire.
+    ///
+    /// The flush policy doesn't fire for the copy itself; the one flush
+    /// covers it.
+    pub fn warm_from<M2, S2>(&self, other: &JsonSync<K, V, M2, S2>) -> Result<()>
+    where
+        M2: MapBackend<K, V> + 'static,
+        S2: Serializer + 'static,
+    {
+        let _serial = self.persister.serial();
+        let added = self.insert_all(other.entries_iter())?;
+        self.record_mutation(added)?;
+        self.flush()
+    }
+
+    /// The writes behind [`extend`](Self::extend), with nothing told about
+    /// them yet. Returns the bytes they added.
+    fn insert_all<I>(&self, iter: I) -> Result<usize>
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let entries: Vec<(K, V)> = iter.into_iter().collect();
+        for (_, v) in &entries {
+            self.check_value_size(v)?;
+        }
+        let mut added = 0;
+        for (k, v) in entries {
+            self.audit("insert", Some(&k), Some(&v))?;
+            added += self.size_hint(&k, &v);
+            self.forget_expiry(&k);
+            self.claim_pending(&k);
+            let touched = self.index_key(&k);
             self.map.insert(k, v);
+            self.reindex(touched.as_ref());
         }
-        self.notify_mutation()
+        Ok(added)
     }
 
     /// Mutate the value at `key` in place. Returns `false` if the key doesn't
     /// exist (nothing happens in that case).
     ///
-    /// Heads up: this does a get-then-put under the hood, so there's a small
-    /// race window with concurrent writers. Fine for single-writer setups.
+    /// Concurrent `update`s never lose each other's changes, on any backend.
+    /// On `RwLock<HashMap>`, `RwLock<BTreeMap>`, and DashMap `f` also runs
+    /// under the backend's lock, so it's atomic against `insert` and `remove`
+    /// too; ShardMap falls back to get-then-put (see [`MapBackend::modify`]),
+    /// where a concurrent `insert` or `remove` of the same key can still be
+    /// overwritten.
     pub fn update<F>(&self, key: &K, f: F) -> Result<bool>
     where
         F: FnOnce(&mut V),
     {
-        match self.map.get(key) {
-            Some(mut v) => {
-                f(&mut v);
-                self.map.insert(key.clone(), v);
-                self.notify_mutation()?;
+        let _serial = self.persister.serial();
+        self.claim_pending(key);
+        let new = {
+            let _stripe = self.update_lock(key).lock();
+            self.map.modify(key, f)
+        };
+        match new {
+            Some(v) => {
+                self.reindex(Some(key));
+                // like JsonCounters, the new value only exists once it's in
+                // the map, so this line trails the mutation
+                self.audit("update", Some(key), Some(&v))?;
+                self.notify_mutation(self.size_hint(key, &v))?;
                 Ok(true)
             }
             None => Ok(false),
@@ -172,185 +874,3121 @@ where
 
     /// Return the existing value for `key`, or insert `default` and return it.
     pub fn get_or_insert(&self, key: K, default: V) -> Result<V> {
+        let _serial = self.persister.serial();
+        self.claim_pending(&key);
         if let Some(v) = self.map.get(&key) {
             return Ok(v);
         }
+        self.check_value_size(&default)?;
+        self.audit("insert", Some(&key), Some(&default))?;
         let ret = default.clone();
+        let added = self.size_hint(&key, &default);
+        self.forget_expiry(&key);
+        let touched = self.index_key(&key);
         self.map.insert(key, default);
-        self.notify_mutation()?;
+        self.reindex(touched.as_ref());
+        self.notify_mutation(added)?;
         Ok(ret)
     }
 
+    /// [`get_or_insert`](Self::get_or_insert) for a batch: for each pair,
+    /// keep the existing value or insert the default. Returns the effective
+    /// values in input order and flushes at most once, at the end, and only if
+    /// something was inserted.
+    pub fn get_or_insert_many<I>(&self, defaults: I) -> Result<Vec<V>>
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let _serial = self.persister.serial();
+        let mut out = Vec::new();
+        let mut inserted = false;
+        let mut added = 0;
+        for (k, default) in defaults {
+            self.claim_pending(&k);
+            if let Some(v) = self.map.get(&k) {
+                out.push(v);
+                continue;
+            }
+            self.check_value_size(&default)?;
+            self.audit("insert", Some(&k), Some(&default))?;
+            added += self.size_hint(&k, &default);
+            out.push(default.clone());
+            self.forget_expiry(&k);
+            let touched = self.index_key(&k);
+            self.map.insert(k, default);
+            self.reindex(touched.as_ref());
+            inserted = true;
+        }
+        if inserted {
+            self.notify_mutation(added)?;
+        }
+        Ok(out)
+    }
+
     /// Like [`get_or_insert`](Self::get_or_insert) but only computes the
     /// default when the key is actually missing.
     pub fn get_or_insert_with<F>(&self, key: K, f: F) -> Result<V>
     where
         F: FnOnce() -> V,
     {
+        let _serial = self.persister.serial();
+        self.claim_pending(&key);
         if let Some(v) = self.map.get(&key) {
             return Ok(v);
         }
         let val = f();
+        self.check_value_size(&val)?;
+        self.audit("insert", Some(&key), Some(&val))?;
         let ret = val.clone();
+        let added = self.size_hint(&key, &val);
+        self.forget_expiry(&key);
+        let touched = self.index_key(&key);
         self.map.insert(key, val);
-        self.notify_mutation()?;
+        self.reindex(touched.as_ref());
+        self.notify_mutation(added)?;
         Ok(ret)
     }
 
-    // ---- persistence ----
-
-    /// Write the current map contents to disk (atomic temp-file + rename).
-    pub fn flush(&self) -> Result<()> {
-        do_flush(self.map.as_ref(), &self.path, &self.serializer)
+    /// Get the value for `key`, asking the builder's
+    /// [`loader`](JsonSyncBuilder::loader) on a miss and caching what it
+    /// returns. `Ok(None)` if the key is absent and the loader has nothing
+    /// (or there is no loader).
+    ///
+    /// The loader runs without any backend lock held, so it may block on I/O.
+    /// Two threads missing the same key at once may both call it; the later
+    /// insert wins.
+    pub fn get_or_load(&self, key: &K) -> Result<Option<V>> {
+        if let Some(v) = self.get(key) {
+            return Ok(Some(v));
+        }
+        let Some(loader) = &self.loader else {
+            return Ok(None);
+        };
+        match loader(key) {
+            Some(val) => {
+                self.insert(key.clone(), val.clone())?;
+                Ok(Some(val))
+            }
+            None => Ok(None),
+        }
     }
 
-    // ---- internal ----
+    /// Replace the whole contents of the store with `entries`, flushing once.
+    ///
+    /// With `RwLock<HashMap>` the clear and the inserts happen under one write
+    /// lock, so readers see either the old contents or the new ones. Sharded
+    /// backends (ShardMap, DashMap) clear shard by shard, so a concurrent reader
+    /// can briefly observe a partially emptied or partially seeded store.
+    /// Flushes wait for the swap to finish on every backend, so the file only
+    /// ever holds the old contents or the new ones.
+    pub fn reset<I>(&self, entries: I) -> Result<()>
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let _serial = self.persister.serial();
+        let entries: Vec<(K, V)> = entries.into_iter().collect();
+        for (_, v) in &entries {
+            self.check_value_size(v)?;
+        }
+        self.audit("reset", None, None)?;
+        let mut added = 0;
+        for (k, v) in &entries {
+            self.audit("insert", Some(k), Some(v))?;
+            added += self.size_hint(k, v);
+        }
+        {
+            // a flush mustn't snapshot the map halfway through the swap
+            let _flushing = self.persister.lock.lock();
+            self.forget_all_expiries();
+            self.forget_all_pending();
+            self.map.reset(entries);
+        }
+        self.rebuild_index();
+        self.notify_mutation(added)
+    }
 
-    fn notify_mutation(&self) -> Result<()> {
-        match &self.policy {
-            FlushPolicy::Immediate => {
-                do_flush(self.map.as_ref(), &self.path, &self.serializer)?;
+    /// Run a batch of operations in order, flushing once at the end. Meant for
+    /// replaying a replication stream or any scripted change set.
+    ///
+    /// If any inserted value is over
+    /// [`max_value_bytes`](JsonSyncBuilder::max_value_bytes), no op is
+    /// applied. Otherwise each op is applied as it's reached; there's no
+    /// rollback, so if auditing fails partway the earlier ops stay applied
+    /// (and unflushed).
+    pub fn apply<I>(&self, ops: I) -> Result<()>
+    where
+        I: IntoIterator<Item = Op<K, V>>,
+    {
+        let _serial = self.persister.serial();
+        let ops: Vec<Op<K, V>> = ops.into_iter().collect();
+        for op in &ops {
+            if let Op::Insert(_, v) = op {
+                self.check_value_size(v)?;
             }
-            FlushPolicy::Async(_) => {
-                if let Some(t) = &self.trigger {
-                    let _ = t.try_send(());
+        }
+        let mut added = 0;
+        for op in ops {
+            match op {
+                Op::Insert(k, v) => {
+                    self.audit("insert", Some(&k), Some(&v))?;
+                    added += self.size_hint(&k, &v);
+                    self.forget_expiry(&k);
+                    self.claim_pending(&k);
+                    let touched = self.index_key(&k);
+                    self.map.insert(k, v);
+                    self.reindex(touched.as_ref());
+                }
+                Op::Remove(k) => {
+                    self.audit("remove", Some(&k), None)?;
+                    self.forget_expiry(&k);
+                    self.claim_pending(&k);
+                    if self.map.remove(&k).is_some() {
+                        self.reindex(Some(&k));
+                        self.bury(&k);
+                    }
+                }
+                Op::Clear => {
+                    self.audit("clear", None, None)?;
+                    self.forget_all_expiries();
+                    self.forget_all_pending();
+                    self.map.clear();
+                    self.rebuild_index();
                 }
             }
-            FlushPolicy::Manual => {}
         }
-        Ok(())
+        self.notify_mutation(added)
     }
-}
 
-impl<K, V, M> std::fmt::Debug for JsonSync<K, V, M> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("JsonSync")
-            .field("path", &self.path)
-            .field("policy", &self.policy)
-            .finish_non_exhaustive()
+    /// Run `f` with the store-wide write lock held, so the reads and writes
+    /// it makes to the store happen as one step: no other thread's write,
+    /// whole-store read, or flush snapshot lands in the middle. Needs
+    /// [`serialized_writes`](JsonSyncBuilder::serialized_writes) on the
+    /// builder; without it this returns `Error::Config`.
+    ///
+    /// `f` can call any store method. There's no rollback: if it fails
+    /// partway, the writes it already made stay. Under
+    /// [`FlushPolicy::Immediate`] each write inside still flushes on its own.
+    ///
+    /// ```rust,no_run
+    /// # use json_sync::JsonSync;
+    /// # use shardmap::ShardMap;
+    /// let accounts = JsonSync::<String, i64, ShardMap<String, i64>>::builder("accounts.json")
+    ///     .serialized_writes(true)
+    ///     .build()
+    ///     .unwrap();
+    /// accounts
+    ///     .atomically(|| {
+    ///         accounts.update(&"alice".into(), |b| *b -= 10)?;
+    ///         accounts.update(&"bob".into(), |b| *b += 10)?;
+    ///         Ok(())
+    ///     })
+    ///     .unwrap();
+    /// ```
+    pub fn atomically<R, F>(&self, f: F) -> Result<R>
+    where
+        F: FnOnce() -> Result<R>,
+    {
+        let Some(_serial) = self.persister.serial() else {
+            return Err(Error::Config(
+                "atomically needs JsonSyncBuilder::serialized_writes(true)".into(),
+            ));
+        };
+        f()
     }
-}
 
-fn do_flush<K, V, M>(map: &M, path: &Path, serializer: &JsonSerializer) -> Result<()>
-where
-    K: Hash + Eq + Send + Sync + Clone + Serialize + DeserializeOwned,
-    V: Send + Sync + Clone + Serialize + DeserializeOwned,
-    M: MapBackend<K, V>,
-{
-    let mut data = HashMap::with_capacity(map.map_len());
-    for (k, v) in map.iter_snapshot() {
-        data.insert(k, v);
+    /// `true` if a thread panicked mid-write on a backend whose lock poisons
+    /// (`std::sync::RwLock`). The store keeps working either way; this is
+    /// your cue to check the entry that panicking write was touching. Stays
+    /// set until [`clear_poison`](Self::clear_poison).
+    #[must_use]
+    pub fn is_poisoned(&self) -> bool {
+        self.map.is_poisoned()
     }
-    let bytes = serializer.serialize(&data)?;
-    atomic_write(path, &bytes)
-}
 
-// ---------------------------------------------------------------------------
-// Builder
-// ---------------------------------------------------------------------------
+    /// Reset [`is_poisoned`](Self::is_poisoned) after dealing with it.
+    pub fn clear_poison(&self) {
+        self.map.clear_poison();
+    }
 
-/// Configures and opens a [`JsonSync`] store.
-///
-/// ```rust,no_run
-/// use json_sync::JsonSync;
-/// use shardmap::ShardMap;
-///
-/// let db = JsonSync::<String, i32, ShardMap<String, i32>>::builder("db.json")
-///     .pretty(true)
-///     .build()
-///     .unwrap();
-/// ```
-pub struct JsonSyncBuilder<K, V, M> {
-    path: PathBuf,
-    policy: FlushPolicy,
-    pretty: bool,
-    _marker: PhantomData<(K, V, M)>,
-}
+    /// Hand spare capacity back to the allocator after a large batch of
+    /// removals. Doesn't touch the file and isn't a mutation, so nothing is
+    /// flushed. A no-op on backends that can't shrink (ShardMap).
+    pub fn shrink_to_fit(&self) {
+        self.map.shrink_to_fit();
+    }
 
-impl<K, V, M> JsonSyncBuilder<K, V, M>
-where
-    K: Hash + Eq + Send + Sync + Clone + Serialize + DeserializeOwned + 'static,
-    V: Send + Sync + Clone + Serialize + DeserializeOwned + 'static,
-    M: MapBackend<K, V> + Default + 'static,
-{
+    // ---- persistence ----
+
+    /// Write the current map contents to disk (atomic temp-file + rename).
+    pub fn flush(&self) -> Result<()> {
+        self.flush_report().map(|_| ())
+    }
+
+    /// [`flush`](Self::flush), returning what was written — handy for
+    /// logging each flush.
+    pub fn flush_report(&self) -> Result<FlushReport> {
+        self.grown.store(0, Ordering::Relaxed);
+        do_flush(self.map.as_ref(), &self.persister, &self.sidecars)
+    }
+
+    /// The last few flushes, oldest first — as many as the builder's
+    /// [`flush_history`](JsonSyncBuilder::flush_history) keeps, whichever
+    /// thread ran them. Empty unless that's set.
+    #[must_use]
+    pub fn recent_flushes(&self) -> Vec<FlushEvent> {
+        self.persister.history.lock().iter().cloned().collect()
+    }
+
+    /// Write a snapshot of the store to a new file,
+    /// `dir/checkpoint-<unix ms>.json`, and return its path. The store's own
+    /// file isn't touched and writers aren't held up beyond taking the
+    /// snapshot. The checkpoint is in the store's format (serializer, TTL
+    /// envelopes, tombstones and all), so a store opened on a copy of it picks up
+    /// where this one was.
+    ///
+    /// `dir` is created if needed. Existing checkpoints are never
+    /// overwritten; pruning old ones is up to you.
+    pub fn checkpoint(&self, dir: &Path) -> Result<PathBuf> {
+        let serial = self.persister.serial();
+        let (data, pending) = snapshot(self.map.as_ref(), &self.sidecars);
+        drop(serial);
+        let bytes = if self.sidecars.is_plain() && pending.is_empty() {
+            self.persister.serializer.serialize(&data)?
+        } else {
+            let entries = on_disk(&data, &pending, &self.sidecars);
+            self.persister.serializer.serialize(&entries)?
+        };
+        std::fs::create_dir_all(dir)?;
+        let mut stamp = unix_ms(self.clock.now());
+        let path = loop {
+            let path = dir.join(format!("checkpoint-{stamp}.json"));
+            if !path.exists() {
+                break path;
+            }
+            stamp += 1;
+        };
+        atomic_write(&path, &bytes)?;
+        Ok(path)
+    }
+
+    /// Move everything in the store to `archive_path` and carry on empty —
+    /// log rotation for a store. Unlike [`checkpoint`](Self::checkpoint),
+    /// which copies, the entries leave the store: they're written to the
+    /// live file, which is then renamed to `archive_path`, and a fresh (empty)
+    /// live file is flushed in its place.
+    ///
+    /// Entries are drained from the map before being written, so a write
+    /// that races the rotation lands either in the archive or in the fresh
+    /// store, never neither. On `RwLock<HashMap>` and `RwLock<BTreeMap>` the
+    /// drain is atomic; on the sharded backends a key written mid-drain can
+    /// have its old value archived and its new one kept. If writing or
+    /// renaming fails, the drained entries go back into the store (where
+    /// nothing newer has replaced them) and the error is returned.
+    ///
+    /// `archive_path`'s directory is created if needed; it must be on the
+    /// same filesystem as the store's file. Stores writing to a custom
+    /// [`target`](JsonSyncBuilder::target) or under a
+    /// [`json_pointer`](JsonSyncBuilder::json_pointer) can't be rotated.
+    pub fn rotate(&self, archive_path: &Path) -> Result<()> {
+        let _serial = self.persister.serial();
+        if self.persister.sink.is_some() || self.persister.pointer.is_some() {
+            return Err(Error::Config(
+                "rotate needs a store that owns its whole file".into(),
+            ));
+        }
+        self.audit("rotate", None, None)?;
+        {
+            let _flushing = self.persister.lock.lock();
+            let pending = match self.sidecars.unparsed.as_ref().and_then(|u| u.lock()) {
+                Some(mut pending) => std::mem::take(&mut *pending),
+                None => HashMap::new(),
+            };
+            let drained: HashMap<K, V> = self.map.drain_filter(|_, _| true).into_iter().collect();
+            if let Err(e) = self.archive(&drained, &pending, archive_path) {
+                for (k, v) in drained {
+                    if !self.map.contains_key(&k) {
+                        self.map.insert(k, v);
+                    }
+                }
+                if let Some(unparsed) = &self.sidecars.unparsed {
+                    unparsed.put_back(pending, |k| !self.map.contains_key(k));
+                }
+                self.rebuild_index();
+                return Err(e);
+            }
+            if let Some(expiries) = &self.sidecars.ttl {
+                expiries.lock().retain(|k, _| self.map.contains_key(k));
+            }
+            if let Some(tombstones) = &self.sidecars.tombstones {
+                tombstones.lock().clear();
+            }
+        }
+        self.rebuild_index();
+        self.version.fetch_add(1, Ordering::Release);
+        self.flush()
+    }
+
+    /// Flush only if the map looks different from the last time this method
+    /// wrote, and return whether it did. Meant for polling loops where most
+    /// passes have nothing to do.
+    ///
+    /// [`ChangeCheck::Len`] just compares entry counts, so it misses updates
+    /// that leave the count alone; [`ChangeCheck::Hash`] catches those at the
+    /// cost of hashing every entry. Flushes from elsewhere (`flush()`, the
+    /// flush policy) aren't tracked, so the first call after one of them may
+    /// write again unnecessarily — never the other way around.
+    pub fn flush_if_changed(&self, check: ChangeCheck) -> Result<bool> {
+        let _serial = self.persister.serial();
+        let mut last = self.fingerprint.lock();
+        let now = Fingerprint {
+            len: self.len(),
+            hash: match check {
+                ChangeCheck::Len => None,
+                ChangeCheck::Hash => Some(self.content_hash()),
+            },
+        };
+        let unchanged = match (&*last, check) {
+            (Some(prev), ChangeCheck::Len) => prev.len == now.len,
+            (Some(prev), ChangeCheck::Hash) => prev.hash.is_some() && *prev == now,
+            (None, _) => false,
+        };
+        if unchanged {
+            return Ok(false);
+        }
+        self.flush()?;
+        *last = Some(now);
+        Ok(true)
+    }
+
+    /// Pick up edits made to the file behind the store's back without losing
+    /// the ones made in memory since the last flush. Needs
+    /// [`merge_baseline`](JsonSyncBuilder::merge_baseline) on the builder.
+    ///
+    /// Each key is compared three ways — the baseline (what this store last
+    /// loaded or flushed), the file now, and memory now. A key only the file
+    /// changed takes the file's value; a key only memory changed keeps its
+    /// value. Only when both changed it, to different results, is `resolve`
+    /// called with `(key, local, external)`, where `None` means "removed on
+    /// that side"; return the value to keep, or `None` to drop the key.
+    ///
+    /// The file isn't rewritten here — the merged result reaches disk with the
+    /// next flush, as any other mutation would.
+    ///
+    /// ```rust,no_run
+    /// # use json_sync::JsonSync;
+    /// # use shardmap::ShardMap;
+    /// let db = JsonSync::<String, u32, ShardMap<String, u32>>::builder("shared.json")
+    ///     .merge_baseline(true)
+    ///     .build()
+    ///     .unwrap();
+    /// // ... someone else edits shared.json ...
+    /// db.reload_merge(|_key, local, external| local.max(external).copied())
+    ///     .unwrap();
+    /// ```
+    pub fn reload_merge<F>(&self, mut resolve: F) -> Result<()>
+    where
+        V: PartialEq,
+        F: FnMut(&K, Option<&V>, Option<&V>) -> Option<V>,
+    {
+        let _serial = self.persister.serial();
+        let Some(baseline) = &self.persister.baseline else {
+            return Err(Error::Config(
+                "reload_merge needs merge_baseline(true) on the builder".into(),
+            ));
+        };
+        let mut changed = false;
+        let mut added = 0;
+        {
+            // no flush may rewrite the file or the baseline while we compare
+            let _flushing = self.persister.lock.lock();
+            let bytes = self.persister.read_map_bytes()?;
+            let external: HashMap<K, V> = decode(&bytes, &self.persister.serializer)?;
+            let mut base: HashMap<K, V> = decode(&baseline.lock(), &self.persister.serializer)?;
+
+            // (key, baseline, file) for every key either side knows about;
+            // whatever's left in the baseline was removed from the file
+            let mut keys: Vec<(K, Option<V>, Option<V>)> = external
+                .into_iter()
+                .map(|(k, v)| {
+                    let was = base.remove(&k);
+                    (k, was, Some(v))
+                })
+                .collect();
+            keys.extend(base.into_iter().map(|(k, v)| (k, Some(v), None)));
+
+            for (k, was, ext) in keys {
+                if ext == was {
+                    continue; // the file didn't touch it
+                }
+                let local = self.map.get(&k);
+                let keep = if local == was || local == ext {
+                    ext
+                } else {
+                    resolve(&k, local.as_ref(), ext.as_ref())
+                };
+                if keep == local {
+                    continue;
+                }
+                match keep {
+                    Some(v) => {
+                        self.audit("insert", Some(&k), Some(&v))?;
+                        added += self.size_hint(&k, &v);
+                        self.map.insert(k, v);
+                    }
+                    None => {
+                        self.audit("remove", Some(&k), None)?;
+                        self.map.remove(&k);
+                    }
+                }
+                changed = true;
+            }
+            *baseline.lock() = bytes;
+        }
+        if changed {
+            self.rebuild_index();
+            self.notify_mutation(added)?;
+        }
+        Ok(())
+    }
+
+    /// Write a snapshot of the store to `out` as one compact JSON object, with
+    /// every value passed through the builder's
+    /// [`redactor`](JsonSyncBuilder::redactor) first. For handing data to
+    /// someone else — the store's own file is never redacted.
+    ///
+    /// Keys must serialize as JSON object keys (strings or integers).
+    pub fn export<W: Write>(&self, out: W) -> Result<()> {
+        let _serial = self.persister.serial();
+        self.parse_all_pending();
+        let mut entries = serde_json::Map::new();
+        for (k, v) in self.map.iter_snapshot() {
+            let key = match serde_json::to_value(&k) {
+                Ok(serde_json::Value::String(s)) => s,
+                // numbers, including the 128-bit ones a Value can't hold
+                _ => serde_json::to_string(&k)?,
+            };
+            entries.insert(key, self.redacted(&k, &v)?);
+        }
+        serde_json::to_writer(out, &entries)?;
+        Ok(())
+    }
+
+    /// Hold back the flush policy until the returned guard is dropped, then
+    /// react once for everything that happened in between — one flush under
+    /// [`FlushPolicy::Immediate`] instead of one per mutation. Guards nest;
+    /// only the outermost one triggers the catch-up.
+    ///
+    /// This only defers what mutations would have triggered. The async
+    /// worker's timer keeps running, and [`flush`](Self::flush) still writes
+    /// immediately.
+    ///
+    /// ```rust,no_run
+    /// # use json_sync::{FlushPolicy, JsonSync};
+    /// # use shardmap::ShardMap;
+    /// let db = JsonSync::<String, i32, ShardMap<String, i32>>::open_with_policy(
+    ///     "staging.json",
+    ///     FlushPolicy::Immediate,
+    /// )
+    /// .unwrap();
+    /// {
+    ///     let _batch = db.suspend_flush();
+    ///     db.clear().unwrap();
+    ///     db.extend((0..100).map(|i| (i.to_string(), i))).unwrap();
+    /// } // one flush here
+    /// ```
+    pub fn suspend_flush(&self) -> FlushSuspendGuard<'_> {
+        self.suspended.fetch_add(1, Ordering::AcqRel);
+        FlushSuspendGuard { store: self }
+    }
+
+    /// Cross-check the backend against itself: `map_len()` must match the
+    /// number of snapshot entries, the snapshot must not repeat a key, and
+    /// `contains_key` / `get` must agree on every key. Walks the whole map, so
+    /// it's a testing aid for [`MapBackend`] implementors rather than
+    /// something to call in production. Run it while nothing else is
+    /// mutating the store, or it may flag a legitimate race.
+    ///
+    /// Under [`lazy_values`](JsonSyncBuilder::lazy_values) it parses every
+    /// value first and fails with `Error::Deserialize` if some don't parse.
+    pub fn check_invariants(&self) -> Result<()> {
+        self.parse_all_pending();
+        let unparsable = self.pending_len();
+        if unparsable > 0 {
+            return Err(Error::Deserialize(format!(
+                "{unparsable} loaded values don't parse as the value type"
+            )));
+        }
+        let snapshot: Vec<(K, V)> = self.map.iter_snapshot().collect();
+        let len = self.map.map_len();
+        if len != snapshot.len() {
+            return Err(Error::Backend(format!(
+                "map_len() is {len} but iter_snapshot() yielded {} entries",
+                snapshot.len()
+            )));
+        }
+        let mut seen = std::collections::HashSet::with_capacity(snapshot.len());
+        for (k, _) in &snapshot {
+            if !seen.insert(k) {
+                return Err(Error::Backend(
+                    "iter_snapshot() yielded the same key twice".into(),
+                ));
+            }
+            let contains = self.map.contains_key(k);
+            let found = self.map.get(k).is_some();
+            if !(contains && found) {
+                return Err(Error::Backend(format!(
+                    "snapshot key has contains_key() = {contains}, get().is_some() = {found}"
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    // ---- internal ----
+
+    /// The write-and-rename half of [`rotate`](Self::rotate): put `data` and
+    /// `pending` in the live file in the store's format, then move it to
+    /// `archive_path`. Called with the persister's lock held.
+    fn archive(
+        &self,
+        data: &HashMap<K, V>,
+        pending: &HashMap<K, Box<RawValue>>,
+        archive_path: &Path,
+    ) -> Result<()> {
+        let bytes = if self.sidecars.is_plain() && pending.is_empty() {
+            self.persister.serializer.serialize(data)?
+        } else {
+            let entries = on_disk(data, pending, &self.sidecars);
+            self.persister.serializer.serialize(&entries)?
+        };
+        let target = self.persister.target()?;
+        atomic_write(&target, &bytes)?;
+        if let Some(dir) = archive_path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::rename(&target, archive_path)?;
+        Ok(())
+    }
+
+    /// Order-independent hash of every entry's JSON encoding.
+    fn content_hash(&self) -> u64 {
+        self.parse_all_pending();
+        self.map.iter_snapshot().fold(0u64, |acc, (k, v)| {
+            let mut h = std::collections::hash_map::DefaultHasher::new();
+            h.write(&serde_json::to_vec(&k).unwrap_or_default());
+            h.write(&serde_json::to_vec(&v).unwrap_or_default());
+            acc.wrapping_add(h.finish())
+        })
+    }
+
+    /// Append a line to the audit log, if one is configured. Called before the
+    /// map is touched, so a mutation that can't be audited doesn't happen.
+    pub(crate) fn audit(&self, op: &str, key: Option<&K>, value: Option<&V>) -> Result<()> {
+        let Some(log) = &self.audit else {
+            return Ok(());
+        };
+        match (key, value, &self.redactor) {
+            (Some(k), Some(v), Some(_)) if log.logs_values() => {
+                log.record(op, key, Some(&self.redacted(k, v)?))
+            }
+            _ => log.record(op, key, value),
+        }
+    }
+
+    /// `Error::Config` if `value` is longer as JSON than the builder's
+    /// [`max_value_bytes`](JsonSyncBuilder::max_value_bytes).
+    fn check_value_size(&self, value: &V) -> Result<()> {
+        match self.max_value_bytes {
+            Some(limit) if longer_than(value, limit) => Err(Error::Config(format!(
+                "value exceeds size limit of {limit} bytes"
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Refuse a snapshot bigger than
+    /// [`max_snapshot_entries`](JsonSyncBuilder::max_snapshot_entries),
+    /// naming the streaming method to use instead.
+    fn check_snapshot_size(&self, streaming: &str) -> Result<()> {
+        match self.max_snapshot_entries {
+            Some(cap) if self.len() > cap => Err(Error::Config(format!(
+                "snapshot exceeds cap; use {streaming}()"
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// `value` as JSON, passed through the builder's redactor if there is one.
+    fn redacted(&self, key: &K, value: &V) -> Result<serde_json::Value> {
+        let mut json = serde_json::to_value(value)?;
+        if let Some(redact) = &self.redactor {
+            redact(key, &mut json);
+        }
+        Ok(json)
+    }
+
+    /// Drop `key`'s TTL, if it had one: whatever goes in next under that key
+    /// doesn't expire unless it's inserted with a TTL itself.
+    fn forget_expiry(&self, key: &K) {
+        if let Some(expiries) = &self.sidecars.ttl {
+            expiries.lock().remove(key);
+        }
+    }
+
+    /// Record that `key` was just removed, under
+    /// [`tombstones`](JsonSyncBuilder::tombstones).
+    fn bury(&self, key: &K) {
+        if let Some(tombstones) = &self.sidecars.tombstones {
+            tombstones
+                .lock()
+                .insert(key.clone(), unix_ms(self.clock.now()));
+        }
+    }
+
+    fn forget_all_expiries(&self) {
+        if let Some(expiries) = &self.sidecars.ttl {
+            expiries.lock().clear();
+        }
+    }
+
+    /// Under [`lazy_values`](JsonSyncBuilder::lazy_values), move `key`'s
+    /// value into the map if it's still waiting to be parsed. One that
+    /// doesn't parse as `V` stays where it is, so the file keeps it.
+    fn parse_pending(&self, key: &K) {
+        let Some(mut pending) = self.sidecars.unparsed.as_ref().and_then(|u| u.lock()) else {
+            return;
+        };
+        let Some(raw) = pending.get(key) else {
+            return;
+        };
+        if let Some(v) = self.parse_raw(raw) {
+            pending.remove(key);
+            self.map.insert(key.clone(), v);
+        }
+    }
+
+    /// [`parse_pending`](Self::parse_pending) for a key about to be written:
+    /// a value that doesn't parse is dropped, since the write replaces it.
+    pub(crate) fn claim_pending(&self, key: &K) {
+        let Some(mut pending) = self.sidecars.unparsed.as_ref().and_then(|u| u.lock()) else {
+            return;
+        };
+        if let Some(raw) = pending.remove(key) {
+            if let Some(v) = self.parse_raw(&raw) {
+                self.map.insert(key.clone(), v);
+            }
+        }
+    }
+
+    /// Parse every value still waiting, ahead of a read that covers the
+    /// whole store.
+    fn parse_all_pending(&self) {
+        let Some(mut pending) = self.sidecars.unparsed.as_ref().and_then(|u| u.lock()) else {
+            return;
+        };
+        pending.retain(|k, raw| match self.parse_raw(raw) {
+            Some(v) => {
+                self.map.insert(k.clone(), v);
+                false
+            }
+            None => true,
+        });
+    }
+
+    /// A value left as text under
+    /// [`lazy_values`](JsonSyncBuilder::lazy_values), read as `V` the way
+    /// the serializer reads values.
+    fn parse_raw(&self, raw: &RawValue) -> Option<V> {
+        let mut de = serde_json::Deserializer::from_str(raw.get());
+        self.persister.serializer.deserialize_value(&mut de).ok()
+    }
+
+    /// How many values are still waiting to be parsed.
+    fn pending_len(&self) -> usize {
+        self.sidecars
+            .unparsed
+            .as_ref()
+            .and_then(|u| u.lock())
+            .map_or(0, |pending| pending.len())
+    }
+
+    fn forget_all_pending(&self) {
+        if let Some(mut pending) = self.sidecars.unparsed.as_ref().and_then(|u| u.lock()) {
+            pending.clear();
+        }
+    }
+
+    /// A copy of `key` to [`reindex`](Self::reindex) once the map owns the
+    /// original, if there's an index to keep up to date.
+    fn index_key(&self, key: &K) -> Option<K> {
+        self.index.as_ref().map(|_| key.clone())
+    }
+
+    /// Refile `key` in the [`index`](JsonSyncBuilder::index) under whatever
+    /// it holds now. The map is read under the index's lock, so of two
+    /// writers racing on a key, the one to reindex last records the value
+    /// that actually stuck.
+    pub(crate) fn reindex(&self, key: Option<&K>) {
+        let (Some(index), Some(key)) = (&self.index, key) else {
+            return;
+        };
+        let mut tables = index.tables.lock();
+        let hash = self.map.get_if(key, |v| (index.hash)(v));
+        tables.put(key, hash);
+    }
+
+    /// Recompute the whole index from the map, after a load or a wholesale
+    /// change like `clear` or `reset`.
+    fn rebuild_index(&self) {
+        let Some(index) = &self.index else {
+            return;
+        };
+        let mut tables = index.tables.lock();
+        tables.by_hash.clear();
+        tables.by_key.clear();
+        self.map
+            .for_each(|k, v| tables.put(k, Some((index.hash)(v))));
+    }
+
+    /// The stripe of [`update_locks`](Self::update_locks) that `key` hashes to.
+    fn update_lock(&self, key: &K) -> &parking_lot::Mutex<()> {
+        &self.update_locks[hash_of(key) as usize % UPDATE_LOCK_STRIPES]
+    }
+
+    /// Estimated on-disk bytes for one entry. Only computed under
+    /// [`FlushPolicy::OnGrowth`] or a
+    /// [`max_memory_bytes`](JsonSyncBuilder::max_memory_bytes) cap; otherwise
+    /// it's 0 for free.
+    pub(crate) fn size_hint(&self, key: &K, value: &V) -> usize {
+        match self.policy {
+            FlushPolicy::OnGrowth(_) => serialized_len(key) + serialized_len(value),
+            _ if self.max_memory_bytes.is_some() => serialized_len(key) + serialized_len(value),
+            _ => 0,
+        }
+    }
+
+    /// Count `added` toward the memory cap, and once the running estimate
+    /// passes it, measure the store properly and evict the largest entries
+    /// until it's down to 90% of the cap, so one pass buys room for the next
+    /// few inserts. The estimate only ever counts up between measurements, so
+    /// it can be high but never misses an overrun.
+    fn enforce_memory_cap(&self, added: usize) -> Result<()> {
+        let Some(cap) = self.max_memory_bytes else {
+            return Ok(());
+        };
+        if self.held.fetch_add(added, Ordering::Relaxed) + added <= cap {
+            return Ok(());
+        }
+        let mut total = 0;
+        let mut sizes = Vec::with_capacity(self.map.map_len());
+        self.map.for_each(|k, v| {
+            let n = serialized_len(k) + serialized_len(v);
+            total += n;
+            sizes.push((n, k.clone()));
+        });
+        if total > cap {
+            let target = cap - cap / 10;
+            sizes.sort_unstable_by_key(|&(n, _)| std::cmp::Reverse(n));
+            for (n, k) in sizes {
+                if total <= target {
+                    break;
+                }
+                self.audit("evict", Some(&k), None)?;
+                self.forget_expiry(&k);
+                if self.map.remove(&k).is_some() {
+                    self.reindex(Some(&k));
+                    total -= n;
+                }
+            }
+        }
+        self.held.store(total, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Count a mutation that grew the map by `added` bytes, without the flush
+    /// policy hearing of it.
+    fn record_mutation(&self, added: usize) -> Result<()> {
+        self.version.fetch_add(1, Ordering::Release);
+        self.enforce_memory_cap(added)
+    }
+
+    /// `added` is the estimated number of bytes this mutation grew the map by.
+    pub(crate) fn notify_mutation(&self, added: usize) -> Result<()> {
+        self.record_mutation(added)?;
+        if self.suspended.load(Ordering::Acquire) > 0 {
+            // re-check under the lock so a guard resuming right now can't
+            // miss this mutation
+            let mut deferred = self.deferred.lock();
+            if self.suspended.load(Ordering::Acquire) > 0 {
+                *deferred = Some(deferred.unwrap_or(0) + added);
+                return Ok(());
+            }
+        }
+        match &self.policy {
+            FlushPolicy::Immediate => {
+                do_flush(self.map.as_ref(), &self.persister, &self.sidecars)?;
+            }
+            FlushPolicy::OnGrowth(limit) => {
+                let total = self.grown.fetch_add(added, Ordering::Relaxed) + added;
+                if total >= *limit {
+                    self.flush()?;
+                }
+            }
+            FlushPolicy::Async(_) | FlushPolicy::AsyncBounded { .. } => {
+                self.worker.start()?;
+                if let Some(t) = &self.trigger {
+                    match self.policy.async_params() {
+                        // the worker takes the serial lock to snapshot, so
+                        // waiting on it while holding that lock would deadlock
+                        Some((_, _, OnFull::Block)) if !self.persister.holds_serial() => {
+                            t.nudge_blocking();
+                        }
+                        _ => t.nudge(),
+                    }
+                }
+            }
+            FlushPolicy::Manual => {}
+            #[cfg(feature = "schedule")]
+            FlushPolicy::Scheduled(_) => {}
+        }
+        Ok(())
+    }
+}
+
+impl<K, V, M> JsonSync<K, V, M, SwappableSerializer> {
+    /// Write with `next` from the next flush on. Files in the previous format
+    /// still load while this store is open. A restart before the next flush
+    /// finds the file in the old format, so it only opens if the new build
+    /// registers that format with
+    /// [`read_also`](SwappableSerializer::read_also). Only for stores built
+    /// with a [`SwappableSerializer`].
+    ///
+    /// ```rust,no_run
+    /// # use json_sync::serializer::{JsonSerializer, SwappableSerializer};
+    /// # use json_sync::JsonSync;
+    /// # use shardmap::ShardMap;
+    /// # use std::sync::Arc;
+    /// let db = JsonSync::<String, i32, ShardMap<String, i32>>::builder("db.json")
+    ///     .serializer(SwappableSerializer::new(Arc::new(JsonSerializer::new())))
+    ///     .build()
+    ///     .unwrap();
+    /// db.set_serializer(Arc::new(JsonSerializer::pretty()));
+    /// db.flush().unwrap(); // pretty from here on
+    /// ```
+    pub fn set_serializer(&self, next: Arc<dyn DynSerializer>) {
+        self.persister.serializer.set(next);
+    }
+}
+
+#[cfg(feature = "schema")]
+impl<K, V, M, S> JsonSync<K, V, M, S>
+where
+    V: schemars::JsonSchema,
+{
+    /// JSON Schema for one stored value, generated from `V`'s
+    /// `schemars::JsonSchema` impl (feature `schema`). Every value in the
+    /// data file should validate against it, which lets editors check and
+    /// autocomplete hand edits.
+    #[must_use]
+    pub fn value_schema(&self) -> serde_json::Value {
+        schemars::schema_for!(V).to_value()
+    }
+
+    /// Write [`value_schema`](Self::value_schema) to `path` as pretty JSON,
+    /// replacing the file atomically.
+    pub fn write_schema_to(&self, path: impl AsRef<Path>) -> Result<()> {
+        atomic_write(
+            path.as_ref(),
+            &serde_json::to_vec_pretty(&self.value_schema())?,
+        )
+    }
+}
+
+impl<K, V, M, S> std::fmt::Debug for JsonSync<K, V, M, S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JsonSync")
+            .field("path", &self.persister.path)
+            .field("policy", &self.policy)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Returned by [`JsonSync::suspend_flush`]. Dropping it runs the flush policy
+/// once for every mutation made while it was held; use
+/// [`resume`](Self::resume) instead to see whether that flush failed.
+#[must_use = "flushing resumes as soon as the guard is dropped"]
+pub struct FlushSuspendGuard<'a> {
+    store: &'a dyn Suspendable,
+}
+
+impl FlushSuspendGuard<'_> {
+    /// Drop the guard, returning any error from the catch-up flush.
+    pub fn resume(self) -> Result<()> {
+        let store = self.store;
+        std::mem::forget(self);
+        store.resume()
+    }
+}
+
+impl Drop for FlushSuspendGuard<'_> {
+    fn drop(&mut self) {
+        let _ = self.store.resume();
+    }
+}
+
+impl std::fmt::Debug for FlushSuspendGuard<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FlushSuspendGuard").finish_non_exhaustive()
+    }
+}
+
+/// Lets [`FlushSuspendGuard`] call back into a store without carrying its
+/// type parameters.
+trait Suspendable {
+    fn resume(&self) -> Result<()>;
+}
+
+impl<K, V, M, S> Suspendable for JsonSync<K, V, M, S>
+where
+    K: Hash + Eq + Send + Sync + Clone + Serialize + DeserializeOwned + 'static,
+    V: Send + Sync + Clone + Serialize + DeserializeOwned + 'static,
+    M: MapBackend<K, V> + 'static,
+    S: Serializer + 'static,
+{
+    fn resume(&self) -> Result<()> {
+        let deferred = {
+            let mut deferred = self.deferred.lock();
+            if self.suspended.fetch_sub(1, Ordering::AcqRel) != 1 {
+                return Ok(());
+            }
+            deferred.take()
+        };
+        match deferred {
+            Some(added) => self.notify_mutation(added),
+            None => Ok(()),
+        }
+    }
+}
+
+/// What one flush did, from [`JsonSync::flush_report`].
+#[non_exhaustive]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlushReport {
+    /// Bytes written to the file (0 if
+    /// [`delete_when_empty`](JsonSyncBuilder::delete_when_empty) removed it).
+    pub bytes: usize,
+    /// Entries in the snapshot that was written.
+    pub entries: usize,
+    /// Wall time from taking the snapshot to the rename, including any wait
+    /// for a concurrent flush to finish.
+    pub duration: Duration,
+    /// File that was written.
+    pub path: PathBuf,
+    /// The snapshot matched what was already on disk, so nothing was
+    /// written; see
+    /// [`skip_unchanged_writes`](JsonSyncBuilder::skip_unchanged_writes).
+    pub skipped: bool,
+}
+
+/// One flush remembered by [`JsonSync::recent_flushes`].
+#[non_exhaustive]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlushEvent {
+    /// When the flush finished, by the system clock.
+    pub at: SystemTime,
+    /// Bytes written; 0 if the flush failed or was skipped.
+    pub bytes: usize,
+    /// How long the flush took, whether or not it succeeded.
+    pub duration: Duration,
+    /// Why the flush failed, or `None` if it succeeded.
+    pub error: Option<Error>,
+}
+
+/// Differences between the store and a base map, from
+/// [`JsonSync::changes_since`]. Each list is in no particular order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Changes<K, V> {
+    /// Entries in the store whose keys aren't in the base.
+    pub added: Vec<(K, V)>,
+    /// Keys in the base that the store no longer has.
+    pub removed: Vec<K>,
+    /// Keys in both whose values differ, with the store's current value.
+    pub changed: Vec<(K, V)>,
+}
+
+impl<K, V> Changes<K, V> {
+    /// `true` when the store and the base hold the same entries.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// How [`JsonSync::flush_if_changed`] decides whether anything changed.
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeCheck {
+    /// Compare entry counts. Free, but blind to updates that keep the count.
+    Len,
+    /// Hash every entry. Sees any change, but walks the whole map each call.
+    Hash,
+}
+
+/// What the map looked like at the last conditional flush.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Fingerprint {
+    len: usize,
+    hash: Option<u64>,
+}
+
+/// One mutation in a batch passed to [`JsonSync::apply`].
+///
+/// Serializable, so a stream of ops can be shipped between processes as-is.
+#[non_exhaustive]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Op<K, V> {
+    /// Insert or overwrite a key.
+    Insert(K, V),
+    /// Remove a key (no-op if absent).
+    Remove(K),
+    /// Drop every entry.
+    Clear,
+}
+
+/// Salvages a value that didn't deserialize, from its key and its JSON.
+pub type ValueFallback<K, V> = Arc<dyn Fn(&K, serde_json::Value) -> Option<V> + Send + Sync>;
+
+/// What [`JsonSyncBuilder::on_value_error`] does with a value in the file
+/// that doesn't deserialize as `V`.
+#[non_exhaustive]
+#[derive(Default)]
+pub enum ValueErrorPolicy<K, V> {
+    /// Refuse to open the file (the default).
+    #[default]
+    FailFile,
+    /// Leave the entry out and load the rest. The next flush drops it from
+    /// the file.
+    SkipEntry,
+    /// Pass the key and the value's JSON to a function and keep what it
+    /// returns; `None` leaves the entry out, as with `SkipEntry`.
+    Fallback(ValueFallback<K, V>),
+}
+
+impl<K, V> ValueErrorPolicy<K, V> {
+    /// [`Fallback`](Self::Fallback) to `f`.
+    pub fn fallback<F>(f: F) -> Self
+    where
+        F: Fn(&K, serde_json::Value) -> Option<V> + Send + Sync + 'static,
+    {
+        ValueErrorPolicy::Fallback(Arc::new(f))
+    }
+}
+
+impl<K, V> Clone for ValueErrorPolicy<K, V> {
+    fn clone(&self) -> Self {
+        match self {
+            ValueErrorPolicy::FailFile => ValueErrorPolicy::FailFile,
+            ValueErrorPolicy::SkipEntry => ValueErrorPolicy::SkipEntry,
+            ValueErrorPolicy::Fallback(f) => ValueErrorPolicy::Fallback(Arc::clone(f)),
+        }
+    }
+}
+
+impl<K, V> std::fmt::Debug for ValueErrorPolicy<K, V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValueErrorPolicy::FailFile => f.write_str("FailFile"),
+            ValueErrorPolicy::SkipEntry => f.write_str("SkipEntry"),
+            ValueErrorPolicy::Fallback(_) => f.write_str("Fallback(..)"),
+        }
+    }
+}
+
+/// Counts the bytes written to it, failing once there are more than `limit`.
+struct Counter {
+    len: usize,
+    limit: usize,
+}
+
+impl std::io::Write for Counter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.len += buf.len();
+        if self.len > self.limit {
+            return Err(std::io::Error::other("over limit"));
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// [`serialized_len`] of every key and value in `map`, added up.
+fn total_size<K, V, M>(map: &M) -> usize
+where
+    K: Hash + Eq + Send + Sync + Clone + Serialize + DeserializeOwned,
+    V: Send + Sync + Clone + Serialize + DeserializeOwned,
+    M: MapBackend<K, V>,
+{
+    let mut total = 0;
+    map.for_each(|k, v| total += serialized_len(k) + serialized_len(v));
+    total
+}
+
+/// Length of `value` as compact JSON, without allocating the output.
+fn serialized_len<T: Serialize>(value: &T) -> usize {
+    let mut counter = Counter {
+        len: 0,
+        limit: usize::MAX,
+    };
+    let _ = serde_json::to_writer(&mut counter, value);
+    counter.len
+}
+
+/// Whether `value` as compact JSON is longer than `limit` bytes. Serializing
+/// stops as soon as it is, so a huge value costs no more than `limit` bytes
+/// of work to turn away.
+fn longer_than<T: Serialize>(value: &T, limit: usize) -> bool {
+    let mut counter = Counter { len: 0, limit };
+    let _ = serde_json::to_writer(&mut counter, value);
+    counter.len > limit
+}
+
+/// Number of per-key lock stripes behind [`JsonSync::update`].
+const UPDATE_LOCK_STRIPES: usize = 16;
+
+/// Callback run after every successful flush.
+pub(crate) type FlushHook = Arc<dyn Fn() + Send + Sync>;
+
+/// Read-through source consulted by [`JsonSync::get_or_load`] on a miss.
+pub(crate) type Loader<K, V> = Arc<dyn Fn(&K) -> Option<V> + Send + Sync>;
+
+/// Secondary index set up by [`JsonSyncBuilder::index`]. Keys are bucketed
+/// by the hash of the field the extractor pulls out, so the field's type
+/// stays out of the store's; [`JsonSync::get_by_index`] re-checks the field
+/// itself to weed out collisions.
+pub(crate) struct Index<K, V> {
+    hash: Box<dyn Fn(&V) -> u64 + Send + Sync>,
+    /// The extractor as an `Arc<dyn Fn(&V) -> IK + Send + Sync>`, for
+    /// `get_by_index` to downcast back.
+    extract: Box<dyn std::any::Any + Send + Sync>,
+    tables: parking_lot::Mutex<IndexTables<K>>,
+}
+
+struct IndexTables<K> {
+    by_hash: HashMap<u64, HashSet<K>>,
+    by_key: HashMap<K, u64>,
+}
+
+impl<K, V: 'static> Index<K, V> {
+    fn new<IK, F>(extractor: F) -> Self
+    where
+        IK: Hash + Eq + 'static,
+        F: Fn(&V) -> IK + Send + Sync + 'static,
+    {
+        let extract: Arc<dyn Fn(&V) -> IK + Send + Sync> = Arc::new(extractor);
+        let hasher = Arc::clone(&extract);
+        Index {
+            hash: Box::new(move |v| hash_of(&hasher(v))),
+            extract: Box::new(extract),
+            tables: parking_lot::Mutex::new(IndexTables {
+                by_hash: HashMap::new(),
+                by_key: HashMap::new(),
+            }),
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone> IndexTables<K> {
+    /// File `key` under bucket `hash`, or drop it from the index on `None`.
+    fn put(&mut self, key: &K, hash: Option<u64>) {
+        if let Some(old) = self.by_key.remove(key) {
+            if let Some(keys) = self.by_hash.get_mut(&old) {
+                keys.remove(key);
+                if keys.is_empty() {
+                    self.by_hash.remove(&old);
+                }
+            }
+        }
+        if let Some(hash) = hash {
+            self.by_hash.entry(hash).or_default().insert(key.clone());
+            self.by_key.insert(key.clone(), hash);
+        }
+    }
+}
+
+fn hash_of<T: Hash + ?Sized>(t: &T) -> u64 {
+    let mut h = std::collections::hash_map::DefaultHasher::new();
+    t.hash(&mut h);
+    h.finish()
+}
+
+/// The store's end of the async worker's nudge channel.
+pub(crate) type Trigger = Arc<Nudges>;
+
+/// Tickets handed out by [`JsonSyncHandle::sync`] and how far the async
+/// worker has got through them.
+#[derive(Default)]
+pub(crate) struct Syncs {
+    state: parking_lot::Mutex<SyncState>,
+    progressed: parking_lot::Condvar,
+}
+
+#[derive(Default)]
+struct SyncState {
+    requested: u64,
+    /// Newest ticket a finished flush covered, and that flush's error.
+    done: u64,
+    error: Option<Error>,
+}
+
+impl Syncs {
+    fn ticket(&self) -> u64 {
+        let mut state = self.state.lock();
+        state.requested += 1;
+        state.requested
+    }
+
+    /// The newest ticket a flush starting now covers. Read before the
+    /// snapshot, so everything done before those tickets were taken is in it.
+    fn covering(&self) -> u64 {
+        self.state.lock().requested
+    }
+
+    fn finished(&self, covered: u64, result: &Result<FlushReport>) {
+        let mut state = self.state.lock();
+        if covered > state.done {
+            state.done = covered;
+            state.error = result.as_ref().err().cloned();
+            self.progressed.notify_all();
+        }
+    }
+
+    /// Whether a flush covering `covered` still has a sync waiting on it.
+    fn owed(&self, covered: u64) -> bool {
+        covered > self.state.lock().done
+    }
+
+    /// Block until a flush covering `ticket` has finished, and return how
+    /// it went.
+    fn wait(&self, ticket: u64) -> Result<()> {
+        let mut state = self.state.lock();
+        while state.done < ticket {
+            self.progressed.wait(&mut state);
+        }
+        match &state.error {
+            Some(e) => Err(e.clone()),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Edits a value's JSON before it leaves through an export or the audit log.
+pub(crate) type Redactor<K> = Arc<dyn Fn(&K, &mut serde_json::Value) + Send + Sync>;
+
+/// Callback run with the duration and byte size of a flush that took too long.
+pub(crate) type SlowFlushHook = Arc<dyn Fn(Duration, usize) + Send + Sync>;
+
+/// What a handle owns besides the store, left behind when it drops while other
+/// handles still share the store (feature `single-instance`). The last handle
+/// picks it up and tears it down.
+#[cfg(all(unix, feature = "signal", feature = "single-instance"))]
+#[derive(Default)]
+pub(crate) struct Parked {
+    signals: Option<crate::signal::Registration>,
+}
+
+/// The background flush worker. The async one is spawned by the first
+/// mutation rather than when the store opens, so a store that's only read
+/// never has a thread; the scheduled one runs from the start.
+pub(crate) struct LazyWorker {
+    /// Set once nothing is left to start, so mutations skip the lock.
+    settled: AtomicBool,
+    state: parking_lot::Mutex<WorkerState>,
+}
+
+/// Spawns a worker on its channel.
+type WorkerStart = Box<dyn FnOnce() -> Result<AsyncFlushWorker> + Send>;
+
+enum WorkerState {
+    Waiting(WorkerStart),
+    Running(AsyncFlushWorker),
+    Gone,
+}
+
+impl LazyWorker {
+    fn new(state: WorkerState) -> Self {
+        Self {
+            settled: AtomicBool::new(!matches!(state, WorkerState::Waiting(_))),
+            state: parking_lot::Mutex::new(state),
+        }
+    }
+
+    /// Spawn the worker if it's still waiting. If the thread can't be
+    /// spawned, the caller gets the error and nothing tries again.
+    fn start(&self) -> Result<()> {
+        if self.settled.load(Ordering::Acquire) {
+            return Ok(());
+        }
+        let mut state = self.state.lock();
+        let started = match std::mem::replace(&mut *state, WorkerState::Gone) {
+            WorkerState::Waiting(start) => {
+                start().map(|worker| *state = WorkerState::Running(worker))
+            }
+            other => {
+                *state = other;
+                Ok(())
+            }
+        };
+        self.settled.store(true, Ordering::Release);
+        started
+    }
+
+    /// Whether a worker is running right now.
+    fn is_running(&self) -> bool {
+        matches!(*self.state.lock(), WorkerState::Running(_))
+    }
+
+    /// The running worker, for the handle to stop. None is started after.
+    fn take(&self) -> Option<AsyncFlushWorker> {
+        self.settled.store(true, Ordering::Release);
+        match std::mem::replace(&mut *self.state.lock(), WorkerState::Gone) {
+            WorkerState::Running(worker) => Some(worker),
+            _ => None,
+        }
+    }
+}
+
+/// Per-key state kept beside the map and written out with it. Shared with
+/// the async worker and signal flushes, which write it too.
+pub(crate) struct Sidecars<K> {
+    /// Expiry times of entries inserted with a TTL. `None` unless the builder
+    /// turned on [`value_ttl`](JsonSyncBuilder::value_ttl).
+    pub(crate) ttl: Option<Arc<Expiries<K>>>,
+    /// Deletion times of removed keys. `None` unless the builder turned on
+    /// [`tombstones`](JsonSyncBuilder::tombstones).
+    pub(crate) tombstones: Option<Arc<Tombstones<K>>>,
+    /// Loaded values not parsed yet. `None` unless the builder turned on
+    /// [`lazy_values`](JsonSyncBuilder::lazy_values).
+    pub(crate) unparsed: Option<Arc<Unparsed<K>>>,
+}
+
+impl<K> Sidecars<K> {
+    /// No envelopes or tombstones to write around the values.
+    fn is_plain(&self) -> bool {
+        self.ttl.is_none() && self.tombstones.is_none()
+    }
+}
+
+impl<K> Clone for Sidecars<K> {
+    fn clone(&self) -> Self {
+        Self {
+            ttl: self.ttl.clone(),
+            tombstones: self.tombstones.clone(),
+            unparsed: self.unparsed.clone(),
+        }
+    }
+}
+
+impl<K> Default for Sidecars<K> {
+    fn default() -> Self {
+        Self {
+            ttl: None,
+            tombstones: None,
+            unparsed: None,
+        }
+    }
+}
+
+/// Values read from disk under [`JsonSyncBuilder::lazy_values`] that nothing
+/// has asked for yet, as the JSON text they were stored as. A key is in here
+/// or in the map, never both.
+pub(crate) struct Unparsed<K> {
+    values: parking_lot::Mutex<HashMap<K, Box<RawValue>>>,
+    /// Set once `values` is empty, so reads stop taking the lock. Only a
+    /// failed [`rotate`](JsonSync::rotate) ever refills it.
+    drained: AtomicBool,
+}
+
+impl<K: Hash + Eq> Unparsed<K> {
+    fn new(values: HashMap<K, Box<RawValue>>) -> Self {
+        let drained = AtomicBool::new(values.is_empty());
+        Self {
+            values: parking_lot::Mutex::new(values),
+            drained,
+        }
+    }
+
+    /// Return `values` taken out by a failed rotate, except where `keep`
+    /// says the key has been written since.
+    fn put_back(&self, values: HashMap<K, Box<RawValue>>, keep: impl Fn(&K) -> bool) {
+        let mut table = self.values.lock();
+        for (k, raw) in values {
+            if keep(&k) {
+                table.entry(k).or_insert(raw);
+            }
+        }
+        if !table.is_empty() {
+            self.drained.store(false, Ordering::Release);
+        }
+    }
+
+    /// The table, or `None` once it's drained.
+    fn lock(&self) -> Option<parking_lot::MutexGuard<'_, HashMap<K, Box<RawValue>>>> {
+        if self.drained.load(Ordering::Acquire) {
+            return None;
+        }
+        let values = self.values.lock();
+        if values.is_empty() {
+            self.drained.store(true, Ordering::Release);
+            return None;
+        }
+        Some(values)
+    }
+}
+
+/// Per-key expiry times, in milliseconds since the Unix epoch.
+pub(crate) type Expiries<K> = parking_lot::Mutex<HashMap<K, u64>>;
+
+/// On-disk form of a value under [`JsonSyncBuilder::value_ttl`]:
+/// `{"v": <value>, "exp": <unix ms or null>}`.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Envelope<T> {
+    v: T,
+    exp: Option<u64>,
+}
+
+/// What a `value_ttl` store reads: the envelope, or a bare value from a file
+/// written before TTLs were turned on.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Stored<V> {
+    Wrapped(Envelope<V>),
+    Bare(V),
+}
+
+/// Deletion times of removed keys, in milliseconds since the Unix epoch.
+pub(crate) type Tombstones<K> = parking_lot::Mutex<HashMap<K, u64>>;
+
+/// On-disk form of a removed key under [`JsonSyncBuilder::tombstones`]:
+/// `{"__deleted": true, "ts": <unix ms>}`.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Tombstone {
+    #[serde(rename = "__deleted", deserialize_with = "only_true")]
+    deleted: bool,
+    ts: u64,
+}
+
+fn only_true<'de, D: serde::Deserializer<'de>>(d: D) -> std::result::Result<bool, D::Error> {
+    match bool::deserialize(d)? {
+        true => Ok(true),
+        false => Err(serde::de::Error::custom("expected true")),
+    }
+}
+
+/// What a `tombstones` store reads: a tombstone, or a live value.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Marked<T> {
+    Deleted(Tombstone),
+    Live(T),
+}
+
+/// One entry as written by a store with `value_ttl`, `tombstones`, or
+/// `lazy_values` on.
+#[derive(Serialize)]
+#[serde(untagged)]
+enum OnDisk<'a, V> {
+    Bare(&'a V),
+    Wrapped(Envelope<&'a V>),
+    Deleted(Tombstone),
+    Raw(&'a RawValue),
+}
+
+fn unix_ms(t: SystemTime) -> u64 {
+    t.duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis().try_into().unwrap_or(u64::MAX))
+}
+
+/// Unwrap loaded envelopes, dropping the ones already expired at `now`.
+fn split_expired<K, V>(
+    stored: HashMap<K, Stored<V>>,
+    now: SystemTime,
+) -> (Vec<(K, V)>, HashMap<K, u64>)
+where
+    K: Hash + Eq + Clone,
+{
+    let now = unix_ms(now);
+    let mut data = Vec::with_capacity(stored.len());
+    let mut expiries = HashMap::new();
+    for (k, s) in stored {
+        match s {
+            Stored::Wrapped(Envelope { exp: Some(exp), .. }) if exp <= now => {}
+            Stored::Wrapped(Envelope { v, exp }) => {
+                if let Some(exp) = exp {
+                    expiries.insert(k.clone(), exp);
+                }
+                data.push((k, v));
+            }
+            Stored::Bare(v) => data.push((k, v)),
+        }
+    }
+    (data, expiries)
+}
+
+/// Everything needed to write a snapshot to disk. Shared between the store and
+/// the async worker so both flush the same way.
+pub(crate) struct Persister<S> {
+    pub(crate) path: PathBuf,
+    pub(crate) serializer: S,
+    pub(crate) write_buffer_size: usize,
+    pub(crate) direct_io: bool,
+    pub(crate) delete_when_empty: bool,
+    pub(crate) preallocate: u64,
+    pub(crate) last_size: AtomicU64,
+    pub(crate) on_flush: Option<FlushHook>,
+    pub(crate) slow_flush: Option<(Duration, SlowFlushHook)>,
+    /// The last `history_len` flushes, oldest first, for
+    /// [`JsonSync::recent_flushes`].
+    pub(crate) history: parking_lot::Mutex<VecDeque<FlushEvent>>,
+    pub(crate) history_len: usize,
+    /// Set by [`JsonSyncHandle::pause_flushing`]; the async worker skips its
+    /// flushes while it's up.
+    pub(crate) paused: AtomicBool,
+    /// Held from snapshot to rename. Flushes share one temp file, and letting
+    /// two run at once would have one truncate the other's half-written temp
+    /// — or land an older snapshot on top of a newer one. Operations that
+    /// replace the whole map (`clear`, `reset`, `reload_merge`) hold it too,
+    /// so no flush writes out a map that's only partly replaced.
+    pub(crate) lock: parking_lot::Mutex<()>,
+    /// Under [`serialized_writes`](JsonSyncBuilder::serialized_writes), held
+    /// by every mutation and whole-store read, and by a flush while it takes
+    /// its snapshot. Always taken before `lock`. Reentrant, so calls inside
+    /// [`JsonSync::atomically`] can take it again.
+    pub(crate) serial: Option<parking_lot::ReentrantMutex<()>>,
+    /// What the last flush wrote (or what was loaded), kept for
+    /// [`JsonSync::reload_merge`]. `None` unless the builder asked for it.
+    pub(crate) baseline: Option<parking_lot::Mutex<Vec<u8>>>,
+    /// Where in the file the map lives, if not at the top level.
+    pub(crate) pointer: Option<String>,
+    pub(crate) follow_symlinks: bool,
+    /// Where flushes go instead of the file, if the builder set one.
+    pub(crate) sink: Option<Arc<dyn PersistTarget>>,
+    pub(crate) skip_unchanged: bool,
+    /// Digest of the last snapshot written and what the file looked like
+    /// right after, for [`skip_unchanged`](Self::skip_unchanged).
+    pub(crate) last_write: parking_lot::Mutex<Option<WriteStamp>>,
+}
+
+/// A written snapshot's digest and, if it went to the file, the file's length
+/// and mtime just after the write. If the file still matches, nothing else
+/// has replaced it since.
+pub(crate) struct WriteStamp {
+    digest: u64,
+    file: Option<(u64, SystemTime)>,
+}
+
+impl WriteStamp {
+    fn of_file(digest: u64, target: &Path) -> Option<Self> {
+        let meta = std::fs::metadata(target).ok()?;
+        Some(Self {
+            digest,
+            file: Some((meta.len(), meta.modified().ok()?)),
+        })
+    }
+
+    fn still_on_disk(&self, target: &Path) -> bool {
+        let Some((len, modified)) = self.file else {
+            // a custom target; there's nothing to look at
+            return true;
+        };
+        std::fs::metadata(target)
+            .is_ok_and(|meta| meta.len() == len && meta.modified().is_ok_and(|m| m == modified))
+    }
+}
+
+/// Order-independent digest of a snapshot, each entry's JSON hashed on its
+/// own and the results summed — a map iterates in a different order every
+/// time it's rebuilt, so hashing the file's bytes would never match.
+fn snapshot_digest<K: Serialize, T: Serialize>(data: &HashMap<K, T>) -> u64 {
+    data.iter().fold(0u64, |acc, entry| {
+        let mut h = std::collections::hash_map::DefaultHasher::new();
+        h.write(&serde_json::to_vec(&entry).unwrap_or_default());
+        acc.wrapping_add(h.finish())
+    })
+}
+
+impl<S> Persister<S> {
+    /// The [`serial`](Self::serial) lock, if the store has one.
+    pub(crate) fn serial(&self) -> Option<parking_lot::ReentrantMutexGuard<'_, ()>> {
+        self.serial.as_ref().map(|serial| serial.lock())
+    }
+
+    /// `true` if this thread holds the [`serial`](Self::serial) lock.
+    fn holds_serial(&self) -> bool {
+        self.serial
+            .as_ref()
+            .is_some_and(|serial| serial.is_owned_by_current_thread())
+    }
+
+    /// Add a flush's outcome to the [`history`](Self::history), if it's kept.
+    fn remember(&self, outcome: &Result<FlushReport>, started: Instant) {
+        if self.history_len == 0 {
+            return;
+        }
+        let event = match outcome {
+            Ok(report) => FlushEvent {
+                at: SystemTime::now(),
+                bytes: report.bytes,
+                duration: report.duration,
+                error: None,
+            },
+            Err(e) => FlushEvent {
+                at: SystemTime::now(),
+                bytes: 0,
+                duration: started.elapsed(),
+                error: Some(e.clone()),
+            },
+        };
+        let mut history = self.history.lock();
+        if history.len() == self.history_len {
+            history.pop_front();
+        }
+        history.push_back(event);
+    }
+
+    /// The file flushes actually replace: `path`, or with `follow_symlinks`
+    /// whatever it links to, resolved fresh each time.
+    fn target(&self) -> Result<PathBuf> {
+        if self.follow_symlinks {
+            resolve_symlinks(&self.path)
+        } else {
+            Ok(self.path.clone())
+        }
+    }
+
+    /// The bytes of the map as stored on disk right now — just the part under
+    /// [`pointer`](Self::pointer) if there is one.
+    fn read_map_bytes(&self) -> Result<Vec<u8>> {
+        let bytes = read_or_empty(&self.path)?;
+        match &self.pointer {
+            Some(pointer) => extract_pointer(&bytes, pointer),
+            None => Ok(bytes),
+        }
+    }
+}
+
+fn do_flush<K, V, M, S>(
+    map: &M,
+    persister: &Persister<S>,
+    sidecars: &Sidecars<K>,
+) -> Result<FlushReport>
+where
+    K: Hash + Eq + Send + Sync + Clone + Serialize + DeserializeOwned,
+    V: Send + Sync + Clone + Serialize + DeserializeOwned,
+    M: MapBackend<K, V>,
+    S: Serializer,
+{
+    let started = Instant::now();
+    let outcome = flush_once(map, persister, sidecars, started);
+    persister.remember(&outcome, started);
+    outcome
+}
+
+fn flush_once<K, V, M, S>(
+    map: &M,
+    persister: &Persister<S>,
+    sidecars: &Sidecars<K>,
+    started: Instant,
+) -> Result<FlushReport>
+where
+    K: Hash + Eq + Send + Sync + Clone + Serialize + DeserializeOwned,
+    V: Send + Sync + Clone + Serialize + DeserializeOwned,
+    M: MapBackend<K, V>,
+    S: Serializer,
+{
+    let serial = persister.serial();
+    let guard = persister.lock.lock();
+    let target = persister.target()?;
+    let (data, pending) = snapshot(map, sidecars);
+    drop(serial);
+    let entries = data.len() + pending.len();
+    let written = if sidecars.is_plain() && pending.is_empty() {
+        write_snapshot(persister, &target, &data)?
+    } else {
+        write_snapshot(persister, &target, &on_disk(&data, &pending, sidecars))?
+    };
+    drop(guard);
+    let took = started.elapsed();
+    let Some(written) = written else {
+        return Ok(FlushReport {
+            bytes: 0,
+            entries,
+            duration: took,
+            path: target,
+            skipped: true,
+        });
+    };
+    if let Some((threshold, hook)) = &persister.slow_flush {
+        if took > *threshold {
+            hook(took, written as usize);
+        }
+    }
+    if let Some(hook) = &persister.on_flush {
+        hook();
+    }
+    Ok(FlushReport {
+        bytes: written as usize,
+        entries,
+        duration: took,
+        path: target,
+        skipped: false,
+    })
+}
+
+/// Everything a flush writes: the map, plus under `lazy_values` the values
+/// nobody has parsed yet. The unparsed table stays locked while the map is
+/// copied, so a value moving from one to the other is caught exactly once.
+fn snapshot<K, V, M>(map: &M, sidecars: &Sidecars<K>) -> (HashMap<K, V>, HashMap<K, Box<RawValue>>)
+where
+    K: Hash + Eq + Send + Sync + Clone + Serialize + DeserializeOwned,
+    V: Send + Sync + Clone + Serialize + DeserializeOwned,
+    M: MapBackend<K, V>,
+{
+    let pending = sidecars.unparsed.as_ref().and_then(|u| u.lock());
+    let mut data = HashMap::with_capacity(map.map_len());
+    for (k, v) in map.iter_snapshot() {
+        data.insert(k, v);
+    }
+    let pending = pending.map(|p| p.clone()).unwrap_or_default();
+    (data, pending)
+}
+
+/// `data` the way it's written under `value_ttl`, `tombstones`, and
+/// `lazy_values`: with `ttl`, each value wrapped in an [`Envelope`] carrying
+/// its expiry; with `tombstones`, a [`Tombstone`] added for each removed key
+/// that hasn't been inserted again since; and `pending` values written back
+/// as the text they were loaded as.
+fn on_disk<'a, K, V>(
+    data: &'a HashMap<K, V>,
+    pending: &'a HashMap<K, Box<RawValue>>,
+    sidecars: &Sidecars<K>,
+) -> HashMap<Cow<'a, K>, OnDisk<'a, V>>
+where
+    K: Hash + Eq + Clone,
+{
+    let mut out: HashMap<Cow<'a, K>, OnDisk<'a, V>> = match &sidecars.ttl {
+        Some(ttl) => {
+            let expiries = ttl.lock();
+            data.iter()
+                .map(|(k, v)| {
+                    let exp = expiries.get(k).copied();
+                    (Cow::Borrowed(k), OnDisk::Wrapped(Envelope { v, exp }))
+                })
+                .collect()
+        }
+        None => data
+            .iter()
+            .map(|(k, v)| (Cow::Borrowed(k), OnDisk::Bare(v)))
+            .collect(),
+    };
+    if let Some(tombstones) = &sidecars.tombstones {
+        for (k, &ts) in tombstones.lock().iter() {
+            if !data.contains_key(k) {
+                let deleted = OnDisk::Deleted(Tombstone { deleted: true, ts });
+                out.insert(Cow::Owned(k.clone()), deleted);
+            }
+        }
+    }
+    for (k, raw) in pending {
+        out.insert(Cow::Borrowed(k), OnDisk::Raw(raw));
+    }
+    out
+}
+
+/// Write `data` to `target` the way the persister is configured to, returning
+/// the byte count, or `None` if `skip_unchanged` found the file already holds
+/// it. Called with the persister's lock held.
+fn write_snapshot<K, T, S>(
+    persister: &Persister<S>,
+    target: &Path,
+    data: &HashMap<K, T>,
+) -> Result<Option<u64>>
+where
+    K: Serialize,
+    T: Serialize,
+    S: Serializer,
+{
+    let digest = persister.skip_unchanged.then(|| snapshot_digest(data));
+    if let (Some(digest), Some(last)) = (digest, &*persister.last_write.lock()) {
+        if last.digest == digest && last.still_on_disk(target) {
+            return Ok(None);
+        }
+    }
+    let written = if let Some(sink) = &persister.sink {
+        let bytes = persister.serializer.serialize(data)?;
+        sink.write(&bytes)?;
+        if let Some(baseline) = &persister.baseline {
+            baseline.lock().clone_from(&bytes);
+        }
+        bytes.len() as u64
+    } else if let Some(pointer) = &persister.pointer {
+        // the rest of the file isn't ours, so read it back and splice
+        let sub = persister.serializer.serialize(data)?;
+        let doc = splice_pointer(&read_or_empty(target)?, pointer, &sub)?;
+        let written = atomic_write_with(target, persister.write_buffer_size, |w| {
+            w.write_all(&doc).map_err(Error::from)
+        })?;
+        if let Some(baseline) = &persister.baseline {
+            *baseline.lock() = sub;
+        }
+        written
+    } else if persister.delete_when_empty && data.is_empty() {
+        match std::fs::remove_file(target) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+        if let Some(baseline) = &persister.baseline {
+            baseline.lock().clear();
+        }
+        0
+    } else if persister.direct_io {
+        let bytes = persister.serializer.serialize(data)?;
+        atomic_write_direct(target, &bytes)?;
+        if let Some(baseline) = &persister.baseline {
+            baseline.lock().clone_from(&bytes);
+        }
+        bytes.len() as u64
+    } else {
+        // reserve room for the last flush's size plus an eighth, in case it grew
+        let last = persister.last_size.load(Ordering::Relaxed);
+        let reserve = match persister.preallocate {
+            0 => 0,
+            floor => floor.max(last + last / 8),
+        };
+        let mut copy = Vec::new();
+        let written =
+            atomic_write_preallocated(target, persister.write_buffer_size, reserve, |w| {
+                match &persister.baseline {
+                    Some(_) => persister.serializer.serialize_to(
+                        data,
+                        Tee {
+                            out: w,
+                            copy: &mut copy,
+                        },
+                    ),
+                    None => persister.serializer.serialize_to(data, w),
+                }
+            })?;
+        if let Some(baseline) = &persister.baseline {
+            *baseline.lock() = copy;
+        }
+        persister.last_size.store(written, Ordering::Relaxed);
+        written
+    };
+    if let Some(digest) = digest {
+        *persister.last_write.lock() = match persister.sink {
+            Some(_) => Some(WriteStamp { digest, file: None }),
+            None => WriteStamp::of_file(digest, target),
+        };
+    }
+    Ok(Some(written))
+}
+
+/// Background worker and its nudge channel for the async and scheduled
+/// policies; nothing for the rest. The async worker is only spawned by the
+/// first mutation, and parks after `park_after` without a nudge. Both keep
+/// time by `clock`, and skip their flushes while the persister is paused,
+/// except to answer a sync. Also hands back the [`Syncs`] the worker reports
+/// its flushes to.
+fn start_worker<K, V, M, S>(
+    policy: &FlushPolicy,
+    thread: &WorkerThread,
+    park_after: Duration,
+    clock: &Arc<dyn Clock>,
+    map: &Arc<M>,
+    persister: &Arc<Persister<S>>,
+    sidecars: &Sidecars<K>,
+) -> Result<(LazyWorker, Option<Trigger>, Arc<Syncs>)>
+where
+    K: Hash + Eq + Send + Sync + Clone + Serialize + DeserializeOwned + 'static,
+    V: Send + Sync + Clone + Serialize + DeserializeOwned + 'static,
+    M: MapBackend<K, V> + 'static,
+    S: Serializer + 'static,
+{
+    let syncs = Arc::new(Syncs::default());
+    let map_ref = Arc::clone(map);
+    let persister_ref = Arc::clone(persister);
+    let sidecars_ref = sidecars.clone();
+    let syncs_ref = Arc::clone(&syncs);
+    let low_priority = thread.low_priority;
+    // the closure only ever runs on the worker thread
+    let lowered = std::sync::Once::new();
+    let flush = move || {
+        if low_priority {
+            lowered.call_once(lower_thread_priority);
+        }
+        let covered = syncs_ref.covering();
+        if persister_ref.paused.load(Ordering::Acquire) && !syncs_ref.owed(covered) {
+            return;
+        }
+        let result = do_flush(map_ref.as_ref(), &persister_ref, &sidecars_ref);
+        syncs_ref.finished(covered, &result);
+    };
+    #[cfg(feature = "schedule")]
+    if let FlushPolicy::Scheduled(schedule) = policy {
+        // the channel only carries syncs, the stop request, and clock jumps
+        let (trigger, wakeups) = Nudges::channel(1);
+        let w = AsyncFlushWorker::start_scheduled(
+            thread.name.clone(),
+            schedule.clone(),
+            Arc::clone(clock),
+            flush,
+            wakeups,
+        )?;
+        let worker = LazyWorker::new(WorkerState::Running(w));
+        return Ok((worker, Some(watch_clock(clock, trigger)), syncs));
+    }
+    let Some((interval, capacity, _)) = policy.async_params() else {
+        return Ok((LazyWorker::new(WorkerState::Gone), None, syncs));
+    };
+    let (trigger, wakeups) = Nudges::channel(capacity);
+    let name = thread.name.clone();
+    let clock_ref = Arc::clone(clock);
+    let start: WorkerStart = Box::new(move || {
+        AsyncFlushWorker::start_parked(name, interval, park_after, clock_ref, flush, wakeups)
+    });
+    let worker = LazyWorker::new(WorkerState::Waiting(start));
+    Ok((worker, Some(watch_clock(clock, trigger)), syncs))
+}
+
+/// Have `clock` wake the worker behind `trigger` when it jumps, for as long
+/// as the store keeps the trigger.
+fn watch_clock(clock: &Arc<dyn Clock>, trigger: Nudges) -> Trigger {
+    let trigger = Arc::new(trigger);
+    let weak = Arc::downgrade(&trigger);
+    clock.watch(Box::new(move || {
+        weak.upgrade().inspect(|trigger| trigger.wake()).is_some()
+    }));
+    trigger
+}
+
+/// Hook the store's flush into the process-wide signal watcher, if the
+/// builder asked for any signals.
+#[cfg(all(unix, feature = "signal"))]
+fn register_signals<K, V, M, S>(
+    signals: &[crate::signal::Signal],
+    map: &Arc<M>,
+    persister: &Arc<Persister<S>>,
+    sidecars: &Sidecars<K>,
+) -> Result<Option<crate::signal::Registration>>
+where
+    K: Hash + Eq + Send + Sync + Clone + Serialize + DeserializeOwned + 'static,
+    V: Send + Sync + Clone + Serialize + DeserializeOwned + 'static,
+    M: MapBackend<K, V> + 'static,
+    S: Serializer + 'static,
+{
+    if signals.is_empty() {
+        return Ok(None);
+    }
+    let map_ref = Arc::clone(map);
+    let persister_ref = Arc::clone(persister);
+    let sidecars_ref = sidecars.clone();
+    let flush = Arc::new(move || {
+        let _ = do_flush(map_ref.as_ref(), &persister_ref, &sidecars_ref);
+    });
+    crate::signal::register(signals, flush).map(Some)
+}
+
+/// Passes writes through to `out` and keeps a copy of everything written.
+struct Tee<'a> {
+    out: &'a mut dyn Write,
+    copy: &'a mut Vec<u8>,
+}
+
+impl Write for Tee<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.out.write(buf)?;
+        self.copy.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.out.flush()
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Builder
+// ---------------------------------------------------------------------------
+
+/// Configures and opens a [`JsonSync`] store.
+///
+/// ```rust,no_run
+/// use json_sync::JsonSync;
+/// use shardmap::ShardMap;
+///
+/// let db = JsonSync::<String, i32, ShardMap<String, i32>>::builder("db.json")
+///     .pretty(true)
+///     .build()
+///     .unwrap();
+/// ```
+pub struct JsonSyncBuilder<K, V, M, S = JsonSerializer> {
+    path: PathBuf,
+    policy: FlushPolicy,
+    serializer: S,
+    write_buffer_size: usize,
+    direct_io: bool,
+    delete_when_empty: bool,
+    preallocate: u64,
+    recover_temp: bool,
+    fallback_source: Option<PathBuf>,
+    detect_unclean_shutdown: bool,
+    merge_baseline: bool,
+    json_pointer: Option<String>,
+    follow_symlinks: bool,
+    canonicalize_path: bool,
+    target: Option<Arc<dyn PersistTarget>>,
+    skip_unchanged_writes: bool,
+    value_ttl: bool,
+    tombstones: bool,
+    lazy_values: bool,
+    on_value_error: ValueErrorPolicy<K, V>,
+    serialized_writes: bool,
+    on_flush: Option<FlushHook>,
+    slow_flush: Option<(Duration, SlowFlushHook)>,
+    flush_history: usize,
+    loader: Option<Loader<K, V>>,
+    index: Option<Index<K, V>>,
+    redactor: Option<Redactor<K>>,
+    max_value_bytes: Option<usize>,
+    max_snapshot_entries: Option<usize>,
+    max_depth: Option<usize>,
+    max_memory_bytes: Option<usize>,
+    clock: Arc<dyn Clock>,
+    #[cfg(all(unix, feature = "signal"))]
+    flush_signals: Vec<crate::signal::Signal>,
+    worker_thread: WorkerThread,
+    park_worker_after: Duration,
+    audit_log: Option<PathBuf>,
+    audit_values: bool,
+    audit_fsync: bool,
+    _marker: PhantomData<(K, V, M)>,
+}
+
+impl<K, V, M> JsonSyncBuilder<K, V, M>
+where
+    K: Hash + Eq + Send + Sync + Clone + Serialize + DeserializeOwned + 'static,
+    V: Send + Sync + Clone + Serialize + DeserializeOwned + 'static,
+    M: MapBackend<K, V> + Default + 'static,
+{
     fn new(path: impl AsRef<Path>) -> Self {
         Self {
             path: path.as_ref().to_path_buf(),
             policy: FlushPolicy::Manual,
-            pretty: false,
+            serializer: JsonSerializer::new(),
+            write_buffer_size: DEFAULT_WRITE_BUFFER_SIZE,
+            direct_io: false,
+            delete_when_empty: false,
+            preallocate: 0,
+            recover_temp: false,
+            fallback_source: None,
+            detect_unclean_shutdown: false,
+            merge_baseline: false,
+            json_pointer: None,
+            follow_symlinks: false,
+            canonicalize_path: false,
+            target: None,
+            skip_unchanged_writes: false,
+            value_ttl: false,
+            tombstones: false,
+            lazy_values: false,
+            on_value_error: ValueErrorPolicy::FailFile,
+            serialized_writes: false,
+            on_flush: None,
+            slow_flush: None,
+            flush_history: 0,
+            loader: None,
+            index: None,
+            redactor: None,
+            max_value_bytes: None,
+            max_snapshot_entries: None,
+            max_depth: None,
+            max_memory_bytes: None,
+            clock: Arc::new(SystemClock),
+            #[cfg(all(unix, feature = "signal"))]
+            flush_signals: Vec::new(),
+            worker_thread: WorkerThread::default(),
+            park_worker_after: Duration::MAX,
+            audit_log: None,
+            audit_values: false,
+            audit_fsync: false,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Write human-readable JSON with indentation (default: compact).
+    pub fn pretty(mut self, yes: bool) -> Self {
+        self.serializer.pretty = yes;
+        self
+    }
+
+    /// Refuse to open a file that repeats a top-level key (default: off, last
+    /// occurrence wins). See [`JsonSerializer::reject_duplicate_keys`].
+    pub fn reject_duplicate_keys(mut self, yes: bool) -> Self {
+        self.serializer = self.serializer.reject_duplicate_keys(yes);
+        self
+    }
+
+    /// What to do when the file repeats a key — keep the last value, keep the
+    /// first, or refuse to open (default: last wins). Mostly matters for
+    /// [`as_pairs`](Self::as_pairs) files, where a repeat is easy to write by
+    /// hand. See [`JsonSerializer::on_duplicate_key`].
+    pub fn on_duplicate_key(mut self, policy: DuplicatePolicy) -> Self {
+        self.serializer = self.serializer.on_duplicate_key(policy);
+        self
+    }
+
+    /// Write the map as an array of `[key, value]` pairs so non-string keys
+    /// persist losslessly. See [`JsonSerializer::as_pairs`].
+    pub fn as_pairs(mut self, yes: bool) -> Self {
+        self.serializer = self.serializer.as_pairs(yes);
+        self
+    }
+
+    /// Leave `null` fields out of objects inside values. See
+    /// [`JsonSerializer::skip_nulls`].
+    pub fn skip_nulls(mut self, yes: bool) -> Self {
+        self.serializer = self.serializer.skip_nulls(yes);
+        self
+    }
+
+    /// Write integers past JavaScript's exact range as quoted strings, and
+    /// read them back as numbers. See
+    /// [`JsonSerializer::large_ints_as_strings`].
+    pub fn large_ints_as_strings(mut self, yes: bool) -> Self {
+        self.serializer = self.serializer.large_ints_as_strings(yes);
+        self
+    }
+
+    /// Stamp written files with schema version `n` (in a
+    /// `{"json_sync_version": n, "data": ...}` envelope). See
+    /// [`JsonSerializer::version`].
+    pub fn format_version(mut self, n: u32) -> Self {
+        self.serializer = self.serializer.version(n);
+        self
+    }
+
+    /// Make `build()` fail with `Error::Config` if the file was written by a
+    /// newer schema than `n`, instead of loading it and clobbering it on the
+    /// next flush. Unversioned files count as version 0.
+    pub fn max_supported_version(mut self, n: u32) -> Self {
+        self.serializer = self.serializer.max_version(n);
+        self
+    }
+
+    /// Run `on_write` over the whole document just before it's written, and
+    /// `on_read` over it just after it's read and before it becomes keys and
+    /// values — for edits that concern the file rather than any one value,
+    /// like stamping a `_generated_at` field or dropping metadata another
+    /// tool added. The document is the map as a JSON object (inside the
+    /// [`format_version`](Self::format_version) envelope, if there is one).
+    ///
+    /// This wraps the serializer in a [`Transformed`], so set the other JSON
+    /// options first; [`as_pairs`](Self::as_pairs) and
+    /// [`skip_nulls`](Self::skip_nulls) don't apply under it, and keys must
+    /// serialize as strings. Whatever `on_write` adds, `on_read` should take
+    /// back out, or it loads as an entry.
+    pub fn document_transform<W, R>(
+        self,
+        on_write: W,
+        on_read: R,
+    ) -> JsonSyncBuilder<K, V, M, Transformed>
+    where
+        W: Fn(&mut serde_json::Value) + Send + Sync + 'static,
+        R: Fn(&mut serde_json::Value) + Send + Sync + 'static,
+    {
+        let inner = self.serializer.clone();
+        self.serializer(Transformed::new(
+            inner,
+            move |mut doc| {
+                on_write(&mut doc);
+                doc
+            },
+            move |mut doc| {
+                on_read(&mut doc);
+                doc
+            },
+        ))
+    }
+}
+
+impl<K, V, M, S> JsonSyncBuilder<K, V, M, S>
+where
+    K: Hash + Eq + Send + Sync + Clone + Serialize + DeserializeOwned + 'static,
+    V: Send + Sync + Clone + Serialize + DeserializeOwned + 'static,
+    M: MapBackend<K, V> + Default + 'static,
+    S: Serializer + 'static,
+{
+    /// Set the flush policy (default: [`FlushPolicy::Manual`]).
+    pub fn policy(mut self, policy: FlushPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Use a different on-disk format. Call this before JSON-specific options
+    /// like [`pretty`](JsonSyncBuilder::pretty), which only exist while the
+    /// serializer is a [`JsonSerializer`] — configure the one you pass in
+    /// directly instead.
+    pub fn serializer<S2>(self, serializer: S2) -> JsonSyncBuilder<K, V, M, S2>
+    where
+        S2: Serializer + 'static,
+    {
+        JsonSyncBuilder {
+            path: self.path,
+            policy: self.policy,
+            serializer,
+            write_buffer_size: self.write_buffer_size,
+            direct_io: self.direct_io,
+            delete_when_empty: self.delete_when_empty,
+            preallocate: self.preallocate,
+            recover_temp: self.recover_temp,
+            fallback_source: self.fallback_source,
+            detect_unclean_shutdown: self.detect_unclean_shutdown,
+            merge_baseline: self.merge_baseline,
+            json_pointer: self.json_pointer,
+            follow_symlinks: self.follow_symlinks,
+            canonicalize_path: self.canonicalize_path,
+            target: self.target,
+            skip_unchanged_writes: self.skip_unchanged_writes,
+            value_ttl: self.value_ttl,
+            tombstones: self.tombstones,
+            lazy_values: self.lazy_values,
+            on_value_error: self.on_value_error,
+            serialized_writes: self.serialized_writes,
+            on_flush: self.on_flush,
+            slow_flush: self.slow_flush,
+            flush_history: self.flush_history,
+            loader: self.loader,
+            index: self.index,
+            redactor: self.redactor,
+            max_value_bytes: self.max_value_bytes,
+            max_snapshot_entries: self.max_snapshot_entries,
+            max_depth: self.max_depth,
+            max_memory_bytes: self.max_memory_bytes,
+            clock: self.clock,
+            #[cfg(all(unix, feature = "signal"))]
+            flush_signals: self.flush_signals,
+            worker_thread: self.worker_thread,
+            park_worker_after: self.park_worker_after,
+            audit_log: self.audit_log,
+            audit_values: self.audit_values,
+            audit_fsync: self.audit_fsync,
             _marker: PhantomData,
         }
     }
 
-    /// Set the flush policy (default: [`FlushPolicy::Manual`]).
-    pub fn policy(mut self, policy: FlushPolicy) -> Self {
-        self.policy = policy;
+    /// Capacity of the buffer a flush streams through on its way to disk
+    /// (default: 64 KiB). The serialized file is never built in memory as a
+    /// whole, so this bounds what a flush holds beyond the map snapshot itself.
+    pub fn write_buffer_size(mut self, bytes: usize) -> Self {
+        self.write_buffer_size = bytes.max(1);
         self
     }
 
-    /// Write human-readable JSON with indentation (default: compact).
-    pub fn pretty(mut self, yes: bool) -> Self {
-        self.pretty = yes;
+    /// Write flushes with `O_DIRECT`, skipping the OS page cache (default:
+    /// off). Linux only — elsewhere, and on filesystems that don't support it,
+    /// flushes are written normally. The serialized file is built in memory
+    /// (block-aligned) rather than streamed, so
+    /// [`write_buffer_size`](Self::write_buffer_size) doesn't apply.
+    pub fn direct_io(mut self, yes: bool) -> Self {
+        self.direct_io = yes;
+        self
+    }
+
+    /// Delete the file instead of writing `{}` when a flush finds the map
+    /// empty (default: off). A missing file opens as an empty store, and the
+    /// next flush with data recreates it.
+    pub fn delete_when_empty(mut self, yes: bool) -> Self {
+        self.delete_when_empty = yes;
+        self
+    }
+
+    /// Reserve disk space for each flush's temp file before writing it, so
+    /// the filesystem doesn't extend the file piecemeal mid-write (default: 0,
+    /// off). The reservation is `bytes` or the previous flush's size plus an
+    /// eighth, whichever is larger; the file still ends up exactly as long as
+    /// the data. Linux only (`fallocate`); a no-op elsewhere and with
+    /// [`direct_io`](Self::direct_io).
+    pub fn preallocate(mut self, bytes: usize) -> Self {
+        self.preallocate = bytes as u64;
+        self
+    }
+
+    /// If the data file is missing or unreadable but a complete temp file
+    /// from an interrupted flush is lying next to it, promote the temp file
+    /// instead (default: off). A stale temp file next to a good data file is
+    /// always deleted on open.
+    pub fn recover_temp(mut self, yes: bool) -> Self {
+        self.recover_temp = yes;
+        self
+    }
+
+    /// Start from the file at `path` when the store's own file is missing,
+    /// blank, or doesn't parse — say, a read-only file of defaults for a
+    /// first boot. The store's file is only read from; the fallback's
+    /// contents become the store's and reach its file with the first flush,
+    /// which overwrites a file that didn't parse. A
+    /// [`recover_temp`](Self::recover_temp) promotion of the store's file is
+    /// tried first. A missing fallback starts the store empty, and one that
+    /// doesn't parse is an error like the store's own.
+    pub fn fallback_source(mut self, path: impl Into<PathBuf>) -> Self {
+        self.fallback_source = Some(path.into());
+        self
+    }
+
+    /// Keep a `<file>.lock` sentinel next to the data file while the store is
+    /// open, removed when the handle is dropped (default: off). Finding one on
+    /// open means the previous run never got that far — see
+    /// [`was_unclean_shutdown`](JsonSync::was_unclean_shutdown). This is only
+    /// a marker; it doesn't stop another process from opening the file.
+    pub fn detect_unclean_shutdown(mut self, yes: bool) -> Self {
+        self.detect_unclean_shutdown = yes;
+        self
+    }
+
+    /// Remember what was last loaded or flushed so
+    /// [`reload_merge`](JsonSync::reload_merge) can tell local edits from
+    /// external ones (default: off). Costs one serialized copy of the file in
+    /// memory.
+    pub fn merge_baseline(mut self, yes: bool) -> Self {
+        self.merge_baseline = yes;
+        self
+    }
+
+    /// Keep the map at `pointer` (a JSON Pointer like `"/cache"`) inside a
+    /// larger file instead of owning the whole file (default: the whole file).
+    /// Loading reads only that object; flushing reads the file back, replaces
+    /// that object, and writes the whole document, so sibling fields survive.
+    /// Missing objects along the path are created on the first flush.
+    ///
+    /// The serializer has to write plain JSON (no `Compressed`). The rest of
+    /// the document survives by value, not byte for byte: it's re-encoded
+    /// with the map, indented if the serializer indents, and with keys
+    /// reordered alphabetically. [`delete_when_empty`](Self::delete_when_empty)
+    /// and [`direct_io`](Self::direct_io) don't apply.
+    pub fn json_pointer(mut self, pointer: impl Into<String>) -> Self {
+        self.json_pointer = Some(pointer.into());
+        self
+    }
+
+    /// If the path is a symlink, write flushes through it to the file it
+    /// points at, leaving the link in place (default: off). The temp file
+    /// goes next to the real file so the final rename stays on one
+    /// filesystem. The link is re-resolved on every flush.
+    ///
+    /// With this off, a flush's rename replaces the symlink itself with a
+    /// regular file, and the file it pointed at stops being updated.
+    pub fn follow_symlinks(mut self, yes: bool) -> Self {
+        self.follow_symlinks = yes;
+        self
+    }
+
+    /// Make the path absolute when the store is built (default: off), so
+    /// `./db.json` and `/srv/app/db.json` come out of
+    /// [`JsonSync::path`] the same. The directory is canonicalized — created
+    /// first if it's missing — and the file name joined back on; the file
+    /// itself needn't exist, and a symlinked file stays the link (see
+    /// [`follow_symlinks`](Self::follow_symlinks)).
+    pub fn canonicalize_path(mut self, yes: bool) -> Self {
+        self.canonicalize_path = yes;
+        self
+    }
+
+    /// Send flushes to `target` instead of replacing the file. Each flush
+    /// hands it the whole serialized snapshot. The store still loads from its
+    /// path when it opens, so point that at a missing file to start empty.
+    ///
+    /// The file-writing options — [`direct_io`](Self::direct_io),
+    /// [`preallocate`](Self::preallocate),
+    /// [`delete_when_empty`](Self::delete_when_empty),
+    /// [`follow_symlinks`](Self::follow_symlinks) — don't apply, and
+    /// [`json_pointer`](Self::json_pointer) is an `Error::Config` at build
+    /// time, since there's no document to splice into.
+    pub fn target(mut self, target: Arc<dyn PersistTarget>) -> Self {
+        self.target = Some(target);
+        self
+    }
+
+    /// Skip a flush's write entirely when the snapshot is the same as the
+    /// last one this store wrote and the file hasn't been touched since
+    /// (default: off). Each flush hashes every entry's JSON to tell, which
+    /// costs about as much as serializing the map again; in exchange a
+    /// store that's flushed often but rarely changes stops rewriting the
+    /// file. A skipped flush reports [`FlushReport::skipped`] and doesn't run
+    /// the [`on_flush`](Self::on_flush) or
+    /// [`slow_flush_threshold`](Self::slow_flush_threshold)
+    /// hooks.
+    pub fn skip_unchanged_writes(mut self, yes: bool) -> Self {
+        self.skip_unchanged_writes = yes;
+        self
+    }
+
+    /// Store each value in an envelope that carries its expiry —
+    /// `{"v": <value>, "exp": <unix ms or null>}` — so TTLs set with
+    /// [`insert_with_ttl`](JsonSync::insert_with_ttl) survive a restart
+    /// (default: off). `get`, `insert`, and the rest still deal in bare
+    /// values; the envelope only exists on disk.
+    ///
+    /// Mixing TTL and plain inserts is fine: plain entries are written with
+    /// `"exp": null` and never expire. Entries whose time has passed are
+    /// dropped on open. A file of bare values (written before this was
+    /// turned on) loads as entries without TTLs. Can't be combined with
+    /// [`merge_baseline`](Self::merge_baseline).
+    pub fn value_ttl(mut self, yes: bool) -> Self {
+        self.value_ttl = yes;
+        self
+    }
+
+    /// Keep a tombstone for each removed key — written to the file as
+    /// `{"__deleted": true, "ts": <unix ms>}` in place of the value — so a
+    /// peer replicating from the file can tell a deletion from a key it never
+    /// had (default: off). Tombstones never show up in reads, and inserting
+    /// the key again replaces its tombstone. They stay in the file until
+    /// [`purge_tombstones`](JsonSync::purge_tombstones).
+    ///
+    /// [`remove`](JsonSync::remove), [`drain_filter`](JsonSync::drain_filter),
+    /// and [`Op::Remove`] leave tombstones; `clear`, `reset`, and expiry
+    /// don't. A file with tombstones in it only loads with this on. Can't be
+    /// combined with [`merge_baseline`](Self::merge_baseline).
+    pub fn tombstones(mut self, yes: bool) -> Self {
+        self.tombstones = yes;
+        self
+    }
+
+    /// Leave values as JSON text when the file is loaded and parse each one
+    /// into `V` the first time it's read (default: off). Opening a big file
+    /// then costs one pass to find where each value starts and ends, and a
+    /// process that only touches a few keys only pays to build those.
+    ///
+    /// Single-key reads and writes parse just their key. Anything that looks
+    /// at every entry — iteration, `fold`, `export`, `memory_usage`, and the
+    /// like — parses everything still waiting first. `len` and flushes don't:
+    /// values nobody asked for are written back exactly as they were read. A
+    /// value that doesn't parse as `V` reads as missing (though `len` still
+    /// counts it and the file keeps it) until a write to its key replaces it;
+    /// [`check_invariants`](JsonSync::check_invariants) reports them.
+    ///
+    /// Can't be combined with [`value_ttl`](Self::value_ttl),
+    /// [`tombstones`](Self::tombstones),
+    /// [`merge_baseline`](Self::merge_baseline),
+    /// [`max_memory_bytes`](Self::max_memory_bytes), or
+    /// [`index`](Self::index).
+    pub fn lazy_values(mut self, yes: bool) -> Self {
+        self.lazy_values = yes;
+        self
+    }
+
+    /// What to do when opening a file with a value that doesn't deserialize
+    /// as `V` — say, one written by a newer version of the program with an
+    /// enum variant this one doesn't know. By default
+    /// ([`ValueErrorPolicy::FailFile`]) `build()` fails; the other policies
+    /// parse the file's values one at a time and skip or salvage the bad
+    /// ones, at the cost of a slower open.
+    ///
+    /// Only the initial load is affected. Can't be combined with
+    /// [`value_ttl`](Self::value_ttl), [`tombstones`](Self::tombstones), or
+    /// [`lazy_values`](Self::lazy_values), and needs a serializer that reads
+    /// JSON ([`Serializer::reads_json`]); `build()` returns `Error::Config`
+    /// otherwise.
+    pub fn on_value_error(mut self, policy: ValueErrorPolicy<K, V>) -> Self {
+        self.on_value_error = policy;
+        self
+    }
+
+    /// Run every write one at a time under a single store-wide lock
+    /// (default: off), whatever the backend. Whole-store reads (`iter`,
+    /// `fold`, `export`, ...) and flush snapshots take the lock too, so they
+    /// never see half of a [`JsonSync::atomically`] block — which is what
+    /// lets invariants spanning several keys hold. Costs all write
+    /// concurrency; single-key reads aren't affected.
+    pub fn serialized_writes(mut self, yes: bool) -> Self {
+        self.serialized_writes = yes;
+        self
+    }
+
+    /// Read the time from `clock` instead of the system clock: TTL expiry,
+    /// tombstone times, checkpoint names, and the flush worker's timer all
+    /// follow it (see [`clock`](crate::clock)). Give it a
+    /// [`ManualClock`](crate::clock::ManualClock) to test expiry or async
+    /// flushes without waiting.
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Refuse values longer than `bytes` as compact JSON (default: no
+    /// limit). Inserts check each value before storing it and return
+    /// `Error::Config` if it's over, leaving the store as it was; the batch
+    /// methods ([`extend`](JsonSync::extend), [`reset`](JsonSync::reset))
+    /// check the whole batch first. The check serializes the value into a
+    /// byte counter and gives up at the limit, so an oversized value is
+    /// turned away without ever being buffered.
+    ///
+    /// [`update`](JsonSync::update) edits values in place and isn't checked.
+    pub fn max_value_bytes(mut self, bytes: usize) -> Self {
+        self.max_value_bytes = Some(bytes);
+        self
+    }
+
+    /// Make [`try_iter`](JsonSync::try_iter),
+    /// [`try_keys`](JsonSync::try_keys), and
+    /// [`try_values`](JsonSync::try_values) return `Error::Config` instead of
+    /// copying more than `entries` entries into a `Vec` (default: no limit).
+    /// A guard against a store that's grown too big to snapshot in one go;
+    /// `iter`, `keys`, and `values` themselves aren't limited.
+    pub fn max_snapshot_entries(mut self, entries: usize) -> Self {
+        self.max_snapshot_entries = Some(entries);
+        self
+    }
+
+    /// Refuse to load a file with arrays or objects nested more than `depth`
+    /// levels deep (default: no limit of our own), failing `build` with
+    /// `Error::Deserialize("max nesting depth exceeded")`. The outermost
+    /// value counts as one level — the one at
+    /// [`json_pointer`](Self::json_pointer) if that's set — so a plain store
+    /// of numbers needs a depth of 1 and one of flat structs 2. Add one under
+    /// [`as_pairs`](Self::as_pairs), where each entry is an array of its own,
+    /// and one for a [`format_version`](Self::format_version) envelope.
+    ///
+    /// The depth is checked by scanning the text before it's parsed, so even
+    /// absurdly nested input is turned away without touching the stack. Meant
+    /// for files you don't fully trust; serde_json's own limit of 128 still
+    /// applies on top. Serializers that don't read JSON
+    /// ([`Serializer::reads_json`]) skip the check.
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    /// Cap the store's [`memory_usage`](JsonSync::memory_usage) at `bytes`
+    /// (default: no cap). When a mutation takes it over, the largest entries
+    /// are evicted until it's down to 90% of the cap — so a single value
+    /// bigger than the cap is evicted as soon as it's inserted. Evictions show
+    /// up in the audit log as `evict`, and reach the file with the next flush.
+    ///
+    /// Each insert's size is added to a running estimate; only when that
+    /// passes the cap is the store measured for real, at the cost of a pass
+    /// over every entry. Evicting past the cap leaves room for the next few
+    /// inserts, so a full store isn't measured on every write. A file already
+    /// over the cap loads whole and is trimmed on the first write.
+    pub fn max_memory_bytes(mut self, bytes: usize) -> Self {
+        self.max_memory_bytes = Some(bytes);
+        self
+    }
+
+    /// Flush when the process receives any of `signals`, then carry on with
+    /// the signal's default action (for [`SIGTERM`](crate::signal::SIGTERM)
+    /// and friends, terminating). Covers the `kill`/Ctrl-C case where
+    /// destructors never run. Unix only, behind the `signal` feature.
+    ///
+    /// Signal handlers are process-wide, so this takes over the given
+    /// signals for the whole process, not just this store: one shared
+    /// watcher thread flushes every store that asked for a signal, and the
+    /// signals stay routed through it after the stores are dropped. Don't
+    /// combine it with other code that installs its own handlers for the
+    /// same signals. The flush runs on that thread, not inside the handler,
+    /// so it's as safe as any other flush.
+    ///
+    /// ```rust,no_run
+    /// # use json_sync::JsonSync;
+    /// # use json_sync::signal::{SIGINT, SIGTERM};
+    /// # use shardmap::ShardMap;
+    /// let db = JsonSync::<String, i32, ShardMap<String, i32>>::builder("db.json")
+    ///     .flush_on_signal(&[SIGTERM, SIGINT])
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    #[cfg(all(unix, feature = "signal"))]
+    pub fn flush_on_signal(mut self, signals: &[crate::signal::Signal]) -> Self {
+        self.flush_signals = signals.to_vec();
+        self
+    }
+
+    /// Name of the background flush thread under [`FlushPolicy::Async`]
+    /// (default: `json-sync-flush`). Useful when several stores run in one
+    /// process and you want to tell their threads apart.
+    pub fn thread_name(mut self, name: impl Into<String>) -> Self {
+        self.worker_thread.name = name.into();
+        self
+    }
+
+    /// Run the background flush thread at a lower scheduling priority (nice
+    /// 10), so flushing gives way to request handling under load (default:
+    /// off). Only Linux has a per-thread priority to lower; elsewhere this
+    /// does nothing.
+    pub fn thread_low_priority(mut self, low: bool) -> Self {
+        self.worker_thread.low_priority = low;
+        self
+    }
+
+    /// Under the async policies, let the flush worker stop waking on its
+    /// timer once `grace` has passed without a mutation, and sleep until the
+    /// next one (default: once woken, it wakes every interval for good).
+    /// Everything written before it parks has already been flushed, so a
+    /// mostly idle store stops costing wakeups without losing anything. The
+    /// worker isn't spawned until the first mutation either way.
+    pub fn park_worker_after(mut self, grace: Duration) -> Self {
+        self.park_worker_after = grace;
+        self
+    }
+
+    /// Append a JSON line to `path` for every mutation (insert, remove, update,
+    /// clear, ...). The log is bookkeeping only — it's never read on open and
+    /// doesn't affect the data file. If a line can't be written, the mutation
+    /// fails and the map is left unchanged.
+    pub fn audit_log(mut self, path: impl Into<PathBuf>) -> Self {
+        self.audit_log = Some(path.into());
+        self
+    }
+
+    /// Include values in audit lines (default: off — only the key and op are
+    /// logged, since values may be sensitive).
+    pub fn audit_values(mut self, yes: bool) -> Self {
+        self.audit_values = yes;
+        self
+    }
+
+    /// `fsync` the audit log after every line (default: off). Slower, but a
+    /// logged mutation survives a power cut.
+    pub fn audit_fsync(mut self, yes: bool) -> Self {
+        self.audit_fsync = yes;
+        self
+    }
+
+    /// Run `f` after every successful flush, whether it came from `flush()`,
+    /// the immediate policy, or the async worker. Handy for metrics and tests.
+    pub fn on_flush<F>(mut self, f: F) -> Self
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        self.on_flush = Some(Arc::new(f));
+        self
+    }
+
+    /// Fetch missing keys from somewhere else (a database, an API) in
+    /// [`get_or_load`](JsonSync::get_or_load). Whatever `f` returns is
+    /// inserted, so the store acts as a persistent read-through cache.
+    pub fn loader<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&K) -> Option<V> + Send + Sync + 'static,
+    {
+        self.loader = Some(Arc::new(f));
+        self
+    }
+
+    /// Keep a secondary index on the field `extractor` pulls out of each
+    /// value, so [`get_by_index`](JsonSync::get_by_index) can find entries by
+    /// it without a scan. The index lives in memory only: it's built when the
+    /// file is loaded and kept up to date by every write after that. Calling
+    /// this again replaces the earlier index.
+    ///
+    /// Can't be combined with [`lazy_values`](Self::lazy_values), which
+    /// would have to parse every value up front to build it.
+    pub fn index<IK, F>(mut self, extractor: F) -> Self
+    where
+        IK: Hash + Eq + 'static,
+        F: Fn(&V) -> IK + Send + Sync + 'static,
+        V: 'static,
+    {
+        self.index = Some(Index::new(extractor));
+        self
+    }
+
+    /// Scrub values on their way out through [`export`](JsonSync::export) and
+    /// the audit log (with [`audit_values`](Self::audit_values) on). `f` gets
+    /// each value as JSON and can blank or drop fields in place. The data
+    /// file and everything read from the store are untouched.
+    ///
+    /// ```rust,no_run
+    /// # use json_sync::JsonSync;
+    /// # use shardmap::ShardMap;
+    /// # use serde_json::Value;
+    /// let db = JsonSync::<String, Value, ShardMap<String, Value>>::builder("users.json")
+    ///     .redactor(|_key, v| {
+    ///         if let Some(obj) = v.as_object_mut() {
+    ///             obj.remove("password");
+    ///         }
+    ///     })
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn redactor<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&K, &mut serde_json::Value) + Send + Sync + 'static,
+    {
+        self.redactor = Some(Arc::new(f));
+        self
+    }
+
+    /// Call `f` with the elapsed time and the number of bytes written whenever
+    /// a flush (snapshot, serialize, and write) takes longer than `threshold`.
+    /// Purely a signal — the flush itself still succeeds — but it tells you
+    /// when a bigger file has made `Immediate` too slow for the callers paying
+    /// for it.
+    pub fn slow_flush_threshold<F>(mut self, threshold: Duration, f: F) -> Self
+    where
+        F: Fn(Duration, usize) + Send + Sync + 'static,
+    {
+        self.slow_flush = Some((threshold, Arc::new(f)));
+        self
+    }
+
+    /// Remember the outcome of the last `n` flushes — manual, async, or any
+    /// other — for [`recent_flushes`](JsonSync::recent_flushes) (default: 0,
+    /// none kept). The oldest is dropped once there are `n`.
+    pub fn flush_history(mut self, n: usize) -> Self {
+        self.flush_history = n;
         self
     }
 
     /// Load (or create) the store and return a handle.
-    pub fn build(self) -> Result<JsonSyncHandle<K, V, M>> {
-        let serializer = if self.pretty {
-            JsonSerializer::pretty()
+    ///
+    /// A path that's a directory, or that sits under a file, is
+    /// `Error::Config` before anything is read.
+    pub fn build(mut self) -> Result<JsonSyncHandle<K, V, M, S>> {
+        check_file_path(&self.path)?;
+        if self.canonicalize_path {
+            self.path = canonical_path(&self.path)?;
+        }
+        #[cfg(feature = "single-instance")]
+        let mut open = crate::registry::lock();
+        #[cfg(feature = "single-instance")]
+        let key = crate::registry::key(&self.path);
+        #[cfg(feature = "single-instance")]
+        if let Some(inner) = open.get::<JsonSync<K, V, M, S>>(&key)? {
+            return Ok(JsonSyncHandle {
+                inner,
+                #[cfg(all(unix, feature = "signal"))]
+                signals: None,
+            });
+        }
+
+        self.serializer.check_keys::<K>()?;
+        // leftover temp files sit next to whichever file flushes replace
+        let source = if self.follow_symlinks {
+            resolve_symlinks(&self.path)?
+        } else {
+            self.path.clone()
+        };
+        if self.tombstones && self.merge_baseline {
+            return Err(Error::Config(
+                "tombstones can't be combined with merge_baseline".into(),
+            ));
+        }
+        if self.lazy_values {
+            let clash = [
+                (self.value_ttl, "value_ttl"),
+                (self.tombstones, "tombstones"),
+                (self.merge_baseline, "merge_baseline"),
+                (self.max_memory_bytes.is_some(), "max_memory_bytes"),
+                (self.index.is_some(), "index"),
+            ];
+            if let Some((_, other)) = clash.iter().find(|(on, _)| *on) {
+                return Err(Error::Config(format!(
+                    "lazy_values can't be combined with {other}"
+                )));
+            }
+        }
+        #[cfg(feature = "schedule")]
+        if let FlushPolicy::Scheduled(schedule) = &self.policy {
+            if schedule.next_after(self.clock.now()).is_none() {
+                return Err(Error::Config("the flush schedule never fires".into()));
+            }
+        }
+        let salvaging = !matches!(self.on_value_error, ValueErrorPolicy::FailFile);
+        if salvaging {
+            let clash = [
+                (self.value_ttl, "value_ttl"),
+                (self.tombstones, "tombstones"),
+                (self.lazy_values, "lazy_values"),
+            ];
+            if let Some((_, other)) = clash.iter().find(|(on, _)| *on) {
+                return Err(Error::Config(format!(
+                    "on_value_error can't be combined with {other}"
+                )));
+            }
+            if !self.serializer.reads_json() {
+                return Err(Error::Config(
+                    "on_value_error needs a serializer that reads JSON".into(),
+                ));
+            }
+        }
+        let (data, expiries, tombstones, unparsed) = if self.lazy_values {
+            let unparsed = self.load_source(&source)?;
+            (Vec::new(), HashMap::new(), HashMap::new(), unparsed)
+        } else if self.value_ttl {
+            if self.merge_baseline {
+                return Err(Error::Config(
+                    "value_ttl can't be combined with merge_baseline".into(),
+                ));
+            }
+            let (stored, tombstones) = self.load_live(&source)?;
+            let (data, expiries) = split_expired(stored, self.clock.now());
+            (data, expiries, tombstones, HashMap::new())
+        } else if salvaging {
+            let data = self.load_salvaging(&source)?;
+            (data, HashMap::new(), HashMap::new(), HashMap::new())
         } else {
-            JsonSerializer::new()
+            let (stored, tombstones) = self.load_live(&source)?;
+            let data = stored.into_iter().collect();
+            (data, HashMap::new(), tombstones, HashMap::new())
         };
+        // checked after loading, since recovery may have promoted a temp file
+        let created = !source.exists();
+        let handle = self.build_from(data, created, expiries, tombstones, unparsed)?;
+        #[cfg(feature = "single-instance")]
+        open.insert(key, &handle.inner);
+        Ok(handle)
+    }
 
-        let map = Arc::new(M::default());
+    /// [`load_source`](Self::load_source), setting the tombstones aside
+    /// under `tombstones`.
+    fn load_live<T: DeserializeOwned>(
+        &self,
+        source: &Path,
+    ) -> Result<(HashMap<K, T>, HashMap<K, u64>)> {
+        if !self.tombstones {
+            return Ok((self.load_source(source)?, HashMap::new()));
+        }
+        let mut live = HashMap::new();
+        let mut tombstones = HashMap::new();
+        for (k, entry) in self.load_source::<Marked<T>>(source)? {
+            match entry {
+                Marked::Deleted(t) => {
+                    tombstones.insert(k, t.ts);
+                }
+                Marked::Live(v) => {
+                    live.insert(k, v);
+                }
+            }
+        }
+        Ok((live, tombstones))
+    }
+
+    /// [`load_source`](Self::load_source) a value at a time, handing any
+    /// that doesn't parse as `V` to the
+    /// [`on_value_error`](Self::on_value_error) policy.
+    fn load_salvaging(&self, source: &Path) -> Result<Vec<(K, V)>> {
+        let values: HashMap<K, serde_json::Value> = self.load_source(source)?;
+        let mut data = Vec::with_capacity(values.len());
+        for (k, value) in values {
+            match self.serializer.deserialize_value(&value) {
+                Ok(v) => data.push((k, v)),
+                Err(e) => match &self.on_value_error {
+                    ValueErrorPolicy::FailFile => return Err(Error::Deserialize(e.to_string())),
+                    ValueErrorPolicy::SkipEntry => {}
+                    ValueErrorPolicy::Fallback(f) => {
+                        if let Some(v) = f(&k, value) {
+                            data.push((k, v));
+                        }
+                    }
+                },
+            }
+        }
+        Ok(data)
+    }
+
+    /// Read the map from `source`, with values as `T`, or from the
+    /// [`fallback_source`](Self::fallback_source) if `source` has nothing
+    /// usable.
+    fn load_source<T: DeserializeOwned>(&self, source: &Path) -> Result<HashMap<K, T>> {
+        let loaded = self.load_file(source, self.recover_temp);
+        let Some(fallback) = &self.fallback_source else {
+            return loaded;
+        };
+        match loaded {
+            Ok(map) if map.is_empty() && is_blank(source)? => self.load_file(fallback, false),
+            Err(Error::Deserialize(_)) => self.load_file(fallback, false),
+            loaded => loaded,
+        }
+    }
+
+    /// Read the map from `path`, promoting a leftover temp file if `recover`.
+    fn load_file<T: DeserializeOwned>(&self, path: &Path, recover: bool) -> Result<HashMap<K, T>> {
+        let max_depth = self.max_depth.filter(|_| self.serializer.reads_json());
+        if self.json_pointer.is_none() && max_depth.is_none() {
+            return load_recovering(path, &self.serializer, recover);
+        }
+        if let Some(pointer) = &self.json_pointer {
+            validate_pointer(pointer)?;
+        }
+        load_recovering_with(path, recover, |p| {
+            let raw = read_or_empty(p)?;
+            let bytes = decompressed(&raw)?;
+            let doc = match &self.json_pointer {
+                Some(pointer) => Cow::Owned(extract_pointer(&bytes, pointer)?),
+                None => bytes,
+            };
+            if let Some(max) = max_depth {
+                check_depth(&doc, max)?;
+            }
+            decode(&doc, &self.serializer)
+        })
+    }
 
-        let data = load::<K, V, _>(&self.path, &serializer)?;
+    /// Like [`build`](Self::build), but seeds the map with `data` instead of
+    /// reading the file. `created` says whether the file was missing;
+    /// `expiries` seeds the TTL table under `value_ttl`, `tombstones` the
+    /// deleted keys under `tombstones`, and `unparsed` the values left as
+    /// text under `lazy_values`.
+    fn build_from<I>(
+        self,
+        data: I,
+        created: bool,
+        expiries: HashMap<K, u64>,
+        tombstones: HashMap<K, u64>,
+        unparsed: HashMap<K, Box<RawValue>>,
+    ) -> Result<JsonSyncHandle<K, V, M, S>>
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        if self.target.is_some() && self.json_pointer.is_some() {
+            return Err(Error::Config(
+                "a custom target can't be combined with json_pointer".into(),
+            ));
+        }
+        let serializer = self.serializer;
+        let map = Arc::new(M::default());
         for (k, v) in data {
             map.insert(k, v);
         }
 
-        let (worker, trigger) = match &self.policy {
-            FlushPolicy::Async(interval) => {
-                let (tx, rx) = std::sync::mpsc::sync_channel(0);
-                let map_ref = Arc::clone(&map);
-                let path = self.path.clone();
-                let ser = serializer.clone();
-                let interval = *interval;
-                let w = AsyncFlushWorker::start_with_receiver(
-                    interval,
-                    move || {
-                        let _ = do_flush(map_ref.as_ref(), &path, &ser);
-                    },
-                    rx,
-                );
-                (Some(w), Some(Arc::new(tx)))
-            }
-            _ => (None, None),
+        let held = match self.max_memory_bytes {
+            Some(_) => total_size(map.as_ref()),
+            None => 0,
         };
 
-        let store = JsonSync {
-            map,
+        let audit = match &self.audit_log {
+            Some(path) => Some(AuditLog::open(path, self.audit_values, self.audit_fsync)?),
+            None => None,
+        };
+
+        let baseline = if self.merge_baseline {
+            let loaded: HashMap<K, V> = map.iter_snapshot().collect();
+            Some(parking_lot::Mutex::new(serializer.serialize(&loaded)?))
+        } else {
+            None
+        };
+
+        let persister = Arc::new(Persister {
             path: self.path,
             serializer,
+            write_buffer_size: self.write_buffer_size,
+            direct_io: self.direct_io,
+            delete_when_empty: self.delete_when_empty,
+            preallocate: self.preallocate,
+            last_size: AtomicU64::new(0),
+            on_flush: self.on_flush,
+            slow_flush: self.slow_flush,
+            history: parking_lot::Mutex::new(VecDeque::with_capacity(self.flush_history)),
+            history_len: self.flush_history,
+            paused: AtomicBool::new(false),
+            lock: parking_lot::Mutex::new(()),
+            serial: self
+                .serialized_writes
+                .then(|| parking_lot::ReentrantMutex::new(())),
+            baseline,
+            pointer: self.json_pointer,
+            follow_symlinks: self.follow_symlinks,
+            sink: self.target,
+            skip_unchanged: self.skip_unchanged_writes,
+            last_write: parking_lot::Mutex::new(None),
+        });
+
+        let sidecars = Sidecars {
+            ttl: self
+                .value_ttl
+                .then(|| Arc::new(parking_lot::Mutex::new(expiries))),
+            tombstones: self
+                .tombstones
+                .then(|| Arc::new(parking_lot::Mutex::new(tombstones))),
+            unparsed: self.lazy_values.then(|| Arc::new(Unparsed::new(unparsed))),
+        };
+        let (worker, trigger, syncs) = start_worker(
+            &self.policy,
+            &self.worker_thread,
+            self.park_worker_after,
+            &self.clock,
+            &map,
+            &persister,
+            &sidecars,
+        )?;
+        #[cfg(all(unix, feature = "signal"))]
+        let signals = register_signals(&self.flush_signals, &map, &persister, &sidecars)?;
+
+        let (sentinel, unclean) = if self.detect_unclean_shutdown {
+            let sentinel = sidecar_path(&persister.path, "lock");
+            let unclean = sentinel.exists();
+            std::fs::write(&sentinel, std::process::id().to_string())?;
+            (Some(sentinel), unclean)
+        } else {
+            (None, false)
+        };
+
+        let store = JsonSync {
+            map,
+            persister,
             policy: self.policy,
+            worker_thread: self.worker_thread,
+            park_worker_after: self.park_worker_after,
+            grown: AtomicUsize::new(0),
+            version: AtomicU64::new(0),
+            audit,
+            fingerprint: parking_lot::Mutex::new(None),
+            loader: self.loader,
+            index: self.index,
+            redactor: self.redactor,
+            max_value_bytes: self.max_value_bytes,
+            max_snapshot_entries: self.max_snapshot_entries,
+            max_memory_bytes: self.max_memory_bytes,
+            held: AtomicUsize::new(held),
+            sidecars,
+            clock: self.clock,
+            suspended: AtomicUsize::new(0),
+            sentinel,
+            unclean,
+            created,
+            deferred: parking_lot::Mutex::new(None),
+            update_locks: std::array::from_fn(|_| parking_lot::Mutex::new(())),
             trigger,
+            syncs,
+            worker,
+            #[cfg(all(unix, feature = "signal", feature = "single-instance"))]
+            parked: parking_lot::Mutex::default(),
             _marker: PhantomData,
         };
+        store.rebuild_index();
 
         Ok(JsonSyncHandle {
             inner: Arc::new(store),
-            worker,
+            #[cfg(all(unix, feature = "signal"))]
+            signals,
         })
     }
 }
 
-impl<K, V, M> std::fmt::Debug for JsonSyncBuilder<K, V, M> {
+impl<K, V, M, S> std::fmt::Debug for JsonSyncBuilder<K, V, M, S> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("JsonSyncBuilder")
             .field("path", &self.path)
             .field("policy", &self.policy)
-            .field("pretty", &self.pretty)
-            .finish()
+            .field("worker_thread", &self.worker_thread)
+            .field("audit_log", &self.audit_log)
+            .finish_non_exhaustive()
     }
 }
 
@@ -358,26 +3996,261 @@ impl<K, V, M> std::fmt::Debug for JsonSyncBuilder<K, V, M> {
 // Handle
 // ---------------------------------------------------------------------------
 
-/// Owns the store and (for async policy) the background flush thread.
+/// Owns the store and (for async policy) the background flush thread, which
+/// the store spawns on its first mutation.
 ///
 /// Derefs to [`JsonSync`] so you can call store methods directly on it.
-/// Dropping this will join the background thread if one is running, which may
-/// block for up to one flush interval.
-pub struct JsonSyncHandle<K, V, M> {
-    pub(crate) inner: Arc<JsonSync<K, V, M>>,
-    #[allow(dead_code)]
-    pub(crate) worker: Option<AsyncFlushWorker>,
+/// Dropping this wakes the background thread, if there is one, and joins
+/// it, so it only blocks for as long as a flush already in progress takes.
+/// Use [`shutdown`](Self::shutdown) for a bounded wait and a final flush.
+pub struct JsonSyncHandle<K, V, M, S = JsonSerializer> {
+    pub(crate) inner: Arc<JsonSync<K, V, M, S>>,
+    #[cfg(all(unix, feature = "signal"))]
+    pub(crate) signals: Option<crate::signal::Registration>,
+}
+
+impl<K, V, M, S> JsonSyncHandle<K, V, M, S>
+where
+    K: Hash + Eq + Send + Sync + Clone + Serialize + DeserializeOwned + 'static,
+    V: Send + Sync + Clone + Serialize + DeserializeOwned + 'static,
+    M: MapBackend<K, V> + 'static,
+    S: Serializer + 'static,
+{
+    /// Stop the background worker, waiting at most `timeout` for it to exit,
+    /// then do a final flush on the calling thread.
+    ///
+    /// Returns `Error::Io("worker did not stop in time")` if the worker is still
+    /// busy (e.g. stuck in a slow write) when the timeout runs out; in that case
+    /// no final flush is attempted. Without a worker this is just `flush()`.
+    pub fn shutdown(mut self, timeout: Duration) -> Result<()> {
+        if !self.release() {
+            return self.inner.flush();
+        }
+        if let Some(worker) = self.inner.worker.take() {
+            worker.stop();
+            if let Some(t) = &self.inner.trigger {
+                t.wake();
+            }
+            worker.join_timeout(timeout)?;
+        }
+        self.inner.flush()
+    }
+
+    /// Block until everything done to the store before this call is on disk.
+    ///
+    /// Under the async policies this nudges the worker and waits for it to
+    /// finish a flush that started after the call, returning that flush's
+    /// error if it failed; unlike [`flush`](JsonSync::flush), it doesn't
+    /// write from this thread alongside the worker. Under the other policies
+    /// it's just `flush()`. Don't call it from an
+    /// [`on_flush`](JsonSyncBuilder::on_flush) hook — the worker would be
+    /// waiting on itself.
+    pub fn sync(&self) -> Result<()> {
+        let Some(trigger) = &self.inner.trigger else {
+            return self.inner.flush();
+        };
+        if !self.inner.worker.is_running() {
+            // not spawned yet, or already stopped
+            return self.inner.flush();
+        }
+        let ticket = self.inner.syncs.ticket();
+        if !trigger.nudge_blocking() {
+            // the worker has already gone; nobody else will write it
+            return self.inner.flush();
+        }
+        self.inner.syncs.wait(ticket)
+    }
+
+    /// Stop the background worker flushing until
+    /// [`resume_flushing`](Self::resume_flushing) — say, for a bulk import
+    /// whose half-done states aren't worth writing. The worker still wakes
+    /// but skips its writes, and changes stay in memory.
+    /// [`flush`](JsonSync::flush), [`sync`](Self::sync), and
+    /// [`shutdown`](Self::shutdown) still write, and dropping the handle
+    /// stops the worker as usual.
+    ///
+    /// Only the async and scheduled policies have a worker to pause; under
+    /// the others, [`suspend_flush`](JsonSync::suspend_flush) holds flushes
+    /// back instead.
+    pub fn pause_flushing(&self) {
+        self.inner.persister.paused.store(true, Ordering::Release);
+    }
+
+    /// Let the worker flush again after
+    /// [`pause_flushing`](Self::pause_flushing), and nudge it so everything
+    /// changed during the pause is written now rather than on its next tick.
+    /// Does nothing if it isn't paused.
+    pub fn resume_flushing(&self) {
+        if self.inner.persister.paused.swap(false, Ordering::AcqRel) {
+            if let Some(t) = &self.inner.trigger {
+                t.nudge();
+            }
+        }
+    }
+
+    /// Move the store onto a different map backend, keeping its path, flush
+    /// policy, and the rest of its configuration. The current contents are
+    /// copied into a fresh `M2`; nothing is written to disk, so unflushed
+    /// changes stay unflushed and follow the policy from there.
+    ///
+    /// ```rust,no_run
+    /// # use json_sync::JsonSync;
+    /// # use parking_lot::RwLock;
+    /// # use shardmap::ShardMap;
+    /// # use std::collections::HashMap;
+    /// let db = JsonSync::<String, i32, RwLock<HashMap<String, i32>>>::open("db.json").unwrap();
+    /// let db = db.convert_to::<ShardMap<String, i32>>().unwrap();
+    /// ```
+    pub fn convert_to<M2>(mut self) -> Result<JsonSyncHandle<K, V, M2, S>>
+    where
+        M2: MapBackend<K, V> + Default + 'static,
+    {
+        if !self.release() {
+            return Err(Error::Config(
+                "convert_to: the store is still borrowed elsewhere".into(),
+            ));
+        }
+        if let Some(worker) = self.inner.worker.take() {
+            worker.stop();
+            if let Some(inner) = Arc::get_mut(&mut self.inner) {
+                inner.trigger = None;
+            }
+            drop(worker);
+        }
+        let Some(old) = Arc::get_mut(&mut self.inner) else {
+            return Err(Error::Config(
+                "convert_to: the store is still borrowed elsewhere".into(),
+            ));
+        };
+
+        let map = Arc::new(M2::default());
+        for (k, v) in old.map.iter_snapshot() {
+            map.insert(k, v);
+        }
+        let persister = Arc::clone(&old.persister);
+        let sidecars = std::mem::take(&mut old.sidecars);
+        let (worker, trigger, syncs) = start_worker(
+            &old.policy,
+            &old.worker_thread,
+            old.park_worker_after,
+            &old.clock,
+            &map,
+            &persister,
+            &sidecars,
+        )?;
+        // re-register so the signal flush reads the new map, not the old one
+        #[cfg(all(unix, feature = "signal"))]
+        let signals = match self.signals.take() {
+            Some(old) => register_signals(old.signals(), &map, &persister, &sidecars)?,
+            None => None,
+        };
+        let store = JsonSync {
+            map,
+            persister,
+            policy: old.policy.clone(),
+            worker_thread: std::mem::take(&mut old.worker_thread),
+            park_worker_after: old.park_worker_after,
+            grown: AtomicUsize::new(old.grown.load(Ordering::Relaxed)),
+            version: AtomicU64::new(old.version.load(Ordering::Acquire)),
+            audit: old.audit.take(),
+            fingerprint: parking_lot::Mutex::new(None),
+            loader: old.loader.take(),
+            index: old.index.take(),
+            redactor: old.redactor.take(),
+            max_value_bytes: old.max_value_bytes,
+            max_snapshot_entries: old.max_snapshot_entries,
+            max_memory_bytes: old.max_memory_bytes,
+            held: AtomicUsize::new(old.held.load(Ordering::Relaxed)),
+            sidecars,
+            clock: Arc::clone(&old.clock),
+            suspended: AtomicUsize::new(0),
+            // the new handle owns the sentinel now; the old one mustn't remove it
+            sentinel: old.sentinel.take(),
+            unclean: old.unclean,
+            created: old.created,
+            deferred: parking_lot::Mutex::new(None),
+            update_locks: std::array::from_fn(|_| parking_lot::Mutex::new(())),
+            trigger,
+            syncs,
+            worker,
+            #[cfg(all(unix, feature = "signal", feature = "single-instance"))]
+            parked: parking_lot::Mutex::default(),
+            _marker: PhantomData,
+        };
+        store.rebuild_index();
+        let inner = Arc::new(store);
+        #[cfg(feature = "single-instance")]
+        crate::registry::lock().insert(crate::registry::key(&inner.persister.path), &inner);
+        Ok(JsonSyncHandle {
+            inner,
+            #[cfg(all(unix, feature = "signal"))]
+            signals,
+        })
+    }
+}
+
+impl<K, V, M, S> JsonSyncHandle<K, V, M, S> {
+    /// Whether this handle is the last one on its store and should tear it
+    /// down. Always `true` unless `single-instance` lets handles share a
+    /// store; then a handle that isn't last parks its signal hooks in the
+    /// store for whichever is, and the last one takes them back, stops the
+    /// worker, and drops the store from the registry so later opens start
+    /// fresh.
+    fn release(&mut self) -> bool {
+        #[cfg(feature = "single-instance")]
+        {
+            let mut open = crate::registry::lock();
+            if Arc::strong_count(&self.inner) > 1 {
+                #[cfg(all(unix, feature = "signal"))]
+                if self.signals.is_some() {
+                    self.inner.parked.lock().signals = self.signals.take();
+                }
+                return false;
+            }
+            #[cfg(all(unix, feature = "signal"))]
+            if self.signals.is_none() {
+                self.signals = self.inner.parked.lock().signals.take();
+            }
+            open.remove(&self.inner);
+        }
+        true
+    }
+}
+
+impl<K, V, M, S> Drop for JsonSyncHandle<K, V, M, S> {
+    fn drop(&mut self) {
+        if !self.release() {
+            return;
+        }
+        if let Some(worker) = self.inner.worker.take() {
+            worker.stop();
+            // Drop the store's sender before joining so the worker sees the
+            // channel disconnect instead of sleeping out the rest of its interval.
+            match Arc::get_mut(&mut self.inner) {
+                Some(inner) => inner.trigger = None,
+                None => {
+                    if let Some(t) = &self.inner.trigger {
+                        t.wake();
+                    }
+                }
+            }
+            drop(worker);
+        }
+        if let Some(sentinel) = &self.inner.sentinel {
+            let _ = std::fs::remove_file(sentinel);
+        }
+    }
 }
 
-impl<K, V, M> std::ops::Deref for JsonSyncHandle<K, V, M> {
-    type Target = JsonSync<K, V, M>;
+impl<K, V, M, S> std::ops::Deref for JsonSyncHandle<K, V, M, S> {
+    type Target = JsonSync<K, V, M, S>;
 
     fn deref(&self) -> &Self::Target {
         &self.inner
     }
 }
 
-impl<K, V, M> std::fmt::Debug for JsonSyncHandle<K, V, M> {
+impl<K, V, M, S> std::fmt::Debug for JsonSyncHandle<K, V, M, S> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         std::fmt::Debug::fmt(&*self.inner, f)
     }
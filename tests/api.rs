@@ -53,6 +53,68 @@ fn keys_and_values() {
     let _ = std::fs::remove_file(&path);
 }
 
+#[test]
+fn entries_iter_stops_early() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static CLONES: AtomicUsize = AtomicUsize::new(0);
+
+    #[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+    struct Counted(u32);
+
+    impl Clone for Counted {
+        fn clone(&self) -> Self {
+            CLONES.fetch_add(1, Ordering::SeqCst);
+            Counted(self.0)
+        }
+    }
+
+    let path = temp_path("entries_iter");
+    let _ = std::fs::remove_file(&path);
+    let db = JsonSync::<String, Counted, ShardMap<String, Counted>>::open(&path).unwrap();
+    db.extend((0..10_000).map(|i| (format!("k{i}"), Counted(i))))
+        .unwrap();
+
+    CLONES.store(0, Ordering::SeqCst);
+    assert_eq!(db.entries_iter().take(2).count(), 2);
+    assert_eq!(CLONES.load(Ordering::SeqCst), 2);
+
+    assert!(db.keys_iter().any(|k| k == "k9999"));
+    assert_eq!(db.values_iter().filter(|v| v.0 < 10).count(), 10);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn try_iter_refuses_snapshots_past_the_cap() {
+    let path = temp_path("max_snapshot_entries");
+    let _ = std::fs::remove_file(&path);
+    let db = JsonSync::<String, i32, ShardMap<String, i32>>::builder(&path)
+        .max_snapshot_entries(3)
+        .build()
+        .unwrap();
+    db.extend((0..3).map(|i| (format!("k{i}"), i))).unwrap();
+    assert_eq!(db.try_iter().unwrap().len(), 3);
+
+    db.insert("k3".into(), 3).unwrap();
+    let err = db.try_iter().unwrap_err();
+    assert!(matches!(err, json_sync::Error::Config(_)));
+    assert!(err.to_string().contains("use entries_iter()"), "{err}");
+    assert!(db
+        .try_keys()
+        .unwrap_err()
+        .to_string()
+        .contains("keys_iter()"));
+    assert!(db
+        .try_values()
+        .unwrap_err()
+        .to_string()
+        .contains("values_iter()"));
+    // the streaming and unchecked calls still see everything
+    assert_eq!(db.entries_iter().count(), 4);
+    assert_eq!(db.iter().len(), 4);
+    let _ = std::fs::remove_file(&path);
+}
+
 // ---- extend -----------------------------------------------------------------
 
 #[test]
@@ -82,6 +144,48 @@ fn extend_overwrites_existing() {
     let _ = std::fs::remove_file(&path);
 }
 
+// ---- warm_from --------------------------------------------------------------
+
+#[test]
+fn warm_from_copies_another_store_and_flushes_once() {
+    use parking_lot::RwLock;
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let old_path = temp_path("warm_from_old");
+    let new_path = temp_path("warm_from_new");
+    let _ = std::fs::remove_file(&old_path);
+    let _ = std::fs::remove_file(&new_path);
+    let old = JsonSync::<String, i32, ShardMap<String, i32>>::open(&old_path).unwrap();
+    old.extend((0..100).map(|i| (format!("k{i}"), i))).unwrap();
+
+    let flushes = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&flushes);
+    let new = JsonSync::<String, i32, RwLock<HashMap<String, i32>>>::builder(&new_path)
+        .policy(FlushPolicy::Immediate)
+        .on_flush(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        })
+        .build()
+        .unwrap();
+    new.warm_from(&old).unwrap();
+    assert_eq!(flushes.load(Ordering::SeqCst), 1);
+
+    let mut want = old.iter();
+    let mut got = new.iter();
+    want.sort();
+    got.sort();
+    assert_eq!(got, want);
+    assert_eq!(old.len(), 100);
+    assert!(!old_path.exists());
+    drop(new);
+    let reopened = JsonSync::<String, i32, ShardMap<String, i32>>::open(&new_path).unwrap();
+    assert_eq!(reopened.len(), 100);
+    assert_eq!(reopened.get(&"k42".into()), Some(42));
+    let _ = std::fs::remove_file(&new_path);
+}
+
 // ---- update -----------------------------------------------------------------
 
 #[test]
@@ -164,6 +268,45 @@ fn get_or_insert_with_when_absent() {
     let _ = std::fs::remove_file(&path);
 }
 
+// ---- apply ------------------------------------------------------------------
+
+#[test]
+fn apply_runs_ops_in_order_with_one_flush() {
+    use json_sync::Op;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let path = temp_path("apply");
+    let _ = std::fs::remove_file(&path);
+    let flushes = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&flushes);
+    let db = JsonSync::<String, i32, ShardMap<String, i32>>::builder(&path)
+        .policy(FlushPolicy::Immediate)
+        .on_flush(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        })
+        .build()
+        .unwrap();
+    db.insert("stale".into(), 0).unwrap();
+    flushes.store(0, Ordering::SeqCst);
+
+    db.apply(vec![
+        Op::Insert("a".into(), 1),
+        Op::Clear,
+        Op::Insert("b".into(), 2),
+        Op::Insert("c".into(), 3),
+        Op::Remove("b".into()),
+        Op::Insert("c".into(), 30),
+    ])
+    .unwrap();
+
+    assert_eq!(flushes.load(Ordering::SeqCst), 1);
+    let mut entries = db.iter();
+    entries.sort();
+    assert_eq!(entries, vec![("c".to_string(), 30)]);
+    let _ = std::fs::remove_file(&path);
+}
+
 // ---- builder ----------------------------------------------------------------
 
 #[test]
@@ -236,6 +379,145 @@ fn builder_with_async_policy() {
     let _ = std::fs::remove_file(&path);
 }
 
+#[test]
+fn builder_reject_duplicate_keys() {
+    let path = temp_path("builder_dup_keys");
+    std::fs::write(&path, r#"{"a": 1, "b": 2, "a": 3}"#).unwrap();
+
+    // default: last one wins
+    let db = JsonSync::<String, i32, ShardMap<String, i32>>::open(&path).unwrap();
+    assert_eq!(db.get(&"a".into()), Some(3));
+    drop(db);
+
+    let err = JsonSync::<String, i32, ShardMap<String, i32>>::builder(&path)
+        .reject_duplicate_keys(true)
+        .build()
+        .unwrap_err();
+    assert!(matches!(err, json_sync::Error::Deserialize(ref m) if m.contains("duplicate key")));
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn on_duplicate_key_picks_the_winner_in_pairs_files() {
+    use json_sync::DuplicatePolicy;
+
+    let path = temp_path("builder_dup_pairs");
+    std::fs::write(&path, r#"[["a", 1], ["b", 2], ["a", 3]]"#).unwrap();
+    let open = |policy| {
+        JsonSync::<String, i32, ShardMap<String, i32>>::builder(&path)
+            .as_pairs(true)
+            .on_duplicate_key(policy)
+            .build()
+    };
+
+    let db = open(DuplicatePolicy::LastWins).unwrap();
+    assert_eq!(db.get(&"a".into()), Some(3));
+    assert_eq!(db.len(), 2);
+    drop(db);
+
+    let db = open(DuplicatePolicy::FirstWins).unwrap();
+    assert_eq!(db.get(&"a".into()), Some(1));
+    assert_eq!(db.get(&"b".into()), Some(2));
+    drop(db);
+
+    let err = open(DuplicatePolicy::Error).unwrap_err();
+    assert!(matches!(err, json_sync::Error::Deserialize(ref m) if m.contains("duplicate key")));
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn builder_rejects_a_directory_or_a_file_as_parent() {
+    let dir = std::env::temp_dir().join("json_sync_test_dir_as_path");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let err = JsonSync::<String, i32, ShardMap<String, i32>>::open(&dir).unwrap_err();
+    match err {
+        json_sync::Error::Config(msg) => {
+            assert!(
+                msg.starts_with("path is a directory, expected a file"),
+                "{msg}"
+            );
+            assert!(msg.contains(&dir.display().to_string()), "{msg}");
+        }
+        e => panic!("expected Error::Config, got {e:?}"),
+    }
+
+    let file = dir.join("db.json");
+    std::fs::write(&file, "{}").unwrap();
+    for path in [file.join("inner.json"), file.join("a").join("b.json")] {
+        let err = JsonSync::<String, i32, ShardMap<String, i32>>::builder(&path)
+            .canonicalize_path(true)
+            .build()
+            .unwrap_err();
+        assert!(
+            matches!(err, json_sync::Error::Config(ref m) if m.contains("parent is a file")),
+            "{err:?}"
+        );
+    }
+    // the file itself is still a fine store
+    assert!(JsonSync::<String, i32, ShardMap<String, i32>>::open(&file).is_ok());
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+// ---- audit log --------------------------------------------------------------
+
+#[test]
+fn audit_log_records_each_mutation() {
+    let path = temp_path("audit_data");
+    let log = temp_path("audit_log");
+    let _ = std::fs::remove_file(&path);
+    let _ = std::fs::remove_file(&log);
+
+    let db = JsonSync::<String, i32, ShardMap<String, i32>>::builder(&path)
+        .audit_log(&log)
+        .build()
+        .unwrap();
+    db.insert("a".into(), 1).unwrap();
+    db.insert("b".into(), 2).unwrap();
+    db.update(&"a".into(), |v| *v += 1).unwrap();
+    db.remove(&"b".into()).unwrap();
+    db.clear().unwrap();
+    let _ = db.get(&"a".into());
+
+    let raw = std::fs::read_to_string(&log).unwrap();
+    let lines: Vec<serde_json::Value> = raw
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    let ops: Vec<&str> = lines.iter().map(|l| l["op"].as_str().unwrap()).collect();
+    assert_eq!(ops, vec!["insert", "insert", "update", "remove", "clear"]);
+    assert_eq!(lines[0]["key"], "a");
+    assert!(lines[0]["ts"].is_u64());
+    // values are redacted unless asked for
+    assert!(lines[0].get("value").is_none());
+
+    // the data file is untouched by auditing (manual policy, never flushed)
+    assert!(!path.exists());
+    let _ = std::fs::remove_file(&log);
+}
+
+#[test]
+fn audit_log_can_include_values() {
+    let path = temp_path("audit_values_data");
+    let log = temp_path("audit_values_log");
+    let _ = std::fs::remove_file(&path);
+    let _ = std::fs::remove_file(&log);
+
+    let db = JsonSync::<String, i32, ShardMap<String, i32>>::builder(&path)
+        .audit_log(&log)
+        .audit_values(true)
+        .audit_fsync(true)
+        .build()
+        .unwrap();
+    db.insert("k".into(), 42).unwrap();
+
+    let raw = std::fs::read_to_string(&log).unwrap();
+    let line: serde_json::Value = serde_json::from_str(raw.trim()).unwrap();
+    assert_eq!(line["value"], 42);
+    let _ = std::fs::remove_file(&log);
+}
+
 // ---- debug ------------------------------------------------------------------
 
 #[test]
@@ -275,3 +557,1646 @@ fn clear_then_flush_persists_empty() {
     assert!(db.is_empty());
     let _ = std::fs::remove_file(&path);
 }
+
+// ---- reset ------------------------------------------------------------------
+
+#[test]
+fn reset_replaces_contents_with_one_flush() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let path = temp_path("reset");
+    let _ = std::fs::remove_file(&path);
+    let flushes = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&flushes);
+
+    let db = JsonSync::<String, i32, ShardMap<String, i32>>::builder(&path)
+        .policy(FlushPolicy::Immediate)
+        .on_flush(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        })
+        .build()
+        .unwrap();
+    db.extend(vec![("old1".into(), 1), ("old2".into(), 2)])
+        .unwrap();
+    flushes.store(0, Ordering::SeqCst);
+
+    db.reset(vec![
+        ("new1".into(), 10),
+        ("new2".into(), 20),
+        ("new3".into(), 30),
+    ])
+    .unwrap();
+    assert_eq!(flushes.load(Ordering::SeqCst), 1);
+    assert_eq!(db.len(), 3);
+    assert_eq!(db.get(&"old1".into()), None);
+    assert_eq!(db.get(&"new2".into()), Some(20));
+    drop(db);
+
+    let db = JsonSync::<String, i32, ShardMap<String, i32>>::open(&path).unwrap();
+    let mut keys = db.keys();
+    keys.sort();
+    assert_eq!(keys, vec!["new1".to_string(), "new2".into(), "new3".into()]);
+    let _ = std::fs::remove_file(&path);
+}
+
+// ---- open_migrating ---------------------------------------------------------
+
+#[test]
+fn open_migrating_converts_old_values() {
+    #[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct User {
+        name: String,
+        admin: bool,
+    }
+
+    let path = temp_path("open_migrating");
+    std::fs::write(&path, r#"{"u1": "ada", "u2": "grace"}"#).unwrap();
+
+    let db =
+        JsonSync::<String, User, ShardMap<String, User>>::open_migrating(&path, |name: String| {
+            User {
+                admin: name == "ada",
+                name,
+            }
+        })
+        .unwrap();
+    assert_eq!(
+        db.get(&"u1".into()),
+        Some(User {
+            name: "ada".into(),
+            admin: true
+        })
+    );
+    db.flush().unwrap();
+    drop(db);
+
+    // after the flush the file holds the new shape
+    let db = JsonSync::<String, User, ShardMap<String, User>>::open(&path).unwrap();
+    assert_eq!(db.get(&"u2".into()).map(|u| u.admin), Some(false));
+    let _ = std::fs::remove_file(&path);
+}
+
+// ---- flush_if_changed -------------------------------------------------------
+
+#[test]
+fn flush_if_changed_skips_when_nothing_changed() {
+    use json_sync::ChangeCheck;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let path = temp_path("flush_if_changed");
+    let _ = std::fs::remove_file(&path);
+    let flushes = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&flushes);
+    let db = JsonSync::<String, i32, ShardMap<String, i32>>::builder(&path)
+        .on_flush(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        })
+        .build()
+        .unwrap();
+
+    db.insert("a".into(), 1).unwrap();
+    assert!(db.flush_if_changed(ChangeCheck::Len).unwrap());
+    assert!(!db.flush_if_changed(ChangeCheck::Len).unwrap());
+
+    // same count, different value: only the hash notices
+    db.insert("a".into(), 2).unwrap();
+    assert!(!db.flush_if_changed(ChangeCheck::Len).unwrap());
+    assert!(db.flush_if_changed(ChangeCheck::Hash).unwrap());
+    assert!(!db.flush_if_changed(ChangeCheck::Hash).unwrap());
+    assert_eq!(flushes.load(Ordering::SeqCst), 2);
+
+    let reopened = JsonSync::<String, i32, ShardMap<String, i32>>::open(&path).unwrap();
+    assert_eq!(reopened.get(&"a".into()), Some(2));
+    let _ = std::fs::remove_file(&path);
+}
+
+// ---- direct_io --------------------------------------------------------------
+
+#[cfg(target_os = "linux")]
+#[test]
+fn direct_io_flush_roundtrips() {
+    let path = temp_path("direct_io");
+    let _ = std::fs::remove_file(&path);
+    {
+        let db = JsonSync::<String, String, ShardMap<String, String>>::builder(&path)
+            .direct_io(true)
+            .build()
+            .unwrap();
+        // a bit over one block, so the padding has to be trimmed off
+        db.extend((0..300).map(|i| (format!("key{i}"), "x".repeat(i % 7))))
+            .unwrap();
+        db.flush().unwrap();
+    }
+    let len = std::fs::metadata(&path).unwrap().len();
+    assert!(len > 4096 && len % 4096 != 0);
+
+    let db = JsonSync::<String, String, ShardMap<String, String>>::open(&path).unwrap();
+    assert_eq!(db.len(), 300);
+    assert_eq!(db.get(&"key13".into()), Some("xxxxxx".into()));
+    let _ = std::fs::remove_file(&path);
+}
+
+// ---- leftover temp files ----------------------------------------------------
+
+fn temp_file_of(path: &std::path::Path) -> std::path::PathBuf {
+    path.with_extension("json.tmp")
+}
+
+#[test]
+fn stale_temp_file_is_deleted_when_main_is_valid() {
+    let path = temp_path("stale_tmp");
+    let tmp = temp_file_of(&path);
+    std::fs::write(&path, r#"{"a": 1}"#).unwrap();
+    std::fs::write(&tmp, r#"{"a": 2}"#).unwrap();
+
+    let db = JsonSync::<String, i32, ShardMap<String, i32>>::open(&path).unwrap();
+    assert_eq!(db.get(&"a".into()), Some(1));
+    assert!(!tmp.exists());
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn recover_temp_promotes_temp_over_missing_or_corrupt_main() {
+    for (name, main) in [
+        ("recover_missing", None),
+        ("recover_corrupt", Some("{not json")),
+    ] {
+        let path = temp_path(name);
+        let tmp = temp_file_of(&path);
+        let _ = std::fs::remove_file(&path);
+        if let Some(main) = main {
+            std::fs::write(&path, main).unwrap();
+        }
+        std::fs::write(&tmp, r#"{"a": 2}"#).unwrap();
+
+        let db = JsonSync::<String, i32, ShardMap<String, i32>>::builder(&path)
+            .recover_temp(true)
+            .build()
+            .unwrap();
+        assert_eq!(db.get(&"a".into()), Some(2), "{name}");
+        assert!(!tmp.exists());
+        assert!(std::fs::read_to_string(&path).unwrap().contains("2"));
+        let _ = std::fs::remove_file(&path);
+    }
+}
+
+#[test]
+fn temp_file_is_kept_without_recover_temp() {
+    let path = temp_path("recover_off");
+    let tmp = temp_file_of(&path);
+    std::fs::write(&path, "{not json").unwrap();
+    std::fs::write(&tmp, r#"{"a": 2}"#).unwrap();
+
+    let result = JsonSync::<String, i32, ShardMap<String, i32>>::open(&path);
+    assert!(matches!(result, Err(json_sync::Error::Deserialize(_))));
+    assert!(tmp.exists());
+    let _ = std::fs::remove_file(&path);
+    let _ = std::fs::remove_file(&tmp);
+}
+
+#[test]
+fn cleanup_temp_files_removes_only_stale_ones() {
+    use std::time::SystemTime;
+
+    let dir = std::env::temp_dir().join("json_sync_test_cleanup_tmp");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("nested.json.tmp")).unwrap();
+    let aged = |name: &str, age: Duration| {
+        let path = dir.join(name);
+        let file = std::fs::File::create(&path).unwrap();
+        file.set_modified(SystemTime::now() - age).unwrap();
+        path
+    };
+    let hour = Duration::from_secs(3600);
+    let stale = aged("old.json.tmp", 2 * hour);
+    let stale_gz = aged("old.json.gz.tmp", 3 * hour);
+    let fresh = aged("new.json.tmp", Duration::ZERO);
+    let data = aged("old.json", 2 * hour);
+    aged("old.json.gz", 2 * hour);
+    aged("new.json", 2 * hour);
+    let unrelated = aged("notes.tmp", 2 * hour);
+    // another program's, with no file of ours beside it
+    let foreign = aged("report.pdf.tmp", 2 * hour);
+
+    let removed = json_sync::persist::cleanup_temp_files(&dir, hour).unwrap();
+    assert_eq!(removed, 2);
+    assert!(!stale.exists());
+    assert!(!stale_gz.exists());
+    assert!(fresh.exists());
+    assert!(data.exists());
+    assert!(unrelated.exists());
+    assert!(foreign.exists());
+    assert!(dir.join("nested.json.tmp").is_dir());
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+// ---- fallback_source --------------------------------------------------------
+
+#[test]
+fn fallback_source_seeds_a_missing_or_corrupt_store() {
+    let defaults = temp_path("fallback_defaults");
+    std::fs::write(&defaults, r#"{"theme": 1, "volume": 7}"#).unwrap();
+    let open = |path: &std::path::Path| {
+        JsonSync::<String, i32, ShardMap<String, i32>>::builder(path)
+            .fallback_source(&defaults)
+            .build()
+    };
+
+    for (name, main) in [
+        ("fallback_missing", None),
+        ("fallback_blank", Some("\n")),
+        ("fallback_corrupt", Some("{not json")),
+    ] {
+        let path = temp_path(name);
+        let _ = std::fs::remove_file(&path);
+        if let Some(main) = main {
+            std::fs::write(&path, main).unwrap();
+        }
+        let db = open(&path).unwrap();
+        assert_eq!(db.len(), 2, "{name}");
+        assert_eq!(db.get(&"volume".into()), Some(7), "{name}");
+
+        // the first flush writes the defaults out as the store's own
+        db.insert("volume".into(), 3).unwrap();
+        db.flush().unwrap();
+        drop(db);
+        let db = JsonSync::<String, i32, ShardMap<String, i32>>::open(&path).unwrap();
+        assert_eq!(db.get(&"theme".into()), Some(1), "{name}");
+        assert_eq!(db.get(&"volume".into()), Some(3), "{name}");
+        drop(db);
+        let _ = std::fs::remove_file(&path);
+    }
+    assert_eq!(
+        std::fs::read_to_string(&defaults).unwrap(),
+        r#"{"theme": 1, "volume": 7}"#
+    );
+
+    // a store that's just empty keeps being empty
+    let path = temp_path("fallback_empty_object");
+    std::fs::write(&path, "{}").unwrap();
+    assert!(open(&path).unwrap().is_empty());
+
+    // and a fallback that doesn't parse either is an error
+    std::fs::write(&defaults, "[oops").unwrap();
+    std::fs::write(&path, "{not json").unwrap();
+    assert!(matches!(open(&path), Err(json_sync::Error::Deserialize(_))));
+    let _ = std::fs::remove_file(&path);
+    let _ = std::fs::remove_file(&defaults);
+}
+
+// ---- get_or_load ------------------------------------------------------------
+
+#[test]
+fn get_or_load_caches_loader_results() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let path = temp_path("get_or_load");
+    let _ = std::fs::remove_file(&path);
+    let calls = Arc::new(AtomicUsize::new(0));
+    let seen = Arc::clone(&calls);
+    let db = JsonSync::<String, usize, ShardMap<String, usize>>::builder(&path)
+        .loader(move |key: &String| {
+            seen.fetch_add(1, Ordering::SeqCst);
+            (!key.starts_with("missing")).then_some(key.len())
+        })
+        .build()
+        .unwrap();
+    db.insert("local".into(), 99).unwrap();
+
+    assert_eq!(db.get_or_load(&"local".into()).unwrap(), Some(99));
+    assert_eq!(calls.load(Ordering::SeqCst), 0);
+
+    assert_eq!(db.get_or_load(&"abc".into()).unwrap(), Some(3));
+    assert_eq!(db.get_or_load(&"abc".into()).unwrap(), Some(3));
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+    assert_eq!(db.get(&"abc".into()), Some(3));
+
+    assert_eq!(db.get_or_load(&"missing".into()).unwrap(), None);
+    assert!(!db.contains_key(&"missing".into()));
+    let _ = std::fs::remove_file(&path);
+}
+
+// ---- index ------------------------------------------------------------------
+
+#[test]
+fn index_follows_every_kind_of_write() {
+    #[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct User {
+        name: String,
+        team: String,
+    }
+    let user = |name: &str, team: &str| User {
+        name: name.into(),
+        team: team.into(),
+    };
+    let ids = |found: Vec<(u32, User)>| {
+        let mut ids: Vec<u32> = found.into_iter().map(|(k, _)| k).collect();
+        ids.sort_unstable();
+        ids
+    };
+
+    let path = temp_path("index");
+    let _ = std::fs::remove_file(&path);
+    let open = || {
+        JsonSync::<u32, User, ShardMap<u32, User>>::builder(&path)
+            .index(|u: &User| u.team.clone())
+            .build()
+            .unwrap()
+    };
+    let db = open();
+    db.insert(1, user("ada", "core")).unwrap();
+    db.insert(2, user("bob", "core")).unwrap();
+    db.extend([(3, user("cy", "docs")), (4, user("di", "ops"))])
+        .unwrap();
+    assert_eq!(ids(db.get_by_index(&"core".to_string())), [1, 2]);
+    assert_eq!(db.get_by_index(&"core".to_string())[0].1.team, "core");
+
+    db.update(&2, |u| u.team = "docs".into()).unwrap();
+    db.insert(1, user("ada", "ops")).unwrap();
+    db.remove(&4).unwrap();
+    assert!(db.get_by_index(&"core".to_string()).is_empty());
+    assert_eq!(ids(db.get_by_index(&"docs".to_string())), [2, 3]);
+    assert_eq!(ids(db.get_by_index(&"ops".to_string())), [1]);
+    // wrong key type: nothing, rather than a panic
+    assert!(db.get_by_index(&7u8).is_empty());
+
+    db.flush().unwrap();
+    drop(db);
+    let db = open();
+    assert_eq!(ids(db.get_by_index(&"docs".to_string())), [2, 3]);
+
+    db.clear().unwrap();
+    assert!(db.get_by_index(&"docs".to_string()).is_empty());
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn index_refuses_lazy_values() {
+    let path = temp_path("index_lazy");
+    let err = JsonSync::<String, String, ShardMap<String, String>>::builder(&path)
+        .index(|v: &String| v.len())
+        .lazy_values(true)
+        .build()
+        .unwrap_err();
+    assert!(err.to_string().contains("index"), "{err}");
+}
+
+// ---- changes_since ----------------------------------------------------------
+
+#[test]
+fn changes_since_classifies_differences() {
+    use std::collections::HashMap;
+
+    let path = temp_path("changes_since");
+    let _ = std::fs::remove_file(&path);
+    let db = JsonSync::<String, i32, ShardMap<String, i32>>::open(&path).unwrap();
+    db.extend(vec![
+        ("same".into(), 1),
+        ("edited".into(), 20),
+        ("new".into(), 3),
+    ])
+    .unwrap();
+    let base: HashMap<String, i32> = [
+        ("same".to_string(), 1),
+        ("edited".to_string(), 2),
+        ("gone".to_string(), 4),
+    ]
+    .into();
+
+    let changes = db.changes_since(&base);
+    assert_eq!(changes.added, vec![("new".to_string(), 3)]);
+    assert_eq!(changes.removed, vec!["gone".to_string()]);
+    assert_eq!(changes.changed, vec![("edited".to_string(), 20)]);
+    assert!(!changes.is_empty());
+
+    let current: HashMap<String, i32> = db.iter().into_iter().collect();
+    assert!(db.changes_since(&current).is_empty());
+    let _ = std::fs::remove_file(&path);
+}
+
+// ---- unclean shutdown sentinel ----------------------------------------------
+
+// a leaked handle keeps its store registered, so under single-instance the
+// reopen below would share it instead of finding the sentinel
+#[cfg(not(feature = "single-instance"))]
+#[test]
+fn leaked_handle_is_reported_as_unclean_shutdown() {
+    let path = temp_path("unclean_shutdown");
+    let lock = path.with_extension("json.lock");
+    let _ = std::fs::remove_file(&path);
+    let _ = std::fs::remove_file(&lock);
+    let open = || {
+        JsonSync::<String, i32, ShardMap<String, i32>>::builder(&path)
+            .detect_unclean_shutdown(true)
+            .build()
+            .unwrap()
+    };
+
+    let db = open();
+    assert!(!db.was_unclean_shutdown());
+    assert!(lock.exists());
+    std::mem::forget(db); // "crash": the handle never gets to clean up
+
+    let db = open();
+    assert!(db.was_unclean_shutdown());
+    drop(db);
+    assert!(!lock.exists());
+
+    let db = open();
+    assert!(!db.was_unclean_shutdown());
+    drop(db);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn was_created_only_on_first_open() {
+    let path = temp_path("was_created");
+    let _ = std::fs::remove_file(&path);
+
+    let db = JsonSync::<String, i32, ShardMap<String, i32>>::open(&path).unwrap();
+    assert!(db.was_created());
+    db.flush().unwrap();
+    drop(db);
+
+    // still empty, but the file exists now
+    let db = JsonSync::<String, i32, ShardMap<String, i32>>::open(&path).unwrap();
+    assert!(!db.was_created());
+    assert!(db.is_empty());
+    drop(db);
+    let _ = std::fs::remove_file(&path);
+}
+
+// ---- delete_when_empty ------------------------------------------------------
+
+#[test]
+fn delete_when_empty_removes_and_recreates_file() {
+    let path = temp_path("delete_when_empty");
+    let _ = std::fs::remove_file(&path);
+    let db = JsonSync::<String, i32, ShardMap<String, i32>>::builder(&path)
+        .delete_when_empty(true)
+        .build()
+        .unwrap();
+    db.insert("a".into(), 1).unwrap();
+    db.flush().unwrap();
+    assert!(path.exists());
+
+    db.clear().unwrap();
+    db.flush().unwrap();
+    assert!(!path.exists());
+    db.flush().unwrap(); // already gone: still fine
+
+    db.insert("b".into(), 2).unwrap();
+    db.flush().unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), r#"{"b":2}"#);
+    let _ = std::fs::remove_file(&path);
+}
+
+// ---- get_or_insert_many -----------------------------------------------------
+
+#[test]
+fn get_or_insert_many_keeps_existing_and_flushes_once() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let path = temp_path("get_or_insert_many");
+    let _ = std::fs::remove_file(&path);
+    let flushes = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&flushes);
+    let db = JsonSync::<String, bool, ShardMap<String, bool>>::builder(&path)
+        .policy(FlushPolicy::Immediate)
+        .on_flush(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        })
+        .build()
+        .unwrap();
+    db.insert("dark_mode".into(), true).unwrap();
+    flushes.store(0, Ordering::SeqCst);
+
+    let flags = db
+        .get_or_insert_many(vec![
+            ("beta".into(), false),
+            ("dark_mode".into(), false),
+            ("search_v2".into(), true),
+        ])
+        .unwrap();
+    assert_eq!(flags, vec![false, true, true]);
+    assert_eq!(flushes.load(Ordering::SeqCst), 1);
+    assert_eq!(db.len(), 3);
+
+    // all present: nothing to write
+    db.get_or_insert_many(vec![("beta".into(), true)]).unwrap();
+    assert_eq!(flushes.load(Ordering::SeqCst), 1);
+    assert_eq!(db.get(&"beta".into()), Some(false));
+    let _ = std::fs::remove_file(&path);
+}
+
+// ---- version counter --------------------------------------------------------
+
+#[test]
+fn version_rises_on_mutation_not_on_reads() {
+    let path = temp_path("version_counter");
+    let _ = std::fs::remove_file(&path);
+    let db = JsonSync::<String, i32, ShardMap<String, i32>>::open(&path).unwrap();
+    assert_eq!(db.version(), 0);
+
+    db.insert("a".into(), 1).unwrap();
+    let v1 = db.version();
+    assert!(v1 > 0);
+
+    let _ = db.get(&"a".into());
+    let _ = db.iter();
+    let _ = db.contains_key(&"a".into());
+    db.flush().unwrap();
+    assert_eq!(db.version(), v1);
+
+    db.remove(&"a".into()).unwrap();
+    let v2 = db.version();
+    assert!(v2 > v1);
+    db.clear().unwrap();
+    assert!(db.version() > v2);
+    let _ = std::fs::remove_file(&path);
+}
+
+// ---- preallocate ------------------------------------------------------------
+
+#[cfg(target_os = "linux")]
+#[test]
+fn preallocated_flush_keeps_exact_length() {
+    let path = temp_path("preallocate");
+    let _ = std::fs::remove_file(&path);
+    let db = JsonSync::<String, i32, ShardMap<String, i32>>::builder(&path)
+        .preallocate(1 << 20)
+        .build()
+        .unwrap();
+    db.insert("a".into(), 1).unwrap();
+    db.flush().unwrap();
+
+    // the reservation doesn't leak into the file's length
+    let len = std::fs::metadata(&path).unwrap().len();
+    assert_eq!(len, r#"{"a":1}"#.len() as u64);
+    drop(db);
+
+    let db = JsonSync::<String, i32, ShardMap<String, i32>>::open(&path).unwrap();
+    assert_eq!(db.get(&"a".into()), Some(1));
+    let _ = std::fs::remove_file(&path);
+}
+
+// ---- reload_merge -----------------------------------------------------------
+
+#[test]
+fn reload_merge_consults_resolver_only_on_conflicts() {
+    let path = temp_path("reload_merge");
+    let _ = std::fs::remove_file(&path);
+    std::fs::write(&path, r#"{"a":1,"b":1,"c":1,"d":1,"e":1}"#).unwrap();
+    let db = JsonSync::<String, u32, ShardMap<String, u32>>::builder(&path)
+        .merge_baseline(true)
+        .build()
+        .unwrap();
+
+    db.insert("a".into(), 2).unwrap(); // local only
+    db.insert("c".into(), 5).unwrap(); // both sides, differently
+    db.insert("d".into(), 9).unwrap(); // both sides, same result
+    db.remove(&"e".into()).unwrap(); // local only
+    std::fs::write(&path, r#"{"a":1,"b":3,"c":7,"d":9,"e":1,"f":1}"#).unwrap();
+
+    let mut asked = Vec::new();
+    db.reload_merge(|k, local, external| {
+        asked.push((k.clone(), local.copied(), external.copied()));
+        local.max(external).copied()
+    })
+    .unwrap();
+
+    assert_eq!(asked, vec![("c".to_string(), Some(5), Some(7))]);
+    let mut merged = db.iter();
+    merged.sort();
+    let expected: Vec<(String, u32)> = [("a", 2), ("b", 3), ("c", 7), ("d", 9), ("f", 1)]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v))
+        .collect();
+    assert_eq!(merged, expected);
+
+    // the file is the new baseline, so merging again finds nothing to do
+    db.reload_merge(|_, _, _| panic!("no conflicts left"))
+        .unwrap();
+    assert_eq!(db.len(), 5);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn reload_merge_requires_baseline() {
+    let path = temp_path("reload_merge_off");
+    let _ = std::fs::remove_file(&path);
+    let db = JsonSync::<String, u32, ShardMap<String, u32>>::open(&path).unwrap();
+    assert!(matches!(
+        db.reload_merge(|_, l, _| l.copied()),
+        Err(json_sync::Error::Config(_))
+    ));
+    let _ = std::fs::remove_file(&path);
+}
+
+// ---- json_pointer -----------------------------------------------------------
+
+#[test]
+fn json_pointer_keeps_sibling_fields() {
+    let path = temp_path("json_pointer");
+    let _ = std::fs::remove_file(&path);
+    std::fs::write(
+        &path,
+        r#"{"version":3,"cache":{"a":1},"other":{"x":[1,2],"y":null}}"#,
+    )
+    .unwrap();
+
+    let db = JsonSync::<String, i32, ShardMap<String, i32>>::builder(&path)
+        .json_pointer("/cache")
+        .build()
+        .unwrap();
+    assert_eq!(db.len(), 1);
+    assert_eq!(db.get(&"a".into()), Some(1));
+    db.insert("b".into(), 2).unwrap();
+    db.flush().unwrap();
+    drop(db);
+
+    let doc: serde_json::Value = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+    assert_eq!(
+        doc,
+        serde_json::json!({"version": 3, "cache": {"a": 1, "b": 2}, "other": {"x": [1, 2], "y": null}})
+    );
+
+    // and the next open sees just the sub-object again
+    let db = JsonSync::<String, i32, ShardMap<String, i32>>::builder(&path)
+        .json_pointer("/cache")
+        .build()
+        .unwrap();
+    assert_eq!(db.len(), 2);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn json_pointer_creates_missing_path() {
+    let path = temp_path("json_pointer_new");
+    let _ = std::fs::remove_file(&path);
+    std::fs::write(&path, r#"{"name":"app"}"#).unwrap();
+    let db = JsonSync::<String, i32, ShardMap<String, i32>>::builder(&path)
+        .json_pointer("/state/counts")
+        .build()
+        .unwrap();
+    assert!(db.is_empty());
+    db.insert("hits".into(), 7).unwrap();
+    db.flush().unwrap();
+
+    let doc: serde_json::Value = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+    assert_eq!(
+        doc,
+        serde_json::json!({"name": "app", "state": {"counts": {"hits": 7}}})
+    );
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn json_pointer_must_start_with_slash() {
+    let path = temp_path("json_pointer_bad");
+    let res = JsonSync::<String, i32, ShardMap<String, i32>>::builder(&path)
+        .json_pointer("cache")
+        .build();
+    assert!(matches!(res, Err(json_sync::Error::Config(_))));
+}
+
+// ---- flush_report -----------------------------------------------------------
+
+#[test]
+fn flush_report_matches_file() {
+    let path = temp_path("flush_report");
+    let _ = std::fs::remove_file(&path);
+    let db = JsonSync::<String, String, ShardMap<String, String>>::builder(&path)
+        .pretty(true)
+        .build()
+        .unwrap();
+    for i in 0..50 {
+        db.insert(format!("k{i}"), "v".repeat(i)).unwrap();
+    }
+
+    let report = db.flush_report().unwrap();
+    assert_eq!(report.bytes as u64, std::fs::metadata(&path).unwrap().len());
+    assert_eq!(report.entries, 50);
+    assert_eq!(report.path, path);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn recent_flushes_keeps_the_last_few_in_order() {
+    let dir = std::env::temp_dir().join("json_sync_test_recent_flushes");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let db = JsonSync::<String, u32, ShardMap<String, u32>>::builder(dir.join("db.json"))
+        .flush_history(3)
+        .policy(FlushPolicy::Manual)
+        .build()
+        .unwrap();
+    assert!(db.recent_flushes().is_empty());
+
+    db.insert("a".into(), 1).unwrap();
+    // four flushes into a history of three: this one gets evicted
+    db.flush().unwrap();
+    db.flush().unwrap();
+    // nowhere to write the temp file
+    std::fs::remove_dir_all(&dir).unwrap();
+    db.flush().unwrap_err();
+    std::fs::create_dir_all(&dir).unwrap();
+    db.flush().unwrap();
+
+    let events = db.recent_flushes();
+    assert_eq!(events.len(), 3, "{events:?}");
+    assert!(events.windows(2).all(|w| w[0].at <= w[1].at));
+    assert!(events[0].error.is_none() && events[0].bytes > 0);
+    assert!(matches!(events[1].error, Some(json_sync::Error::Io(_))));
+    assert_eq!(events[1].bytes, 0);
+    assert!(events[2].error.is_none() && events[2].bytes > 0);
+    drop(db);
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+// ---- fold -------------------------------------------------------------------
+
+#[test]
+fn fold_and_aggregates_over_values() {
+    let path = temp_path("fold");
+    let _ = std::fs::remove_file(&path);
+    let db = JsonSync::<String, i64, ShardMap<String, i64>>::open(&path).unwrap();
+    assert_eq!(db.sum_values(), 0);
+    assert_eq!(db.max_value(), None);
+    db.extend((1..=10).map(|i| (format!("k{i}"), i))).unwrap();
+
+    assert_eq!(db.sum_values(), 55);
+    assert_eq!(db.max_value(), Some(10));
+    let (count, min) = db.fold((0, i64::MAX), |(n, min), _, v| (n + 1, min.min(*v)));
+    assert_eq!((count, min), (10, 1));
+    let long_keys = db.fold(0, |n, k, _| n + usize::from(k.len() > 2));
+    assert_eq!(long_keys, 1);
+}
+
+// ---- checkpoint -------------------------------------------------------------
+
+#[test]
+fn checkpoints_capture_state_at_their_time() {
+    let path = temp_path("checkpoint");
+    let dir = std::env::temp_dir().join("json_sync_test_checkpoints");
+    let _ = std::fs::remove_file(&path);
+    let _ = std::fs::remove_dir_all(&dir);
+    let db = JsonSync::<String, i32, ShardMap<String, i32>>::open(&path).unwrap();
+    db.insert("a".into(), 1).unwrap();
+    let first = db.checkpoint(&dir).unwrap();
+    db.insert("a".into(), 2).unwrap();
+    db.insert("b".into(), 3).unwrap();
+    let second = db.checkpoint(&dir).unwrap();
+
+    assert_ne!(first, second);
+    assert!(first.starts_with(&dir));
+    let json = json_sync::serializer::JsonSerializer::new();
+    let at_first: std::collections::HashMap<String, i32> =
+        json_sync::persist::load(&first, &json).unwrap();
+    let at_second: std::collections::HashMap<String, i32> =
+        json_sync::persist::load(&second, &json).unwrap();
+    assert_eq!(at_first, [("a".to_string(), 1)].into());
+    assert_eq!(
+        at_second,
+        [("a".to_string(), 2), ("b".to_string(), 3)].into()
+    );
+    // the store's own file is left alone
+    assert!(!path.exists());
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+// ---- rotate -----------------------------------------------------------------
+
+#[test]
+fn rotate_moves_everything_to_the_archive() {
+    let path = temp_path("rotate");
+    let dir = std::env::temp_dir().join("json_sync_test_rotated");
+    let _ = std::fs::remove_file(&path);
+    let _ = std::fs::remove_dir_all(&dir);
+    let json = json_sync::serializer::JsonSerializer::new();
+    let db = JsonSync::<String, i32, ShardMap<String, i32>>::open(&path).unwrap();
+    db.insert("a".into(), 1).unwrap();
+    db.insert("b".into(), 2).unwrap();
+
+    // unflushed entries are archived too
+    let archive = dir.join("rotate-1.json");
+    db.rotate(&archive).unwrap();
+    let archived: std::collections::HashMap<String, i32> =
+        json_sync::persist::load(&archive, &json).unwrap();
+    assert_eq!(
+        archived,
+        [("a".to_string(), 1), ("b".to_string(), 2)].into()
+    );
+    assert!(db.is_empty());
+    let live: std::collections::HashMap<String, i32> =
+        json_sync::persist::load(&path, &json).unwrap();
+    assert!(live.is_empty());
+
+    db.insert("c".into(), 3).unwrap();
+    db.flush().unwrap();
+    drop(db);
+    let db = JsonSync::<String, i32, ShardMap<String, i32>>::open(&path).unwrap();
+    assert_eq!(db.keys(), vec!["c".to_string()]);
+    drop(db);
+    let _ = std::fs::remove_file(&path);
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+// ---- skip_unchanged_writes --------------------------------------------------
+
+#[test]
+fn skip_unchanged_writes_leaves_file_alone() {
+    let path = temp_path("skip_unchanged");
+    let _ = std::fs::remove_file(&path);
+    let db = JsonSync::<String, i32, ShardMap<String, i32>>::builder(&path)
+        .skip_unchanged_writes(true)
+        .build()
+        .unwrap();
+    for i in 0..20 {
+        db.insert(format!("k{i}"), i).unwrap();
+    }
+    assert!(!db.flush_report().unwrap().skipped);
+    let mtime = std::fs::metadata(&path).unwrap().modified().unwrap();
+
+    std::thread::sleep(std::time::Duration::from_millis(20));
+    for i in 0..20 {
+        db.insert(format!("k{i}"), i).unwrap();
+    }
+    let report = db.flush_report().unwrap();
+    assert!(report.skipped);
+    assert_eq!(report.bytes, 0);
+    assert_eq!(std::fs::metadata(&path).unwrap().modified().unwrap(), mtime);
+
+    // a real change, or the file being replaced behind our back, writes again
+    db.insert("k0".into(), 100).unwrap();
+    assert!(!db.flush_report().unwrap().skipped);
+    std::fs::write(&path, "{}").unwrap();
+    db.insert("k0".into(), 100).unwrap();
+    assert!(!db.flush_report().unwrap().skipped);
+    assert_eq!(
+        JsonSync::<String, i32, ShardMap<String, i32>>::open(&path)
+            .unwrap()
+            .len(),
+        20
+    );
+    let _ = std::fs::remove_file(&path);
+}
+
+// ---- max_value_bytes --------------------------------------------------------
+
+#[test]
+fn max_value_bytes_rejects_oversized_values() {
+    let path = temp_path("max_value_bytes");
+    let _ = std::fs::remove_file(&path);
+    let db = JsonSync::<String, String, ShardMap<String, String>>::builder(&path)
+        .max_value_bytes(16)
+        .build()
+        .unwrap();
+    // 14 characters plus the quotes is exactly at the limit
+    db.insert("ok".into(), "a".repeat(14)).unwrap();
+
+    let res = db.insert("big".into(), "a".repeat(1 << 20));
+    assert!(matches!(res, Err(json_sync::Error::Config(_))));
+    let res = db.extend([
+        ("x".to_string(), "small".to_string()),
+        ("y".to_string(), "a".repeat(15)),
+    ]);
+    assert!(matches!(res, Err(json_sync::Error::Config(_))));
+    // a batch with one oversized insert applies none of its ops
+    let res = db.apply([
+        json_sync::Op::Remove("ok".to_string()),
+        json_sync::Op::Insert("x".to_string(), "small".to_string()),
+        json_sync::Op::Insert("y".to_string(), "a".repeat(15)),
+    ]);
+    assert!(matches!(res, Err(json_sync::Error::Config(_))));
+    assert_eq!(db.len(), 1);
+    assert_eq!(db.get(&"ok".into()), Some("a".repeat(14)));
+    let _ = std::fs::remove_file(&path);
+}
+
+// ---- max_depth --------------------------------------------------------------
+
+#[test]
+fn max_depth_refuses_deeply_nested_files() {
+    let path = temp_path("max_depth");
+    let open = |depth| {
+        JsonSync::<String, serde_json::Value, ShardMap<String, serde_json::Value>>::builder(&path)
+            .max_depth(depth)
+            .build()
+    };
+
+    // brackets inside strings don't count
+    std::fs::write(&path, r#"{"a": {"b": [1, "[[[{{{"]}}"#).unwrap();
+    assert!(open(3).is_ok());
+    let err = open(2).err().unwrap();
+    assert!(
+        matches!(err, json_sync::Error::Deserialize(ref m) if m == "max nesting depth exceeded")
+    );
+
+    // far deeper than any parser's stack would take
+    let deep = format!(
+        r#"{{"a": {}1{}}}"#,
+        "[".repeat(100_000),
+        "]".repeat(100_000)
+    );
+    std::fs::write(&path, deep).unwrap();
+    assert!(matches!(open(64), Err(json_sync::Error::Deserialize(_))));
+
+    // under json_pointer only the pointed-to object counts
+    std::fs::write(&path, r#"{"app": {"cache": {"a": 1}}, "x": [[[[]]]]}"#).unwrap();
+    let db = JsonSync::<String, u32, ShardMap<String, u32>>::builder(&path)
+        .json_pointer("/app/cache")
+        .max_depth(1)
+        .build()
+        .unwrap();
+    assert_eq!(db.get(&"a".into()), Some(1));
+    drop(db);
+
+    // pairs and the version envelope each add a level
+    std::fs::write(&path, r#"{"json_sync_version": 1, "data": [["a", 1]]}"#).unwrap();
+    let open_wrapped = |depth| {
+        JsonSync::<String, u32, ShardMap<String, u32>>::builder(&path)
+            .as_pairs(true)
+            .format_version(1)
+            .max_depth(depth)
+            .build()
+    };
+    assert!(open_wrapped(2).is_err());
+    assert_eq!(open_wrapped(3).unwrap().get(&"a".into()), Some(1));
+    let _ = std::fs::remove_file(&path);
+}
+
+/// JSON behind a header of brackets: not JSON as far as `max_depth` goes.
+#[derive(Clone, Default)]
+struct Bracketed;
+
+impl json_sync::serializer::Serializer for Bracketed {
+    fn serialize<K, V>(&self, data: &std::collections::HashMap<K, V>) -> json_sync::Result<Vec<u8>>
+    where
+        K: serde::Serialize,
+        V: serde::Serialize,
+    {
+        let mut out = b"[[[[".to_vec();
+        out.extend(serde_json::to_vec(data)?);
+        Ok(out)
+    }
+
+    fn deserialize<K, V>(&self, bytes: &[u8]) -> json_sync::Result<std::collections::HashMap<K, V>>
+    where
+        K: for<'de> serde::Deserialize<'de> + Eq + std::hash::Hash,
+        V: for<'de> serde::Deserialize<'de>,
+    {
+        match bytes.strip_prefix(b"[[[[") {
+            Some(json) => Ok(serde_json::from_slice(json)?),
+            None => Ok(Default::default()),
+        }
+    }
+}
+
+#[test]
+fn max_depth_skips_serializers_that_dont_read_json() {
+    let path = temp_path("max_depth_binary");
+    std::fs::write(&path, r#"[[[[{"a": 1}"#).unwrap();
+    let db = JsonSync::<String, u32, ShardMap<String, u32>>::builder(&path)
+        .serializer(Bracketed)
+        .max_depth(1)
+        .build()
+        .unwrap();
+    assert_eq!(db.get(&"a".into()), Some(1));
+    drop(db);
+    let _ = std::fs::remove_file(&path);
+}
+
+// ---- max_memory_bytes -------------------------------------------------------
+
+#[test]
+fn max_memory_bytes_evicts_the_largest_entries() {
+    let path = temp_path("max_memory");
+    let _ = std::fs::remove_file(&path);
+    let db = JsonSync::<String, String, ShardMap<String, String>>::builder(&path)
+        .max_memory_bytes(1000)
+        .build()
+        .unwrap();
+
+    // each small entry is 3 bytes of key and 12 of value, quotes included
+    for k in ["a", "b", "c", "d", "e"] {
+        db.insert(k.into(), "x".repeat(10)).unwrap();
+    }
+    assert_eq!(db.memory_usage(), 75);
+    db.insert("big1".into(), "y".repeat(500)).unwrap();
+    assert_eq!(db.memory_usage(), 75 + 508);
+    db.insert("big2".into(), "z".repeat(400)).unwrap();
+    assert_eq!(db.memory_usage(), 75 + 508 + 408);
+
+    // 308 more would make 1299; dropping big1 brings it back under
+    db.insert("big3".into(), "w".repeat(300)).unwrap();
+    assert_eq!(db.get(&"big1".into()), None);
+    assert!(db.contains_key(&"big2".into()));
+    assert!(db.contains_key(&"big3".into()));
+    assert_eq!(db.len(), 7);
+    assert_eq!(db.memory_usage(), 75 + 408 + 308);
+
+    db.remove(&"big2".into()).unwrap();
+    assert_eq!(db.memory_usage(), 75 + 308);
+    drop(db);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn max_memory_bytes_evicts_below_the_cap() {
+    let path = temp_path("max_memory_headroom");
+    let _ = std::fs::remove_file(&path);
+    let db = JsonSync::<String, String, ShardMap<String, String>>::builder(&path)
+        .max_memory_bytes(1000)
+        .build()
+        .unwrap();
+
+    // 100 bytes apiece: four of key, 96 of value
+    for i in 0..10 {
+        db.insert(format!("k{i}"), "x".repeat(94)).unwrap();
+    }
+    assert_eq!(db.len(), 10);
+
+    // going over evicts down to 90%, so the next insert fits without a pass
+    db.insert("ka".into(), "x".repeat(94)).unwrap();
+    assert_eq!(db.len(), 9);
+    assert_eq!(db.memory_usage(), 900);
+    db.insert("kb".into(), "x".repeat(94)).unwrap();
+    assert_eq!(db.len(), 10);
+    drop(db);
+    let _ = std::fs::remove_file(&path);
+}
+
+// ---- target -----------------------------------------------------------------
+
+#[derive(Default)]
+struct Captured(std::sync::Mutex<Vec<u8>>);
+
+impl json_sync::persist::PersistTarget for Captured {
+    fn write(&self, bytes: &[u8]) -> json_sync::Result<()> {
+        *self.0.lock().unwrap() = bytes.to_vec();
+        Ok(())
+    }
+}
+
+#[test]
+fn target_receives_flushes_instead_of_file() {
+    let path = temp_path("custom_target");
+    let _ = std::fs::remove_file(&path);
+    let sink = std::sync::Arc::new(Captured::default());
+    let db = JsonSync::<String, i32, ShardMap<String, i32>>::builder(&path)
+        .target(sink.clone())
+        .build()
+        .unwrap();
+    db.insert("a".into(), 1).unwrap();
+    let report = db.flush_report().unwrap();
+
+    assert_eq!(*sink.0.lock().unwrap(), br#"{"a":1}"#);
+    assert_eq!(report.bytes, 7);
+    assert!(!path.exists());
+
+    let res =
+        JsonSync::<String, i32, ShardMap<String, i32>>::builder(temp_path("custom_target_ptr"))
+            .target(sink)
+            .json_pointer("/cache")
+            .build();
+    assert!(matches!(res, Err(json_sync::Error::Config(_))));
+}
+
+// ---- open_with_io -----------------------------------------------------------
+
+#[test]
+fn open_with_io_reads_and_writes_without_a_file() {
+    let sink = std::sync::Arc::new(Captured::default());
+    let seed = std::io::Cursor::new(br#"{"a":1,"b":2}"#.to_vec());
+    let db =
+        JsonSync::<String, i32, ShardMap<String, i32>>::open_with_io(seed, sink.clone()).unwrap();
+    assert!(!db.was_created());
+    assert_eq!(db.get(&"a".into()), Some(1));
+    assert_eq!(db.get(&"b".into()), Some(2));
+
+    db.remove(&"a".into()).unwrap();
+    db.insert("c".into(), 3).unwrap();
+    db.flush().unwrap();
+    let written: std::collections::HashMap<String, i32> =
+        serde_json::from_slice(&sink.0.lock().unwrap()).unwrap();
+    assert_eq!(written, [("b".into(), 2), ("c".into(), 3)].into());
+
+    let empty = JsonSync::<String, i32, ShardMap<String, i32>>::open_with_io(
+        std::io::empty(),
+        std::sync::Arc::new(Captured::default()),
+    )
+    .unwrap();
+    assert!(empty.was_created());
+    assert!(empty.is_empty());
+}
+
+// ---- value_schema -----------------------------------------------------------
+
+#[cfg(feature = "schema")]
+#[test]
+fn value_schema_lists_struct_fields() {
+    #[derive(Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+    struct Service {
+        host: String,
+        port: u16,
+        tags: Option<Vec<String>>,
+    }
+
+    let path = temp_path("value_schema");
+    let schema_path = temp_path("value_schema_out");
+    let db = JsonSync::<String, Service, ShardMap<String, Service>>::open(&path).unwrap();
+    let schema = db.value_schema();
+    assert_eq!(schema["title"], "Service");
+    let props = schema["properties"].as_object().unwrap();
+    assert_eq!(props.len(), 3);
+    assert_eq!(props["port"]["type"], "integer");
+    let required = schema["required"].as_array().unwrap();
+    assert!(required.contains(&"host".into()));
+    assert!(!required.contains(&"tags".into()));
+
+    db.write_schema_to(&schema_path).unwrap();
+    let written: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(&schema_path).unwrap()).unwrap();
+    assert_eq!(written, schema);
+    let _ = std::fs::remove_file(&schema_path);
+}
+
+// ---- follow_symlinks --------------------------------------------------------
+
+#[cfg(unix)]
+#[test]
+fn follow_symlinks_keeps_the_link() {
+    let dir = std::env::temp_dir().join("json_sync_test_symlink_dir");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("data")).unwrap();
+    let real = dir.join("data").join("db.json");
+    let link = dir.join("db.json");
+    std::fs::write(&real, r#"{"a":1}"#).unwrap();
+    std::os::unix::fs::symlink("data/db.json", &link).unwrap();
+
+    let db = JsonSync::<String, i32, ShardMap<String, i32>>::builder(&link)
+        .follow_symlinks(true)
+        .build()
+        .unwrap();
+    assert_eq!(db.get(&"a".into()), Some(1));
+    db.insert("b".into(), 2).unwrap();
+    let report = db.flush_report().unwrap();
+    drop(db);
+
+    assert!(std::fs::symlink_metadata(&link)
+        .unwrap()
+        .file_type()
+        .is_symlink());
+    assert_eq!(report.path, dir.join("data/db.json"));
+    let on_disk: std::collections::HashMap<String, i32> =
+        serde_json::from_slice(&std::fs::read(&real).unwrap()).unwrap();
+    assert_eq!(on_disk.len(), 2);
+
+    // without the option the rename replaces the link with a plain file
+    let db = JsonSync::<String, i32, ShardMap<String, i32>>::open(&link).unwrap();
+    db.flush().unwrap();
+    drop(db);
+    assert!(!std::fs::symlink_metadata(&link)
+        .unwrap()
+        .file_type()
+        .is_symlink());
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+// ---- canonicalize_path ------------------------------------------------------
+
+#[test]
+fn canonicalize_path_makes_relative_and_absolute_agree() {
+    let name = "json_sync_test_canonical.json";
+    let relative = std::path::Path::new("target").join(name);
+    let absolute = std::env::current_dir()
+        .unwrap()
+        .join("target/../target")
+        .join(name);
+    let _ = std::fs::remove_file(&relative);
+
+    let a = JsonSync::<String, i32, ShardMap<String, i32>>::builder(&relative)
+        .canonicalize_path(true)
+        .build()
+        .unwrap();
+    let b = JsonSync::<String, i32, ShardMap<String, i32>>::builder(&absolute)
+        .canonicalize_path(true)
+        .build()
+        .unwrap();
+    assert!(a.path().is_absolute());
+    assert_eq!(a.path(), b.path());
+    assert_eq!(a.path().file_name().unwrap(), name);
+    drop((a, b));
+
+    // off by default: the path comes back as given
+    let c = JsonSync::<String, i32, ShardMap<String, i32>>::open(&relative).unwrap();
+    assert_eq!(c.path(), relative.as_path());
+    drop(c);
+    let _ = std::fs::remove_file(&relative);
+}
+
+// ---- redactor ---------------------------------------------------------------
+
+#[test]
+fn redactor_scrubs_export_and_audit_only() {
+    use serde_json::{json, Value};
+
+    let path = temp_path("redactor");
+    let log = temp_path("redactor_audit");
+    let _ = std::fs::remove_file(&path);
+    let _ = std::fs::remove_file(&log);
+    let db = JsonSync::<String, Value, ShardMap<String, Value>>::builder(&path)
+        .audit_log(&log)
+        .audit_values(true)
+        .redactor(|_, v| {
+            if let Some(obj) = v.as_object_mut() {
+                obj.remove("password");
+            }
+        })
+        .build()
+        .unwrap();
+    db.insert("ann".into(), json!({"name": "Ann", "password": "hunter2"}))
+        .unwrap();
+
+    let mut out = Vec::new();
+    db.export(&mut out).unwrap();
+    let exported: Value = serde_json::from_slice(&out).unwrap();
+    assert_eq!(exported, json!({"ann": {"name": "Ann"}}));
+
+    let audit = std::fs::read_to_string(&log).unwrap();
+    assert!(audit.contains("Ann"));
+    assert!(!audit.contains("hunter2"));
+
+    // the live store and its own file keep the field
+    assert_eq!(db.get(&"ann".into()).unwrap()["password"], "hunter2");
+    db.flush().unwrap();
+    assert!(std::fs::read_to_string(&path).unwrap().contains("hunter2"));
+    let _ = std::fs::remove_file(&path);
+    let _ = std::fs::remove_file(&log);
+}
+
+// ---- open_mmap_readonly -----------------------------------------------------
+
+#[cfg(feature = "mmap")]
+#[test]
+fn mmap_readonly_handles_share_a_file() {
+    type Db = JsonSync<String, i32, ShardMap<String, i32>>;
+
+    let path = temp_path("mmap_readonly");
+    let _ = std::fs::remove_file(&path);
+    let writer = Db::open(&path).unwrap();
+    writer.insert("a".into(), 1).unwrap();
+    writer.insert("b".into(), 2).unwrap();
+    writer.flush().unwrap();
+
+    let r1 = Db::open_mmap_readonly(&path).unwrap();
+    let r2 = Db::open_mmap_readonly(&path).unwrap();
+    for r in [&r1, &r2] {
+        assert_eq!(r.len(), 2);
+        assert_eq!(r.get(&"a".into()), Some(1));
+        assert_eq!(r.get(&"b".into()), Some(2));
+    }
+    assert!(!r1.refresh().unwrap());
+
+    let before = r1.snapshot();
+    writer.insert("c".into(), 3).unwrap();
+    writer.flush().unwrap();
+    assert!(r1.refresh().unwrap());
+    assert_eq!(r1.get(&"c".into()), Some(3));
+    assert_eq!(before.len(), 2);
+    assert_eq!(r2.get(&"c".into()), None); // not refreshed yet
+    let _ = std::fs::remove_file(&path);
+}
+
+// ---- value_ttl --------------------------------------------------------------
+
+#[test]
+fn value_ttl_expires_entries_across_reopen() {
+    let path = temp_path("value_ttl");
+    let _ = std::fs::remove_file(&path);
+    let open = || {
+        JsonSync::<String, i32, ShardMap<String, i32>>::builder(&path)
+            .value_ttl(true)
+            .build()
+            .unwrap()
+    };
+
+    let db = open();
+    db.insert_with_ttl("short".into(), 1, Duration::from_millis(50))
+        .unwrap();
+    db.insert_with_ttl("long".into(), 2, Duration::from_secs(3600))
+        .unwrap();
+    db.insert("plain".into(), 3).unwrap();
+    assert!(db.expires_at(&"short".into()).is_some());
+    assert_eq!(db.expires_at(&"plain".into()), None);
+    db.flush().unwrap();
+    drop(db);
+
+    let on_disk: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+    assert_eq!(on_disk["plain"], serde_json::json!({"v": 3, "exp": null}));
+    assert_eq!(on_disk["long"]["v"], 2);
+    assert!(on_disk["long"]["exp"].is_u64());
+
+    std::thread::sleep(Duration::from_millis(100));
+    let db = open();
+    assert_eq!(db.get(&"short".into()), None);
+    assert_eq!(db.get(&"long".into()), Some(2));
+    assert_eq!(db.get(&"plain".into()), Some(3));
+    assert!(db.expires_at(&"long".into()).is_some());
+
+    // a plain insert over a TTL entry makes it permanent
+    db.insert("long".into(), 4).unwrap();
+    assert_eq!(db.expires_at(&"long".into()), None);
+    drop(db);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn value_ttl_purges_and_reads_bare_files() {
+    let path = temp_path("value_ttl_purge");
+    std::fs::write(&path, r#"{"old": 1}"#).unwrap();
+
+    let db = JsonSync::<String, i32, ShardMap<String, i32>>::builder(&path)
+        .value_ttl(true)
+        .build()
+        .unwrap();
+    assert_eq!(db.get(&"old".into()), Some(1));
+    db.insert_with_ttl("gone".into(), 2, Duration::ZERO)
+        .unwrap();
+    assert_eq!(db.purge_expired().unwrap(), 1);
+    assert_eq!(db.keys(), vec!["old".to_string()]);
+
+    // a TTL past the end of time means never
+    db.insert_with_ttl("forever".into(), 3, Duration::MAX)
+        .unwrap();
+    assert_eq!(
+        db.expires_at(&"forever".into()),
+        Some(std::time::UNIX_EPOCH + Duration::from_millis(u64::MAX))
+    );
+    assert_eq!(db.purge_expired().unwrap(), 0);
+    db.remove(&"forever".into()).unwrap();
+    drop(db);
+
+    let plain = JsonSync::<String, i32, ShardMap<String, i32>>::open(&path).unwrap();
+    assert!(plain
+        .insert_with_ttl("k".into(), 1, Duration::from_secs(1))
+        .is_err());
+    drop(plain);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn manual_clock_expires_entries_without_waiting() {
+    use json_sync::clock::ManualClock;
+    use std::time::UNIX_EPOCH;
+
+    let path = temp_path("manual_clock");
+    let _ = std::fs::remove_file(&path);
+    let clock = std::sync::Arc::new(ManualClock::new(UNIX_EPOCH + Duration::from_secs(1_000)));
+    let open = || {
+        JsonSync::<String, i32, ShardMap<String, i32>>::builder(&path)
+            .value_ttl(true)
+            .clock(clock.clone())
+            .build()
+            .unwrap()
+    };
+
+    let db = open();
+    db.insert_with_ttl("a".into(), 1, Duration::from_secs(3600))
+        .unwrap();
+    db.insert_with_ttl("b".into(), 2, Duration::from_secs(7200))
+        .unwrap();
+    assert_eq!(
+        db.expires_at(&"a".into()),
+        Some(UNIX_EPOCH + Duration::from_secs(4_600))
+    );
+    assert_eq!(db.purge_expired().unwrap(), 0);
+
+    clock.advance(Duration::from_secs(3600));
+    assert_eq!(db.purge_expired().unwrap(), 1);
+    assert_eq!(db.get(&"a".into()), None);
+    db.flush().unwrap();
+    drop(db);
+
+    // expiry on open goes by the same clock
+    clock.advance(Duration::from_secs(3600));
+    let db = open();
+    assert!(db.is_empty());
+    drop(db);
+    let _ = std::fs::remove_file(&path);
+}
+
+// ---- tombstones -------------------------------------------------------------
+
+#[test]
+fn tombstones_record_removals_until_purged() {
+    let path = temp_path("tombstones");
+    let _ = std::fs::remove_file(&path);
+    let open = || {
+        JsonSync::<String, i32, ShardMap<String, i32>>::builder(&path)
+            .tombstones(true)
+            .policy(FlushPolicy::Immediate)
+            .build()
+            .unwrap()
+    };
+    let on_disk =
+        || -> serde_json::Value { serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap() };
+
+    let db = open();
+    db.insert("a".into(), 1).unwrap();
+    db.insert("b".into(), 2).unwrap();
+    assert_eq!(db.remove(&"a".into()).unwrap(), Some(1));
+    assert_eq!(db.remove(&"missing".into()).unwrap(), None);
+    assert_eq!(db.get(&"a".into()), None);
+    assert_eq!(db.len(), 1);
+    let file = on_disk();
+    assert_eq!(file["b"], 2);
+    assert_eq!(file["a"]["__deleted"], true);
+    assert!(file["a"]["ts"].as_u64().unwrap() > 0);
+    assert!(file.get("missing").is_none());
+    drop(db);
+
+    // the tombstone survives a reopen and stays out of reads
+    let db = open();
+    assert_eq!(db.get(&"a".into()), None);
+    assert_eq!(db.keys(), vec!["b".to_string()]);
+    assert_eq!(db.purge_tombstones(Duration::from_secs(3600)).unwrap(), 0);
+    assert!(on_disk()["a"].is_object());
+
+    assert_eq!(db.purge_tombstones(Duration::ZERO).unwrap(), 1);
+    assert!(on_disk().get("a").is_none());
+
+    // inserting again replaces the tombstone
+    db.remove(&"b".into()).unwrap();
+    db.insert("b".into(), 3).unwrap();
+    assert_eq!(on_disk()["b"], 3);
+    drop(db);
+    let _ = std::fs::remove_file(&path);
+}
+
+// ---- lazy_values ------------------------------------------------------------
+
+static PARSES: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+/// A value that counts how many times it's been deserialized.
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+struct Counted(u64);
+
+impl<'de> serde::Deserialize<'de> for Counted {
+    fn deserialize<D: serde::Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        PARSES.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        u64::deserialize(d).map(Counted)
+    }
+}
+
+#[test]
+fn lazy_values_parse_only_what_is_read() {
+    let path = temp_path("lazy_values");
+    let seed: std::collections::HashMap<String, u64> =
+        (0..1000).map(|i| (format!("k{i}"), i)).collect();
+    std::fs::write(&path, serde_json::to_vec(&seed).unwrap()).unwrap();
+
+    let db = JsonSync::<String, Counted, ShardMap<String, Counted>>::builder(&path)
+        .lazy_values(true)
+        .build()
+        .unwrap();
+    assert_eq!(PARSES.load(std::sync::atomic::Ordering::SeqCst), 0);
+    assert_eq!(db.len(), 1000);
+    assert_eq!(db.get(&"k7".into()), Some(Counted(7)));
+    assert_eq!(db.get(&"k42".into()), Some(Counted(42)));
+    assert_eq!(db.get(&"k7".into()), Some(Counted(7)));
+    assert_eq!(db.get(&"missing".into()), None);
+    assert_eq!(PARSES.load(std::sync::atomic::Ordering::SeqCst), 2);
+
+    // unread values go back to disk as they were read
+    db.insert("k42".into(), Counted(0)).unwrap();
+    db.remove(&"k1".into()).unwrap();
+    db.flush().unwrap();
+    assert_eq!(PARSES.load(std::sync::atomic::Ordering::SeqCst), 3);
+    let file: std::collections::HashMap<String, u64> =
+        serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+    assert_eq!(file.len(), 999);
+    assert_eq!(file["k42"], 0);
+    assert_eq!(file["k999"], 999);
+    assert!(!file.contains_key("k1"));
+
+    // whole-store reads parse the rest
+    assert_eq!(db.iter().len(), 999);
+    assert_eq!(PARSES.load(std::sync::atomic::Ordering::SeqCst), 1000);
+    drop(db);
+
+    let err = JsonSync::<String, Counted, ShardMap<String, Counted>>::builder(&path)
+        .lazy_values(true)
+        .value_ttl(true)
+        .build()
+        .unwrap_err();
+    assert!(err.to_string().contains("value_ttl"), "{err}");
+    let _ = std::fs::remove_file(&path);
+}
+
+// ---- on_value_error ---------------------------------------------------------
+
+#[test]
+fn on_value_error_skips_or_salvages_bad_values() {
+    use json_sync::ValueErrorPolicy;
+
+    #[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    enum Shape {
+        Circle(u32),
+        Square(u32),
+    }
+
+    let path = temp_path("on_value_error");
+    // written by a newer version that knows about triangles
+    std::fs::write(
+        &path,
+        r#"{"a": {"Circle": 1}, "b": {"Triangle": 3}, "c": {"Square": 2}}"#,
+    )
+    .unwrap();
+    let open = |policy| {
+        JsonSync::<String, Shape, ShardMap<String, Shape>>::builder(&path)
+            .on_value_error(policy)
+            .build()
+    };
+
+    assert!(open(ValueErrorPolicy::FailFile).is_err());
+
+    let db = open(ValueErrorPolicy::SkipEntry).unwrap();
+    assert_eq!(db.len(), 2);
+    assert_eq!(db.get(&"a".into()), Some(Shape::Circle(1)));
+    assert_eq!(db.get(&"c".into()), Some(Shape::Square(2)));
+    drop(db);
+
+    let db = open(ValueErrorPolicy::fallback(|k: &String, v| {
+        assert_eq!(k, "b");
+        let sides = v["Triangle"].as_u64()?;
+        Some(Shape::Circle(sides as u32))
+    }))
+    .unwrap();
+    assert_eq!(db.len(), 3);
+    assert_eq!(db.get(&"b".into()), Some(Shape::Circle(3)));
+    drop(db);
+
+    let err = JsonSync::<String, Shape, ShardMap<String, Shape>>::builder(&path)
+        .on_value_error(ValueErrorPolicy::SkipEntry)
+        .tombstones(true)
+        .build()
+        .unwrap_err();
+    assert!(err.to_string().contains("tombstones"), "{err}");
+
+    let err = JsonSync::<String, Shape, ShardMap<String, Shape>>::builder(&path)
+        .serializer(Bracketed)
+        .on_value_error(ValueErrorPolicy::SkipEntry)
+        .build()
+        .unwrap_err();
+    assert_eq!(
+        err,
+        json_sync::Error::Config("on_value_error needs a serializer that reads JSON".into())
+    );
+    let _ = std::fs::remove_file(&path);
+}
+
+// ---- single-instance --------------------------------------------------------
+
+#[cfg(feature = "single-instance")]
+#[test]
+fn single_instance_shares_one_store_per_path() {
+    let path = temp_path("single_instance");
+    let _ = std::fs::remove_file(&path);
+    let first = JsonSync::<String, i32, ShardMap<String, i32>>::builder(&path)
+        .policy(FlushPolicy::Async(Duration::from_millis(10)))
+        .build()
+        .unwrap();
+    // a different spelling of the same file
+    let alias = path
+        .parent()
+        .unwrap()
+        .join(".")
+        .join(path.file_name().unwrap());
+    let second = JsonSync::<String, i32, ShardMap<String, i32>>::open(&alias).unwrap();
+
+    first.insert("a".into(), 1).unwrap();
+    assert_eq!(second.get(&"a".into()), Some(1));
+    assert!(JsonSync::<String, u8, ShardMap<String, u8>>::open(&path).is_err());
+
+    // the first handle's worker keeps flushing for the second
+    drop(first);
+    second.insert("b".into(), 2).unwrap();
+    std::thread::sleep(Duration::from_millis(200));
+    let on_disk: std::collections::HashMap<String, i32> =
+        serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+    assert_eq!(on_disk.len(), 2);
+    drop(second);
+
+    // with every handle gone, the next open starts a new store from the file
+    let third = JsonSync::<String, u8, ShardMap<String, u8>>::open(&path).unwrap();
+    assert_eq!(third.get(&"b".into()), Some(2));
+    drop(third);
+    let _ = std::fs::remove_file(&path);
+}
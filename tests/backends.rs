@@ -1,7 +1,8 @@
 use json_sync::JsonSync;
 use parking_lot::RwLock;
 use shardmap::ShardMap;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Bound;
 
 fn temp_path(name: &str) -> std::path::PathBuf {
     std::env::temp_dir().join(format!("json_sync_test_{}.json", name))
@@ -71,6 +72,17 @@ fn len_contains_key_is_empty() {
     let _ = std::fs::remove_file(&path);
 }
 
+#[test]
+fn shardmap_get_if_projects_value() {
+    let path = temp_path("sm_get_if");
+    let _ = std::fs::remove_file(&path);
+    let db = JsonSync::<String, Vec<u32>, ShardMap<String, Vec<u32>>>::open(&path).unwrap();
+    db.insert("v".into(), vec![1, 2, 3]).unwrap();
+    assert_eq!(db.get_if(&"v".into(), |v| v.len()), Some(3));
+    assert_eq!(db.get_if(&"missing".into(), |v| v.len()), None);
+    let _ = std::fs::remove_file(&path);
+}
+
 #[test]
 fn rwlock_hashmap_crud() {
     let path = temp_path("rwlock");
@@ -80,12 +92,125 @@ fn rwlock_hashmap_crud() {
     assert_eq!(db.get(&"k".into()), Some(100));
     assert!(db.contains_key(&"k".into()));
     assert_eq!(db.len(), 1);
+    assert_eq!(db.get_if(&"k".into(), |v| v * 2), Some(200));
     db.remove(&"k".into()).unwrap();
     assert!(db.is_empty());
     db.flush().unwrap();
     let _ = std::fs::remove_file(&path);
 }
 
+#[test]
+fn rwlock_hashmap_shrink_to_fit_releases_capacity() {
+    use json_sync::backend::MapBackend;
+
+    let map = RwLock::new(HashMap::<String, u32>::new());
+    for i in 0..100_000 {
+        MapBackend::insert(&map, format!("k{i}"), i);
+    }
+    for i in 1000..100_000 {
+        MapBackend::remove(&map, &format!("k{i}"));
+    }
+    let before = map.read().capacity();
+    MapBackend::shrink_to_fit(&map);
+    assert!(map.read().capacity() < before);
+    assert_eq!(MapBackend::map_len(&map), 1000);
+}
+
+#[test]
+fn shrink_to_fit_keeps_entries() {
+    let path = temp_path("shrink");
+    let _ = std::fs::remove_file(&path);
+    let db = JsonSync::<String, u32, RwLock<HashMap<String, u32>>>::open(&path).unwrap();
+    db.extend((0..10_000).map(|i| (format!("k{i}"), i)))
+        .unwrap();
+    for i in 100..10_000 {
+        db.remove(&format!("k{i}")).unwrap();
+    }
+    db.shrink_to_fit();
+    assert_eq!(db.len(), 100);
+    assert_eq!(db.get(&"k99".into()), Some(99));
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn btreemap_crud_and_sorted_iter() {
+    let path = temp_path("btree_crud");
+    let _ = std::fs::remove_file(&path);
+    {
+        let db = JsonSync::<u64, String, RwLock<BTreeMap<u64, String>>>::open(&path).unwrap();
+        db.insert(30, "c".into()).unwrap();
+        db.insert(10, "a".into()).unwrap();
+        db.insert(20, "b".into()).unwrap();
+        assert_eq!(db.keys(), vec![10, 20, 30]);
+        assert_eq!(db.remove(&20).unwrap(), Some("b".into()));
+        db.flush().unwrap();
+    }
+    let db = JsonSync::<u64, String, RwLock<BTreeMap<u64, String>>>::open(&path).unwrap();
+    assert_eq!(db.keys(), vec![10, 30]);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn btreemap_first_last() {
+    let path = temp_path("btree_first_last");
+    let _ = std::fs::remove_file(&path);
+    let db = JsonSync::<u64, i32, RwLock<BTreeMap<u64, i32>>>::open(&path).unwrap();
+    assert_eq!(db.first(), None);
+    assert_eq!(db.last(), None);
+    db.extend(vec![(5, 50), (1, 10), (9, 90), (3, 30)]).unwrap();
+    assert_eq!(db.first(), Some((1, 10)));
+    assert_eq!(db.last(), Some((9, 90)));
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn btreemap_range_bounds() {
+    let path = temp_path("btree_range");
+    let _ = std::fs::remove_file(&path);
+    let db = JsonSync::<u64, u64, RwLock<BTreeMap<u64, u64>>>::open(&path).unwrap();
+    db.extend((0..10).map(|i| (i * 10, i))).unwrap();
+
+    let keys = |v: Vec<(u64, u64)>| v.into_iter().map(|(k, _)| k).collect::<Vec<_>>();
+    assert_eq!(keys(db.range(20..50)), vec![20, 30, 40]);
+    assert_eq!(keys(db.range(20..=50)), vec![20, 30, 40, 50]);
+    assert_eq!(keys(db.range(..20)), vec![0, 10]);
+    assert_eq!(keys(db.range(85..)), vec![90]);
+    assert_eq!(keys(db.range(21..29)), Vec::<u64>::new());
+    assert_eq!(db.range(30..=30), vec![(30, 3)]);
+
+    // backwards or empty bounds find nothing, like the hash backends
+    let (hi, lo) = (50, 20);
+    assert_eq!(db.range(hi..lo), vec![]);
+    assert_eq!(db.range(hi..=lo), vec![]);
+    assert_eq!(db.range(30..30), vec![]);
+    assert_eq!(db.range((Bound::Excluded(30), Bound::Excluded(30))), vec![]);
+    assert_eq!(db.range((Bound::Excluded(30), Bound::Included(30))), vec![]);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn hash_backend_range_is_sorted() {
+    let path = temp_path("sm_range");
+    let _ = std::fs::remove_file(&path);
+    let db = JsonSync::<u64, u64, ShardMap<u64, u64>>::open(&path).unwrap();
+    db.extend((0..100).map(|i| (i, i * 2))).unwrap();
+    let got = db.range(40..45);
+    assert_eq!(got, vec![(40, 80), (41, 82), (42, 84), (43, 86), (44, 88)]);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn hash_backend_first_last_scans() {
+    let path = temp_path("sm_first_last");
+    let _ = std::fs::remove_file(&path);
+    let db = JsonSync::<String, i32, ShardMap<String, i32>>::open(&path).unwrap();
+    db.extend(vec![("m".into(), 1), ("a".into(), 2), ("z".into(), 3)])
+        .unwrap();
+    assert_eq!(db.first(), Some(("a".into(), 2)));
+    assert_eq!(db.last(), Some(("z".into(), 3)));
+    let _ = std::fs::remove_file(&path);
+}
+
 #[cfg(feature = "dashmap")]
 mod dashmap_tests {
     use super::temp_path;
@@ -101,6 +226,7 @@ mod dashmap_tests {
         assert_eq!(db.get(&"a".into()), Some(1));
         assert!(db.contains_key(&"a".into()));
         assert_eq!(db.len(), 1);
+        assert_eq!(db.get_if(&"a".into(), |v| v + 1), Some(2));
         db.remove(&"a".into()).unwrap();
         assert!(db.is_empty());
         db.flush().unwrap();
@@ -133,4 +259,264 @@ mod dashmap_tests {
         assert_eq!(db.get(&"key".into()), Some("val".into()));
         let _ = std::fs::remove_file(&path);
     }
+
+    #[test]
+    fn dashmap_backend_escape_hatch() {
+        let path = temp_path("dashmap_backend");
+        let _ = std::fs::remove_file(&path);
+        {
+            let db = JsonSync::<String, i32, DashMap<String, i32>>::open(&path).unwrap();
+            db.extend([("a".into(), 1), ("b".into(), 2)]).unwrap();
+            let version = db.version();
+            db.backend().alter_all(|_, v| v * 10);
+            assert_eq!(db.get(&"b".into()), Some(20));
+            assert_eq!(db.version(), version);
+            db.flush().unwrap();
+        }
+        let db = JsonSync::<String, i32, DashMap<String, i32>>::open(&path).unwrap();
+        assert_eq!(db.get(&"a".into()), Some(10));
+        let _ = std::fs::remove_file(&path);
+    }
+}
+
+#[test]
+fn rwlock_hashmap_reset() {
+    let path = temp_path("rwlock_reset");
+    let _ = std::fs::remove_file(&path);
+    let db = JsonSync::<String, i32, RwLock<HashMap<String, i32>>>::open(&path).unwrap();
+    db.insert("a".into(), 1).unwrap();
+    db.reset(vec![("b".into(), 2), ("c".into(), 3)]).unwrap();
+    assert!(!db.contains_key(&"a".into()));
+    assert_eq!(db.get(&"b".into()), Some(2));
+    assert_eq!(db.len(), 2);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn iter_paged_covers_everything_once() {
+    let path = temp_path("iter_paged");
+    let _ = std::fs::remove_file(&path);
+    let db = JsonSync::<u64, u64, ShardMap<u64, u64>>::open(&path).unwrap();
+    db.extend((0..103).map(|i| (i, i))).unwrap();
+
+    let pages: Vec<_> = db.iter_paged(10).collect();
+    assert_eq!(pages.len(), 11);
+    assert!(pages[..10].iter().all(|p| p.len() == 10));
+    let mut keys: Vec<u64> = pages.into_iter().flatten().map(|(k, _)| k).collect();
+    keys.sort_unstable();
+    assert_eq!(keys, (0..103).collect::<Vec<_>>());
+    let _ = std::fs::remove_file(&path);
+}
+
+/// Follow a `scan` cursor until it runs dry, returning every key seen.
+fn drain_scan(scan: impl Fn(Option<&u64>) -> Vec<(u64, u64)>) -> Vec<u64> {
+    let mut seen = Vec::new();
+    let mut cursor = None;
+    loop {
+        let page = scan(cursor.as_ref());
+        if page.is_empty() {
+            return seen;
+        }
+        assert!(page.len() <= 7);
+        cursor = page.last().map(|(k, _)| *k);
+        seen.extend(page.into_iter().map(|(k, _)| k));
+    }
+}
+
+#[test]
+fn scan_resumes_after_cursor() {
+    let btree = temp_path("scan_btree");
+    let hashed = temp_path("scan_hashed");
+    let _ = std::fs::remove_file(&btree);
+    let _ = std::fs::remove_file(&hashed);
+    let ordered = JsonSync::<u64, u64, RwLock<BTreeMap<u64, u64>>>::open(&btree).unwrap();
+    let unordered = JsonSync::<u64, u64, ShardMap<u64, u64>>::open(&hashed).unwrap();
+    ordered.extend((0..25).map(|i| (i * 2, i))).unwrap();
+    unordered.extend((0..25).map(|i| (i * 2, i))).unwrap();
+
+    let expected: Vec<u64> = (0..25).map(|i| i * 2).collect();
+    assert_eq!(drain_scan(|after| ordered.scan(after, 7)), expected);
+    assert_eq!(drain_scan(|after| unordered.scan(after, 7)), expected);
+    let _ = std::fs::remove_file(&btree);
+    let _ = std::fs::remove_file(&hashed);
+}
+
+#[test]
+fn shardmap_get_arc_shares_the_allocation() {
+    let path = temp_path("sm_get_arc");
+    let _ = std::fs::remove_file(&path);
+    let db = JsonSync::<String, Vec<u8>, ShardMap<String, Vec<u8>>>::open(&path).unwrap();
+    db.insert("big".into(), vec![7; 1 << 16]).unwrap();
+
+    let a = db.get_arc(&"big".into()).unwrap();
+    let b = db.get_arc(&"big".into()).unwrap();
+    assert!(std::sync::Arc::ptr_eq(&a, &b));
+    assert_eq!(a.len(), 1 << 16);
+
+    // overwriting swaps in a new Arc; the old handle keeps the old value
+    db.insert("big".into(), vec![1]).unwrap();
+    assert_eq!(a.len(), 1 << 16);
+    assert_eq!(*db.get_arc(&"big".into()).unwrap(), vec![1]);
+    assert!(db.get_arc(&"nope".into()).is_none());
+    let _ = std::fs::remove_file(&path);
+}
+
+/// A backend that forgets to override `map_len`, like a first draft might.
+#[derive(Default)]
+struct ForgetfulBackend(RwLock<HashMap<String, i32>>);
+
+impl json_sync::backend::MapBackend<String, i32> for ForgetfulBackend {
+    fn insert(&self, key: String, value: i32) -> Option<i32> {
+        self.0.write().insert(key, value)
+    }
+
+    fn get(&self, key: &String) -> Option<i32> {
+        self.0.read().get(key).copied()
+    }
+
+    fn remove(&self, key: &String) -> Option<i32> {
+        self.0.write().remove(key)
+    }
+
+    fn iter_snapshot(&self) -> Box<dyn Iterator<Item = (String, i32)> + Send + '_> {
+        let snap: Vec<_> = self.0.read().clone().into_iter().collect();
+        Box::new(snap.into_iter())
+    }
+}
+
+#[test]
+fn check_invariants_catches_buggy_backend() {
+    let good = temp_path("invariants_good");
+    let bad = temp_path("invariants_bad");
+    let _ = std::fs::remove_file(&good);
+    let _ = std::fs::remove_file(&bad);
+
+    let db = JsonSync::<String, i32, RwLock<HashMap<String, i32>>>::open(&good).unwrap();
+    db.extend(vec![("a".into(), 1), ("b".into(), 2)]).unwrap();
+    assert!(db.check_invariants().is_ok());
+
+    let db = JsonSync::<String, i32, ForgetfulBackend>::open(&bad).unwrap();
+    assert!(db.check_invariants().is_ok()); // empty: 0 happens to be right
+    db.extend(vec![("a".into(), 1), ("b".into(), 2)]).unwrap();
+    assert_eq!(
+        db.check_invariants(),
+        Err(json_sync::Error::Backend(
+            "map_len() is 0 but iter_snapshot() yielded 2 entries".into()
+        ))
+    );
+    db.flush().unwrap();
+    drop(db);
+
+    // opening a non-empty file with it works; only check_invariants complains
+    let db = JsonSync::<String, i32, ForgetfulBackend>::open(&bad).unwrap();
+    assert_eq!(db.get(&"b".into()), Some(2));
+    assert!(db.check_invariants().is_err());
+    drop(db);
+    let _ = std::fs::remove_file(&good);
+    let _ = std::fs::remove_file(&bad);
+}
+
+#[test]
+fn convert_rwlock_store_to_shardmap() {
+    let path = temp_path("convert_to");
+    let _ = std::fs::remove_file(&path);
+    let db = JsonSync::<String, i32, RwLock<HashMap<String, i32>>>::open_with_policy(
+        &path,
+        json_sync::FlushPolicy::Async(std::time::Duration::from_secs(60)),
+    )
+    .unwrap();
+    for i in 0..100 {
+        db.insert(format!("k{i}"), i).unwrap();
+    }
+    let mut before = db.iter();
+    before.sort();
+
+    let db = db.convert_to::<ShardMap<String, i32>>().unwrap();
+    let mut after = db.iter();
+    after.sort();
+    assert_eq!(after, before);
+    assert_eq!(db.path(), path.as_path());
+
+    db.insert("new".into(), -1).unwrap();
+    db.flush().unwrap();
+    drop(db);
+    let db = JsonSync::<String, i32, ShardMap<String, i32>>::open(&path).unwrap();
+    assert_eq!(db.len(), 101);
+    let _ = std::fs::remove_file(&path);
+}
+
+fn drain_filter_takes_only_matches<M>(name: &str)
+where
+    M: json_sync::backend::MapBackend<String, i32> + Default + 'static,
+{
+    let path = temp_path(name);
+    let _ = std::fs::remove_file(&path);
+    let db = JsonSync::<String, i32, M>::open(&path).unwrap();
+    db.extend((0..10).map(|i| (format!("k{i}"), i))).unwrap();
+
+    let mut drained = db.drain_filter(|_, v| v % 3 == 0).unwrap();
+    drained.sort();
+    assert_eq!(
+        drained,
+        vec![
+            ("k0".to_string(), 0),
+            ("k3".to_string(), 3),
+            ("k6".to_string(), 6),
+            ("k9".to_string(), 9),
+        ]
+    );
+    assert_eq!(db.len(), 6);
+    assert!(db.iter().iter().all(|(_, v)| v % 3 != 0));
+    assert!(db.drain_filter(|_, _| false).unwrap().is_empty());
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn drain_filter_shardmap() {
+    drain_filter_takes_only_matches::<ShardMap<String, i32>>("drain_filter_sm");
+}
+
+#[test]
+fn drain_filter_rwlock_hashmap() {
+    drain_filter_takes_only_matches::<RwLock<HashMap<String, i32>>>("drain_filter_rw");
+}
+
+#[test]
+fn drain_filter_rwlock_btreemap() {
+    drain_filter_takes_only_matches::<RwLock<BTreeMap<String, i32>>>("drain_filter_bt");
+}
+
+#[test]
+fn std_rwlock_keeps_working_after_poisoning() {
+    use std::sync::Arc;
+
+    type StdRwLock<T> = std::sync::RwLock<T>;
+    let path = temp_path("std_rwlock_poison");
+    let _ = std::fs::remove_file(&path);
+    let db =
+        Arc::new(JsonSync::<String, i32, StdRwLock<HashMap<String, i32>>>::open(&path).unwrap());
+    db.insert("a".into(), 1).unwrap();
+    assert!(!db.is_poisoned());
+
+    // panic while the backend's write lock is held
+    let db2 = Arc::clone(&db);
+    let panicked = std::thread::spawn(move || {
+        db2.update(&"a".into(), |_| panic!("boom")).unwrap();
+    })
+    .join();
+    assert!(panicked.is_err());
+
+    assert!(db.is_poisoned());
+    assert_eq!(db.get(&"a".into()), Some(1));
+    db.insert("b".into(), 2).unwrap();
+    assert!(db.update(&"a".into(), |v| *v += 10).unwrap());
+    db.flush().unwrap();
+    assert_eq!(db.len(), 2);
+
+    db.clear_poison();
+    assert!(!db.is_poisoned());
+    drop(db);
+    let db = JsonSync::<String, i32, StdRwLock<HashMap<String, i32>>>::open(&path).unwrap();
+    assert_eq!(db.get(&"a".into()), Some(11));
+    let _ = std::fs::remove_file(&path);
 }
@@ -0,0 +1,151 @@
+use json_sync::collections::{JsonCell, JsonCounters, JsonSet};
+use parking_lot::RwLock;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+fn temp_path(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("json_sync_test_{}.json", name))
+}
+
+// ---- JsonCounters -----------------------------------------------------------
+
+type Counters = JsonCounters<RwLock<HashMap<String, u64>>>;
+
+#[test]
+fn counters_incr_decr_reset_persist() {
+    let path = temp_path("counters");
+    let _ = std::fs::remove_file(&path);
+    {
+        let c = Counters::open(&path).unwrap();
+        assert_eq!(c.get("hits"), 0);
+        assert_eq!(c.incr("hits", 5).unwrap(), 5);
+        assert_eq!(c.decr("hits", 2).unwrap(), 3);
+        assert_eq!(c.decr("hits", 10).unwrap(), 0);
+        assert_eq!(c.incr("misses", 1).unwrap(), 1);
+        assert_eq!(c.reset("hits").unwrap(), Some(0));
+        assert_eq!(c.reset("hits").unwrap(), None);
+        c.flush().unwrap();
+    }
+    let c = Counters::open(&path).unwrap();
+    assert_eq!(c.get("misses"), 1);
+    assert_eq!(c.get("hits"), 0);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn counters_concurrent_incr_is_exact() {
+    let path = temp_path("counters_concurrent");
+    let _ = std::fs::remove_file(&path);
+    let c = Arc::new(Counters::open(&path).unwrap());
+
+    let threads: Vec<_> = (0..8)
+        .map(|_| {
+            let c = Arc::clone(&c);
+            std::thread::spawn(move || {
+                for _ in 0..1000 {
+                    c.incr("total", 1).unwrap();
+                }
+            })
+        })
+        .collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+
+    assert_eq!(c.get("total"), 8000);
+    let _ = std::fs::remove_file(&path);
+}
+
+// ---- JsonSet ----------------------------------------------------------------
+
+type Set = JsonSet<String, RwLock<HashMap<String, ()>>>;
+
+#[test]
+fn set_ignores_duplicates_and_persists() {
+    let path = temp_path("set");
+    let _ = std::fs::remove_file(&path);
+    {
+        let s = Set::open(&path).unwrap();
+        assert!(s.insert("a".into()).unwrap());
+        assert!(s.insert("b".into()).unwrap());
+        assert!(!s.insert("a".into()).unwrap());
+        assert_eq!(s.len(), 2);
+        assert!(s.remove(&"b".into()).unwrap());
+        assert!(!s.remove(&"b".into()).unwrap());
+        s.flush().unwrap();
+    }
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), r#"{"a":null}"#);
+
+    let s = Set::open(&path).unwrap();
+    assert!(s.contains(&"a".into()));
+    assert!(!s.contains(&"b".into()));
+    assert_eq!(s.iter(), vec!["a".to_string()]);
+    let _ = std::fs::remove_file(&path);
+}
+
+// ---- JsonCell ---------------------------------------------------------------
+
+#[test]
+fn cell_roundtrips_a_single_value() {
+    #[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct Config {
+        name: String,
+        retries: u32,
+    }
+
+    let path = temp_path("cell");
+    let _ = std::fs::remove_file(&path);
+    {
+        let cell = JsonCell::<Config>::open(&path).unwrap();
+        assert_eq!(cell.get(), None);
+        assert!(!cell.update(|c| c.retries += 1).unwrap());
+        cell.set(Config {
+            name: "svc".into(),
+            retries: 1,
+        })
+        .unwrap();
+        assert!(cell.update(|c| c.retries += 1).unwrap());
+        cell.flush().unwrap();
+    }
+    // just the value, no surrounding map
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        r#"{"name":"svc","retries":2}"#
+    );
+
+    let cell = JsonCell::<Config>::open(&path).unwrap();
+    assert_eq!(cell.get().map(|c| c.retries), Some(2));
+    assert_eq!(cell.take().unwrap().map(|c| c.name), Some("svc".into()));
+    cell.flush().unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "");
+    drop(cell);
+    assert_eq!(JsonCell::<Config>::open(&path).unwrap().get(), None);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn cell_keeps_values_that_serialize_to_null() {
+    let path = temp_path("cell_null");
+    let _ = std::fs::remove_file(&path);
+    {
+        let cell = JsonCell::<Option<u32>>::open(&path).unwrap();
+        assert_eq!(cell.get(), None);
+        cell.set(None).unwrap();
+        assert_eq!(cell.get(), Some(None));
+        cell.flush().unwrap();
+    }
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "null");
+    let cell = JsonCell::<Option<u32>>::open(&path).unwrap();
+    assert_eq!(cell.get(), Some(None));
+    assert_eq!(cell.take().unwrap(), Some(None));
+    cell.flush().unwrap();
+    drop(cell);
+    assert_eq!(JsonCell::<Option<u32>>::open(&path).unwrap().get(), None);
+
+    let unit = JsonCell::<()>::open(&path).unwrap();
+    unit.set(()).unwrap();
+    unit.flush().unwrap();
+    drop(unit);
+    assert_eq!(JsonCell::<()>::open(&path).unwrap().get(), Some(()));
+    let _ = std::fs::remove_file(&path);
+}
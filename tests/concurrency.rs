@@ -0,0 +1,416 @@
+use json_sync::backend::MapBackend;
+use json_sync::{FlushPolicy, JsonSync};
+use parking_lot::RwLock;
+use shardmap::ShardMap;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::thread;
+
+fn temp_path(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("json_sync_test_{}.json", name))
+}
+
+const THREADS: usize = 8;
+
+/// Every thread inserts, reads back, and removes its own keys while all of
+/// them bump one shared counter through `update` and flush now and then.
+/// Immediate policy, so flushes overlap constantly.
+fn mixed_workload<M>(name: &str)
+where
+    M: MapBackend<String, u64> + Default + 'static,
+{
+    const OPS: u64 = 100;
+    let path = temp_path(name);
+    let _ = std::fs::remove_file(&path);
+
+    let db = Arc::new(
+        JsonSync::<String, u64, M>::open_with_policy(&path, FlushPolicy::Immediate).unwrap(),
+    );
+    db.insert("hits".into(), 0).unwrap();
+
+    let workers: Vec<_> = (0..THREADS)
+        .map(|t| {
+            let db = Arc::clone(&db);
+            thread::spawn(move || {
+                for i in 0..OPS {
+                    let key = format!("t{t}-{i}");
+                    db.insert(key.clone(), i).unwrap();
+                    assert_eq!(db.get(&key), Some(i));
+                    assert!(db.update(&"hits".into(), |v| *v += 1).unwrap());
+                    if i % 3 == 0 {
+                        assert_eq!(db.remove(&key).unwrap(), Some(i));
+                    }
+                    if i % 16 == 0 {
+                        db.flush().unwrap();
+                    }
+                }
+            })
+        })
+        .collect();
+    for w in workers {
+        w.join().unwrap();
+    }
+
+    let mut expected: HashMap<String, u64> = (0..THREADS)
+        .flat_map(|t| {
+            (0..OPS)
+                .filter(|i| i % 3 != 0)
+                .map(move |i| (format!("t{t}-{i}"), i))
+        })
+        .collect();
+    expected.insert("hits".into(), THREADS as u64 * OPS);
+    let actual: HashMap<String, u64> = db.iter().into_iter().collect();
+    assert_eq!(actual, expected);
+
+    // the last immediate flush saw the final state, and the file is whole
+    let on_disk: HashMap<String, u64> =
+        serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+    assert_eq!(on_disk, expected);
+    drop(db);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn mixed_workload_shardmap() {
+    mixed_workload::<ShardMap<String, u64>>("conc_mixed_sm");
+}
+
+#[test]
+fn mixed_workload_rwlock_hashmap() {
+    mixed_workload::<RwLock<HashMap<String, u64>>>("conc_mixed_rw");
+}
+
+#[test]
+fn mixed_workload_rwlock_btreemap() {
+    mixed_workload::<RwLock<BTreeMap<String, u64>>>("conc_mixed_bt");
+}
+
+#[cfg(feature = "dashmap")]
+#[test]
+fn mixed_workload_dashmap() {
+    mixed_workload::<dashmap::DashMap<String, u64>>("conc_mixed_dm");
+}
+
+fn hammer_update<M>(name: &str)
+where
+    M: MapBackend<String, u64> + Default + 'static,
+{
+    const PER_THREAD: u64 = 2_000;
+    let path = temp_path(name);
+    let _ = std::fs::remove_file(&path);
+    let db = Arc::new(JsonSync::<String, u64, M>::open(&path).unwrap());
+    db.insert("n".into(), 0).unwrap();
+
+    let workers: Vec<_> = (0..THREADS)
+        .map(|_| {
+            let db = Arc::clone(&db);
+            thread::spawn(move || {
+                for _ in 0..PER_THREAD {
+                    db.update(&"n".into(), |v| *v += 1).unwrap();
+                }
+            })
+        })
+        .collect();
+    for w in workers {
+        w.join().unwrap();
+    }
+
+    assert_eq!(db.get(&"n".into()), Some(THREADS as u64 * PER_THREAD));
+    drop(db);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn update_loses_nothing_shardmap() {
+    hammer_update::<ShardMap<String, u64>>("conc_update_sm");
+}
+
+#[test]
+fn update_loses_nothing_rwlock_hashmap() {
+    hammer_update::<RwLock<HashMap<String, u64>>>("conc_update_rw");
+}
+
+#[cfg(feature = "dashmap")]
+#[test]
+fn update_loses_nothing_dashmap() {
+    hammer_update::<dashmap::DashMap<String, u64>>("conc_update_dm");
+}
+
+#[test]
+fn overlapping_flushes_never_clobber_temp_file() {
+    const ROUNDS: usize = 50;
+    let path = temp_path("conc_flush");
+    let _ = std::fs::remove_file(&path);
+    let db = Arc::new(JsonSync::<String, String, ShardMap<String, String>>::open(&path).unwrap());
+    // big enough that a write takes a while, so flushes really do overlap
+    for i in 0..500 {
+        db.insert(format!("k{i}"), "x".repeat(64)).unwrap();
+    }
+
+    let workers: Vec<_> = (0..THREADS)
+        .map(|t| {
+            let db = Arc::clone(&db);
+            let path = path.clone();
+            thread::spawn(move || {
+                for i in 0..ROUNDS {
+                    db.insert(format!("t{t}"), i.to_string()).unwrap();
+                    db.flush().unwrap();
+                    // whatever's there must be a complete file
+                    let bytes = std::fs::read(&path).unwrap();
+                    serde_json::from_slice::<HashMap<String, String>>(&bytes).unwrap();
+                }
+            })
+        })
+        .collect();
+    for w in workers {
+        w.join().unwrap();
+    }
+
+    db.flush().unwrap();
+    let on_disk: HashMap<String, String> =
+        serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+    assert_eq!(on_disk.len(), 500 + THREADS);
+    assert_eq!(on_disk["t0"], (ROUNDS - 1).to_string());
+    drop(db);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn flushes_never_write_a_half_done_reset() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Duration;
+
+    const ROUNDS: u64 = 200;
+    let path = temp_path("conc_reset");
+    let _ = std::fs::remove_file(&path);
+    let db = JsonSync::<String, u64, ShardMap<String, u64>>::open_with_policy(
+        &path,
+        FlushPolicy::Async(Duration::from_millis(1)),
+    )
+    .unwrap();
+    // each round swaps in a whole new generation of keys, so a flush that
+    // lands mid-reset would mix two generations or come up short
+    let generation = |g: u64| -> HashMap<String, u64> {
+        (0..200).map(|i| (format!("g{}-{i}", g % 2), g)).collect()
+    };
+
+    let done = Arc::new(AtomicBool::new(false));
+    let reader = {
+        let done = Arc::clone(&done);
+        let path = path.clone();
+        thread::spawn(move || {
+            let mut checked = 0;
+            while !done.load(Ordering::Relaxed) {
+                let Ok(bytes) = std::fs::read(&path) else {
+                    continue;
+                };
+                let on_disk: HashMap<String, u64> = serde_json::from_slice(&bytes).unwrap();
+                if let Some(&g) = on_disk.values().next() {
+                    assert_eq!(on_disk, generation(g), "flush caught a reset halfway");
+                    checked += 1;
+                }
+            }
+            checked
+        })
+    };
+    for g in 0..ROUNDS {
+        db.reset(generation(g)).unwrap();
+    }
+    db.sync().unwrap();
+    done.store(true, Ordering::Relaxed);
+    assert!(reader.join().unwrap() > 0);
+
+    let on_disk: HashMap<String, u64> =
+        serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+    assert_eq!(on_disk, generation(ROUNDS - 1));
+    drop(db);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn serialized_transfers_keep_the_total() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Duration;
+
+    const ACCOUNTS: u64 = 10;
+    const TRANSFERS: u64 = 300;
+    let total = ACCOUNTS as i64 * 100;
+    let path = temp_path("conc_serialized");
+    let _ = std::fs::remove_file(&path);
+    let db = Arc::new(
+        JsonSync::<u64, i64, ShardMap<u64, i64>>::builder(&path)
+            .serialized_writes(true)
+            .as_pairs(true)
+            .policy(FlushPolicy::Async(Duration::from_millis(1)))
+            .build()
+            .unwrap(),
+    );
+    db.extend((0..ACCOUNTS).map(|a| (a, 100))).unwrap();
+
+    let done = Arc::new(AtomicBool::new(false));
+    let auditor = {
+        let db = Arc::clone(&db);
+        let done = Arc::clone(&done);
+        thread::spawn(move || {
+            let mut checked = 0;
+            while !done.load(Ordering::Relaxed) {
+                assert_eq!(db.fold(0, |sum, _, v| sum + v), total);
+                assert_eq!(db.values().iter().sum::<i64>(), total);
+                checked += 1;
+            }
+            checked
+        })
+    };
+    let movers: Vec<_> = (0..THREADS as u64)
+        .map(|t| {
+            let db = Arc::clone(&db);
+            thread::spawn(move || {
+                for i in 0..TRANSFERS {
+                    let from = (t + i) % ACCOUNTS;
+                    let to = (t * 3 + i * 7 + 1) % ACCOUNTS;
+                    db.atomically(|| {
+                        let amount = db.get(&from).unwrap() / 2;
+                        db.update(&from, |b| *b -= amount)?;
+                        // a reader slipping in here would see money missing
+                        thread::yield_now();
+                        db.update(&to, |b| *b += amount)?;
+                        Ok(())
+                    })
+                    .unwrap();
+                }
+            })
+        })
+        .collect();
+    for m in movers {
+        m.join().unwrap();
+    }
+    done.store(true, Ordering::Relaxed);
+    assert!(auditor.join().unwrap() > 0);
+
+    // every flush snapshot was taken between transfers too
+    db.sync().unwrap();
+    let on_disk: Vec<(u64, i64)> = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+    assert_eq!(on_disk.iter().map(|(_, b)| b).sum::<i64>(), total);
+
+    let plain =
+        JsonSync::<u64, i64, ShardMap<u64, i64>>::open(temp_path("conc_unserialized")).unwrap();
+    assert!(matches!(
+        plain.atomically(|| Ok(())),
+        Err(json_sync::Error::Config(_))
+    ));
+    drop(db);
+    let _ = std::fs::remove_file(&path);
+}
+
+/// Snapshots the keys up front but reads each value only when it's reached,
+/// the way a sharded map can.
+#[derive(Default)]
+struct LateValues(RwLock<HashMap<String, i32>>);
+
+impl MapBackend<String, i32> for LateValues {
+    fn insert(&self, key: String, value: i32) -> Option<i32> {
+        self.0.write().insert(key, value)
+    }
+
+    fn get(&self, key: &String) -> Option<i32> {
+        self.0.read().get(key).copied()
+    }
+
+    fn remove(&self, key: &String) -> Option<i32> {
+        self.0.write().remove(key)
+    }
+
+    fn iter_snapshot(&self) -> Box<dyn Iterator<Item = (String, i32)> + Send + '_> {
+        let mut keys: Vec<String> = self.0.read().keys().cloned().collect();
+        keys.sort();
+        Box::new(
+            keys.into_iter()
+                .filter_map(|k| self.get(&k).map(|v| (k, v))),
+        )
+    }
+
+    fn map_len(&self) -> usize {
+        self.0.read().len()
+    }
+}
+
+#[test]
+fn lazy_iterators_dont_see_half_an_atomic_block() {
+    let path = temp_path("conc_lazy_iter");
+    let _ = std::fs::remove_file(&path);
+    let db = JsonSync::<String, i32, LateValues>::builder(&path)
+        .serialized_writes(true)
+        .build()
+        .unwrap();
+    db.extend(vec![("a".into(), 0), ("b".into(), 0)]).unwrap();
+    let set_both = |n| {
+        db.atomically(|| {
+            db.insert("a".into(), n)?;
+            db.insert("b".into(), n)?;
+            Ok(())
+        })
+        .unwrap()
+    };
+
+    {
+        let mut entries = db.entries_iter();
+        let (_, a) = entries.next().unwrap();
+        set_both(1);
+        assert_eq!(entries.next().unwrap().1, a);
+    }
+    {
+        let mut values = db.values_iter();
+        let a = values.next().unwrap();
+        set_both(2);
+        assert_eq!(values.next().unwrap(), a);
+    }
+    drop(db);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn blocking_nudges_dont_deadlock_serialized_writes() {
+    use json_sync::OnFull;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    let path = temp_path("conc_serialized_block");
+    let _ = std::fs::remove_file(&path);
+    let db = JsonSync::<String, u64, ShardMap<String, u64>>::builder(&path)
+        .serialized_writes(true)
+        .policy(FlushPolicy::AsyncBounded {
+            interval: Duration::from_millis(1),
+            channel_capacity: 1,
+            on_full: OnFull::Block,
+        })
+        .build()
+        .unwrap();
+
+    // the worker wants the serial lock to flush while the writer holds it
+    // for the whole batch; a writer waiting on the worker would never return
+    let (done, finished) = mpsc::channel();
+    let writer = thread::spawn(move || {
+        for round in 0..20u64 {
+            db.atomically(|| {
+                for i in 0..50 {
+                    db.insert(format!("k{i}"), round)?;
+                }
+                Ok(())
+            })
+            .unwrap();
+        }
+        db.sync().unwrap();
+        done.send(()).unwrap();
+    });
+    assert!(
+        finished.recv_timeout(Duration::from_secs(30)).is_ok(),
+        "writer deadlocked against the flush worker"
+    );
+    writer.join().unwrap();
+
+    let on_disk: HashMap<String, u64> =
+        serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+    assert_eq!(on_disk.len(), 50);
+    assert!(on_disk.values().all(|&round| round == 19));
+    let _ = std::fs::remove_file(&path);
+}
@@ -49,3 +49,644 @@ fn async_flush_worker_graceful_drop() {
     drop(db);
     let _ = std::fs::remove_file(&path);
 }
+
+#[test]
+fn on_growth_flushes_once_threshold_crossed() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let path = temp_path("on_growth");
+    let _ = std::fs::remove_file(&path);
+    let flushes = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&flushes);
+
+    let db = JsonSync::<String, String, ShardMap<String, String>>::builder(&path)
+        .policy(FlushPolicy::OnGrowth(100))
+        .on_flush(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        })
+        .build()
+        .unwrap();
+
+    // each entry is ~16 bytes of JSON: "kN" plus a 10-char string
+    db.insert("k0".into(), "0123456789".into()).unwrap();
+    assert_eq!(flushes.load(Ordering::SeqCst), 0);
+    assert!(!path.exists());
+
+    for i in 1..10 {
+        db.insert(format!("k{i}"), "0123456789".into()).unwrap();
+    }
+    assert_eq!(flushes.load(Ordering::SeqCst), 1);
+    assert!(path.exists());
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn shutdown_with_long_interval_is_fast() {
+    let path = temp_path("async_shutdown");
+    let _ = std::fs::remove_file(&path);
+    let db = JsonSync::<String, i32, ShardMap<String, i32>>::open_with_policy(
+        &path,
+        FlushPolicy::Async(Duration::from_secs(60)),
+    )
+    .unwrap();
+    db.insert("s".into(), 5).unwrap();
+
+    let started = std::time::Instant::now();
+    db.shutdown(Duration::from_secs(5)).unwrap();
+    assert!(started.elapsed() < Duration::from_secs(5));
+
+    let db = JsonSync::<String, i32, ShardMap<String, i32>>::open(&path).unwrap();
+    assert_eq!(db.get(&"s".into()), Some(5));
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn drop_with_long_interval_does_not_wait_out_interval() {
+    let path = temp_path("async_drop_fast");
+    let _ = std::fs::remove_file(&path);
+    let db = JsonSync::<String, i32, ShardMap<String, i32>>::open_with_policy(
+        &path,
+        FlushPolicy::Async(Duration::from_secs(60)),
+    )
+    .unwrap();
+    db.insert("d".into(), 1).unwrap();
+
+    let started = std::time::Instant::now();
+    drop(db);
+    assert!(started.elapsed() < Duration::from_secs(5));
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn async_worker_thread_is_named() {
+    use std::sync::{Arc, Mutex};
+
+    let path = temp_path("thread_name");
+    let _ = std::fs::remove_file(&path);
+    let seen = Arc::new(Mutex::new(None::<String>));
+    let seen_ref = Arc::clone(&seen);
+
+    let db = JsonSync::<String, i32, ShardMap<String, i32>>::builder(&path)
+        .policy(FlushPolicy::Async(Duration::from_secs(60)))
+        .thread_name("my-store-flush")
+        .on_flush(move || {
+            *seen_ref.lock().unwrap() = std::thread::current().name().map(String::from);
+        })
+        .build()
+        .unwrap();
+    db.insert("n".into(), 1).unwrap();
+
+    let deadline = std::time::Instant::now() + Duration::from_secs(5);
+    while seen.lock().unwrap().is_none() && std::time::Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    assert_eq!(seen.lock().unwrap().as_deref(), Some("my-store-flush"));
+    drop(db);
+    let _ = std::fs::remove_file(&path);
+}
+
+/// The calling thread's nice value, from `/proc`.
+#[cfg(target_os = "linux")]
+fn current_nice() -> i32 {
+    let stat = std::fs::read_to_string("/proc/thread-self/stat").unwrap();
+    // fields after the parenthesized command name; nice is the 19th overall
+    let rest = &stat[stat.rfind(')').unwrap() + 2..];
+    rest.split(' ').nth(16).unwrap().parse().unwrap()
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn async_worker_can_run_at_low_priority() {
+    use std::sync::{Arc, Mutex};
+
+    let path = temp_path("thread_low_priority");
+    let _ = std::fs::remove_file(&path);
+    let seen = Arc::new(Mutex::new(None::<i32>));
+    let seen_ref = Arc::clone(&seen);
+
+    let db = JsonSync::<String, i32, ShardMap<String, i32>>::builder(&path)
+        .policy(FlushPolicy::Async(Duration::from_secs(60)))
+        .thread_low_priority(true)
+        .on_flush(move || {
+            *seen_ref.lock().unwrap() = Some(current_nice());
+        })
+        .build()
+        .unwrap();
+    db.insert("n".into(), 1).unwrap();
+    db.sync().unwrap();
+
+    let deadline = std::time::Instant::now() + Duration::from_secs(5);
+    while seen.lock().unwrap().is_none() && std::time::Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    let here = current_nice();
+    let worker = seen.lock().unwrap().unwrap();
+    assert!(
+        worker >= 10.max(here),
+        "worker nice {worker}, caller {here}"
+    );
+    drop(db);
+    let _ = std::fs::remove_file(&path);
+}
+
+/// JSON, but takes its time about it.
+struct SlowSerializer(Duration);
+
+impl json_sync::serializer::Serializer for SlowSerializer {
+    fn serialize<K, V>(&self, data: &std::collections::HashMap<K, V>) -> json_sync::Result<Vec<u8>>
+    where
+        K: serde::Serialize,
+        V: serde::Serialize,
+    {
+        std::thread::sleep(self.0);
+        json_sync::serializer::JsonSerializer::new().serialize(data)
+    }
+
+    fn deserialize<K, V>(&self, bytes: &[u8]) -> json_sync::Result<std::collections::HashMap<K, V>>
+    where
+        K: for<'de> serde::Deserialize<'de> + Eq + std::hash::Hash,
+        V: for<'de> serde::Deserialize<'de>,
+    {
+        json_sync::serializer::JsonSerializer::new().deserialize(bytes)
+    }
+}
+
+#[test]
+fn slow_flush_threshold_reports_duration_and_size() {
+    use std::sync::{Arc, Mutex};
+
+    let path = temp_path("slow_flush");
+    let _ = std::fs::remove_file(&path);
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let sink = Arc::clone(&seen);
+    let db = JsonSync::<String, i32, ShardMap<String, i32>>::builder(&path)
+        .serializer(SlowSerializer(Duration::from_millis(30)))
+        .slow_flush_threshold(Duration::from_millis(10), move |took, bytes| {
+            sink.lock().unwrap().push((took, bytes));
+        })
+        .build()
+        .unwrap();
+    db.insert("a".into(), 1).unwrap();
+    db.flush().unwrap();
+
+    let seen = seen.lock().unwrap();
+    assert_eq!(seen.len(), 1);
+    assert!(seen[0].0 >= Duration::from_millis(30));
+    assert_eq!(seen[0].1, std::fs::metadata(&path).unwrap().len() as usize);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn suspend_flush_coalesces_into_one_flush() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let path = temp_path("suspend_flush");
+    let _ = std::fs::remove_file(&path);
+    let flushes = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&flushes);
+    let db = JsonSync::<String, i32, ShardMap<String, i32>>::builder(&path)
+        .policy(FlushPolicy::Immediate)
+        .on_flush(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        })
+        .build()
+        .unwrap();
+
+    {
+        let _outer = db.suspend_flush();
+        db.extend((0..10).map(|i| (i.to_string(), i))).unwrap();
+        {
+            let _inner = db.suspend_flush();
+            db.clear().unwrap();
+        }
+        db.insert("last".into(), 1).unwrap();
+        assert_eq!(flushes.load(Ordering::SeqCst), 0);
+    }
+    assert_eq!(flushes.load(Ordering::SeqCst), 1);
+
+    // nothing changed while suspended: no flush on resume
+    db.suspend_flush().resume().unwrap();
+    assert_eq!(flushes.load(Ordering::SeqCst), 1);
+
+    // back to one flush per mutation
+    db.insert("after".into(), 2).unwrap();
+    assert_eq!(flushes.load(Ordering::SeqCst), 2);
+
+    let reopened = JsonSync::<String, i32, ShardMap<String, i32>>::open(&path).unwrap();
+    assert_eq!(reopened.len(), 2);
+    let _ = std::fs::remove_file(&path);
+}
+
+fn wait_for(cond: impl Fn() -> bool) {
+    let deadline = std::time::Instant::now() + Duration::from_secs(5);
+    while !cond() && std::time::Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(5));
+    }
+}
+
+#[test]
+fn async_block_flushes_for_every_nudge() {
+    use json_sync::OnFull;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let path = temp_path("async_block");
+    let _ = std::fs::remove_file(&path);
+    let flushes = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&flushes);
+    let db = JsonSync::<String, i32, ShardMap<String, i32>>::builder(&path)
+        .policy(FlushPolicy::AsyncBounded {
+            interval: Duration::from_secs(60),
+            channel_capacity: 0,
+            on_full: OnFull::Block,
+        })
+        .on_flush(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        })
+        .build()
+        .unwrap();
+
+    // the timer never fires, so each entry can only reach disk through its
+    // own insert's nudge — which Block never skips, even with no buffer
+    let on_disk = |n: usize| {
+        std::fs::read(&path)
+            .ok()
+            .and_then(|b| serde_json::from_slice::<std::collections::HashMap<String, i32>>(&b).ok())
+            .is_some_and(|m| m.len() == n)
+    };
+    for i in 0..20 {
+        db.insert(format!("k{i}"), i).unwrap();
+        wait_for(|| on_disk(i as usize + 1));
+        assert!(on_disk(i as usize + 1), "insert {i} was never flushed");
+    }
+    wait_for(|| flushes.load(Ordering::SeqCst) == 20);
+    assert_eq!(flushes.load(Ordering::SeqCst), 20);
+    drop(db);
+
+    let db = JsonSync::<String, i32, ShardMap<String, i32>>::open(&path).unwrap();
+    assert_eq!(db.len(), 20);
+    let _ = std::fs::remove_file(&path);
+}
+
+/// Whether a thread called `name` is alive in this process, where the OS
+/// lets us look (`/proc` on Linux); `None` elsewhere.
+fn thread_alive(name: &str) -> Option<bool> {
+    let tasks = std::fs::read_dir("/proc/self/task").ok()?;
+    Some(tasks.flatten().any(|task| {
+        std::fs::read_to_string(task.path().join("comm")).is_ok_and(|comm| comm.trim_end() == name)
+    }))
+}
+
+#[test]
+fn async_worker_sleeps_while_the_store_is_idle() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let path = temp_path("async_idle");
+    let _ = std::fs::remove_file(&path);
+    let flushes = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&flushes);
+    let db = JsonSync::<String, i32, ShardMap<String, i32>>::builder(&path)
+        .policy(FlushPolicy::Async(Duration::from_millis(5)))
+        .park_worker_after(Duration::from_millis(50))
+        .thread_name("idle-flush")
+        .on_flush(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        })
+        .build()
+        .unwrap();
+
+    // untouched: dozens of intervals pass with no thread to flush
+    std::thread::sleep(Duration::from_millis(100));
+    assert_eq!(flushes.load(Ordering::SeqCst), 0);
+    assert!(!path.exists());
+    assert_ne!(thread_alive("idle-flush"), Some(true));
+
+    // the first mutation spawns it, and the timer runs until the grace period
+    db.insert("a".into(), 1).unwrap();
+    wait_for(|| flushes.load(Ordering::SeqCst) > 1);
+    assert!(path.exists());
+    assert_ne!(thread_alive("idle-flush"), Some(false));
+
+    // then it parks again and stays parked
+    std::thread::sleep(Duration::from_millis(150));
+    let parked = flushes.load(Ordering::SeqCst);
+    std::thread::sleep(Duration::from_millis(100));
+    assert_eq!(flushes.load(Ordering::SeqCst), parked);
+
+    db.insert("b".into(), 2).unwrap();
+    wait_for(|| flushes.load(Ordering::SeqCst) > parked);
+    assert!(flushes.load(Ordering::SeqCst) > parked);
+    drop(db);
+    assert_ne!(thread_alive("idle-flush"), Some(true));
+    let _ = std::fs::remove_file(&path);
+
+    // with no worker yet, sync and shutdown write from the caller
+    let db = JsonSync::<String, i32, ShardMap<String, i32>>::builder(&path)
+        .policy(FlushPolicy::Async(Duration::from_millis(5)))
+        .thread_name("never-flush")
+        .build()
+        .unwrap();
+    db.sync().unwrap();
+    assert!(path.exists());
+    db.shutdown(Duration::from_secs(1)).unwrap();
+    assert_ne!(thread_alive("never-flush"), Some(true));
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn async_timer_runs_on_the_store_clock() {
+    use json_sync::clock::ManualClock;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::UNIX_EPOCH;
+
+    let path = temp_path("async_clock");
+    let _ = std::fs::remove_file(&path);
+    let flushes = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&flushes);
+    let clock = Arc::new(ManualClock::new(UNIX_EPOCH + Duration::from_secs(1 << 30)));
+    let db = JsonSync::<String, i32, ShardMap<String, i32>>::builder(&path)
+        .policy(FlushPolicy::Async(Duration::from_secs(60)))
+        .park_worker_after(Duration::from_secs(150))
+        .clock(Arc::clone(&clock) as _)
+        .on_flush(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        })
+        .build()
+        .unwrap();
+    let count = || flushes.load(Ordering::SeqCst);
+
+    db.insert("a".into(), 1).unwrap();
+    wait_for(|| count() == 1);
+    assert_eq!(count(), 1);
+
+    // a minute by the clock, not by the wall, runs the timer out
+    clock.advance(Duration::from_secs(59));
+    std::thread::sleep(Duration::from_millis(50));
+    assert_eq!(count(), 1);
+    clock.advance(Duration::from_secs(1));
+    wait_for(|| count() == 2);
+    assert_eq!(count(), 2);
+    clock.advance(Duration::from_secs(60));
+    wait_for(|| count() == 3);
+    assert_eq!(count(), 3);
+
+    // 150 s by the clock without a mutation parks it
+    clock.advance(Duration::from_secs(60));
+    std::thread::sleep(Duration::from_millis(50));
+    clock.advance(Duration::from_secs(600));
+    std::thread::sleep(Duration::from_millis(50));
+    assert_eq!(count(), 3);
+    db.insert("b".into(), 2).unwrap();
+    wait_for(|| count() == 4);
+    assert_eq!(count(), 4);
+    drop(db);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn async_buffered_nudges_are_batched() {
+    use json_sync::OnFull;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let path = temp_path("async_batched");
+    let _ = std::fs::remove_file(&path);
+    let flushes = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&flushes);
+    let db = JsonSync::<String, i32, ShardMap<String, i32>>::builder(&path)
+        .policy(FlushPolicy::AsyncBounded {
+            interval: Duration::from_secs(60),
+            channel_capacity: 64,
+            on_full: OnFull::Block,
+        })
+        .serializer(SlowSerializer(Duration::from_millis(20)))
+        .on_flush(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        })
+        .build()
+        .unwrap();
+
+    for i in 0..30 {
+        db.insert(format!("k{i}"), i).unwrap();
+    }
+    let on_disk = || {
+        std::fs::read(&path)
+            .ok()
+            .and_then(|b| serde_json::from_slice::<std::collections::HashMap<String, i32>>(&b).ok())
+            .is_some_and(|m| m.len() == 30)
+    };
+    wait_for(on_disk);
+    assert!(on_disk());
+    // the worker was busy writing while most nudges queued up
+    assert!(flushes.load(Ordering::SeqCst) < 30);
+    drop(db);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn sync_waits_for_the_worker_to_write() {
+    let path = temp_path("async_sync");
+    let _ = std::fs::remove_file(&path);
+    let db = JsonSync::<String, i32, ShardMap<String, i32>>::builder(&path)
+        .policy(FlushPolicy::Async(Duration::from_secs(60)))
+        .serializer(SlowSerializer(Duration::from_millis(50)))
+        .build()
+        .unwrap();
+    let read = || -> std::collections::HashMap<String, i32> {
+        serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap()
+    };
+
+    // the first insert's flush is still writing when the second lands, so
+    // sync has to wait for a later one
+    db.insert("a".into(), 1).unwrap();
+    std::thread::sleep(Duration::from_millis(10));
+    db.insert("b".into(), 2).unwrap();
+    db.sync().unwrap();
+    assert_eq!(read().get("b"), Some(&2));
+
+    db.insert("c".into(), 3).unwrap();
+    db.sync().unwrap();
+    assert_eq!(read().len(), 3);
+    drop(db);
+
+    // without a worker it's a plain flush
+    let db = JsonSync::<String, i32, ShardMap<String, i32>>::open(&path).unwrap();
+    db.insert("d".into(), 4).unwrap();
+    db.sync().unwrap();
+    assert_eq!(read().len(), 4);
+    drop(db);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn paused_worker_leaves_the_file_alone_until_resumed() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let path = temp_path("paused_worker");
+    let _ = std::fs::remove_file(&path);
+    let flushes = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&flushes);
+    let db = JsonSync::<String, i32, ShardMap<String, i32>>::builder(&path)
+        .policy(FlushPolicy::Async(Duration::from_secs(60)))
+        .on_flush(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        })
+        .build()
+        .unwrap();
+    db.insert("before".into(), 0).unwrap();
+    db.sync().unwrap();
+    let before = std::fs::read(&path).unwrap();
+    let read = || {
+        serde_json::from_slice::<std::collections::HashMap<String, i32>>(
+            &std::fs::read(&path).unwrap(),
+        )
+        .unwrap()
+    };
+
+    db.pause_flushing();
+    for i in 0..200 {
+        db.insert(format!("k{i}"), i).unwrap();
+    }
+    // every insert nudged the worker, and none of them got written
+    std::thread::sleep(Duration::from_millis(100));
+    let paused = flushes.load(Ordering::SeqCst);
+    assert_eq!(std::fs::read(&path).unwrap(), before);
+
+    // the timer never fires, so this is the resume's own flush
+    db.resume_flushing();
+    wait_for(|| read().len() == 201);
+    assert_eq!(read().len(), 201);
+    std::thread::sleep(Duration::from_millis(50));
+    assert_eq!(flushes.load(Ordering::SeqCst), paused + 1);
+
+    // a sync still gets its flush while paused
+    db.pause_flushing();
+    db.insert("late".into(), 1).unwrap();
+    db.sync().unwrap();
+    assert_eq!(read().get("late"), Some(&1));
+    drop(db);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[cfg(feature = "schedule")]
+#[test]
+fn scheduled_flush_fires_when_the_clock_passes_a_boundary() {
+    use json_sync::clock::{Clock, ManualClock};
+    use json_sync::schedule::Schedule;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::UNIX_EPOCH;
+
+    let path = temp_path("scheduled");
+    let _ = std::fs::remove_file(&path);
+    let flushes = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&flushes);
+    // half a minute before the top of an hour
+    let clock = Arc::new(ManualClock::new(
+        UNIX_EPOCH + Duration::from_secs(480_000 * 3600 - 30),
+    ));
+    let db = JsonSync::<String, i32, ShardMap<String, i32>>::builder(&path)
+        .policy(FlushPolicy::Scheduled(Schedule::hourly()))
+        .clock(Arc::clone(&clock) as _)
+        .on_flush(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        })
+        .build()
+        .unwrap();
+
+    // mutations alone never flush, however long real time runs
+    db.insert("a".into(), 1).unwrap();
+    std::thread::sleep(Duration::from_millis(1200));
+    assert_eq!(flushes.load(Ordering::SeqCst), 0);
+    assert!(!path.exists());
+
+    clock.advance(Duration::from_secs(60));
+    wait_for(|| flushes.load(Ordering::SeqCst) == 1);
+    assert_eq!(flushes.load(Ordering::SeqCst), 1);
+    let on_disk = JsonSync::<String, i32, ShardMap<String, i32>>::open(&path).unwrap();
+    assert_eq!(on_disk.get(&"a".into()), Some(1));
+    drop(on_disk);
+
+    // a jump over three boundaries flushes once, and setting the clock back
+    // over them doesn't flush them again
+    db.insert("b".into(), 2).unwrap();
+    clock.advance(Duration::from_secs(3 * 3600));
+    wait_for(|| flushes.load(Ordering::SeqCst) == 2);
+    clock.set(clock.now() - Duration::from_secs(2 * 3600));
+    std::thread::sleep(Duration::from_millis(1200));
+    assert_eq!(flushes.load(Ordering::SeqCst), 2);
+    drop(db);
+
+    let db = JsonSync::<String, i32, ShardMap<String, i32>>::open(&path).unwrap();
+    assert_eq!(db.len(), 2);
+    drop(db);
+    let _ = std::fs::remove_file(&path);
+
+    let never = JsonSync::<String, i32, ShardMap<String, i32>>::builder(&path)
+        .policy(FlushPolicy::Scheduled(Schedule::daily_at([])))
+        .build();
+    assert!(matches!(never, Err(json_sync::Error::Config(_))));
+}
+
+/// Re-runs this test binary as a child that opens a store with
+/// `flush_on_signal(SIGTERM)`, inserts without flushing, and waits to be
+/// killed. The parent sends SIGTERM and checks the data made it to disk.
+#[cfg(all(unix, feature = "signal"))]
+#[test]
+fn flush_on_signal_persists_before_exit() {
+    use std::io::BufRead;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::{Command, Stdio};
+
+    const CHILD: &str = "JSON_SYNC_SIGNAL_CHILD";
+    if let Some(path) = std::env::var_os(CHILD) {
+        let db = JsonSync::<String, i32, ShardMap<String, i32>>::builder(&path)
+            .flush_on_signal(&[json_sync::signal::SIGTERM])
+            .build()
+            .unwrap();
+        db.insert("a".into(), 1).unwrap();
+        println!("ready");
+        loop {
+            std::thread::sleep(Duration::from_secs(1));
+        }
+    }
+
+    let path = temp_path("signal");
+    let _ = std::fs::remove_file(&path);
+    let mut child = Command::new(std::env::current_exe().unwrap())
+        .args([
+            "--exact",
+            "flush_on_signal_persists_before_exit",
+            "--nocapture",
+        ])
+        .env(CHILD, &path)
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let stdout = std::io::BufReader::new(child.stdout.take().unwrap());
+    // libtest prints "test <name> ... " on the same line first
+    for line in stdout.lines() {
+        if line.unwrap().ends_with("ready") {
+            break;
+        }
+    }
+    assert!(!path.exists());
+
+    let killed = Command::new("kill")
+        .args(["-TERM", &child.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(killed.success());
+    let status = child.wait().unwrap();
+    assert_eq!(status.signal(), Some(json_sync::signal::SIGTERM));
+
+    let db = JsonSync::<String, i32, ShardMap<String, i32>>::open(&path).unwrap();
+    assert_eq!(db.get(&"a".into()), Some(1));
+    drop(db);
+    let _ = std::fs::remove_file(&path);
+}
@@ -0,0 +1,1407 @@
+use json_sync::serializer::JsonSerializer;
+use json_sync::JsonSync;
+use shardmap::ShardMap;
+
+fn temp_path(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("json_sync_test_{}.json", name))
+}
+
+#[test]
+fn builder_takes_a_serializer() {
+    let path = temp_path("custom_serializer");
+    let _ = std::fs::remove_file(&path);
+    let db = JsonSync::<String, i32, ShardMap<String, i32>>::builder(&path)
+        .serializer(JsonSerializer::pretty())
+        .build()
+        .unwrap();
+    db.insert("a".into(), 1).unwrap();
+    db.flush().unwrap();
+    assert!(std::fs::read_to_string(&path).unwrap().contains('\n'));
+    let _ = std::fs::remove_file(&path);
+}
+
+#[cfg(feature = "gzip")]
+mod gzip_tests {
+    use super::temp_path;
+    use json_sync::serializer::{is_gzip, Compressed, JsonSerializer, Serializer};
+    use json_sync::JsonSync;
+    use shardmap::ShardMap;
+    use std::collections::HashMap;
+
+    fn sample() -> HashMap<String, i32> {
+        (0..20).map(|i| (format!("key{i}"), i)).collect()
+    }
+
+    #[test]
+    fn compressed_roundtrip_writes_gzip() {
+        let path = temp_path("gzip_roundtrip");
+        let _ = std::fs::remove_file(&path);
+        {
+            let db = JsonSync::<String, i32, ShardMap<String, i32>>::builder(&path)
+                .serializer(Compressed::new(JsonSerializer::new()))
+                .build()
+                .unwrap();
+            db.extend(sample()).unwrap();
+            db.flush().unwrap();
+        }
+        assert!(is_gzip(&std::fs::read(&path).unwrap()));
+
+        let db = JsonSync::<String, i32, ShardMap<String, i32>>::builder(&path)
+            .serializer(Compressed::new(JsonSerializer::new()).level(9))
+            .build()
+            .unwrap();
+        assert_eq!(db.len(), 20);
+        assert_eq!(db.get(&"key7".into()), Some(7));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn plain_and_gzipped_files_both_load() {
+        let plain = temp_path("gzip_sniff_plain");
+        let packed = temp_path("gzip_sniff_packed");
+        let data = sample();
+        std::fs::write(&plain, JsonSerializer::new().serialize(&data).unwrap()).unwrap();
+        std::fs::write(
+            &packed,
+            Compressed::new(JsonSerializer::new())
+                .serialize(&data)
+                .unwrap(),
+        )
+        .unwrap();
+
+        for path in [&plain, &packed] {
+            // compressed store reading either format
+            let db = JsonSync::<String, i32, ShardMap<String, i32>>::builder(path)
+                .serializer(Compressed::new(JsonSerializer::new()))
+                .build()
+                .unwrap();
+            assert_eq!(db.len(), 20);
+            drop(db);
+
+            // plain JSON store reading either format
+            let db = JsonSync::<String, i32, ShardMap<String, i32>>::open(path).unwrap();
+            assert_eq!(db.get(&"key19".into()), Some(19));
+        }
+        let _ = std::fs::remove_file(&plain);
+        let _ = std::fs::remove_file(&packed);
+    }
+
+    /// Hands out one byte per `read`, like a slow pipe.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl std::io::Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            match (self.0.split_first(), buf.first_mut()) {
+                (Some((&byte, rest)), Some(slot)) => {
+                    *slot = byte;
+                    self.0 = rest;
+                    Ok(1)
+                }
+                _ => Ok(0),
+            }
+        }
+    }
+
+    #[test]
+    fn gzip_sniffing_survives_short_reads() {
+        let gzip = Compressed::new(JsonSerializer::new());
+        let packed = gzip.serialize(&sample()).unwrap();
+        let back: HashMap<String, i32> = gzip.deserialize_from(Trickle(&packed)).unwrap();
+        assert_eq!(back, sample());
+
+        let plain = JsonSerializer::new().serialize(&sample()).unwrap();
+        let back: HashMap<String, i32> = gzip.deserialize_from(Trickle(&plain)).unwrap();
+        assert_eq!(back, sample());
+    }
+}
+
+#[cfg(feature = "zstd")]
+mod zstd_tests {
+    use super::temp_path;
+    use json_sync::serializer::{is_zstd, JsonSerializer, Serializer, Zstd};
+    use json_sync::JsonSync;
+    use shardmap::ShardMap;
+    use std::collections::HashMap;
+
+    fn sample() -> HashMap<String, String> {
+        (0..500)
+            .map(|i| (format!("key{i}"), format!("value {} of the sample", i % 7)))
+            .collect()
+    }
+
+    #[test]
+    fn zstd_roundtrips_at_each_level() {
+        let mut sizes = Vec::new();
+        for level in [1, 19] {
+            let path = temp_path(&format!("zstd_level_{level}"));
+            let _ = std::fs::remove_file(&path);
+            {
+                let db = JsonSync::<String, String, ShardMap<String, String>>::builder(&path)
+                    .serializer(Zstd::new(JsonSerializer::new()).level(level))
+                    .build()
+                    .unwrap();
+                db.extend(sample()).unwrap();
+                db.flush().unwrap();
+            }
+            let bytes = std::fs::read(&path).unwrap();
+            assert!(is_zstd(&bytes));
+            sizes.push(bytes.len());
+
+            // a plain JSON store reads it too
+            let db = JsonSync::<String, String, ShardMap<String, String>>::open(&path).unwrap();
+            assert_eq!(db.len(), 500);
+            assert_eq!(db.get(&"key8".into()), Some("value 1 of the sample".into()));
+            drop(db);
+            let _ = std::fs::remove_file(&path);
+        }
+        assert!(sizes[1] <= sizes[0], "sizes at levels 1 and 19: {sizes:?}");
+    }
+
+    #[test]
+    fn zstd_reads_plain_and_empty_input() {
+        let zstd = Zstd::new(JsonSerializer::new());
+        let plain = JsonSerializer::new().serialize(&sample()).unwrap();
+        let back: HashMap<String, String> = zstd.deserialize(&plain).unwrap();
+        assert_eq!(back, sample());
+
+        let path = temp_path("zstd_empty");
+        std::fs::write(&path, b"").unwrap();
+        let db = JsonSync::<String, String, ShardMap<String, String>>::builder(&path)
+            .serializer(Zstd::new(JsonSerializer::new()))
+            .build()
+            .unwrap();
+        assert!(db.is_empty());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    /// Hands out one byte per `read`, like a slow pipe.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl std::io::Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            match (self.0.split_first(), buf.first_mut()) {
+                (Some((&byte, rest)), Some(slot)) => {
+                    *slot = byte;
+                    self.0 = rest;
+                    Ok(1)
+                }
+                _ => Ok(0),
+            }
+        }
+    }
+
+    #[test]
+    fn zstd_sniffing_survives_short_reads() {
+        let zstd = Zstd::new(JsonSerializer::new());
+        let packed = zstd.serialize(&sample()).unwrap();
+        let back: HashMap<String, String> = zstd.deserialize_from(Trickle(&packed)).unwrap();
+        assert_eq!(back, sample());
+
+        let plain = JsonSerializer::new().serialize(&sample()).unwrap();
+        let back: HashMap<String, String> = zstd.deserialize_from(Trickle(&plain)).unwrap();
+        assert_eq!(back, sample());
+    }
+}
+
+// ---- versioning -------------------------------------------------------------
+
+#[test]
+fn format_version_wraps_data_in_envelope() {
+    let path = temp_path("version_envelope");
+    let _ = std::fs::remove_file(&path);
+    {
+        let db = JsonSync::<String, i32, ShardMap<String, i32>>::builder(&path)
+            .format_version(2)
+            .build()
+            .unwrap();
+        db.insert("a".into(), 1).unwrap();
+        db.flush().unwrap();
+    }
+    let raw: serde_json::Value = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+    assert_eq!(raw["json_sync_version"], 2);
+    assert_eq!(raw["data"]["a"], 1);
+
+    let db = JsonSync::<String, i32, ShardMap<String, i32>>::builder(&path)
+        .format_version(2)
+        .max_supported_version(2)
+        .build()
+        .unwrap();
+    assert_eq!(db.get(&"a".into()), Some(1));
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn versioned_store_keeps_a_map_keyed_version_and_data() {
+    let path = temp_path("version_lookalike");
+    let _ = std::fs::remove_file(&path);
+    let open = || {
+        JsonSync::<String, serde_json::Value, ShardMap<String, serde_json::Value>>::builder(&path)
+            .format_version(2)
+            .max_supported_version(2)
+            .build()
+            .unwrap()
+    };
+    // an unversioned file whose map happens to look like the old envelope
+    std::fs::write(&path, r#"{"version": 7, "data": {"a": 1}}"#).unwrap();
+    let db = open();
+    assert_eq!(db.len(), 2);
+    assert_eq!(db.get(&"version".into()), Some(serde_json::json!(7)));
+    assert_eq!(db.get(&"data".into()), Some(serde_json::json!({"a": 1})));
+    db.flush().unwrap();
+    drop(db);
+
+    // and it survives a roundtrip inside the real envelope
+    let raw: serde_json::Value = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+    assert_eq!(raw["json_sync_version"], 2);
+    assert_eq!(raw["data"]["version"], 7);
+    let db = open();
+    assert_eq!(db.len(), 2);
+    assert_eq!(db.get(&"version".into()), Some(serde_json::json!(7)));
+    drop(db);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn max_supported_version_refuses_newer_file() {
+    let path = temp_path("version_too_new");
+    std::fs::write(
+        &path,
+        r#"{"json_sync_version": 5, "data": {"a": "from the future"}}"#,
+    )
+    .unwrap();
+
+    let err = JsonSync::<String, i32, ShardMap<String, i32>>::builder(&path)
+        .max_supported_version(3)
+        .build()
+        .unwrap_err();
+    assert_eq!(
+        err,
+        json_sync::Error::Config("file version 5 is newer than supported 3".into())
+    );
+    // the file is left alone
+    assert!(std::fs::read_to_string(&path).unwrap().contains("future"));
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn migration_dry_run_reports_without_writing() {
+    use json_sync::migrate::{self, Migration};
+
+    let path = temp_path("migrate_dry_run");
+    let before = r#"{"json_sync_version": 1, "data": {"a": 3, "b": "x", "c": 10}}"#;
+    std::fs::write(&path, before).unwrap();
+    let migrations = [
+        // out of order on purpose, plus one for a version the file is past
+        Migration::new(2, |v| match v.as_i64() {
+            Some(n) => serde_json::json!({ "n": n }),
+            None => v,
+        }),
+        Migration::new(0, |_| unreachable!("the file is already at v1")),
+        Migration::new(1, |v| match v.as_i64() {
+            Some(n) if n < 5 => serde_json::json!(n * 2),
+            _ => v,
+        }),
+    ];
+
+    let report = migrate::dry_run(&path, &migrations).unwrap();
+    assert_eq!((report.from_version, report.to_version), (1, 3));
+    assert_eq!((report.entries, report.changed), (3, 2));
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), before);
+
+    let forked = [Migration::new(1, |v| v), Migration::new(1, |v| v)];
+    assert!(matches!(
+        migrate::dry_run(&path, &forked),
+        Err(json_sync::Error::Config(_))
+    ));
+
+    // a plain store keyed `version` and `data` is at version 0, not 7
+    std::fs::write(&path, r#"{"version": 7, "data": 3}"#).unwrap();
+    let wrap = [Migration::new(0, |v| serde_json::json!([v]))];
+    let report = migrate::dry_run(&path, &wrap).unwrap();
+    assert_eq!((report.from_version, report.to_version), (0, 1));
+    assert_eq!((report.entries, report.changed), (2, 2));
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn unversioned_file_counts_as_version_zero() {
+    let path = temp_path("version_legacy");
+    std::fs::write(&path, r#"{"a": 1}"#).unwrap();
+    let db = JsonSync::<String, i32, ShardMap<String, i32>>::builder(&path)
+        .max_supported_version(1)
+        .build()
+        .unwrap();
+    assert_eq!(db.get(&"a".into()), Some(1));
+    let _ = std::fs::remove_file(&path);
+}
+
+// ---- integer keys -----------------------------------------------------------
+
+/// Write `keys` as a JSON object and read them back through a fresh store,
+/// with a serializer from `make` each time.
+fn roundtrip_keys<K, S>(name: &str, keys: &[K], make: impl Fn() -> S)
+where
+    S: json_sync::serializer::Serializer + 'static,
+    K: std::hash::Hash
+        + Eq
+        + Clone
+        + std::fmt::Debug
+        + std::fmt::Display
+        + serde::Serialize
+        + serde::de::DeserializeOwned
+        + Send
+        + Sync
+        + 'static,
+{
+    let path = temp_path(name);
+    let _ = std::fs::remove_file(&path);
+    {
+        let db = JsonSync::<K, usize, ShardMap<K, usize>>::builder(&path)
+            .serializer(make())
+            .build()
+            .unwrap();
+        for (i, k) in keys.iter().enumerate() {
+            db.insert(k.clone(), i).unwrap();
+        }
+        db.flush().unwrap();
+    }
+    // object keys are the decimal strings, not floats or anything lossy
+    let raw: serde_json::Map<String, serde_json::Value> =
+        serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+    for k in keys {
+        assert!(raw.contains_key(&k.to_string()), "{k} missing from {raw:?}");
+    }
+
+    let db = JsonSync::<K, usize, ShardMap<K, usize>>::builder(&path)
+        .serializer(make())
+        .build()
+        .unwrap();
+    assert_eq!(db.len(), keys.len());
+    for (i, k) in keys.iter().enumerate() {
+        assert_eq!(db.get(k), Some(i), "{k}");
+    }
+    db.flush().unwrap();
+    let again: serde_json::Map<String, serde_json::Value> =
+        serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+    assert_eq!(again, raw);
+    let mut exported = Vec::new();
+    db.export(&mut exported).unwrap();
+    assert_eq!(
+        serde_json::from_slice::<serde_json::Map<_, _>>(&exported).unwrap(),
+        raw
+    );
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn integer_keys_roundtrip_at_their_limits() {
+    use json_sync::serializer::{SwappableSerializer, Transformed};
+
+    let json = JsonSerializer::new;
+    roundtrip_keys(
+        "int_keys_i64",
+        &[i64::MIN, -1, 0, 42, i64::MAX - 1, i64::MAX],
+        json,
+    );
+    roundtrip_keys(
+        "int_keys_u64",
+        &[0u64, 42, 1 << 53, (1 << 53) + 1, u64::MAX],
+        json,
+    );
+    roundtrip_keys("int_keys_i8", &[i8::MIN, -1, 0, i8::MAX], json);
+    roundtrip_keys("int_keys_i128", &[i128::MIN, -1, 0, i128::MAX], json);
+    roundtrip_keys("int_keys_u128", &[0u128, u128::MAX], json);
+
+    // the serializers that go through serde_json::Value parse them back too
+    let swappable = SwappableSerializer::default;
+    roundtrip_keys("int_keys_swap_i64", &[i64::MIN, 0, i64::MAX], swappable);
+    roundtrip_keys(
+        "int_keys_swap_u64",
+        &[0u64, (1 << 53) + 1, u64::MAX],
+        swappable,
+    );
+    let identity = || Transformed::new(JsonSerializer::new(), |v| v, |v| v);
+    roundtrip_keys("int_keys_tf_i64", &[i64::MIN, 0, i64::MAX], identity);
+    roundtrip_keys(
+        "int_keys_tf_u64",
+        &[0u64, (1 << 53) + 1, u64::MAX],
+        identity,
+    );
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug, serde::Serialize, serde::Deserialize)]
+struct UserId(String);
+
+impl std::fmt::Display for UserId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+#[test]
+fn newtype_string_keys_are_plain_object_keys() {
+    use json_sync::serializer::SwappableSerializer;
+
+    let ids = ["alice", "", "bob \"the\" builder"].map(|s| UserId(s.into()));
+    roundtrip_keys("newtype_keys", &ids, JsonSerializer::new);
+    roundtrip_keys("newtype_keys_swap", &ids, SwappableSerializer::default);
+
+    // the envelopes keyed by the store's own key type read them back too
+    let path = temp_path("newtype_keys_ttl");
+    let _ = std::fs::remove_file(&path);
+    let open = || {
+        JsonSync::<UserId, u32, ShardMap<UserId, u32>>::builder(&path)
+            .value_ttl(true)
+            .tombstones(true)
+            .build()
+            .unwrap()
+    };
+    let db = open();
+    db.insert(UserId("alice".into()), 1).unwrap();
+    db.insert(UserId("bob".into()), 2).unwrap();
+    db.remove(&UserId("bob".into())).unwrap();
+    db.flush().unwrap();
+    drop(db);
+    let raw: serde_json::Map<String, serde_json::Value> =
+        serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+    assert!(
+        raw.contains_key("alice") && raw.contains_key("bob"),
+        "{raw:?}"
+    );
+
+    let db = open();
+    assert_eq!(db.get(&UserId("alice".into())), Some(1));
+    assert_eq!(db.get(&UserId("bob".into())), None);
+    assert_eq!(db.len(), 1);
+    drop(db);
+    let _ = std::fs::remove_file(&path);
+}
+
+// ---- pairs layout -----------------------------------------------------------
+
+#[test]
+fn as_pairs_roundtrips_u64_keys() {
+    let path = temp_path("pairs_u64");
+    let _ = std::fs::remove_file(&path);
+    {
+        let db = JsonSync::<u64, String, ShardMap<u64, String>>::builder(&path)
+            .as_pairs(true)
+            .build()
+            .unwrap();
+        db.insert(u64::MAX, "max".into()).unwrap();
+        db.insert(7, "seven".into()).unwrap();
+        db.flush().unwrap();
+    }
+    let raw: serde_json::Value = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+    assert!(raw
+        .as_array()
+        .unwrap()
+        .contains(&serde_json::json!([7, "seven"])));
+
+    // the reader picks the layout from the file, not from the flag
+    let db = JsonSync::<u64, String, ShardMap<u64, String>>::open(&path).unwrap();
+    assert_eq!(db.len(), 2);
+    assert_eq!(db.get(&u64::MAX), Some("max".into()));
+    assert_eq!(db.get(&7), Some("seven".into()));
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn as_pairs_persists_struct_keys() {
+    #[derive(Clone, PartialEq, Eq, Hash, Debug, serde::Serialize, serde::Deserialize)]
+    struct Point {
+        x: i32,
+        y: i32,
+    }
+
+    let path = temp_path("pairs_struct");
+    let _ = std::fs::remove_file(&path);
+    let serializer = JsonSerializer::new().as_pairs(true).version(1);
+    {
+        let db = JsonSync::<Point, u8, ShardMap<Point, u8>>::builder(&path)
+            .serializer(serializer.clone())
+            .build()
+            .unwrap();
+        db.insert(Point { x: 1, y: -2 }, 3).unwrap();
+        db.flush().unwrap();
+    }
+    let db = JsonSync::<Point, u8, ShardMap<Point, u8>>::builder(&path)
+        .serializer(serializer)
+        .build()
+        .unwrap();
+    assert_eq!(db.get(&Point { x: 1, y: -2 }), Some(3));
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn struct_keys_without_pairs_fail_at_build() {
+    #[derive(Clone, PartialEq, Eq, Hash, Debug, serde::Serialize, serde::Deserialize)]
+    struct Point {
+        x: i32,
+        y: i32,
+    }
+    #[derive(serde::Deserialize)]
+    struct UserId(#[allow(dead_code)] u64);
+
+    let path = temp_path("struct_keys_object");
+    let _ = std::fs::remove_file(&path);
+    match JsonSync::<Point, u8, ShardMap<Point, u8>>::open(&path) {
+        Err(json_sync::Error::Config(msg)) => assert!(msg.contains("as_pairs"), "{msg}"),
+        Err(e) => panic!("expected Error::Config, got {e:?}"),
+        Ok(_) => panic!("struct keys opened without as_pairs"),
+    }
+    assert!(!path.exists());
+
+    assert!(JsonSerializer::check_key_type::<String>().is_ok());
+    assert!(JsonSerializer::check_key_type::<u64>().is_ok());
+    assert!(JsonSerializer::check_key_type::<UserId>().is_ok());
+    assert!(JsonSerializer::check_key_type::<(u8, u8)>().is_err());
+    assert!(JsonSerializer::check_key_type::<Option<String>>().is_err());
+}
+
+// ---- streaming --------------------------------------------------------------
+
+/// Records the size of every write it receives.
+#[derive(Default)]
+struct ChunkLog {
+    total: usize,
+    largest: usize,
+}
+
+impl std::io::Write for ChunkLog {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.total += buf.len();
+        self.largest = self.largest.max(buf.len());
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn serialize_to_streams_in_bounded_chunks() {
+    use json_sync::serializer::Serializer;
+    use std::collections::HashMap;
+
+    let data: HashMap<String, String> = (0..5_000)
+        .map(|i| (format!("key{i}"), "v".repeat(32)))
+        .collect();
+    let serializer = JsonSerializer::new();
+    let whole = serializer.serialize(&data).unwrap();
+
+    let mut log = ChunkLog::default();
+    {
+        let mut out = std::io::BufWriter::with_capacity(1024, &mut log);
+        serializer.serialize_to(&data, &mut out).unwrap();
+    }
+    assert_eq!(log.total, whole.len());
+    assert!(
+        log.largest <= 1024,
+        "largest write was {} bytes",
+        log.largest
+    );
+}
+
+#[test]
+fn small_write_buffer_still_writes_whole_file() {
+    let path = temp_path("write_buffer_size");
+    let _ = std::fs::remove_file(&path);
+    {
+        let db = JsonSync::<String, u32, ShardMap<String, u32>>::builder(&path)
+            .write_buffer_size(256)
+            .build()
+            .unwrap();
+        db.extend((0..10_000).map(|i| (format!("key{i}"), i)))
+            .unwrap();
+        db.flush().unwrap();
+    }
+    let db = JsonSync::<String, u32, ShardMap<String, u32>>::open(&path).unwrap();
+    assert_eq!(db.len(), 10_000);
+    assert_eq!(db.get(&"key9999".into()), Some(9999));
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn cursor_roundtrip_without_a_store() {
+    use json_sync::serializer::Serializer;
+    use std::collections::HashMap;
+    use std::io::Cursor;
+
+    let data: HashMap<String, Vec<i32>> =
+        [("a".to_string(), vec![1, 2]), ("b".to_string(), vec![])].into();
+    for serializer in [JsonSerializer::new(), JsonSerializer::pretty().version(3)] {
+        let mut cursor = Cursor::new(Vec::new());
+        serializer.serialize_to(&data, &mut cursor).unwrap();
+        cursor.set_position(0);
+        let back: HashMap<String, Vec<i32>> = serializer.deserialize_from(cursor).unwrap();
+        assert_eq!(back, data);
+    }
+}
+
+// ---- opaque values ----------------------------------------------------------
+
+#[test]
+fn raw_value_roundtrips_byte_for_byte() {
+    use serde_json::value::RawValue;
+
+    let path = temp_path("raw_value");
+    let _ = std::fs::remove_file(&path);
+    let fragment = r#"{"z": 1,   "a": [1.50, "x"]}"#;
+    {
+        let db = JsonSync::<String, Box<RawValue>, ShardMap<String, Box<RawValue>>>::open(&path)
+            .unwrap();
+        db.insert(
+            "blob".into(),
+            RawValue::from_string(fragment.into()).unwrap(),
+        )
+        .unwrap();
+        db.flush().unwrap();
+    }
+    // key order, spacing, and number formatting all survive
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        format!(r#"{{"blob":{fragment}}}"#)
+    );
+
+    let db =
+        JsonSync::<String, Box<RawValue>, ShardMap<String, Box<RawValue>>>::open(&path).unwrap();
+    assert_eq!(db.get(&"blob".into()).unwrap().get(), fragment);
+    let _ = std::fs::remove_file(&path);
+}
+
+// ---- schema-less values -----------------------------------------------------
+
+/// One of each kind of JSON value, nested a few levels deep.
+fn documents() -> Vec<(String, serde_json::Value)> {
+    use serde_json::json;
+
+    vec![
+        ("null".into(), json!(null)),
+        ("bool".into(), json!(false)),
+        ("int".into(), json!(-42)),
+        ("big".into(), json!(u64::MAX)),
+        ("float".into(), json!(2.5)),
+        ("string".into(), json!("")),
+        ("empty_array".into(), json!([])),
+        ("empty_object".into(), json!({})),
+        (
+            "nested".into(),
+            json!({
+                "user": {"name": "ada", "tags": ["a", null, 3, [true]], "meta": null},
+                "scores": [1.25, -0.5, 1e300],
+                "deeper": {"a": {"b": {"c": {"d": [{}]}}}},
+            }),
+        ),
+    ]
+}
+
+#[test]
+fn value_store_roundtrips_any_json() {
+    use serde_json::Value;
+
+    let path = temp_path("schemaless");
+    for (pretty, pairs) in [(false, false), (true, false), (false, true)] {
+        let _ = std::fs::remove_file(&path);
+        let open = || {
+            JsonSync::<String, Value, ShardMap<String, Value>>::builder(&path)
+                .pretty(pretty)
+                .as_pairs(pairs)
+                .build()
+                .unwrap()
+        };
+        let db = open();
+        db.extend(documents()).unwrap();
+        db.flush().unwrap();
+        drop(db);
+
+        let db = open();
+        let mut got = db.iter();
+        got.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut want = documents();
+        want.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(got, want, "pretty({pretty}), as_pairs({pairs})");
+        // the stored null is a value, not a missing key
+        assert_eq!(db.get(&"null".into()), Some(Value::Null));
+    }
+
+    // reach into a large document without cloning it, and edit it in place
+    let db = JsonSync::<String, Value, ShardMap<String, Value>>::open(&path).unwrap();
+    let name = db.get_if(&"nested".into(), |v| v["user"]["name"].clone());
+    assert_eq!(name, Some(Value::from("ada")));
+    assert!(db
+        .update(&"nested".into(), |v| v["user"]["tags"][3][0] =
+            Value::from(false))
+        .unwrap());
+    let nulls = db.fold(0, |n, _, v| n + usize::from(v.is_null()));
+    assert_eq!(nulls, 1);
+    db.flush().unwrap();
+    drop(db);
+    let db = JsonSync::<String, Value, ShardMap<String, Value>>::open(&path).unwrap();
+    assert_eq!(
+        db.get_if(&"nested".into(), |v| v.pointer("/user/tags/3/0").cloned()),
+        Some(Some(Value::from(false)))
+    );
+    drop(db);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn value_store_roundtrips_through_envelopes() {
+    use serde_json::Value;
+
+    let path = temp_path("schemaless_envelopes");
+    let _ = std::fs::remove_file(&path);
+    // values shaped like the envelopes themselves stay values
+    let lookalikes = [
+        (
+            "ttl_shaped".to_string(),
+            serde_json::json!({"v": 1, "exp": null}),
+        ),
+        (
+            "marker_shaped".to_string(),
+            serde_json::json!({"__deleted": false}),
+        ),
+    ];
+    let open = || {
+        JsonSync::<String, Value, ShardMap<String, Value>>::builder(&path)
+            .value_ttl(true)
+            .build()
+            .unwrap()
+    };
+    let db = open();
+    db.extend(documents()).unwrap();
+    db.extend(lookalikes.clone()).unwrap();
+    db.flush().unwrap();
+    drop(db);
+    let db = open();
+    for (k, v) in documents().into_iter().chain(lookalikes.clone()) {
+        assert_eq!(db.get(&k), Some(v), "{k}");
+    }
+    drop(db);
+    let _ = std::fs::remove_file(&path);
+
+    let lazy = || {
+        JsonSync::<String, Value, ShardMap<String, Value>>::builder(&path)
+            .lazy_values(true)
+            .build()
+            .unwrap()
+    };
+    let db = lazy();
+    db.extend(documents()).unwrap();
+    db.flush().unwrap();
+    drop(db);
+    let db = lazy();
+    db.insert("new".into(), Value::Null).unwrap();
+    db.flush().unwrap();
+    drop(db);
+    let db = lazy();
+    assert_eq!(db.len(), documents().len() + 1);
+    for (k, v) in documents() {
+        assert_eq!(db.get(&k), Some(v), "{k}");
+    }
+    drop(db);
+    let _ = std::fs::remove_file(&path);
+}
+
+// ---- value transforms -------------------------------------------------------
+
+/// Rename every object key inside each entry's value with `rename`, leaving
+/// the top-level (store) keys alone.
+fn rename_fields(doc: serde_json::Value, rename: fn(&str) -> String) -> serde_json::Value {
+    fn walk(v: serde_json::Value, rename: fn(&str) -> String) -> serde_json::Value {
+        match v {
+            serde_json::Value::Object(map) => map
+                .into_iter()
+                .map(|(k, v)| (rename(&k), walk(v, rename)))
+                .collect(),
+            serde_json::Value::Array(items) => items.into_iter().map(|v| walk(v, rename)).collect(),
+            other => other,
+        }
+    }
+    match doc {
+        serde_json::Value::Object(map) => {
+            map.into_iter().map(|(k, v)| (k, walk(v, rename))).collect()
+        }
+        other => other,
+    }
+}
+
+fn to_camel(s: &str) -> String {
+    let mut out = String::new();
+    let mut upper = false;
+    for c in s.chars() {
+        if c == '_' {
+            upper = true;
+        } else if upper {
+            out.extend(c.to_uppercase());
+            upper = false;
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+fn to_snake(s: &str) -> String {
+    let mut out = String::new();
+    for c in s.chars() {
+        if c.is_uppercase() {
+            out.push('_');
+            out.extend(c.to_lowercase());
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+#[test]
+fn transformed_writes_camel_case_fields() {
+    use json_sync::serializer::Transformed;
+
+    #[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct Profile {
+        display_name: String,
+        last_login_at: u64,
+    }
+
+    let camel = || {
+        Transformed::new(
+            JsonSerializer::new(),
+            |doc| rename_fields(doc, to_camel),
+            |doc| rename_fields(doc, to_snake),
+        )
+    };
+    let path = temp_path("transformed_camel");
+    let _ = std::fs::remove_file(&path);
+    let profile = Profile {
+        display_name: "Ada".into(),
+        last_login_at: 1_700_000_000,
+    };
+    {
+        let db = JsonSync::<String, Profile, ShardMap<String, Profile>>::builder(&path)
+            .serializer(camel())
+            .build()
+            .unwrap();
+        db.insert("user_1".into(), profile.clone()).unwrap();
+        db.flush().unwrap();
+    }
+    let raw: serde_json::Value = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+    assert_eq!(raw["user_1"]["displayName"], "Ada");
+    assert_eq!(raw["user_1"]["lastLoginAt"], 1_700_000_000);
+
+    let db = JsonSync::<String, Profile, ShardMap<String, Profile>>::builder(&path)
+        .serializer(camel())
+        .build()
+        .unwrap();
+    assert_eq!(db.get(&"user_1".into()), Some(profile));
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn document_transform_stamps_on_write_and_strips_on_read() {
+    use std::sync::{Arc, Mutex};
+
+    let path = temp_path("document_transform");
+    let _ = std::fs::remove_file(&path);
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let open = || {
+        let (w, r) = (Arc::clone(&seen), Arc::clone(&seen));
+        JsonSync::<String, i32, ShardMap<String, i32>>::builder(&path)
+            .pretty(true)
+            .document_transform(
+                move |doc| {
+                    w.lock().unwrap().push("write");
+                    doc["_generated_at"] = "2026-10-15T00:00:00Z".into();
+                },
+                move |doc| {
+                    // sees the stamp before it would fail to parse as an i32
+                    assert!(doc.get("_generated_at").is_some());
+                    r.lock().unwrap().push("read");
+                    doc.as_object_mut().unwrap().remove("_generated_at");
+                },
+            )
+            .build()
+            .unwrap()
+    };
+
+    // nothing to read for a missing file
+    let db = open();
+    db.insert("a".into(), 1).unwrap();
+    db.flush().unwrap();
+    drop(db);
+    let raw: serde_json::Value = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+    assert_eq!(raw["_generated_at"], "2026-10-15T00:00:00Z");
+    assert_eq!(raw["a"], 1);
+    assert!(std::fs::read_to_string(&path).unwrap().contains('\n'));
+
+    let db = open();
+    assert_eq!(db.keys(), vec!["a".to_string()]);
+    assert_eq!(*seen.lock().unwrap(), ["write", "read"]);
+    let _ = std::fs::remove_file(&path);
+}
+
+// ---- format detection -------------------------------------------------------
+
+/// A stand-in "binary" format: a magic header followed by the JSON body.
+struct Tagged;
+
+impl Tagged {
+    const MAGIC: &'static [u8] = b"TAG\x01";
+}
+
+impl json_sync::serializer::Serializer for Tagged {
+    fn serialize<K, V>(&self, data: &std::collections::HashMap<K, V>) -> json_sync::Result<Vec<u8>>
+    where
+        K: serde::Serialize,
+        V: serde::Serialize,
+    {
+        let mut out = Self::MAGIC.to_vec();
+        out.extend(JsonSerializer::new().serialize(data)?);
+        Ok(out)
+    }
+
+    fn deserialize<K, V>(&self, bytes: &[u8]) -> json_sync::Result<std::collections::HashMap<K, V>>
+    where
+        K: for<'de> serde::Deserialize<'de> + Eq + std::hash::Hash,
+        V: for<'de> serde::Deserialize<'de>,
+    {
+        let body = bytes
+            .strip_prefix(Self::MAGIC)
+            .ok_or_else(|| json_sync::Error::Deserialize("missing TAG header".into()))?;
+        JsonSerializer::new().deserialize(body)
+    }
+
+    fn sniff(&self, bytes: &[u8]) -> bool {
+        bytes.starts_with(Self::MAGIC)
+    }
+}
+
+#[test]
+fn auto_serializer_reads_either_format_and_writes_primary() {
+    use json_sync::serializer::{AutoSerializer, Serializer};
+    use std::collections::HashMap;
+
+    let json_file = temp_path("auto_json");
+    let tagged_file = temp_path("auto_tagged");
+    let data: HashMap<String, i32> = [("a".to_string(), 1)].into();
+    std::fs::write(&json_file, JsonSerializer::new().serialize(&data).unwrap()).unwrap();
+    std::fs::write(&tagged_file, Tagged.serialize(&data).unwrap()).unwrap();
+
+    // plain JSON can't read the tagged file on its own
+    assert!(JsonSync::<String, i32, ShardMap<String, i32>>::open(&tagged_file).is_err());
+
+    for path in [&json_file, &tagged_file] {
+        let db = JsonSync::<String, i32, ShardMap<String, i32>>::builder(path)
+            .serializer(AutoSerializer::new(JsonSerializer::new(), Tagged))
+            .build()
+            .unwrap();
+        assert_eq!(db.get(&"a".into()), Some(1));
+        db.flush().unwrap();
+    }
+    // both are JSON now
+    assert_eq!(std::fs::read_to_string(&tagged_file).unwrap(), r#"{"a":1}"#);
+    let _ = std::fs::remove_file(&json_file);
+    let _ = std::fs::remove_file(&tagged_file);
+}
+
+#[test]
+fn swappable_serializer_switches_format_at_runtime() {
+    use json_sync::serializer::SwappableSerializer;
+    use std::sync::Arc;
+
+    let path = temp_path("swappable");
+    std::fs::write(&path, r#"{"a":1}"#).unwrap();
+
+    let db = JsonSync::<String, i32, ShardMap<String, i32>>::builder(&path)
+        .serializer(SwappableSerializer::new(Arc::new(JsonSerializer::new())))
+        .build()
+        .unwrap();
+    assert_eq!(db.get(&"a".into()), Some(1));
+    db.set_serializer(Arc::new(Tagged));
+    db.insert("b".into(), 2).unwrap();
+    db.flush().unwrap();
+    assert!(std::fs::read(&path).unwrap().starts_with(Tagged::MAGIC));
+    drop(db);
+
+    // the new writer plus the legacy reader opens both formats
+    let reopen = || {
+        JsonSync::<String, i32, ShardMap<String, i32>>::builder(&path)
+            .serializer(
+                SwappableSerializer::new(Arc::new(Tagged))
+                    .read_also(Arc::new(JsonSerializer::new())),
+            )
+            .build()
+            .unwrap()
+    };
+    let db = reopen();
+    assert_eq!(db.get(&"b".into()), Some(2));
+    drop(db);
+    std::fs::write(&path, r#"{"c":3}"#).unwrap();
+    assert_eq!(reopen().get(&"c".into()), Some(3));
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn swappable_serializer_lists_each_reader_once() {
+    use json_sync::serializer::{Serializer, SwappableSerializer};
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// Tagged, counting how often it's asked to sniff.
+    #[derive(Default)]
+    struct Counted(AtomicUsize);
+
+    impl Serializer for Counted {
+        fn serialize<K, V>(&self, data: &HashMap<K, V>) -> json_sync::Result<Vec<u8>>
+        where
+            K: serde::Serialize,
+            V: serde::Serialize,
+        {
+            Tagged.serialize(data)
+        }
+
+        fn deserialize<K, V>(&self, bytes: &[u8]) -> json_sync::Result<HashMap<K, V>>
+        where
+            K: for<'de> serde::Deserialize<'de> + Eq + std::hash::Hash,
+            V: for<'de> serde::Deserialize<'de>,
+        {
+            Tagged.deserialize(bytes)
+        }
+
+        fn sniff(&self, bytes: &[u8]) -> bool {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            Tagged.sniff(bytes)
+        }
+    }
+
+    let json: Arc<JsonSerializer> = Arc::new(JsonSerializer::new());
+    let counted = Arc::new(Counted::default());
+    let swap = SwappableSerializer::new(json.clone());
+    for _ in 0..50 {
+        swap.set(counted.clone());
+        swap.set(json.clone());
+    }
+    assert!(!swap.sniff(b"neither"));
+    assert_eq!(counted.0.load(Ordering::Relaxed), 1);
+
+    // both formats still read
+    let data: HashMap<String, i32> = [("a".to_string(), 1)].into();
+    let tagged = Tagged.serialize(&data).unwrap();
+    let plain = JsonSerializer::new().serialize(&data).unwrap();
+    for bytes in [&tagged, &plain] {
+        assert_eq!(swap.deserialize::<String, i32>(bytes).unwrap(), data);
+    }
+}
+
+#[test]
+fn skip_nulls_prunes_none_fields() {
+    #[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+    struct Profile {
+        name: String,
+        nickname: Option<String>,
+        email: Option<String>,
+        address: Option<Address>,
+        tags: Vec<Option<u8>>,
+    }
+    #[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+    struct Address {
+        city: Option<String>,
+        zip: Option<String>,
+    }
+
+    let path = temp_path("skip_nulls");
+    let _ = std::fs::remove_file(&path);
+    let profile = Profile {
+        name: "ann".into(),
+        nickname: None,
+        email: None,
+        address: Some(Address {
+            city: Some("Oslo".into()),
+            zip: None,
+        }),
+        tags: vec![None, Some(1)],
+    };
+    let open = || {
+        JsonSync::<String, Profile, ShardMap<String, Profile>>::builder(&path)
+            .skip_nulls(true)
+            .build()
+            .unwrap()
+    };
+    let db = open();
+    db.insert("ann".into(), profile.clone()).unwrap();
+    db.flush().unwrap();
+    drop(db);
+
+    // nulls in arrays keep their place
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        r#"{"ann":{"address":{"city":"Oslo"},"name":"ann","tags":[null,1]}}"#
+    );
+    assert_eq!(open().get(&"ann".into()), Some(profile));
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn large_ints_as_strings_quotes_u64_max_on_disk() {
+    #[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+    struct Counter {
+        hits: u64,
+        floor: i64,
+        small: u64,
+        label: String,
+    }
+
+    let path = temp_path("large_ints");
+    let _ = std::fs::remove_file(&path);
+    let counter = Counter {
+        hits: u64::MAX,
+        floor: i64::MIN,
+        small: (1 << 53) - 1,
+        label: "42".into(),
+    };
+    let open = || {
+        JsonSync::<String, Counter, ShardMap<String, Counter>>::builder(&path)
+            .large_ints_as_strings(true)
+            .build()
+            .unwrap()
+    };
+    let db = open();
+    db.insert("c".into(), counter.clone()).unwrap();
+    db.flush().unwrap();
+    drop(db);
+
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        r#"{"c":{"hits":"18446744073709551615","floor":"-9223372036854775808","small":9007199254740991,"label":"42"}}"#
+    );
+    assert_eq!(open().get(&"c".into()), Some(counter.clone()));
+    let _ = std::fs::remove_file(&path);
+
+    // pairs put keys through the same quoting
+    let open = || {
+        JsonSync::<u64, u64, ShardMap<u64, u64>>::builder(&path)
+            .as_pairs(true)
+            .large_ints_as_strings(true)
+            .build()
+            .unwrap()
+    };
+    let db = open();
+    db.insert(u64::MAX, 1 << 53).unwrap();
+    db.flush().unwrap();
+    drop(db);
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        r#"[["18446744073709551615","9007199254740992"]]"#
+    );
+    assert_eq!(open().get(&u64::MAX), Some(1 << 53));
+    let _ = std::fs::remove_file(&path);
+
+    // an i128 past 64 bits is quoted and reads back exactly
+    let open = || {
+        JsonSync::<String, i128, ShardMap<String, i128>>::builder(&path)
+            .large_ints_as_strings(true)
+            .build()
+            .unwrap()
+    };
+    let db = open();
+    db.insert("max".into(), i128::MAX).unwrap();
+    db.insert("min".into(), i128::MIN).unwrap();
+    db.flush().unwrap();
+    drop(db);
+    let on_disk = std::fs::read_to_string(&path).unwrap();
+    assert!(on_disk.contains(r#""max":"170141183460469231731687303715884105727""#));
+    assert!(on_disk.contains(r#""min":"-170141183460469231731687303715884105728""#));
+    let db = open();
+    assert_eq!(db.get(&"max".into()), Some(i128::MAX));
+    assert_eq!(db.get(&"min".into()), Some(i128::MIN));
+    drop(db);
+    let _ = std::fs::remove_file(&path);
+
+    // a string of digits stays a string both ways
+    let open = || {
+        JsonSync::<String, String, ShardMap<String, String>>::builder(&path)
+            .large_ints_as_strings(true)
+            .build()
+            .unwrap()
+    };
+    let db = open();
+    db.insert("id".into(), "9007199254740993".into()).unwrap();
+    db.flush().unwrap();
+    drop(db);
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        r#"{"id":"9007199254740993"}"#
+    );
+    assert_eq!(open().get(&"id".into()), Some("9007199254740993".into()));
+    let _ = std::fs::remove_file(&path);
+
+    // a file written without the option still loads
+    std::fs::write(
+        &path,
+        r#"{"c":{"floor":-9223372036854775808,"hits":18446744073709551615,"label":"42","small":9007199254740991}}"#,
+    )
+    .unwrap();
+    let db = JsonSync::<String, Counter, ShardMap<String, Counter>>::builder(&path)
+        .large_ints_as_strings(true)
+        .build()
+        .unwrap();
+    assert_eq!(db.get(&"c".into()), Some(counter));
+    drop(db);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn large_ints_as_strings_reaches_lazy_and_salvaged_values() {
+    use json_sync::ValueErrorPolicy;
+
+    let path = temp_path("large_ints_lazy");
+    let file = r#"{"a":"18446744073709551615"}"#;
+    std::fs::write(&path, file).unwrap();
+    let builder = || {
+        JsonSync::<String, u64, ShardMap<String, u64>>::builder(&path).large_ints_as_strings(true)
+    };
+
+    let db = builder().lazy_values(true).build().unwrap();
+    assert_eq!(db.get(&"a".into()), Some(u64::MAX));
+    db.insert("b".into(), 1).unwrap();
+    assert_eq!(db.iter().len(), 2);
+    db.flush().unwrap();
+    drop(db);
+    let db = builder().lazy_values(true).build().unwrap();
+    assert_eq!(db.get(&"a".into()), Some(u64::MAX));
+    assert_eq!(db.get(&"b".into()), Some(1));
+    drop(db);
+
+    std::fs::write(&path, file).unwrap();
+    let db = builder()
+        .on_value_error(ValueErrorPolicy::SkipEntry)
+        .build()
+        .unwrap();
+    assert_eq!(db.len(), 1);
+    assert_eq!(db.get(&"a".into()), Some(u64::MAX));
+    db.insert("b".into(), 1).unwrap();
+    db.flush().unwrap();
+    drop(db);
+    assert!(std::fs::read_to_string(&path)
+        .unwrap()
+        .contains(r#""a":"18446744073709551615""#));
+
+    let db = builder()
+        .on_value_error(ValueErrorPolicy::fallback(|k: &String, _| {
+            panic!("{k} should have parsed")
+        }))
+        .build()
+        .unwrap();
+    assert_eq!(db.get(&"a".into()), Some(u64::MAX));
+    assert_eq!(db.get(&"b".into()), Some(1));
+    drop(db);
+    let _ = std::fs::remove_file(&path);
+}
+
+// ---- awkward keys and values ------------------------------------------------
+
+/// Keys that have tripped up JSON object keys somewhere: empty, escapes,
+/// control characters, non-ASCII, and one far longer than any buffer.
+fn awkward_keys() -> Vec<String> {
+    vec![
+        String::new(),
+        " ".into(),
+        r#"say "hi""#.into(),
+        r"C:\temp\new".into(),
+        "line\nbreak\ttab\u{0}nul".into(),
+        "ünïcödé ✓ 🦀".into(),
+        "\u{2028}\u{2029}".into(),
+        "k".repeat(100_000),
+    ]
+}
+
+#[test]
+fn awkward_keys_and_empty_values_roundtrip() {
+    let path = temp_path("awkward_keys");
+    for pairs in [false, true] {
+        let _ = std::fs::remove_file(&path);
+        let open = || {
+            JsonSync::<String, Vec<String>, ShardMap<String, Vec<String>>>::builder(&path)
+                .as_pairs(pairs)
+                .build()
+                .unwrap()
+        };
+        let db = open();
+        for (i, key) in awkward_keys().into_iter().enumerate() {
+            let value = match i % 3 {
+                0 => vec![],
+                1 => vec![String::new()],
+                _ => vec![key.clone()],
+            };
+            db.insert(key, value).unwrap();
+        }
+        db.flush().unwrap();
+        drop(db);
+
+        let db = open();
+        assert_eq!(db.len(), awkward_keys().len());
+        for (i, key) in awkward_keys().into_iter().enumerate() {
+            let want = match i % 3 {
+                0 => vec![],
+                1 => vec![String::new()],
+                _ => vec![key.clone()],
+            };
+            assert_eq!(db.get(&key), Some(want), "as_pairs({pairs}), key {i}");
+        }
+    }
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn awkward_keys_roundtrip_through_envelopes() {
+    let path = temp_path("awkward_keys_envelopes");
+    let _ = std::fs::remove_file(&path);
+    let open = || {
+        JsonSync::<String, String, ShardMap<String, String>>::builder(&path)
+            .value_ttl(true)
+            .build()
+            .unwrap()
+    };
+    let db = open();
+    for key in awkward_keys() {
+        db.insert_with_ttl(key, String::new(), std::time::Duration::from_secs(3600))
+            .unwrap();
+    }
+    db.flush().unwrap();
+    drop(db);
+
+    let db = open();
+    for key in awkward_keys() {
+        assert_eq!(db.get(&key), Some(String::new()));
+        assert!(db.expires_at(&key).is_some());
+    }
+    drop(db);
+    let _ = std::fs::remove_file(&path);
+
+    // values nobody parsed are written back under the same keys
+    let lazy = || {
+        JsonSync::<String, String, ShardMap<String, String>>::builder(&path)
+            .lazy_values(true)
+            .build()
+            .unwrap()
+    };
+    let db = lazy();
+    db.extend(awkward_keys().into_iter().map(|k| (k, String::new())))
+        .unwrap();
+    db.flush().unwrap();
+    drop(db);
+    let db = lazy();
+    db.insert("new".into(), "x".into()).unwrap();
+    db.flush().unwrap();
+    drop(db);
+    let db = lazy();
+    assert_eq!(db.len(), awkward_keys().len() + 1);
+    for key in awkward_keys() {
+        assert_eq!(db.get(&key), Some(String::new()));
+    }
+    let _ = std::fs::remove_file(&path);
+}